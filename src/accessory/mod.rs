@@ -1,44 +1,74 @@
 use anyhow::{bail, Context, Result};
+use std::time::Duration;
 
-use crate::config::{AccessoryConfig, ShipitConfig, StageConfig};
+use crate::config::{AccessoryConfig, HostConfig, ShipitConfig, StageConfig};
 use crate::output;
+use crate::ssh::pool::SessionPool;
 use crate::ssh::SshSession;
 
 /// Build the Docker container name for an accessory: {app_name}-{accessory_name}
-fn container_name(app_name: &str, accessory_name: &str) -> String {
+pub fn container_name(app_name: &str, accessory_name: &str) -> String {
     format!("{}-{}", app_name, accessory_name)
 }
 
-/// Find the SSH user and host address for a given accessory host IP.
+/// Find the stage and host for a given accessory host IP.
 /// Searches all stages for a host whose address matches the accessory's host.
 fn find_ssh_target<'a>(
     config: &'a ShipitConfig,
     accessory_host: &str,
-) -> Option<(&'a str, &'a str, Option<u16>, Option<&'a str>)> {
+) -> Option<(&'a StageConfig, &'a HostConfig)> {
     for stage in config.stages.values() {
-        let user = stage.user.as_deref().unwrap_or("deploy");
         for host in &stage.hosts {
             if host.address == accessory_host {
-                return Some((user, host.address.as_str(), stage.port, stage.proxy.as_deref()));
+                return Some((stage, host));
             }
         }
     }
     None
 }
 
-/// Connect to the host where the accessory runs.
+/// Whether an accessory runs on one of the stage's hosts, either by address
+/// or by the host's WireGuard IP (10.10.0.N is the N-th host).
+pub fn runs_on_stage(stage: &StageConfig, accessory: &AccessoryConfig) -> bool {
+    host_index(stage, accessory).is_some()
+}
+
+/// Index of the stage host the accessory runs on, if it is one of them
+pub fn host_index(stage: &StageConfig, accessory: &AccessoryConfig) -> Option<usize> {
+    if let Some(index) = stage.hosts.iter().position(|h| h.address == accessory.host) {
+        return Some(index);
+    }
+    accessory
+        .host
+        .strip_prefix("10.10.0.")
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|n| *n >= 1 && *n <= stage.hosts.len())
+        .map(|n| n - 1)
+}
+
+/// Connect to the host where the accessory runs, reusing a pooled session.
 /// If the accessory host is a WireGuard IP (10.10.0.x), we need to find which
 /// real host has that WG IP by matching index position.
-async fn connect_to_accessory_host(
+async fn connect_to_accessory_host<'p>(
+    pool: &'p mut SessionPool,
     config: &ShipitConfig,
     stage: &StageConfig,
     accessory_host: &str,
-) -> Result<SshSession> {
+) -> Result<&'p SshSession> {
     let user = stage.user.as_deref().unwrap_or("deploy");
 
     // First try direct match (accessory host == real host address)
-    if let Some((u, addr, port, proxy)) = find_ssh_target(config, accessory_host) {
-        return SshSession::connect(u, addr, port, proxy).await;
+    if let Some((target_stage, host)) = find_ssh_target(config, accessory_host) {
+        let target_user = target_stage.user.as_deref().unwrap_or("deploy");
+        return pool
+            .get(
+                target_user,
+                &host.address,
+                target_stage.port,
+                target_stage.proxy.as_deref(),
+                &target_stage.ssh,
+            )
+            .await;
     }
 
     // If accessory host is a WireGuard IP (10.10.0.x), resolve to real host
@@ -51,7 +81,9 @@ async fn connect_to_accessory_host(
         {
             let host_index = index.saturating_sub(1); // 10.10.0.1 -> index 0
             if let Some(host) = stage.hosts.get(host_index) {
-                return SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await;
+                return pool
+                    .get(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh)
+                    .await;
             }
         }
     }
@@ -97,6 +129,7 @@ fn build_run_command(name: &str, accessory: &AccessoryConfig) -> String {
 }
 
 pub async fn boot_accessory(
+    pool: &mut SessionPool,
     config: &ShipitConfig,
     stage: &StageConfig,
     accessory_name: &str,
@@ -105,7 +138,7 @@ pub async fn boot_accessory(
     let name = container_name(&config.app.name, accessory_name);
     output::info(&format!("Booting accessory '{}' on {}...", accessory_name, accessory.host));
 
-    let session = connect_to_accessory_host(config, stage, &accessory.host).await?;
+    let session = connect_to_accessory_host(pool, config, stage, &accessory.host).await?;
 
     // Check if already running
     let is_running = session
@@ -114,11 +147,10 @@ pub async fn boot_accessory(
 
     if is_running {
         let output_str = session
-            .exec(&format!("docker ps -q -f name=^{}$", name))
+            .query(&format!("docker ps -q -f name=^{}$", name))
             .await?;
         if !output_str.trim().is_empty() {
             output::warning(&format!("Container '{}' is already running", name));
-            session.close().await?;
             return Ok(());
         }
     }
@@ -135,12 +167,119 @@ pub async fn boot_accessory(
         .await
         .with_context(|| format!("Failed to boot accessory '{}'", accessory_name))?;
 
-    session.close().await?;
     output::success(&format!("Accessory '{}' is running", accessory_name));
     Ok(())
 }
 
+/// Container state: health status when the image has a healthcheck,
+/// otherwise the run state; empty when there is no such container
+async fn container_state(
+    pool: &mut SessionPool,
+    config: &ShipitConfig,
+    stage: &StageConfig,
+    accessory: &AccessoryConfig,
+    name: &str,
+) -> Result<String> {
+    let session = connect_to_accessory_host(pool, config, stage, &accessory.host).await?;
+    Ok(session
+        .query(&format!(
+            "docker inspect --format '{{{{if .State.Health}}}}{{{{.State.Health.Status}}}}{{{{else}}}}{{{{.State.Status}}}}{{{{end}}}}' {} 2>/dev/null || true",
+            name
+        ))
+        .await?
+        .trim()
+        .to_string())
+}
+
+/// Make sure the accessories in `names` are up before a release starts:
+/// running, or healthy when their image has a healthcheck. Stopped ones are
+/// booted when `boot` is set; otherwise the deploy fails with how to fix it.
+pub async fn ensure_running(
+    config: &ShipitConfig,
+    stage_name: &str,
+    stage: &StageConfig,
+    names: &[String],
+    boot: bool,
+) -> Result<()> {
+    let mut pool = SessionPool::new();
+    let result = wait_for_accessories(&mut pool, config, stage_name, stage, names, boot).await;
+    let closed = pool.close_all().await;
+    result?;
+    closed
+}
+
+async fn wait_for_accessories(
+    pool: &mut SessionPool,
+    config: &ShipitConfig,
+    stage_name: &str,
+    stage: &StageConfig,
+    names: &[String],
+    boot: bool,
+) -> Result<()> {
+    let hc = &config.deploy.health_check;
+
+    for accessory_name in names {
+        let accessory = config
+            .accessories
+            .get(accessory_name)
+            .with_context(|| format!("No accessory named '{}'", accessory_name))?;
+        let name = container_name(&config.app.name, accessory_name);
+
+        let mut state = container_state(pool, config, stage, accessory, &name).await?;
+        if !matches!(state.as_str(), "running" | "healthy" | "starting") {
+            if !boot {
+                bail!(
+                    "Accessory '{}' is {} on {}. Boot it with `shipit accessory boot -s {} {}`, \
+                     or set deploy.boot_accessories = true",
+                    accessory_name,
+                    if state.is_empty() {
+                        "not created"
+                    } else {
+                        state.as_str()
+                    },
+                    accessory.host,
+                    stage_name,
+                    accessory_name
+                );
+            }
+            boot_accessory(pool, config, stage, accessory_name, accessory).await?;
+            // A dry run only printed the boot
+            if crate::ssh::is_dry_run() {
+                continue;
+            }
+            state = container_state(pool, config, stage, accessory, &name).await?;
+        }
+
+        let mut attempts = 1;
+        while state == "starting" && attempts < hc.retries {
+            tokio::time::sleep(Duration::from_secs(hc.interval)).await;
+            state = container_state(pool, config, stage, accessory, &name).await?;
+            attempts += 1;
+        }
+        match state.as_str() {
+            "running" | "healthy" => {
+                output::success(&format!("Accessory '{}' is {}", accessory_name, state))
+            }
+            "starting" => bail!(
+                "Accessory '{}' did not become healthy after {} checks",
+                accessory_name,
+                hc.retries
+            ),
+            other => bail!(
+                "Accessory '{}' is {} on {}. Check `shipit accessory logs -s {} {}`",
+                accessory_name,
+                other,
+                accessory.host,
+                stage_name,
+                accessory_name
+            ),
+        }
+    }
+    Ok(())
+}
+
 pub async fn stop_accessory(
+    pool: &mut SessionPool,
     config: &ShipitConfig,
     stage: &StageConfig,
     accessory_name: &str,
@@ -149,19 +288,19 @@ pub async fn stop_accessory(
     let name = container_name(&config.app.name, accessory_name);
     output::info(&format!("Stopping accessory '{}'...", accessory_name));
 
-    let session = connect_to_accessory_host(config, stage, &accessory.host).await?;
+    let session = connect_to_accessory_host(pool, config, stage, &accessory.host).await?;
 
     session
         .exec(&format!("docker stop {} && docker rm {}", name, name))
         .await
         .with_context(|| format!("Failed to stop accessory '{}'", accessory_name))?;
 
-    session.close().await?;
     output::success(&format!("Accessory '{}' stopped", accessory_name));
     Ok(())
 }
 
 pub async fn restart_accessory(
+    pool: &mut SessionPool,
     config: &ShipitConfig,
     stage: &StageConfig,
     accessory_name: &str,
@@ -170,7 +309,7 @@ pub async fn restart_accessory(
     let name = container_name(&config.app.name, accessory_name);
     output::info(&format!("Restarting accessory '{}'...", accessory_name));
 
-    let session = connect_to_accessory_host(config, stage, &accessory.host).await?;
+    let session = connect_to_accessory_host(pool, config, stage, &accessory.host).await?;
 
     // Stop and remove (ignore errors if not running)
     let _ = session
@@ -187,12 +326,12 @@ pub async fn restart_accessory(
         .await
         .with_context(|| format!("Failed to restart accessory '{}'", accessory_name))?;
 
-    session.close().await?;
     output::success(&format!("Accessory '{}' restarted", accessory_name));
     Ok(())
 }
 
 pub async fn logs_accessory(
+    pool: &mut SessionPool,
     config: &ShipitConfig,
     stage: &StageConfig,
     accessory_name: &str,
@@ -201,7 +340,7 @@ pub async fn logs_accessory(
 ) -> Result<()> {
     let name = container_name(&config.app.name, accessory_name);
 
-    let session = connect_to_accessory_host(config, stage, &accessory.host).await?;
+    let session = connect_to_accessory_host(pool, config, stage, &accessory.host).await?;
 
     let follow_flag = if follow { " -f" } else { "" };
     let output_str = session
@@ -211,6 +350,5 @@ pub async fn logs_accessory(
 
     println!("{}", output_str);
 
-    session.close().await?;
     Ok(())
 }
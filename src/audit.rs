@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::ssh::{shell_quote, SshSession};
+
+const AUDIT_LOG: &str = ".shipit/audit.log";
+
+/// One line of the audit log (JSON Lines)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: String,
+    pub user: String,
+    /// Subcommand path, e.g. "secrets set"
+    pub command: String,
+    pub stage: Option<String>,
+    /// Arguments as typed, with `KEY=VALUE` values redacted
+    pub args: Vec<String>,
+    /// "success", or the error the command failed with
+    pub outcome: String,
+    pub duration_ms: u64,
+}
+
+/// A command that is running and will be recorded when it finishes
+pub struct Invocation {
+    command: String,
+    args: Vec<String>,
+    timestamp: chrono::DateTime<chrono::Local>,
+    started: Instant,
+}
+
+impl Invocation {
+    /// Start recording, unless the command is `audit` itself
+    pub fn start(matches: &clap::ArgMatches) -> Option<Self> {
+        let mut command = Vec::new();
+        let mut current = matches;
+        while let Some((name, sub)) = current.subcommand() {
+            command.push(name);
+            current = sub;
+        }
+        if command.first() == Some(&"audit") {
+            return None;
+        }
+
+        Some(Self {
+            command: command.join(" "),
+            args: std::env::args().skip(1).map(|a| redact(&a)).collect(),
+            timestamp: chrono::Local::now(),
+            started: Instant::now(),
+        })
+    }
+
+    /// Append the entry to .shipit/audit.log (and the hosts' copy when
+    /// `audit.remote` is set). Failing to record never fails the command.
+    pub async fn finish(self, config_path: &Path, project_root: &Path, result: &Result<()>) {
+        let table: Option<toml::Table> = std::fs::read_to_string(config_path)
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok());
+        let stage = stage_arg(&self.args)
+            .or_else(|| std::env::var("SHIPIT_STAGE").ok().filter(|s| !s.is_empty()))
+            .or_else(|| {
+                table
+                    .as_ref()
+                    .and_then(|t| t.get("default_stage"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+            });
+
+        let entry = Entry {
+            timestamp: self.timestamp.to_rfc3339(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "unknown".to_string()),
+            command: self.command,
+            stage,
+            args: self.args,
+            outcome: match result {
+                Ok(()) => "success".to_string(),
+                Err(e) => format!("{:#}", e),
+            },
+            duration_ms: self.started.elapsed().as_millis() as u64,
+        };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        if let Err(e) = append_local(project_root, &line) {
+            output::warning(&format!("Failed to write audit log: {:#}", e));
+        }
+
+        let remote = table
+            .as_ref()
+            .and_then(|t| t.get("audit"))
+            .and_then(|a| a.get("remote"))
+            .and_then(|r| r.as_bool())
+            .unwrap_or(false);
+        if let (true, Some(stage)) = (remote, &entry.stage) {
+            if let Err(e) = append_remote(config_path, project_root, stage, &line).await {
+                output::warning(&format!("Failed to write remote audit log: {:#}", e));
+            }
+        }
+    }
+}
+
+/// `KEY=VALUE` arguments (secrets set, config set, env in run) keep only the key
+pub fn redact(arg: &str) -> String {
+    match arg.split_once('=') {
+        Some((key, _)) if !key.is_empty() && !key.starts_with('-') => {
+            format!("{}=[redacted]", key)
+        }
+        _ => arg.to_string(),
+    }
+}
+
+/// The stage given with -s/--stage, if any
+fn stage_arg(args: &[String]) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-s" || arg == "--stage" {
+            return iter.next().cloned();
+        }
+        if let Some(stage) = arg.strip_prefix("--stage=") {
+            return Some(stage.to_string());
+        }
+        if let Some(stage) = arg.strip_prefix("-s").filter(|s| !s.is_empty()) {
+            return Some(stage.to_string());
+        }
+    }
+    None
+}
+
+fn append_local(project_root: &Path, line: &str) -> Result<()> {
+    let path = project_root.join(AUDIT_LOG);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", line).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Append the entry to `<app path>/audit.log` on every host of the stage
+async fn append_remote(
+    config_path: &Path,
+    project_root: &Path,
+    stage_name: &str,
+    line: &str,
+) -> Result<()> {
+    let mut config = ShipitConfig::parse(config_path)?;
+    // Stages outside shipit.toml (e.g. `local`) have no hosts to copy to
+    if !config.stages.contains_key(stage_name) {
+        return Ok(());
+    }
+    crate::provision::discovery::resolve(&mut config, stage_name, project_root)?;
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+    let user = stage.user.as_deref().unwrap_or("deploy");
+
+    for host in &stage.hosts {
+        let session = SshSession::connect(
+            user,
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+        let result = session
+            .exec(&format!(
+                "mkdir -p {} && printf '%s\\n' {} >> {}/audit.log",
+                app_path,
+                shell_quote(line),
+                app_path
+            ))
+            .await;
+        session.close().await?;
+        result.with_context(|| format!("Failed to append audit log on {}", host.address))?;
+    }
+    Ok(())
+}
+
+/// Read a JSON Lines audit log, skipping lines that don't parse
+pub fn parse(content: &str) -> Vec<Entry> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// The local audit log, oldest first
+pub fn read_local(project_root: &Path) -> Result<Vec<Entry>> {
+    let path = project_root.join(AUDIT_LOG);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(parse(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::redact;
+
+    #[test]
+    fn keeps_only_the_key_of_assignments() {
+        assert_eq!(
+            redact("DATABASE_URL=postgres://u:p@db"),
+            "DATABASE_URL=[redacted]"
+        );
+        assert_eq!(redact("KEY="), "KEY=[redacted]");
+        assert_eq!(
+            redact("--release=20250219-140000"),
+            "--release=20250219-140000"
+        );
+        assert_eq!(redact("=value"), "=value");
+        assert_eq!(redact("deploy"), "deploy");
+    }
+}
@@ -0,0 +1,516 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use crate::accessory;
+use crate::config::{BackupsConfig, ShipitConfig};
+use crate::cron;
+use crate::os::HostOs;
+use crate::secrets::{key, store as secrets_store};
+use crate::ssh::{shell_quote, SshSession};
+
+const AWS_CLI_IMAGE: &str = "amazon/aws-cli";
+
+/// S3 credentials from the stage secrets, written to the hosts
+pub struct Credentials {
+    access_key: String,
+    secret_key: String,
+}
+
+/// Read the S3 credentials (None for backups to a local path)
+pub fn credentials(
+    config: &ShipitConfig,
+    project_root: &Path,
+    stage_name: &str,
+) -> Result<Option<Credentials>> {
+    let Some(backups) = &config.backups else {
+        return Ok(None);
+    };
+    if !is_s3(backups) {
+        return Ok(None);
+    }
+
+    let identity = key::load_identity(&config.app.name)?;
+    let secrets = secrets_store::read_secrets(project_root, stage_name, &identity)?;
+    let get = |name: &str| {
+        secrets.get(name).cloned().with_context(|| {
+            format!(
+                "Backup credential {} not found: set it with `shipit secrets set -s {} {}=...`",
+                name, stage_name, name
+            )
+        })
+    };
+    Ok(Some(Credentials {
+        access_key: get(&backups.access_key_secret)?,
+        secret_key: get(&backups.secret_key_secret)?,
+    }))
+}
+
+fn is_s3(backups: &BackupsConfig) -> bool {
+    backups.target.starts_with("s3://")
+}
+
+/// What gets backed up on one host
+pub enum Item {
+    Volume(String),
+    Database {
+        accessory: String,
+        container: String,
+        kind: Database,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub enum Database {
+    Postgres,
+    Mysql,
+}
+
+impl Item {
+    pub fn name(&self) -> &str {
+        match self {
+            Item::Volume(name) => name,
+            Item::Database { accessory, .. } => accessory,
+        }
+    }
+
+    fn file(&self) -> String {
+        match self {
+            Item::Volume(name) => format!("volume-{}.tar.gz", name),
+            Item::Database { accessory, .. } => format!("accessory-{}.sql.gz", accessory),
+        }
+    }
+}
+
+/// The dump tool to use for an accessory image, if it is a supported database
+pub fn database_kind(image: &str) -> Option<Database> {
+    let name = image
+        .rsplit('/')
+        .next()
+        .unwrap_or(image)
+        .split([':', '@'])
+        .next()
+        .unwrap_or_default();
+    if name.contains("postgres") || name.contains("postgis") {
+        Some(Database::Postgres)
+    } else if name.contains("mysql") || name.contains("mariadb") {
+        Some(Database::Mysql)
+    } else {
+        None
+    }
+}
+
+/// Items backed up on the stage host at `index`: volumes on every host,
+/// accessory databases on the host they run on
+pub fn items_for_host(config: &ShipitConfig, stage_name: &str, index: usize) -> Result<Vec<Item>> {
+    let Some(backups) = &config.backups else {
+        return Ok(Vec::new());
+    };
+    let stage = config.stage(stage_name)?;
+
+    let mut items: Vec<Item> = backups.volumes.iter().cloned().map(Item::Volume).collect();
+    for name in &backups.accessories {
+        let acc = config
+            .accessories
+            .get(name)
+            .with_context(|| format!("backups.accessories: no accessory named '{}'", name))?;
+        if accessory::host_index(stage, acc) != Some(index) {
+            continue;
+        }
+        let kind = database_kind(&acc.image).with_context(|| {
+            format!(
+                "Accessory '{}' ({}) is not a Postgres, MySQL or MariaDB database",
+                name, acc.image
+            )
+        })?;
+        items.push(Item::Database {
+            accessory: name.clone(),
+            container: accessory::container_name(&config.app.name, name),
+            kind,
+        });
+    }
+    Ok(items)
+}
+
+/// Where a host's backups live: `<target>/<app>/<stage>/<host>`, one
+/// directory (or S3 prefix) per backup
+fn location(
+    config: &ShipitConfig,
+    backups: &BackupsConfig,
+    stage_name: &str,
+    host: &str,
+) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        backups.target.trim_end_matches('/'),
+        config.app.name,
+        stage_name,
+        host
+    )
+}
+
+fn base_dir(config: &ShipitConfig) -> String {
+    format!("{}/backups", config.app_path())
+}
+
+/// Shell prelude shared by the scripts: `$work` and, for S3, an `aws`
+/// function running the AWS CLI image with the credentials file
+fn prelude(config: &ShipitConfig, backups: &BackupsConfig, work: &str) -> String {
+    let mut script = format!("work={}\nmkdir -p \"$work\"\n", work);
+    if is_s3(backups) {
+        let endpoint = backups
+            .endpoint
+            .as_deref()
+            .map(|e| format!(" --endpoint-url {}", shell_quote(e)))
+            .unwrap_or_default();
+        script.push_str(&format!(
+            "aws() {{ docker run --rm --env-file {}/s3.env -v \"$work\":/backup {}{} \"$@\"; }}\n",
+            base_dir(config),
+            AWS_CLI_IMAGE,
+            endpoint
+        ));
+    }
+    script
+}
+
+/// The script the timer (and `shipit backup run`) runs on a host
+fn backup_script(
+    config: &ShipitConfig,
+    backups: &BackupsConfig,
+    stage_name: &str,
+    host: &str,
+    items: &[Item],
+) -> String {
+    let dest = location(config, backups, stage_name, host);
+    let mut script = format!(
+        "#!/bin/sh\n# Managed by shipit: backups of {} ({}) on this host\nset -eu\n\
+         stamp=$(date -u +%Y%m%d-%H%M%S)\n",
+        config.app.name, stage_name
+    );
+    script.push_str(&prelude(
+        config,
+        backups,
+        &format!("{}/tmp/$stamp", base_dir(config)),
+    ));
+    script.push_str("trap 'rm -rf \"$work\"' EXIT\n");
+
+    for item in items {
+        let file = item.file();
+        match item {
+            Item::Volume(volume) => script.push_str(&format!(
+                "echo \"Archiving volume {volume}\"\n\
+                 docker run --rm -v {q}:/data:ro -v \"$work\":/backup alpine \
+                 tar czf /backup/{file} -C /data .\n",
+                q = shell_quote(volume),
+            )),
+            Item::Database {
+                accessory,
+                container,
+                kind,
+            } => {
+                let sql = file.trim_end_matches(".gz");
+                // Dump to a file first: sh has no pipefail to catch a failed dump
+                script.push_str(&format!(
+                    "echo \"Dumping {accessory}\"\n\
+                     docker exec {container} {dump} > \"$work/{sql}\"\n\
+                     gzip \"$work/{sql}\"\n",
+                    dump = dump_command(*kind),
+                ));
+            }
+        }
+    }
+
+    if is_s3(backups) {
+        script.push_str(&format!(
+            "aws s3 cp --recursive --only-show-errors /backup \"s3://{dest}/$stamp/\"\n\
+             aws s3 ls \"s3://{dest}/\" | awk '$1 == \"PRE\" {{ print $2 }}' | sort -r \
+             | tail -n +{keep} | while read -r old; do \
+             aws s3 rm --recursive --only-show-errors \"s3://{dest}/$old\"; done\n",
+            dest = dest.trim_start_matches("s3://"),
+            keep = backups.retention + 1,
+        ));
+    } else {
+        script.push_str(&format!(
+            "mkdir -p {dest}\nmv \"$work\" {dest}/\"$stamp\"\n\
+             ls -1 {dest} | sort -r | tail -n +{keep} | while read -r old; do \
+             rm -rf {dest}/\"$old\"; done\n",
+            keep = backups.retention + 1,
+        ));
+    }
+    script.push_str("echo \"Backup $stamp complete\"\n");
+    script
+}
+
+fn dump_command(kind: Database) -> &'static str {
+    match kind {
+        // --clean makes the dump drop and recreate objects on restore
+        Database::Postgres => {
+            "sh -c 'pg_dumpall --clean --if-exists -U \"${POSTGRES_USER:-postgres}\"'"
+        }
+        Database::Mysql => {
+            "sh -c 'exec \"$(command -v mariadb-dump || command -v mysqldump)\" --all-databases \
+             --single-transaction -uroot -p\"${MARIADB_ROOT_PASSWORD:-$MYSQL_ROOT_PASSWORD}\"'"
+        }
+    }
+}
+
+fn restore_command(kind: Database) -> &'static str {
+    match kind {
+        Database::Postgres => "sh -c 'psql -q -U \"${POSTGRES_USER:-postgres}\" -d postgres'",
+        Database::Mysql => {
+            "sh -c 'exec \"$(command -v mariadb || command -v mysql)\" \
+             -uroot -p\"${MARIADB_ROOT_PASSWORD:-$MYSQL_ROOT_PASSWORD}\"'"
+        }
+    }
+}
+
+/// Write the backup script (and S3 credentials) for the host's items.
+/// Returns the script path, or None when nothing is backed up on the host.
+pub async fn write_script(
+    session: &SshSession,
+    config: &ShipitConfig,
+    stage_name: &str,
+    host: &str,
+    items: &[Item],
+    credentials: Option<&Credentials>,
+) -> Result<Option<String>> {
+    let Some(backups) = &config.backups else {
+        return Ok(None);
+    };
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    let base = base_dir(config);
+    session
+        .exec(&format!("mkdir -p {}", base))
+        .await
+        .context("Failed to create the backups directory")?;
+
+    if let Some(creds) = credentials {
+        let mut env = format!(
+            "AWS_ACCESS_KEY_ID={}\nAWS_SECRET_ACCESS_KEY={}\n",
+            creds.access_key, creds.secret_key
+        );
+        if let Some(region) = &backups.region {
+            env.push_str(&format!("AWS_DEFAULT_REGION={}\n", region));
+        }
+        // Created with umask 077 so the keys are never world-readable
+        session
+            .exec_with_input(
+                &format!("umask 077 && cat > {}/s3.env", base),
+                &mut env.as_bytes(),
+            )
+            .await
+            .context("Failed to write backup credentials")?;
+    }
+
+    let script_path = format!("{}/backup.sh", base);
+    session
+        .write_file(
+            &script_path,
+            &backup_script(config, backups, stage_name, host, items),
+        )
+        .await?;
+    Ok(Some(script_path))
+}
+
+fn unit(config: &ShipitConfig) -> String {
+    format!("shipit-{}-backup", config.app.name)
+}
+
+/// Install the backup script and its timer on a host (setup step). Hosts
+/// with nothing to back up get any previous timer removed.
+pub async fn install(
+    session: &SshSession,
+    config: &ShipitConfig,
+    stage_name: &str,
+    host: &str,
+    index: usize,
+    credentials: Option<&Credentials>,
+) -> Result<usize> {
+    let Some(backups) = &config.backups else {
+        return Ok(0);
+    };
+    let stage = config.stage(stage_name)?;
+    let user = stage.user.as_deref().unwrap_or("deploy");
+    let os = HostOs::resolve(stage.os.as_deref(), session).await?;
+    let items = items_for_host(config, stage_name, index)?;
+    let unit = unit(config);
+
+    let Some(script_path) =
+        write_script(session, config, stage_name, host, &items, credentials).await?
+    else {
+        session
+            .sudo_exec(&format!(
+                "{}; systemctl daemon-reload",
+                cron::remove_timer_cmd(os, &unit)
+            ))
+            .await?;
+        return Ok(0);
+    };
+
+    // A local target must exist and be writable by the stage user
+    if !is_s3(backups) {
+        let dest = location(config, backups, stage_name, host);
+        session
+            .sudo_exec(&format!("mkdir -p {} && chown -R {} {}", dest, user, dest))
+            .await
+            .with_context(|| format!("Failed to create {}", dest))?;
+    }
+
+    let on_calendar = cron::on_calendar(&backups.schedule).context("Invalid backups.schedule")?;
+    let description = format!("shipit backups ({})", config.app.name);
+    cron::Timer {
+        unit: &unit,
+        description: &description,
+        user,
+        script_path: &script_path,
+        on_calendar: &on_calendar,
+    }
+    .write(session, os)
+    .await?;
+    session
+        .sudo_exec(&format!(
+            "systemctl daemon-reload && systemctl restart {}.timer",
+            unit
+        ))
+        .await
+        .context("Failed to start the backup timer")?;
+    Ok(items.len())
+}
+
+/// Remove the backup timer (teardown)
+pub async fn remove(session: &SshSession, config: &ShipitConfig, os: HostOs) -> Result<()> {
+    session
+        .sudo_exec(&format!(
+            "{}; systemctl daemon-reload",
+            cron::remove_timer_cmd(os, &unit(config))
+        ))
+        .await
+        .context("Failed to remove the backup timer")?;
+    Ok(())
+}
+
+/// One backup on a host: its id (UTC timestamp) and files with sizes
+pub struct Backup {
+    pub id: String,
+    pub files: Vec<(String, u64)>,
+}
+
+/// The host's backups, newest first
+pub async fn list(
+    session: &SshSession,
+    config: &ShipitConfig,
+    stage_name: &str,
+    host: &str,
+) -> Result<Vec<Backup>> {
+    let Some(backups) = &config.backups else {
+        return Ok(Vec::new());
+    };
+    let dest = location(config, backups, stage_name, host);
+
+    // "<id>/<file> <size>" per line
+    let listing = if is_s3(backups) {
+        let script = format!(
+            "{}aws s3 ls --recursive \"{}/\" | awk '{{ print $4, $3 }}'",
+            prelude(config, backups, &base_dir(config)),
+            dest
+        );
+        session.exec(&script).await?
+    } else {
+        session
+            .exec(&format!(
+                "cd {} 2>/dev/null && find . -mindepth 2 -type f -printf '%P %s\\n' || true",
+                dest
+            ))
+            .await?
+    };
+
+    let mut result: Vec<Backup> = Vec::new();
+    for line in listing.lines() {
+        let Some((path, size)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let mut parts = path.rsplit('/');
+        let (Some(file), Some(id)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let size = size.trim().parse().unwrap_or(0);
+        match result.iter_mut().find(|b| b.id == id) {
+            Some(backup) => backup.files.push((file.to_string(), size)),
+            None => result.push(Backup {
+                id: id.to_string(),
+                files: vec![(file.to_string(), size)],
+            }),
+        }
+    }
+    result.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(result)
+}
+
+/// Restore the host's items from backup `id`. Volumes are emptied and
+/// unpacked; database dumps are replayed into the running accessory.
+pub async fn restore<F>(
+    session: &SshSession,
+    config: &ShipitConfig,
+    stage_name: &str,
+    host: &str,
+    id: &str,
+    items: &[Item],
+    on_line: F,
+) -> Result<()>
+where
+    F: FnMut(&str),
+{
+    let Some(backups) = &config.backups else {
+        bail!("No [backups] configured");
+    };
+    let dest = location(config, backups, stage_name, host);
+
+    let mut script = String::from("set -euo pipefail\n");
+    script.push_str(&prelude(
+        config,
+        backups,
+        &format!("{}/tmp/restore-{}", base_dir(config), id),
+    ));
+    script.push_str("trap 'rm -rf \"$work\"' EXIT\n");
+    if is_s3(backups) {
+        script.push_str(&format!(
+            "aws s3 cp --recursive --only-show-errors \"{}/{}/\" /backup\n",
+            dest, id
+        ));
+    } else {
+        script.push_str(&format!("cp -r {}/{}/. \"$work\"\n", dest, id));
+    }
+
+    for item in items {
+        let file = item.file();
+        script.push_str(&format!(
+            "if [ ! -f \"$work/{file}\" ]; then echo \"{name}: not in this backup, skipped\"; else\n",
+            name = item.name()
+        ));
+        match item {
+            Item::Volume(volume) => script.push_str(&format!(
+                "echo \"Restoring volume {volume}\"\n\
+                 docker run --rm -v {q}:/data -v \"$work\":/backup alpine \
+                 sh -c 'find /data -mindepth 1 -delete && tar xzf /backup/{file} -C /data'\n",
+                q = shell_quote(volume),
+            )),
+            Item::Database {
+                accessory,
+                container,
+                kind,
+            } => script.push_str(&format!(
+                "echo \"Restoring {accessory}\"\n\
+                 gunzip -c \"$work/{file}\" | docker exec -i {container} {restore}\n",
+                restore = restore_command(*kind),
+            )),
+        }
+        script.push_str("fi\n");
+    }
+
+    session
+        .exec_streamed(&format!("bash -c {}", shell_quote(&script)), on_line)
+        .await
+        .with_context(|| format!("Restore of {} failed on {}", id, host))
+}
@@ -1,10 +1,17 @@
 use anyhow::{bail, Result};
+use std::path::Path;
 
 use crate::accessory;
 use crate::config::ShipitConfig;
 use crate::output;
+use crate::ssh::pool::SessionPool;
 
-pub async fn boot(config: ShipitConfig, stage_name: &str, name: Option<&str>) -> Result<()> {
+pub async fn boot(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: &Path,
+    name: Option<&str>,
+) -> Result<()> {
     let stage = config.stage(stage_name)?.clone();
 
     if config.accessories.is_empty() {
@@ -13,22 +20,28 @@ pub async fn boot(config: ShipitConfig, stage_name: &str, name: Option<&str>) ->
 
     output::header(&format!("Booting accessories for {}", stage_name));
 
-    match name {
+    let mut pool = SessionPool::new();
+
+    let targets: Vec<_> = match name {
         Some(n) => {
             let acc = config
                 .accessories
                 .get(n)
                 .ok_or_else(|| anyhow::anyhow!("Accessory '{}' not found in config", n))?;
-            accessory::boot_accessory(&config, &stage, n, acc).await?;
-        }
-        None => {
-            for (n, acc) in &config.accessories {
-                accessory::boot_accessory(&config, &stage, n, acc).await?;
-            }
+            vec![(n, acc)]
         }
+        None => config
+            .accessories
+            .iter()
+            .map(|(n, acc)| (n.as_str(), acc))
+            .collect(),
+    };
+    for (n, acc) in targets {
+        accessory::boot_accessory(&mut pool, &config, &stage, n, acc).await?;
+        crate::notify::accessory_booted(&config, project_root, stage_name, n, &acc.host).await;
     }
 
-    Ok(())
+    pool.close_all().await
 }
 
 pub async fn stop(config: ShipitConfig, stage_name: &str, name: Option<&str>) -> Result<()> {
@@ -40,22 +53,24 @@ pub async fn stop(config: ShipitConfig, stage_name: &str, name: Option<&str>) ->
 
     output::header(&format!("Stopping accessories for {}", stage_name));
 
+    let mut pool = SessionPool::new();
+
     match name {
         Some(n) => {
             let acc = config
                 .accessories
                 .get(n)
                 .ok_or_else(|| anyhow::anyhow!("Accessory '{}' not found in config", n))?;
-            accessory::stop_accessory(&config, &stage, n, acc).await?;
+            accessory::stop_accessory(&mut pool, &config, &stage, n, acc).await?;
         }
         None => {
             for (n, acc) in &config.accessories {
-                accessory::stop_accessory(&config, &stage, n, acc).await?;
+                accessory::stop_accessory(&mut pool, &config, &stage, n, acc).await?;
             }
         }
     }
 
-    Ok(())
+    pool.close_all().await
 }
 
 pub async fn restart(config: ShipitConfig, stage_name: &str, name: Option<&str>) -> Result<()> {
@@ -67,22 +82,24 @@ pub async fn restart(config: ShipitConfig, stage_name: &str, name: Option<&str>)
 
     output::header(&format!("Restarting accessories for {}", stage_name));
 
+    let mut pool = SessionPool::new();
+
     match name {
         Some(n) => {
             let acc = config
                 .accessories
                 .get(n)
                 .ok_or_else(|| anyhow::anyhow!("Accessory '{}' not found in config", n))?;
-            accessory::restart_accessory(&config, &stage, n, acc).await?;
+            accessory::restart_accessory(&mut pool, &config, &stage, n, acc).await?;
         }
         None => {
             for (n, acc) in &config.accessories {
-                accessory::restart_accessory(&config, &stage, n, acc).await?;
+                accessory::restart_accessory(&mut pool, &config, &stage, n, acc).await?;
             }
         }
     }
 
-    Ok(())
+    pool.close_all().await
 }
 
 pub async fn logs(
@@ -98,7 +115,8 @@ pub async fn logs(
         .get(name)
         .ok_or_else(|| anyhow::anyhow!("Accessory '{}' not found in config", name))?;
 
-    accessory::logs_accessory(&config, &stage, name, acc, follow).await?;
+    let mut pool = SessionPool::new();
+    accessory::logs_accessory(&mut pool, &config, &stage, name, acc, follow).await?;
 
-    Ok(())
+    pool.close_all().await
 }
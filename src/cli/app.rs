@@ -0,0 +1,184 @@
+use anyhow::{bail, Context, Result};
+use console::style;
+
+use crate::config::{HostConfig, ShipitConfig, StageConfig};
+use crate::output;
+use crate::ssh::SshSession;
+
+/// `docker compose up -d` in the live release on every host
+pub async fn start(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    output::header(&format!("Starting {} on {}", config.app.name, stage_name));
+    compose_on_hosts(&config, stage_name, "up -d", "Started").await
+}
+
+/// `docker compose stop` in the live release on every host. The containers
+/// are kept, so `shipit app start` brings back the same release.
+pub async fn stop(config: ShipitConfig, stage_name: &str, yes: bool) -> Result<()> {
+    if !yes
+        && console::user_attended()
+        && !dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Stop {} on every host of {}? The app will be offline.",
+                config.app.name, stage_name
+            ))
+            .default(false)
+            .interact()?
+    {
+        bail!("Aborted");
+    }
+    output::header(&format!("Stopping {} on {}", config.app.name, stage_name));
+    compose_on_hosts(&config, stage_name, "stop", "Stopped").await
+}
+
+/// `docker compose restart` in the live release, one host at a time
+pub async fn restart(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    output::header(&format!("Restarting {} on {}", config.app.name, stage_name));
+    compose_on_hosts(&config, stage_name, "restart", "Restarted").await
+}
+
+/// The app's containers per host: state and uptime of each service of the
+/// live release. Exits non-zero when a service is not running.
+pub async fn status(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+
+    output::header(&format!("{} on {}", config.app.name, stage_name));
+
+    let cmd = format!(
+        "{} && docker compose ps -a --format '{{{{.Service}}}}|{{{{.State}}}}|{{{{.Status}}}}'",
+        cd_current(&app_path)
+    );
+    let reports = futures::future::join_all(stage.hosts.iter().map(|host| {
+        let cmd = &cmd;
+        async move {
+            let session = connect(stage, host).await?;
+            let result = session.exec(cmd).await;
+            session.close().await?;
+            result
+        }
+    }))
+    .await;
+
+    println!(
+        "  {:<22} {:<16} {:<10} {}",
+        style("HOST").bold(),
+        style("SERVICE").bold(),
+        style("STATE").bold(),
+        style("STATUS").bold()
+    );
+    let mut problems = Vec::new();
+    for (host, report) in stage.hosts.iter().zip(reports) {
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                println!("  {:<22} {}", host.address, style("unreachable").red());
+                problems.push(format!("{}: {:#}", host.address, e));
+                continue;
+            }
+        };
+
+        let mut services = 0;
+        for line in report.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.trim().splitn(3, '|');
+            let service = parts.next().unwrap_or_default();
+            let state = parts.next().unwrap_or_default();
+            let status = parts.next().unwrap_or_default();
+            // `{:<N}` would count the escape codes as width
+            let state_cell = format!("{:<10}", state);
+            let state_cell = if state == "running" {
+                style(state_cell).green()
+            } else {
+                problems.push(format!("{}: {} is {}", host.address, service, state));
+                style(state_cell).red()
+            };
+            println!(
+                "  {:<22} {:<16} {} {}",
+                if services == 0 {
+                    host.address.as_str()
+                } else {
+                    ""
+                },
+                service,
+                state_cell,
+                status
+            );
+            services += 1;
+        }
+        if services == 0 {
+            println!("  {:<22} {}", host.address, style("no containers").red());
+            problems.push(format!("{}: no containers", host.address));
+        }
+    }
+
+    println!();
+    if problems.is_empty() {
+        output::done("All services running");
+        return Ok(());
+    }
+    for problem in &problems {
+        output::warning(problem);
+    }
+    bail!("{} problem(s) on {}", problems.len(), stage_name);
+}
+
+/// Run `docker compose <args>` in the live release of each host in turn,
+/// stopping at the first failure
+async fn compose_on_hosts(
+    config: &ShipitConfig,
+    stage_name: &str,
+    args: &str,
+    done: &str,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    if stage.hosts.is_empty() {
+        bail!("No hosts configured for stage '{}'", stage_name);
+    }
+    let cmd = format!(
+        "{} && docker compose {}",
+        cd_current(&config.app_path()),
+        args
+    );
+
+    for host in &stage.hosts {
+        let session = connect(stage, host).await?;
+        let spinner = output::create_spinner(&format!("{}...", host.address));
+        let result = session.exec(&cmd).await;
+        spinner.finish_and_clear();
+        session.close().await?;
+        result.with_context(|| {
+            format!(
+                "docker compose {} failed on {} (is anything deployed?)",
+                args, host.address
+            )
+        })?;
+        output::success(&format!("{} {} on {}", done, config.app.name, host.address));
+    }
+
+    output::done(&format!(
+        "{} {} on {} host(s)",
+        done,
+        config.app.name,
+        stage.hosts.len()
+    ));
+    Ok(())
+}
+
+/// cd into the release `current` points at. Compose names the project after
+/// the directory, so the symlink itself would address a different project.
+fn cd_current(app_path: &str) -> String {
+    format!(
+        "test -e {app}/current && cd \"$(readlink -f {app}/current)\"",
+        app = app_path
+    )
+}
+
+async fn connect(stage: &StageConfig, host: &HostConfig) -> Result<SshSession> {
+    SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use console::style;
+use std::path::Path;
+
+use crate::audit::{self, Entry};
+use crate::output;
+use crate::ssh::SshSession;
+
+pub struct AuditOptions {
+    pub stage: Option<String>,
+    pub limit: usize,
+    /// Read the copy on the stage's primary host instead of .shipit/audit.log
+    pub remote: bool,
+}
+
+pub async fn run(config_path: &Path, project_root: &Path, opts: &AuditOptions) -> Result<()> {
+    let entries = if opts.remote {
+        let (config, stage_name) =
+            super::load_stage(config_path, opts.stage.clone(), project_root)?;
+        let stage = config.stage(&stage_name)?;
+        let host = &stage.hosts[0];
+        let session = SshSession::connect(
+            stage.user.as_deref().unwrap_or("deploy"),
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+        let content = session
+            .exec(&format!(
+                "cat {}/audit.log 2>/dev/null || true",
+                config.app_path()
+            ))
+            .await;
+        session.close().await?;
+        audit::parse(&content?)
+    } else {
+        audit::read_local(project_root)?
+    };
+
+    let entries: Vec<&Entry> = entries
+        .iter()
+        .filter(|e| opts.stage.is_none() || e.stage == opts.stage)
+        .collect();
+    if entries.is_empty() {
+        output::info("No audit entries");
+        return Ok(());
+    }
+
+    for entry in &entries[entries.len().saturating_sub(opts.limit)..] {
+        let outcome = if entry.outcome == "success" {
+            style("ok".to_string()).green()
+        } else {
+            style(format!("failed: {}", entry.outcome)).red()
+        };
+        println!(
+            "{}  {:<12} {:<12} {}  {}",
+            style(&entry.timestamp).dim(),
+            entry.user,
+            entry.stage.as_deref().unwrap_or("-"),
+            style(format!("shipit {}", entry.args.join(" "))).bold(),
+            outcome
+        );
+    }
+    Ok(())
+}
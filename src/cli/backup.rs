@@ -0,0 +1,210 @@
+use anyhow::{bail, Result};
+use dialoguer::Confirm;
+use std::path::Path;
+
+use crate::backup;
+use crate::config::{ShipitConfig, StageConfig};
+use crate::output;
+use crate::ssh::SshSession;
+
+/// Back up now, on every host of the stage (or only `host`)
+pub async fn run(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: &Path,
+    host: Option<&str>,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    if config.backups.is_none() {
+        bail!("No [backups] configured in shipit.toml");
+    }
+    let credentials = backup::credentials(&config, project_root, stage_name)?;
+
+    output::header(&format!("Backing up {} on {}", config.app.name, stage_name));
+
+    let mut ran = 0;
+    for (index, h) in stage.hosts.iter().enumerate() {
+        if host.is_some_and(|only| only != h.address) {
+            continue;
+        }
+        let items = backup::items_for_host(&config, stage_name, index)?;
+        if items.is_empty() {
+            continue;
+        }
+        output::info(&format!("Host: {}", h.address));
+
+        let session = connect(stage, &h.address).await?;
+        let result = async {
+            let Some(script) = backup::write_script(
+                &session,
+                &config,
+                stage_name,
+                &h.address,
+                &items,
+                credentials.as_ref(),
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+            session
+                .exec_streamed(&format!("sh {}", script), output::remote_line)
+                .await
+        }
+        .await;
+        session.close().await?;
+        result?;
+        ran += 1;
+    }
+
+    if ran == 0 {
+        bail!("Nothing to back up on the selected host(s)");
+    }
+    output::done("Backup complete");
+    Ok(())
+}
+
+/// Backups available per host, newest first
+pub async fn list(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let Some(backups) = &config.backups else {
+        bail!("No [backups] configured in shipit.toml");
+    };
+
+    output::header(&format!(
+        "Backups of {} on {} ({})",
+        config.app.name, stage_name, backups.target
+    ));
+
+    for (index, host) in stage.hosts.iter().enumerate() {
+        if backup::items_for_host(&config, stage_name, index)?.is_empty() {
+            continue;
+        }
+        output::info(&format!("Host: {}", host.address));
+
+        let session = connect(stage, &host.address).await?;
+        let result = backup::list(&session, &config, stage_name, &host.address).await;
+        session.close().await?;
+
+        let found = result?;
+        if found.is_empty() {
+            output::warning("No backups yet");
+        }
+        for b in found {
+            let files: Vec<String> = b
+                .files
+                .iter()
+                .map(|(file, size)| format!("{} ({})", file, human_size(*size)))
+                .collect();
+            println!("  {}  {}", b.id, files.join(", "));
+        }
+        println!();
+    }
+    Ok(())
+}
+
+pub struct RestoreOptions<'a> {
+    pub id: &'a str,
+    /// Restore only these volumes/accessories (empty = everything)
+    pub only: &'a [String],
+    pub host: Option<&'a str>,
+    pub yes: bool,
+}
+
+/// Restore a backup over the live volumes and databases
+pub async fn restore(
+    config: ShipitConfig,
+    stage_name: &str,
+    opts: &RestoreOptions<'_>,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let Some(backups) = &config.backups else {
+        bail!("No [backups] configured in shipit.toml");
+    };
+    for name in opts.only {
+        if !backups.volumes.contains(name) && !backups.accessories.contains(name) {
+            bail!("'{}' is not a backed-up volume or accessory", name);
+        }
+    }
+
+    let mut plan = Vec::new();
+    for (index, host) in stage.hosts.iter().enumerate() {
+        if opts.host.is_some_and(|only| only != host.address) {
+            continue;
+        }
+        let items: Vec<_> = backup::items_for_host(&config, stage_name, index)?
+            .into_iter()
+            .filter(|item| opts.only.is_empty() || opts.only.iter().any(|o| o == item.name()))
+            .collect();
+        if !items.is_empty() {
+            plan.push((host, items));
+        }
+    }
+    if plan.is_empty() {
+        bail!("Nothing to restore on the selected host(s)");
+    }
+
+    output::header(&format!(
+        "Restoring backup {} of {} on {}",
+        opts.id, config.app.name, stage_name
+    ));
+    output::warning("This overwrites the current data of:");
+    for (host, items) in &plan {
+        let names: Vec<&str> = items.iter().map(|i| i.name()).collect();
+        println!("  {}: {}", host.address, names.join(", "));
+    }
+    if !opts.yes
+        && !Confirm::new()
+            .with_prompt("Restore now?")
+            .default(false)
+            .interact()?
+    {
+        bail!("Restore cancelled");
+    }
+
+    for (host, items) in &plan {
+        output::info(&format!("Host: {}", host.address));
+        let session = connect(stage, &host.address).await?;
+        let result = backup::restore(
+            &session,
+            &config,
+            stage_name,
+            &host.address,
+            opts.id,
+            items,
+            output::remote_line,
+        )
+        .await;
+        session.close().await?;
+        result?;
+    }
+
+    output::done(&format!("Backup {} restored", opts.id));
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+async fn connect(stage: &StageConfig, address: &str) -> Result<SshSession> {
+    SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Result};
+use console::style;
+
+use crate::config::{default_min_cert_days, HostConfig, ShipitConfig, StageConfig};
+use crate::output;
+use crate::ssh::SshSession;
+use crate::traefik::public::{self, ACME_FILE};
+
+/// Per domain: whether Traefik holds an ACME certificate for it on each host,
+/// and the issuer and expiry of the certificate actually served. Exits
+/// non-zero when a certificate is missing, unreachable or about to expire.
+pub async fn run(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let Some(traefik) = stage.traefik.as_ref().filter(|t| t.tls) else {
+        bail!("Stage '{}' does not serve TLS (traefik.tls)", stage_name);
+    };
+    let min_days = config
+        .deploy
+        .public_check
+        .as_ref()
+        .map_or(default_min_cert_days(), |c| c.min_cert_days);
+
+    output::header(&format!(
+        "Certificates of {} on {}",
+        config.app.name, stage_name
+    ));
+
+    let stored =
+        futures::future::join_all(stage.hosts.iter().map(|host| acme_domains(stage, host))).await;
+
+    let mut problems = Vec::new();
+    let mut domains = vec![traefik.domain.clone()];
+    for (host, result) in stage.hosts.iter().zip(&stored) {
+        match result {
+            Ok(names) => {
+                for name in names {
+                    if !domains.contains(name) {
+                        domains.push(name.clone());
+                    }
+                }
+            }
+            Err(e) => problems.push(format!("{}: {:#}", host.address, e)),
+        }
+    }
+
+    println!(
+        "  {:<32} {:<10} {:<28} {:<12} {}",
+        style("DOMAIN").bold(),
+        style("ACME").bold(),
+        style("ISSUER").bold(),
+        style("EXPIRES").bold(),
+        style("DAYS").bold()
+    );
+    for domain in &domains {
+        let holding = stored
+            .iter()
+            .filter(|r| r.as_ref().is_ok_and(|names| names.contains(domain)))
+            .count();
+        if holding == 0 {
+            problems.push(format!("No ACME certificate for {} on any host", domain));
+        }
+        let acme = format!("{}/{} hosts", holding, stage.hosts.len());
+
+        match public::served(domain) {
+            Ok(cert) => {
+                let days = cert.days_left();
+                let issuer = cert
+                    .issuer
+                    .rsplit("CN = ")
+                    .next()
+                    .unwrap_or(&cert.issuer)
+                    .to_string();
+                let days_cell = if days < min_days {
+                    problems.push(format!("{} expires in {} day(s)", domain, days));
+                    style(days).red().to_string()
+                } else {
+                    style(days).green().to_string()
+                };
+                println!(
+                    "  {:<32} {:<10} {:<28} {:<12} {}",
+                    domain,
+                    acme,
+                    issuer,
+                    cert.expires.format("%Y-%m-%d"),
+                    days_cell
+                );
+            }
+            Err(e) => {
+                println!(
+                    "  {:<32} {:<10} {}",
+                    domain,
+                    acme,
+                    style("not served").red()
+                );
+                problems.push(format!("{}: {:#}", domain, e));
+            }
+        }
+    }
+
+    println!();
+    if problems.is_empty() {
+        output::done(&format!(
+            "All certificates valid for at least {} days",
+            min_days
+        ));
+        return Ok(());
+    }
+    for problem in &problems {
+        output::warning(problem);
+    }
+    bail!(
+        "{} certificate problem(s) on {}",
+        problems.len(),
+        stage_name
+    );
+}
+
+async fn acme_domains(stage: &StageConfig, host: &HostConfig) -> Result<Vec<String>> {
+    let session = SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+    let result = session
+        .sudo_exec(&format!("cat {} 2>/dev/null || true", ACME_FILE))
+        .await;
+    session.close().await?;
+    Ok(public::stored_domains(&result?))
+}
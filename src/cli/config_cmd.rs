@@ -1,7 +1,12 @@
 use anyhow::{bail, Context, Result};
+use console::style;
+use dialoguer::Confirm;
+use std::io::Write as _;
+use std::path::Path;
 
-use crate::config::ShipitConfig;
+use crate::config::{migrate, HostConfig, ShipitConfig, StageConfig};
 use crate::output;
+use crate::secrets::store::parse_dotenv;
 use crate::ssh::SshSession;
 
 pub async fn set(config: ShipitConfig, stage_name: &str, pair: &str) -> Result<()> {
@@ -14,7 +19,7 @@ pub async fn set(config: ShipitConfig, stage_name: &str, pair: &str) -> Result<(
     let env_path = format!("{}/shared/.env", config.app_path());
 
     for host in &stage.hosts {
-        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
 
         // Remove existing key if present, then append
         session
@@ -38,7 +43,7 @@ pub async fn unset(config: ShipitConfig, stage_name: &str, key: &str) -> Result<
     let env_path = format!("{}/shared/.env", config.app_path());
 
     for host in &stage.hosts {
-        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
 
         session
             .exec(&format!(
@@ -65,7 +70,7 @@ pub async fn list(config: ShipitConfig, stage_name: &str) -> Result<()> {
     }
 
     let host = &stage.hosts[0];
-    let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+    let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
 
     let content = session
         .exec(&format!("cat {} 2>/dev/null || echo ''", env_path))
@@ -77,3 +82,233 @@ pub async fn list(config: ShipitConfig, stage_name: &str) -> Result<()> {
     session.close().await?;
     Ok(())
 }
+
+/// Fetch shared/.env, edit it in $EDITOR, show what changed and push it to
+/// every host. The new file is staged on all hosts before any of them
+/// switches, so a host that can't be reached leaves the stage untouched.
+pub async fn edit(config: ShipitConfig, stage_name: &str, restart: bool, yes: bool) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+    let env_path = format!("{}/shared/.env", app_path);
+
+    if stage.hosts.is_empty() {
+        bail!("No hosts configured for stage '{}'", stage_name);
+    }
+
+    let mut current: Option<String> = None;
+    for host in &stage.hosts {
+        let session = connect(stage, host).await?;
+        let content = session
+            .exec(&format!("cat {} 2>/dev/null || true", env_path))
+            .await;
+        session.close().await?;
+        let content =
+            content.with_context(|| format!("Failed to read .env on {}", host.address))?;
+        match &current {
+            None => current = Some(content),
+            Some(first) if *first != content => output::warning(&format!(
+                "{} has a different .env than {}; editing the one from {}",
+                host.address, stage.hosts[0].address, stage.hosts[0].address
+            )),
+            Some(_) => {}
+        }
+    }
+    let current = current.unwrap_or_default();
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("shipit-config-")
+        .suffix(".env")
+        .tempfile()
+        .context("Failed to create temp file")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(tmpfile.path(), std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    tmpfile
+        .write_all(current.as_bytes())
+        .context("Failed to write temp file")?;
+    tmpfile.flush()?;
+
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new(&editor)
+        .arg(tmpfile.path())
+        .status()
+        .with_context(|| format!("Failed to open editor '{}'", editor))?;
+
+    if !status.success() {
+        bail!("Editor exited with error");
+    }
+
+    let edited = std::fs::read_to_string(tmpfile.path()).context("Failed to read edited file")?;
+    if edited == current {
+        output::info("No changes");
+        return Ok(());
+    }
+
+    output::header(&format!("Changes to {} on {}", env_path, stage_name));
+    print_diff(&current, &edited);
+    println!();
+
+    if !yes
+        && !Confirm::new()
+            .with_prompt(format!("Push to {} host(s)?", stage.hosts.len()))
+            .default(false)
+            .interact()?
+    {
+        bail!("Aborted, nothing was changed");
+    }
+
+    // Stage everywhere first, then switch everywhere
+    let staged = format!("{}.shipit-edit", env_path);
+    let mut sessions = Vec::new();
+    for host in &stage.hosts {
+        let result = async {
+            let session = connect(stage, host).await?;
+            session.write_file(&staged, &edited).await?;
+            session.exec(&format!("chmod 600 {}", staged)).await?;
+            Ok::<_, anyhow::Error>(session)
+        }
+        .await;
+        match result {
+            Ok(session) => sessions.push(session),
+            Err(e) => {
+                for session in sessions {
+                    let _ = session.exec(&format!("rm -f {}", staged)).await;
+                    let _ = session.close().await;
+                }
+                return Err(e.context(format!(
+                    "Failed to stage .env on {}; no host was changed",
+                    host.address
+                )));
+            }
+        }
+    }
+
+    for session in &sessions {
+        session
+            .exec(&format!("mv -f {} {}", staged, env_path))
+            .await
+            .with_context(|| format!("Failed to replace .env on {}", session.host()))?;
+    }
+    output::success(&format!("Updated .env on {} host(s)", sessions.len()));
+
+    if restart {
+        for session in &sessions {
+            let spinner =
+                output::create_spinner(&format!("Restarting app on {}...", session.host()));
+            let result = session
+                .exec(&format!(
+                    "cd \"$(readlink -f {}/current)\" && docker compose up -d",
+                    app_path
+                ))
+                .await;
+            spinner.finish_and_clear();
+            result.with_context(|| format!("Failed to restart the app on {}", session.host()))?;
+            output::success(&format!("Restarted app on {}", session.host()));
+        }
+    } else {
+        output::info(
+            "Running containers keep the old values until the next deploy (or pass --restart)",
+        );
+    }
+
+    for session in sessions {
+        session.close().await?;
+    }
+    Ok(())
+}
+
+async fn connect(stage: &StageConfig, host: &HostConfig) -> Result<SshSession> {
+    SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await
+}
+
+/// Per-variable diff; comment or ordering edits show up as a note only
+fn print_diff(old: &str, new: &str) {
+    let before = parse_dotenv(old);
+    let after = parse_dotenv(new);
+
+    let mut changed = false;
+    for (key, value) in &before {
+        match after.get(key) {
+            None => {
+                changed = true;
+                println!("  {}", style(format!("- {}={}", key, value)).red());
+            }
+            Some(new_value) if new_value != value => {
+                changed = true;
+                println!("  {}", style(format!("- {}={}", key, value)).red());
+                println!("  {}", style(format!("+ {}={}", key, new_value)).green());
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, value) in &after {
+        if !before.contains_key(key) {
+            changed = true;
+            println!("  {}", style(format!("+ {}={}", key, value)).green());
+        }
+    }
+    if !changed {
+        println!("  (only comments or formatting changed)");
+    }
+}
+
+/// Rewrite shipit.toml in the current config layout. A file that only lacks
+/// the `version` line gets it added in place; otherwise the upgraded config
+/// is re-serialized (dropping comments) after a backup is saved.
+pub fn migrate(config_path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let mut table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+
+    let from = migrate::version(&table)?;
+    if from == migrate::CURRENT_VERSION {
+        output::success(&format!(
+            "{} is already at config version {}",
+            config_path.display(),
+            from
+        ));
+        return Ok(());
+    }
+
+    let changes = migrate::upgrade(&mut table)?;
+    if changes.is_empty() {
+        let updated = format!("version = {}\n\n{}", migrate::CURRENT_VERSION, content);
+        std::fs::write(config_path, updated)
+            .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    } else {
+        let backup = config_path.with_extension("toml.bak");
+        std::fs::copy(config_path, &backup)
+            .with_context(|| format!("Failed to back up to {}", backup.display()))?;
+        let updated = toml::to_string(&table).context("Failed to serialize config")?;
+        std::fs::write(config_path, updated)
+            .with_context(|| format!("Failed to write {}", config_path.display()))?;
+
+        for change in &changes {
+            println!("  {}", change);
+        }
+        output::warning(&format!(
+            "Comments were not preserved; the original is saved as {}",
+            backup.display()
+        ));
+    }
+
+    output::success(&format!(
+        "Upgraded {} from config version {} to {}",
+        config_path.display(),
+        from,
+        migrate::CURRENT_VERSION
+    ));
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use anyhow::{bail, Result};
+
+use crate::config::{CronJob, ShipitConfig, StageConfig};
+use crate::cron;
+use crate::output;
+use crate::ssh::SshSession;
+
+/// Configured jobs with their schedules, plus the next/last run and last
+/// result of each timer on the hosts
+pub async fn list(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+
+    output::header(&format!(
+        "Cron jobs for {} on {}",
+        config.app.name, stage_name
+    ));
+    if config.cron.is_empty() {
+        output::info("No [cron] jobs configured");
+        return Ok(());
+    }
+
+    for (name, job) in cron::jobs_for_host(&config, 0) {
+        let schedule = match cron::on_calendar(&job.schedule) {
+            Ok(spec) if spec != job.schedule => format!("{} ({})", job.schedule, spec),
+            Ok(_) => job.schedule.clone(),
+            Err(e) => format!("{} (invalid: {:#})", job.schedule, e),
+        };
+        println!(
+            "  {:<20} {:<40} {} {}: {}{}",
+            name,
+            schedule,
+            job.mode,
+            job.service,
+            job.cmd,
+            if job.all_hosts { "  [all hosts]" } else { "" }
+        );
+    }
+
+    for (index, host) in stage.hosts.iter().enumerate() {
+        let jobs = cron::jobs_for_host(&config, index);
+        if jobs.is_empty() {
+            continue;
+        }
+        println!();
+        output::info(&format!("Host: {}", host.address));
+
+        let session = connect(stage, &host.address).await?;
+        let result = session.exec(&status_script(&config, &jobs)).await;
+        session.close().await?;
+
+        for line in result?.lines() {
+            let fields: Vec<&str> = line.splitn(5, '|').collect();
+            let [name, load, next, last, outcome] = fields[..] else {
+                continue;
+            };
+            if load != "loaded" {
+                println!(
+                    "  {:<20} {}",
+                    name,
+                    console::style("not scheduled (deploy to install)").yellow()
+                );
+                continue;
+            }
+            let outcome = match outcome {
+                "success" if last.is_empty() || last == "n/a" => console::style("never ran").dim(),
+                "success" => console::style("ok").green(),
+                other => console::style(other).red(),
+            };
+            println!(
+                "  {:<20} next {:<30} last {:<30} {}",
+                name,
+                or_dash(next),
+                or_dash(last),
+                outcome
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Recent journal entries of the jobs' services, per host
+pub async fn runs(
+    config: ShipitConfig,
+    stage_name: &str,
+    job: Option<&str>,
+    lines: usize,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    if let Some(job) = job {
+        if !config.cron.contains_key(job) {
+            let mut names: Vec<&str> = config.cron.keys().map(String::as_str).collect();
+            names.sort();
+            bail!(
+                "No cron job named '{}' (configured: {})",
+                job,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            );
+        }
+    }
+
+    output::header(&format!(
+        "Cron runs for {} on {}",
+        config.app.name, stage_name
+    ));
+
+    for (index, host) in stage.hosts.iter().enumerate() {
+        let units: Vec<String> = cron::jobs_for_host(&config, index)
+            .into_iter()
+            .filter(|(name, _)| job.is_none() || job == Some(name.as_str()))
+            .map(|(name, _)| format!("-u {}.service", cron::unit_name(&config.app.name, name)))
+            .collect();
+        if units.is_empty() {
+            continue;
+        }
+        output::info(&format!("Host: {}", host.address));
+
+        let session = connect(stage, &host.address).await?;
+        let result = session
+            .sudo_exec(&format!(
+                "journalctl {} -n {} -o short-iso --no-pager",
+                units.join(" "),
+                lines
+            ))
+            .await;
+        session.close().await?;
+
+        for line in result?.lines() {
+            println!("  {}", line);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// One `name|load state|next run|last run|last result` line per job
+fn status_script(config: &ShipitConfig, jobs: &[(&String, &CronJob)]) -> String {
+    jobs.iter()
+        .map(|(name, _)| {
+            let unit = cron::unit_name(&config.app.name, name);
+            format!(
+                "printf '%s|%s|%s|%s|%s\\n' {name} \
+                 \"$(systemctl show -p LoadState --value {unit}.timer)\" \
+                 \"$(systemctl show -p NextElapseUSecRealtime --value {unit}.timer)\" \
+                 \"$(systemctl show -p LastTriggerUSec --value {unit}.timer)\" \
+                 \"$(systemctl show -p Result --value {unit}.service)\""
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn or_dash(value: &str) -> &str {
+    if value.is_empty() || value == "n/a" {
+        "-"
+    } else {
+        value
+    }
+}
+
+async fn connect(stage: &StageConfig, address: &str) -> Result<SshSession> {
+    SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await
+}
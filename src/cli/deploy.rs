@@ -4,11 +4,67 @@ use std::path::PathBuf;
 use crate::config::ShipitConfig;
 use crate::deploy;
 use crate::deploy::context::DeployContext;
+use crate::release::Release;
 
-pub async fn run(config: ShipitConfig, stage_name: &str, project_root: PathBuf) -> Result<()> {
+pub async fn run(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: PathBuf,
+    auto_setup: bool,
+) -> Result<()> {
     let stage = config.stage(stage_name)?.clone();
 
-    let ctx = DeployContext::new(config, stage_name.to_string(), stage, project_root);
+    let mut ctx = DeployContext::new(config, stage_name.to_string(), stage, project_root);
+    ctx.auto_setup = auto_setup;
+    execute(ctx).await
+}
+
+/// Deploy under a given release name instead of a new one, so hosts
+/// re-deployed by `shipit verify` end up on the same release as the rest
+pub async fn redeploy(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: PathBuf,
+    release_name: Option<String>,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?.clone();
+
+    let mut ctx = DeployContext::new(config, stage_name.to_string(), stage, project_root);
+    if let Some(name) = release_name {
+        ctx.release.name = name;
+    }
+    execute(ctx).await
+}
+
+/// Deploy `commit` instead of local HEAD, reusing the local images of
+/// `images_from` when set (`shipit promote`)
+pub async fn promote(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: PathBuf,
+    commit: String,
+    images_from: Option<String>,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?.clone();
+
+    let mut ctx = DeployContext::new(config, stage_name.to_string(), stage, project_root);
+    ctx.release = Release::new(ctx.config.deploy.release_format.as_deref(), Some(&commit));
+    ctx.commit = Some(commit);
+    ctx.images_from = images_from;
+    execute(ctx).await
+}
+
+async fn execute(mut ctx: DeployContext) -> Result<()> {
+    // A dry run notifies nobody
+    if crate::ssh::is_dry_run() {
+        return deploy::run(&ctx).await;
+    }
+
+    ctx.webhooks = crate::notify::webhook::load(&ctx.config, &ctx.project_root, &ctx.stage_name);
 
-    deploy::run(&ctx).await
+    crate::notify::deploy_started(&ctx).await;
+    let result = deploy::run(&ctx).await;
+    deploy::print_step_timings(&ctx);
+    crate::notify::deploy_finished(&ctx, &result).await;
+    result
 }
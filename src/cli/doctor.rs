@@ -0,0 +1,346 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::secrets::{key, store as secrets_store};
+
+/// How long a host gets to accept a TCP connection on its SSH port
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Outcome {
+    Pass(String),
+    /// Works, but something will likely bite later
+    Warn(String, String),
+    Fail(String, String),
+}
+
+use Outcome::{Fail, Pass, Warn};
+
+/// Check the local prerequisites of a deploy and print a fix for each
+/// problem. Exits non-zero when something would make a deploy fail.
+pub async fn run(config_path: &Path, project_root: &Path) -> Result<()> {
+    output::header("Checking the local environment");
+
+    let mut failures = 0;
+    let mut warnings = 0;
+    let mut report = |name: &str, outcome: Outcome| match outcome {
+        Pass(detail) => output::success(&format!("{}: {}", name, detail)),
+        Warn(problem, fix) => {
+            warnings += 1;
+            output::warning(&format!("{}: {}", name, problem));
+            println!("    fix: {}", fix);
+        }
+        Fail(problem, fix) => {
+            failures += 1;
+            output::error(&format!("{}: {}", name, problem));
+            println!("    fix: {}", fix);
+        }
+    };
+
+    report("git", check_git(project_root));
+    report("ssh", check_ssh());
+
+    let config = match ShipitConfig::load(config_path) {
+        Ok(config) => {
+            report(
+                "config",
+                Pass(format!("{} is valid", config_path.display())),
+            );
+            Some(config)
+        }
+        Err(e) => {
+            report(
+                "config",
+                Fail(
+                    format!("{:#}", e),
+                    if config_path.exists() {
+                        "Run `shipit validate` for the full list of problems".to_string()
+                    } else {
+                        "Run `shipit init` to create shipit.toml".to_string()
+                    },
+                ),
+            );
+            None
+        }
+    };
+    let local_build = config.as_ref().is_some_and(|c| c.deploy.build != "remote");
+
+    let docker = check_docker(local_build);
+    let has_compose = matches!(docker, Pass(_));
+    report("docker", docker);
+    report("compose file", check_compose(project_root, has_compose));
+
+    if let Some(config) = &config {
+        let mut stages: Vec<&String> = config.stages.keys().collect();
+        stages.sort();
+        for stage in &stages {
+            if let Some(outcome) = check_secrets(config, project_root, stage) {
+                report(&format!("secrets ({})", stage), outcome);
+            }
+        }
+        for stage in stages {
+            for (target, outcome) in check_hosts(config_path, project_root, stage).await {
+                report(&format!("{} ({})", target, stage), outcome);
+            }
+        }
+    }
+
+    println!();
+    if failures > 0 {
+        bail!("{} check(s) failed, {} warning(s)", failures, warnings);
+    }
+    if warnings > 0 {
+        output::warning(&format!("All checks passed with {} warning(s)", warnings));
+    } else {
+        output::done("All checks passed");
+    }
+    Ok(())
+}
+
+/// Run a command, returning its trimmed stdout (or stderr when stdout is
+/// empty, as with `ssh -V`) if it succeeded
+fn probe(program: &str, args: &[&str], dir: Option<&Path>) -> Option<String> {
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let output = cmd.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if stdout.is_empty() {
+        Some(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    } else {
+        Some(stdout)
+    }
+}
+
+fn check_git(project_root: &Path) -> Outcome {
+    if probe("git", &["--version"], None).is_none() {
+        return Fail(
+            "git is not installed".to_string(),
+            "Install git (deploys push the repository to the hosts)".to_string(),
+        );
+    }
+    if probe(
+        "git",
+        &["rev-parse", "--is-inside-work-tree"],
+        Some(project_root),
+    )
+    .is_none()
+    {
+        return Fail(
+            format!("{} is not a git repository", project_root.display()),
+            "Run `git init` and commit your app".to_string(),
+        );
+    }
+    let Some(head) = probe("git", &["rev-parse", "--short", "HEAD"], Some(project_root)) else {
+        return Fail(
+            "The repository has no commits".to_string(),
+            "Commit your app: git add -A && git commit -m \"Initial commit\"".to_string(),
+        );
+    };
+    let dirty = probe(
+        "git",
+        &["status", "--porcelain", "--untracked-files=no"],
+        Some(project_root),
+    )
+    .unwrap_or_default();
+    if !dirty.is_empty() {
+        return Warn(
+            format!(
+                "{} uncommitted change(s); deploys ship HEAD ({}) only",
+                dirty.lines().count(),
+                head
+            ),
+            "Commit or stash the changes before deploying".to_string(),
+        );
+    }
+    Pass(format!("HEAD is {}, working tree clean", head))
+}
+
+fn check_ssh() -> Outcome {
+    match probe("ssh", &["-V"], None) {
+        Some(version) => Pass(version),
+        None => Fail(
+            "No ssh client found".to_string(),
+            "Install OpenSSH (apt install openssh-client, brew install openssh)".to_string(),
+        ),
+    }
+}
+
+/// Docker is only required locally for `deploy.build = "local"` or `"registry"`
+fn check_docker(local_build: bool) -> Outcome {
+    let missing = |problem: &str, fix: &str| {
+        if local_build {
+            Fail(problem.to_string(), fix.to_string())
+        } else {
+            Warn(
+                format!("{} (only needed for local builds)", problem),
+                fix.to_string(),
+            )
+        }
+    };
+
+    let Some(version) = probe("docker", &["--version"], None) else {
+        return missing(
+            "docker is not installed",
+            "Install Docker: https://docs.docker.com/get-docker/",
+        );
+    };
+    let Some(compose) = probe("docker", &["compose", "version", "--short"], None) else {
+        return missing(
+            "The docker compose plugin is missing",
+            "Install it: https://docs.docker.com/compose/install/",
+        );
+    };
+    if local_build && probe("docker", &["info", "--format", "{{.ServerVersion}}"], None).is_none() {
+        return Fail(
+            "The Docker daemon is not reachable".to_string(),
+            "Start Docker (or check DOCKER_HOST)".to_string(),
+        );
+    }
+    Pass(format!("{}, compose {}", version, compose))
+}
+
+/// Parse the compose file with `docker compose config` when available
+fn check_compose(project_root: &Path, has_compose: bool) -> Outcome {
+    let Some(file) = crate::compose::COMPOSE_FILES
+        .iter()
+        .copied()
+        .find(|f| project_root.join(f).exists())
+    else {
+        return Fail(
+            format!(
+                "No compose file found (expected one of: {})",
+                crate::compose::COMPOSE_FILES.join(", ")
+            ),
+            "Run `shipit init --template <framework>` or add a compose.yaml".to_string(),
+        );
+    };
+
+    if has_compose {
+        let output = Command::new("docker")
+            .args(["compose", "-f", file, "config", "--quiet"])
+            .current_dir(project_root)
+            .output();
+        if let Ok(output) = output {
+            if !output.status.success() {
+                return Fail(
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                    format!("Fix {} until `docker compose config` accepts it", file),
+                );
+            }
+            return Pass(format!("{} parses", file));
+        }
+    }
+
+    match super::validate::compose_services(&project_root.join(file)) {
+        Ok(services) => Pass(format!("{} parses ({} service(s))", file, services.len())),
+        Err(e) => Fail(format!("{:#}", e), format!("Fix the YAML in {}", file)),
+    }
+}
+
+/// Stages with a secrets file need the age key to decrypt it
+fn check_secrets(config: &ShipitConfig, project_root: &Path, stage: &str) -> Option<Outcome> {
+    if !secrets_store::secrets_path(project_root, stage).exists() {
+        return None;
+    }
+    let fix = format!(
+        "Get the key from a teammate into ~/.config/shipit/keys/{}.key, or set SHIPIT_AGE_KEY",
+        config.app.name
+    );
+    let identity = match key::load_identity(&config.app.name) {
+        Ok(identity) => identity,
+        Err(e) => return Some(Fail(format!("{:#}", e), fix)),
+    };
+    Some(
+        match secrets_store::read_secrets(project_root, stage, &identity) {
+            Ok(secrets) => Pass(format!("{} secret(s) decrypt", secrets.len())),
+            Err(e) => Fail(
+                format!("Cannot decrypt with this key: {:#}", e),
+                format!(
+                    "{} (or ask a teammate to add your key to secrets.recipients and re-encrypt)",
+                    fix
+                ),
+            ),
+        },
+    )
+}
+
+/// TCP reachability of the SSH port of every host (or of the jump host)
+async fn check_hosts(
+    config_path: &Path,
+    project_root: &Path,
+    stage_name: &str,
+) -> Vec<(String, Outcome)> {
+    let config = match super::load_for_stage(config_path, stage_name, project_root) {
+        Ok(config) => config,
+        Err(e) => {
+            return vec![(
+                "hosts".to_string(),
+                Fail(
+                    format!("{:#}", e),
+                    "Check the stage's discovery settings and provider credentials".to_string(),
+                ),
+            )]
+        }
+    };
+    let Ok(stage) = config.stage(stage_name) else {
+        return Vec::new();
+    };
+    let port = stage.port.unwrap_or(22);
+
+    // Through a jump host, only the jump host is reachable from here
+    if let Some(proxy) = &stage.proxy {
+        let target = proxy.rsplit('@').next().unwrap_or(proxy);
+        let (host, port) = match target.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().unwrap_or(22)),
+            None => (target, 22),
+        };
+        return vec![(format!("proxy {}", target), reach(host, port).await)];
+    }
+
+    if stage.hosts.is_empty() {
+        return vec![(
+            "hosts".to_string(),
+            Warn(
+                "The stage has no hosts".to_string(),
+                "Add hosts to the stage or provision them with `shipit provision`".to_string(),
+            ),
+        )];
+    }
+
+    let results =
+        futures::future::join_all(stage.hosts.iter().map(|h| reach(&h.address, port))).await;
+    stage
+        .hosts
+        .iter()
+        .map(|h| h.address.clone())
+        .zip(results)
+        .collect()
+}
+
+async fn reach(host: &str, port: u16) -> Outcome {
+    match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => Pass(format!("port {} reachable", port)),
+        Ok(Err(e)) => Fail(
+            format!("Cannot connect to port {}: {}", port, e),
+            "Check the address, the firewall and that sshd is running".to_string(),
+        ),
+        Err(_) => Fail(
+            format!(
+                "No answer on port {} within {}s",
+                port,
+                CONNECT_TIMEOUT.as_secs()
+            ),
+            "Check the firewall/security group allows SSH from this machine".to_string(),
+        ),
+    }
+}
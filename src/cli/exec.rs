@@ -0,0 +1,100 @@
+use anyhow::{bail, Result};
+use console::style;
+
+use crate::config::{HostConfig, ShipitConfig, StageConfig};
+use crate::output;
+use crate::ssh::{shell_quote, SshSession};
+
+/// Flags of `shipit exec`
+pub struct ExecOptions {
+    /// Only these hosts (empty = every host of the stage)
+    pub hosts: Vec<String>,
+    pub sudo: bool,
+    /// One host at a time instead of all at once
+    pub serial: bool,
+}
+
+/// Run a raw shell command on the hosts, prefixing each output line with its
+/// host. Fails when the command failed on any host.
+pub async fn run(
+    config: ShipitConfig,
+    stage_name: &str,
+    cmd: &[String],
+    opts: &ExecOptions,
+) -> Result<()> {
+    if cmd.is_empty() {
+        bail!("No command specified");
+    }
+    let stage = config.stage(stage_name)?;
+    for host in &opts.hosts {
+        if !stage.hosts.iter().any(|h| &h.address == host) {
+            bail!("{} is not a host of stage {}", host, stage_name);
+        }
+    }
+    let hosts: Vec<&HostConfig> = stage
+        .hosts
+        .iter()
+        .filter(|h| opts.hosts.is_empty() || opts.hosts.contains(&h.address))
+        .collect();
+
+    // A single argument is a shell snippet; several are joined like `ssh host a b`
+    let command = cmd.join(" ");
+    let command = if opts.sudo {
+        format!("sudo bash -c {}", shell_quote(&command))
+    } else {
+        command
+    };
+
+    let results: Vec<Result<Option<i32>>> = if opts.serial {
+        let mut results = Vec::new();
+        for host in &hosts {
+            results.push(exec_on(stage, host, &command).await);
+        }
+        results
+    } else {
+        futures::future::join_all(hosts.iter().map(|host| exec_on(stage, host, &command))).await
+    };
+
+    println!();
+    let mut failed = 0;
+    for (host, result) in hosts.iter().zip(&results) {
+        match result {
+            Ok(Some(0)) => output::success(&format!("{}: exit 0", host.address)),
+            Ok(Some(code)) => {
+                failed += 1;
+                output::error(&format!("{}: exit {}", host.address, code));
+            }
+            Ok(None) => {
+                failed += 1;
+                output::error(&format!("{}: killed by a signal", host.address));
+            }
+            Err(e) => {
+                failed += 1;
+                output::error(&format!("{}: {:#}", host.address, e));
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("Command failed on {} of {} host(s)", failed, hosts.len());
+    }
+    Ok(())
+}
+
+/// The command's exit code on one host (None when killed by a signal)
+async fn exec_on(stage: &StageConfig, host: &HostConfig, command: &str) -> Result<Option<i32>> {
+    let prefix = format!("{} ", style(format!("[{}]", host.address)).dim());
+    let session = SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+    let result = session
+        .exec_streamed_status(command, |line| println!("{}{}", prefix, line))
+        .await;
+    session.close().await?;
+    Ok(result?.code())
+}
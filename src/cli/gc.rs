@@ -0,0 +1,46 @@
+use anyhow::{Context, Result};
+
+use crate::config::{GcConfig, ShipitConfig};
+use crate::gc;
+use crate::output;
+use crate::ssh::SshSession;
+
+/// Prune dangling images and old build cache on every host of the stage
+/// now, with the `[gc]` retention (or its defaults)
+pub async fn run(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let defaults = GcConfig::default();
+    let gc_config = config.gc.as_ref().unwrap_or(&defaults);
+
+    output::header(&format!(
+        "Reclaiming Docker disk space on {} (older than {})",
+        stage_name, gc_config.keep
+    ));
+
+    for host in &stage.hosts {
+        output::info(&format!("Host: {}", host.address));
+        let session = SshSession::connect(
+            stage.user.as_deref().unwrap_or("deploy"),
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+        let result = async {
+            session
+                .exec_streamed(&gc::command(gc_config), output::remote_line)
+                .await?;
+            session
+                .exec("df -Ph \"$(docker info -f '{{.DockerRootDir}}')\" | awk 'NR==2 {print $4}'")
+                .await
+                .context("Failed to read free disk space")
+        }
+        .await;
+        session.close().await?;
+        output::success(&format!("{}: {} free", host.address, result?.trim()));
+    }
+
+    output::done("Docker GC complete");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::provision;
+
+/// Sync a stage's hosts from a Terraform output, so the infra code stays
+/// the source of truth for addresses
+pub fn import_terraform(
+    config_path: &Path,
+    stage_name: &str,
+    dir: &Path,
+    output_name: &str,
+) -> Result<()> {
+    let config = ShipitConfig::parse(config_path)?;
+
+    let result = Command::new("terraform")
+        .arg(format!("-chdir={}", dir.display()))
+        .args(["output", "-json"])
+        .output()
+        .context("Failed to run terraform (is it installed?)")?;
+    if !result.status.success() {
+        bail!(
+            "terraform output failed in {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+    let outputs: serde_json::Value =
+        serde_json::from_slice(&result.stdout).context("Invalid JSON from terraform output")?;
+
+    let Some(value) = outputs.get(output_name).map(|o| &o["value"]) else {
+        let mut names: Vec<&str> = outputs
+            .as_object()
+            .map(|o| o.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+        names.sort();
+        bail!(
+            "Terraform output '{}' not found in {} (outputs: {})",
+            output_name,
+            dir.display(),
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        );
+    };
+    let addresses = addresses(value).with_context(|| {
+        format!(
+            "Output '{}' must be a string, a list of strings or a map of name to address",
+            output_name
+        )
+    })?;
+    if addresses.is_empty() {
+        bail!("Terraform output '{}' is empty", output_name);
+    }
+
+    let current: Vec<String> = config
+        .stages
+        .get(stage_name)
+        .map(|stage| stage.hosts.iter().map(|h| h.address.clone()).collect())
+        .unwrap_or_default();
+    if current == addresses {
+        output::success(&format!(
+            "stages.{} already matches output '{}' ({} host(s))",
+            stage_name,
+            output_name,
+            addresses.len()
+        ));
+        return Ok(());
+    }
+
+    for address in addresses.iter().filter(|a| !current.contains(a)) {
+        println!("  {} {}", console::style("+").green(), address);
+    }
+    for address in current.iter().filter(|a| !addresses.contains(a)) {
+        println!("  {} {}", console::style("-").red(), address);
+    }
+    provision::write_stage_hosts(config_path, stage_name, &addresses)
+}
+
+/// `"1.2.3.4"`, `["1.2.3.4", ...]` or `{ web1 = "1.2.3.4", ... }` (map values
+/// in key order)
+fn addresses(value: &serde_json::Value) -> Option<Vec<String>> {
+    match value {
+        serde_json::Value::String(address) => Some(vec![address.clone()]),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            entries
+                .into_iter()
+                .map(|(_, item)| item.as_str().map(str::to_string))
+                .collect()
+        }
+        _ => None,
+    }
+}
@@ -1,16 +1,174 @@
 use anyhow::{bail, Context, Result};
-use dialoguer::{Input, Select};
+use dialoguer::{Confirm, Input, Select};
 use minijinja::Environment;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 const TEMPLATE: &str = include_str!("../../templates/shipit.toml.j2");
+const COMPOSE_TEMPLATE: &str = include_str!("../../templates/docker-compose.yml.j2");
+const DOCKERFILE_TEMPLATE: &str = include_str!("../../templates/Dockerfile.j2");
 
-pub fn run() -> Result<()> {
+/// Defaults of [deploy.health_check] written by the shipit.toml template
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_HEALTH_PATH: &str = "/health";
+
+/// CI systems `shipit init --ci` can write a deploy workflow for
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum CiProvider {
+    Github,
+    Gitlab,
+}
+
+impl CiProvider {
+    fn path(self) -> &'static str {
+        match self {
+            CiProvider::Github => ".github/workflows/deploy.yml",
+            CiProvider::Gitlab => ".gitlab-ci.yml",
+        }
+    }
+
+    fn template(self) -> &'static str {
+        match self {
+            CiProvider::Github => include_str!("../../templates/github-workflow.yml.j2"),
+            CiProvider::Gitlab => include_str!("../../templates/gitlab-ci.yml.j2"),
+        }
+    }
+}
+
+/// Framework presets for `shipit init --template`
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum InitTemplate {
+    Rails,
+    Django,
+    Laravel,
+    Node,
+    Static,
+}
+
+impl InitTemplate {
+    fn port(self) -> u16 {
+        match self {
+            InitTemplate::Rails | InitTemplate::Node => 3000,
+            InitTemplate::Django => 8000,
+            InitTemplate::Laravel | InitTemplate::Static => 80,
+        }
+    }
+
+    fn health_path(self) -> &'static str {
+        match self {
+            // Rails 7.1+ and Laravel 11+ ship a /up health route
+            InitTemplate::Rails | InitTemplate::Laravel => "/up",
+            InitTemplate::Django | InitTemplate::Node => "/health",
+            InitTemplate::Static => "/",
+        }
+    }
+
+    fn migrate_cmd(self) -> Option<&'static str> {
+        match self {
+            InitTemplate::Rails => Some("bin/rails db:migrate"),
+            InitTemplate::Django => Some("python manage.py migrate --noinput"),
+            InitTemplate::Laravel => Some("php artisan migrate --force"),
+            InitTemplate::Node | InitTemplate::Static => None,
+        }
+    }
+
+    fn dockerfile(self) -> &'static str {
+        match self {
+            InitTemplate::Rails => include_str!("../../templates/Dockerfile.rails.j2"),
+            InitTemplate::Django => include_str!("../../templates/Dockerfile.django.j2"),
+            InitTemplate::Laravel => include_str!("../../templates/Dockerfile.laravel.j2"),
+            InitTemplate::Node => include_str!("../../templates/Dockerfile.node.j2"),
+            InitTemplate::Static => include_str!("../../templates/Dockerfile.static.j2"),
+        }
+    }
+}
+
+/// Port, health path and Dockerfile used for the generated files
+struct Preset {
+    port: u16,
+    health_path: &'static str,
+    migrate_cmd: Option<&'static str>,
+    dockerfile: &'static str,
+}
+
+impl Preset {
+    fn new(template: Option<InitTemplate>) -> Self {
+        match template {
+            Some(t) => Self {
+                port: t.port(),
+                health_path: t.health_path(),
+                migrate_cmd: t.migrate_cmd(),
+                dockerfile: t.dockerfile(),
+            },
+            None => Self {
+                port: DEFAULT_PORT,
+                health_path: DEFAULT_HEALTH_PATH,
+                migrate_cmd: None,
+                dockerfile: DOCKERFILE_TEMPLATE,
+            },
+        }
+    }
+}
+
+/// Flags of `shipit init`
+pub struct InitOptions {
+    pub template: Option<InitTemplate>,
+    /// Kamal config/deploy.yml to convert
+    pub from_kamal: Option<PathBuf>,
+    /// Capistrano config/deploy.rb to convert (stage files are read from config/deploy/)
+    pub from_capistrano: Option<PathBuf>,
+    /// Append this stage to an existing shipit.toml instead of creating one
+    pub add_stage: Option<NewStage>,
+    /// Also write a deploy workflow for this CI system
+    pub ci: Option<CiProvider>,
+}
+
+/// Values for `shipit init --add-stage`; anything unset is prompted for
+pub struct NewStage {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub user: Option<String>,
+    pub domain: Option<String>,
+    pub tls: bool,
+    pub acme_email: Option<String>,
+}
+
+pub async fn run(opts: &InitOptions) -> Result<()> {
     let config_path = Path::new("shipit.toml");
+    if let Some(stage) = &opts.add_stage {
+        return add_stage(config_path, stage).await;
+    }
     if config_path.exists() {
+        // `--ci` on its own adds a workflow to an already configured project
+        if let (Some(ci), None, None, None) = (
+            opts.ci,
+            opts.template,
+            &opts.from_kamal,
+            &opts.from_capistrano,
+        ) {
+            return write_ci(config_path, ci);
+        }
         bail!("shipit.toml already exists in this directory");
     }
 
+    if let Some(kamal_config) = &opts.from_kamal {
+        let repository = detect_git_remote().unwrap_or_default();
+        let import = crate::import::kamal::convert(kamal_config, &repository)?;
+        import.write(config_path, &kamal_config.display().to_string())?;
+    } else if let Some(deploy_rb) = &opts.from_capistrano {
+        let import = crate::import::capistrano::convert(deploy_rb)?;
+        import.write(config_path, &deploy_rb.display().to_string())?;
+    } else {
+        scaffold(config_path, opts.template)?;
+    }
+
+    if let Some(ci) = opts.ci {
+        write_ci(config_path, ci)?;
+    }
+
+    Ok(())
+}
+
+fn scaffold(config_path: &Path, template: Option<InitTemplate>) -> Result<()> {
     // Detect defaults from git
     let default_name = std::env::current_dir()
         .ok()
@@ -40,20 +198,280 @@ pub fn run() -> Result<()> {
     let mut env = Environment::new();
     env.add_template("shipit.toml", TEMPLATE)?;
     let tmpl = env.get_template("shipit.toml").unwrap();
+    let preset = Preset::new(template);
     let content = tmpl.render(minijinja::context! {
         app_name => app_name,
         repository => repository,
         branch => branch,
+        port => preset.port,
+        health_path => preset.health_path,
+        migrate_cmd => preset.migrate_cmd,
     })?;
 
     std::fs::write(config_path, content).context("Failed to write shipit.toml")?;
 
     crate::output::success("Created shipit.toml");
+
+    check_docker_files(Path::new("."), &preset, &app_name)?;
+
     crate::output::info("Edit the file to configure your stages and hosts.");
 
     Ok(())
 }
 
+/// Write the CI deploy workflow, taking the app name, branch and build mode
+/// from shipit.toml. Deploys `production` when it exists, else the first stage.
+fn write_ci(config_path: &Path, ci: CiProvider) -> Result<()> {
+    let path = Path::new(ci.path());
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+
+    // Read as plain TOML so freshly imported configs with placeholders work too
+    let content = std::fs::read_to_string(config_path).context("Failed to read shipit.toml")?;
+    let config: toml::Table = toml::from_str(&content).context("Failed to parse shipit.toml")?;
+    let lookup = |section: &str, key: &str| {
+        config
+            .get(section)
+            .and_then(|s| s.get(key))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    let stages: Vec<&String> = config
+        .get("stages")
+        .and_then(|s| s.as_table())
+        .map(|stages| stages.keys().collect())
+        .unwrap_or_default();
+    let stage = if stages.is_empty() || stages.iter().any(|s| *s == "production") {
+        "production"
+    } else {
+        stages[0].as_str()
+    };
+
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    write_template(
+        path,
+        ci.template(),
+        &minijinja::context! {
+            app_name => lookup("app", "name").unwrap_or_else(|| "app".to_string()),
+            branch => lookup("app", "branch").unwrap_or_else(|| "main".to_string()),
+            stage => stage,
+            local_build => lookup("deploy", "build").as_deref() == Some("local"),
+        },
+    )?;
+
+    crate::output::info(
+        "Add the SSH_PRIVATE_KEY (deploy user's key) and SHIPIT_AGE_KEY \
+         (your secrets key) to the CI secrets.",
+    );
+    Ok(())
+}
+
+/// Append a `[stages.<name>]` block to the end of shipit.toml. The file is
+/// extended as text so existing formatting and comments are kept.
+async fn add_stage(config_path: &Path, stage: &NewStage) -> Result<()> {
+    if !config_path.exists() {
+        bail!("No shipit.toml in this directory. Run `shipit init` first.");
+    }
+    let name = &stage.name;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "Invalid stage name '{}' (use letters, digits, '-' and '_')",
+            name
+        );
+    }
+
+    let existing = std::fs::read_to_string(config_path).context("Failed to read shipit.toml")?;
+    let table: toml::Table = toml::from_str(&existing).context("Failed to parse shipit.toml")?;
+    if table
+        .get("stages")
+        .and_then(|s| s.as_table())
+        .is_some_and(|stages| stages.contains_key(name))
+    {
+        bail!("Stage '{}' already exists in shipit.toml", name);
+    }
+
+    let hosts = if stage.hosts.is_empty() {
+        let input: String = Input::new()
+            .with_prompt("Host addresses (comma-separated)")
+            .interact_text()?;
+        input
+            .split(',')
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .collect()
+    } else {
+        stage.hosts.clone()
+    };
+    if hosts.is_empty() {
+        bail!("Stage '{}' needs at least one host", name);
+    }
+
+    let user = match &stage.user {
+        Some(user) => user.clone(),
+        None => Input::new()
+            .with_prompt("SSH user")
+            .default("deploy".to_string())
+            .interact_text()?,
+    };
+
+    // Prompt for routing only when nothing was given on the command line
+    let (domain, tls) = match &stage.domain {
+        Some(domain) => (Some(domain.clone()), stage.tls),
+        None if stage.tls => bail!("--tls requires --domain"),
+        None => {
+            let domain: String = Input::new()
+                .with_prompt("Traefik domain (empty to skip)")
+                .allow_empty(true)
+                .interact_text()?;
+            if domain.is_empty() {
+                (None, false)
+            } else {
+                let tls = Confirm::new()
+                    .with_prompt("Enable TLS (Let's Encrypt)?")
+                    .default(true)
+                    .interact()?;
+                (Some(domain), tls)
+            }
+        }
+    };
+    let acme_email = match &stage.acme_email {
+        Some(email) => Some(email.clone()),
+        None if tls => Some(Input::new().with_prompt("ACME email").interact_text()?),
+        None => None,
+    };
+
+    let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+    let mut block = format!("\n[stages.{}]\nuser = {}\n", name, quote(&user));
+    if let Some(domain) = &domain {
+        block.push_str(&format!(
+            "\n[stages.{}.traefik]\ndomain = {}\ntls = {}\n",
+            name,
+            quote(domain),
+            tls
+        ));
+        if let Some(email) = &acme_email {
+            block.push_str(&format!("acme_email = {}\n", quote(email)));
+        }
+    }
+    for host in &hosts {
+        block.push_str(&format!(
+            "\n[[stages.{}.hosts]]\naddress = {}\n",
+            name,
+            quote(host)
+        ));
+    }
+
+    let mut content = existing;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&block);
+    toml::from_str::<toml::Table>(&content).context("Generated stage block is not valid TOML")?;
+    std::fs::write(config_path, content).context("Failed to write shipit.toml")?;
+
+    crate::output::success(&format!(
+        "Added stage '{}' to shipit.toml ({} host(s))",
+        name,
+        hosts.len()
+    ));
+
+    // Catch typo'd addresses and missing keys now rather than minutes into setup
+    if console::user_attended()
+        && Confirm::new()
+            .with_prompt("Check SSH access to the new hosts now?")
+            .default(true)
+            .interact()?
+    {
+        let checked = match crate::config::ShipitConfig::load(config_path) {
+            Ok(config) => crate::cli::ping::run(config, name, config_path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = checked {
+            crate::output::warning(&format!("{:#}", e));
+            crate::output::info(&format!(
+                "Fix shipit.toml if needed and re-check with: shipit ping -s {}",
+                name
+            ));
+            return Ok(());
+        }
+    }
+
+    crate::output::info(&format!("Next: shipit setup -s {}", name));
+
+    Ok(())
+}
+
+/// Check for a compose file and a Dockerfile with a HEALTHCHECK, offering
+/// starters that match the generated health check settings when missing
+fn check_docker_files(dir: &Path, preset: &Preset, app_name: &str) -> Result<()> {
+    let context = minijinja::context! {
+        port => preset.port,
+        health_path => preset.health_path,
+        python_module => app_name.replace('-', "_"),
+    };
+
+    match crate::compose::COMPOSE_FILES.iter().map(|f| dir.join(f)).find(|p| p.exists()) {
+        Some(path) => {
+            let compose = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            // shipit attaches Traefik and the health check to the `web` service
+            if !compose.lines().any(|l| l.trim_end() == "  web:") {
+                crate::output::warning(&format!(
+                    "{} has no `web` service; set deploy.web_service to your main service",
+                    path.display()
+                ));
+            }
+        }
+        None => {
+            if Confirm::new()
+                .with_prompt("No docker-compose.yml found. Generate a starter?")
+                .default(true)
+                .interact()?
+            {
+                write_template(&dir.join("docker-compose.yml"), COMPOSE_TEMPLATE, &context)?;
+            }
+        }
+    }
+
+    let dockerfile = dir.join("Dockerfile");
+    if dockerfile.exists() {
+        let content = std::fs::read_to_string(&dockerfile).context("Failed to read Dockerfile")?;
+        let has_healthcheck = content
+            .lines()
+            .any(|l| l.trim_start().to_uppercase().starts_with("HEALTHCHECK"));
+        if !has_healthcheck {
+            crate::output::warning(&format!(
+                "Dockerfile has no HEALTHCHECK. shipit's compose override checks \
+                 http://localhost:{}{} with curl or wget, so one of them must be in the image",
+                preset.port, preset.health_path
+            ));
+        }
+    } else if Confirm::new()
+        .with_prompt("No Dockerfile found. Generate a starter?")
+        .default(true)
+        .interact()?
+    {
+        write_template(&dockerfile, preset.dockerfile, &context)?;
+    }
+
+    Ok(())
+}
+
+fn write_template(path: &Path, template: &str, context: &minijinja::Value) -> Result<()> {
+    let content = Environment::new().render_str(template, context)?;
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    crate::output::success(&format!("Created {}", path.display()));
+    Ok(())
+}
+
 fn detect_git_remote() -> Option<String> {
     let output = std::process::Command::new("git")
         .args(["remote", "get-url", "origin"])
@@ -1,5 +1,5 @@
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
 
 use crate::cli::LocalAction;
 use crate::config::ShipitConfig;
@@ -14,54 +14,106 @@ pub async fn run(
     project_root: PathBuf,
 ) -> Result<()> {
     match action {
-        LocalAction::Up => {
+        LocalAction::Up { hosts } => {
             let config = config.expect("Config required for local up");
-            let state = local::up(&config, &project_root)?;
+            let state = local::up(&config, &project_root, usize::from(*hosts))?;
 
             output::info("Running setup on local VM...");
 
-            // Create a stage config for the local VM and run setup
-            let stage = local::local_stage_config(&state);
+            // Create a stage config for the local VMs and run setup
+            let stage = local::local_stage_config(&config, &state);
             let user = stage.user.as_deref().unwrap_or("ubuntu");
+            let app_path = config.app_path();
 
-            let session =
-                crate::ssh::SshSession::connect(user, &state.ip, stage.port, stage.proxy.as_deref()).await?;
+            let mut sessions = Vec::new();
+            for host in &stage.hosts {
+                output::info(&format!("Setting up {}", host.address));
 
-            // Install Docker
-            crate::cli::setup::install_docker_on(&session).await?;
+                let session =
+                    crate::ssh::SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
 
-            // Add user to docker group
-            let _ = session
-                .sudo_exec(&format!("usermod -aG docker {}", user))
-                .await;
+                // Guests may be Ubuntu, RHEL-family or NixOS depending on the image/box
+                let host_os = crate::os::HostOs::resolve(stage.os.as_deref(), &session).await?;
+                if host_os.needs_unified_module() {
+                    crate::nixos::apply_module(&session, user).await?;
+                }
 
-            // Install Traefik
-            crate::traefik::install(&session, None, crate::os::HostOs::Ubuntu).await?;
+                // Install Docker
+                crate::cli::setup::install_docker_on(&session, host_os).await?;
 
-            // Setup app directories
-            let app_path = config.app_path();
-            setup_app_dirs(&session, user, &app_path).await?;
+                // Add user to docker group
+                let _ = session
+                    .sudo_exec(&host_os.add_docker_group_cmd(user))
+                    .await;
+
+                // Install Traefik
+                crate::traefik::install(&session, None, host_os).await?;
+
+                // Setup app directories
+                setup_app_dirs(&session, user, &app_path).await?;
 
-            session.close().await?;
+                sessions.push(session);
+            }
+
+            // Mesh the VMs like a real multi-host stage
+            if sessions.len() > 1 {
+                crate::wireguard::setup(&stage.hosts, &sessions, stage.os.as_deref()).await?;
+            }
+
+            for session in sessions {
+                session.close().await?;
+            }
 
             output::success("Local environment is ready!");
             output::info("Deploy with: shipit local deploy");
             Ok(())
         }
 
-        LocalAction::Deploy => {
+        LocalAction::Deploy { sync: true } => {
+            let config = with_local_stage(config, &project_root)?;
+            sync_release(&config, &project_root).await
+        }
+
+        LocalAction::Deploy { sync: false } => {
             let config = config.expect("Config required for local deploy");
             let state = local::LocalState::load(&project_root)?
                 .expect("No local VM. Run 'shipit local up' first.");
 
-            let stage = local::local_stage_config(&state);
-            let ctx = DeployContext::new(config, "local".to_string(), stage, project_root);
+            let stage = local::local_stage_config(&config, &state);
+            let ctx = DeployContext::new(config, local::STAGE_NAME.to_string(), stage, project_root);
 
-            deploy::run(&ctx).await
+            deploy::run(&ctx).await?;
+
+            if let Some(seed_cmd) = &ctx.config.local.seed_cmd {
+                seed(&ctx, seed_cmd).await?;
+            }
+            Ok(())
+        }
+
+        LocalAction::Ssh { host } => {
+            local::ssh(&project_root, *host)?;
+            Ok(())
+        }
+
+        LocalAction::Mount { target, unmount } => {
+            let target = match target {
+                Some(target) => target.clone(),
+                None => {
+                    let config = config.context("No shipit.toml found in this project")?;
+                    local::default_mount_target(&config.app.name)
+                }
+            };
+            local::mount(&project_root, &target, *unmount)?;
+            Ok(())
         }
 
-        LocalAction::Ssh => {
-            local::ssh(&project_root)?;
+        LocalAction::Snapshot { name } => {
+            local::snapshot(&project_root, name)?;
+            Ok(())
+        }
+
+        LocalAction::Restore { name } => {
+            local::restore(&project_root, name)?;
             Ok(())
         }
 
@@ -70,11 +122,180 @@ pub async fn run(
             Ok(())
         }
 
-        LocalAction::Status => {
+        LocalAction::Status { app } => {
             local::status(&project_root)?;
+            if *app {
+                let config = with_local_stage(config, &project_root)?;
+                app_status(&config).await?;
+            }
             Ok(())
         }
+
+        LocalAction::Logs {
+            service,
+            lines,
+            follow,
+        } => {
+            let config = with_local_stage(config, &project_root)?;
+            crate::cli::logs::run(config, local::STAGE_NAME, service.as_deref(), *lines, *follow).await
+        }
+
+        LocalAction::Run { cmd } => {
+            let config = with_local_stage(config, &project_root)?;
+            crate::cli::run::run(config, local::STAGE_NAME, cmd, false).await
+        }
+
+        LocalAction::Releases => {
+            let config = with_local_stage(config, &project_root)?;
+            crate::cli::releases::run(config, local::STAGE_NAME).await
+        }
+
+        LocalAction::Rollback { release } => {
+            let config = with_local_stage(config, &project_root)?;
+            crate::cli::rollback::run(
+                config,
+                local::STAGE_NAME,
+                &project_root,
+                release.as_deref(),
+                false,
+            )
+            .await
+        }
+    }
+}
+
+/// Run `local.seed_cmd` inside the web container of the new release
+/// (primary VM only, since the database is shared).
+async fn seed(ctx: &DeployContext, seed_cmd: &str) -> Result<()> {
+    let host = &ctx.stage.hosts[0];
+    let session = crate::ssh::SshSession::connect(
+        ctx.user(),
+        &host.address,
+        ctx.stage.port,
+        ctx.stage.proxy.as_deref(),
+        &ctx.stage.ssh,
+    )
+    .await?;
+
+    output::info(&format!("Seeding: {}", seed_cmd));
+    session
+        .exec_streamed(
+            &format!(
+                "cd $(readlink -f {}) && docker compose exec -T {} sh -c '{}'",
+                ctx.remote_current_path(),
+                ctx.web_service(),
+                seed_cmd.replace('\'', "'\\''")
+            ),
+            output::remote_line,
+        )
+        .await
+        .context("Seed command failed")?;
+    output::success("Seed complete");
+
+    session.close().await?;
+    Ok(())
+}
+
+/// Register the synthesized local stage in the config so the regular
+/// stage-based commands can run against the local VMs unchanged.
+fn with_local_stage(config: Option<ShipitConfig>, project_root: &Path) -> Result<ShipitConfig> {
+    let mut config = config.context("No shipit.toml found in this project")?;
+    let state = local::LocalState::load(project_root)?
+        .context("No local VM found. Run 'shipit local up' first.")?;
+
+    let stage = local::local_stage_config(&config, &state);
+    config.stages.insert(local::STAGE_NAME.to_string(), stage);
+    Ok(config)
+}
+
+/// Fast local iteration: rsync the working tree over the current release on
+/// each VM and `docker compose up -d --build` in place.
+async fn sync_release(config: &ShipitConfig, project_root: &Path) -> Result<()> {
+    let stage = config.stage(local::STAGE_NAME)?;
+    let user = stage.user.as_deref().unwrap_or("ubuntu");
+    let current_path = format!("{}/current", config.app_path());
+
+    output::header(&format!("Syncing {} to local VM", config.app.name));
+
+    for host in &stage.hosts {
+        let session =
+            crate::ssh::SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
+
+        if !session.path_exists(&current_path).await? {
+            bail!("No current release on {}. Run a full `shipit local deploy` first.", host.address);
+        }
+        let release_path = session
+            .exec(&format!("readlink -f {}", current_path))
+            .await?
+            .trim()
+            .to_string();
+
+        let spinner = output::create_spinner(&format!("Syncing files to {}...", host.address));
+        // Keep the files the deploy pipeline generated inside the release
+        let status = std::process::Command::new("rsync")
+            .args([
+                "-az",
+                "--delete",
+                "--exclude=.git",
+                "--exclude=.shipit",
+                "--exclude=.env",
+                "--exclude=docker-compose.override.yml",
+                "-e",
+                &session.ssh_command(),
+                "./",
+                &format!("{}@{}:{}/", user, host.address, release_path),
+            ])
+            .current_dir(project_root)
+            .status()
+            .context("Failed to run rsync (is it installed?)")?;
+        spinner.finish_and_clear();
+
+        if !status.success() {
+            bail!("rsync to {} failed", host.address);
+        }
+        output::success(&format!("Synced to {}", release_path));
+
+        let spinner = output::create_spinner("Restarting containers...");
+        session
+            .exec(&format!("cd {} && docker compose up -d --build", release_path))
+            .await
+            .context("Failed to restart containers")?;
+        spinner.finish_and_clear();
+        output::success(&format!("{} updated", host.address));
+
+        session.close().await?;
     }
+
+    Ok(())
+}
+
+/// `docker compose ps` for the current release on each local VM
+async fn app_status(config: &ShipitConfig) -> Result<()> {
+    let stage = config.stage(local::STAGE_NAME)?;
+    let user = stage.user.as_deref().unwrap_or("ubuntu");
+    let current_path = format!("{}/current", config.app_path());
+
+    output::header(&format!("{} containers", config.app.name));
+
+    for host in &stage.hosts {
+        output::info(&format!("Host: {}", host.address));
+
+        let session =
+            crate::ssh::SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
+
+        if session.path_exists(&current_path).await? {
+            let ps = session
+                .exec(&format!("cd $(readlink -f {}) && docker compose ps", current_path))
+                .await?;
+            println!("{}", ps.trim_end());
+        } else {
+            output::warning("No current release. Deploy with: shipit local deploy");
+        }
+
+        session.close().await?;
+    }
+
+    Ok(())
 }
 
 async fn setup_app_dirs(
@@ -15,8 +15,20 @@ pub async fn run(
     let user = stage.user.as_deref().unwrap_or("deploy");
     let current_path = format!("{}/current", config.app_path());
 
-    let host = &stage.hosts[0];
-    let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+    // With host roles, a service only runs on some hosts
+    let host = match service {
+        Some(svc) => stage
+            .hosts
+            .iter()
+            .find(|h| config.deploy.runs_on(svc, h))
+            .with_context(|| format!("No host of stage '{}' runs {}", stage_name, svc))?,
+        None => &stage.hosts[0],
+    };
+    let mut session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
+    // Followed logs run until Ctrl-C
+    if follow {
+        session = session.without_command_timeout();
+    }
 
     if !session.path_exists(&current_path).await? {
         output::error("No current release found. Deploy first.");
@@ -38,9 +50,14 @@ pub async fn run(
         cmd.push_str(svc);
     }
 
-    let result = session.exec(&cmd).await.context("Failed to get logs")?;
-    print!("{}", result);
+    let logs = session.exec_streamed(&cmd, |line| println!("{}", line));
+    // `-f` only ends on Ctrl-C: stop reading and close the connection, which
+    // ends `docker compose logs` on the host
+    let result = tokio::select! {
+        result = logs => result.context("Failed to get logs"),
+        _ = tokio::signal::ctrl_c(), if follow => Ok(()),
+    };
 
     session.close().await?;
-    Ok(())
+    result
 }
@@ -1,20 +1,44 @@
+use anyhow::{bail, Result};
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::config::ShipitConfig;
 
 pub mod accessory;
+pub mod app;
+pub mod audit;
+pub mod backup;
+pub mod certs;
 pub mod config_cmd;
+pub mod cron;
 pub mod deploy;
+pub mod doctor;
+pub mod exec;
+pub mod gc;
+pub mod hosts;
 pub mod init;
 pub mod llms;
 pub mod local;
 pub mod logs;
 #[allow(dead_code)]
 pub mod monitor;
+pub mod ping;
+pub mod promote;
+pub mod provision;
+pub mod prune;
 pub mod releases;
 pub mod rollback;
 pub mod run;
 pub mod secrets_cmd;
+pub mod server;
 pub mod setup;
+pub mod shell;
+pub mod status;
+pub mod teardown;
+pub mod trust;
+pub mod validate;
+pub mod verify;
+pub mod watch;
 
 #[derive(Parser)]
 #[command(name = "shipit", version, about = "Deploy to VMs with Docker Compose")]
@@ -27,6 +51,23 @@ pub struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 
+    /// Print only errors, warnings and final results
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Disable colors (also with NO_COLOR set)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// No spinners or progress bars, e.g. for CI logs
+    #[arg(long, global = true)]
+    pub plain: bool,
+
+    /// Record every remote command, its output and timing to this file
+    /// (also SHIPIT_LOG_FILE). Secret values are redacted.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -34,44 +75,226 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     /// Scaffold shipit.toml in the current directory
-    Init,
+    Init {
+        /// Framework preset for shipit.toml, compose file and Dockerfile
+        #[arg(long, value_enum)]
+        template: Option<init::InitTemplate>,
+        /// Convert a Kamal config (e.g. config/deploy.yml) into shipit.toml
+        #[arg(long, value_name = "DEPLOY_YML", conflicts_with = "template")]
+        from_kamal: Option<PathBuf>,
+        /// Convert a Capistrano config/deploy.rb (and config/deploy/*.rb stages) into shipit.toml
+        #[arg(
+            long,
+            value_name = "DEPLOY_RB",
+            num_args = 0..=1,
+            default_missing_value = "config/deploy.rb",
+            conflicts_with_all = ["template", "from_kamal"]
+        )]
+        from_capistrano: Option<PathBuf>,
+        /// Append a new stage to an existing shipit.toml (prompts for values not given)
+        #[arg(
+            long,
+            value_name = "STAGE",
+            conflicts_with_all = ["template", "from_kamal", "from_capistrano"]
+        )]
+        add_stage: Option<String>,
+        /// Host address for --add-stage (repeatable or comma-separated)
+        #[arg(
+            long = "host",
+            value_name = "ADDRESS",
+            value_delimiter = ',',
+            requires = "add_stage"
+        )]
+        hosts: Vec<String>,
+        /// SSH user for --add-stage
+        #[arg(long, requires = "add_stage")]
+        user: Option<String>,
+        /// Traefik domain for --add-stage
+        #[arg(long, requires = "add_stage")]
+        domain: Option<String>,
+        /// Enable TLS for the --add-stage domain
+        #[arg(long, requires = "domain")]
+        tls: bool,
+        /// Let's Encrypt email for --add-stage with --tls
+        #[arg(long, requires = "tls")]
+        acme_email: Option<String>,
+        /// Write a deploy workflow for this CI system
+        #[arg(long, value_enum, conflicts_with = "add_stage")]
+        ci: Option<init::CiProvider>,
+    },
+
+    /// Check shipit.toml and the files it references without connecting to hosts
+    Validate,
+
+    /// Check local prerequisites (git, ssh, docker, age key, host reachability)
+    Doctor,
 
     /// Prepare VM (Docker, Traefik, directories, bare repo)
     Setup {
         /// Target stage
         #[arg(short, long)]
         stage: String,
+        /// Only audit the hosts and report problems (changes nothing)
+        #[arg(long)]
+        check: bool,
+        /// Create the stage user first, connecting as --root-user
+        #[arg(long)]
+        bootstrap: bool,
+        /// Administrative user used by --bootstrap
+        #[arg(long, default_value = "root")]
+        root_user: String,
+        /// Run only these steps (comma-separated)
+        #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "skip")]
+        only: Vec<setup::SetupStep>,
+        /// Skip these steps (comma-separated)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        skip: Vec<setup::SetupStep>,
+        /// Print the commands and file writes instead of running them
+        #[arg(long, conflicts_with = "check")]
+        dry_run: bool,
     },
 
-    /// Deploy the application
-    Deploy {
+    /// Decommission a stage (stop app and accessories, remove files)
+    Teardown {
         /// Target stage
         #[arg(short, long)]
         stage: String,
+        /// Also remove the Traefik service and config
+        #[arg(long)]
+        remove_traefik: bool,
+        /// Also uninstall Docker (deletes all images and volumes)
+        #[arg(long)]
+        remove_docker: bool,
+        /// Skip confirmation prompts
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Deploy the application
+    Deploy {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Run setup on hosts that have never been set up instead of failing
+        #[arg(long)]
+        auto_setup: bool,
+        /// Print the commands, override file and file writes instead of running them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Poll the branch and deploy every new commit (push-to-deploy)
+    Watch {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Seconds between polls
+        #[arg(long, default_value = "60")]
+        interval: u64,
+        /// Minimum seconds between two deploys
+        #[arg(long, default_value = "300")]
+        cooldown: u64,
+        /// Git remote to fetch the branch from
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+
+    /// Serve an HTTP API to trigger deploys/rollbacks and query releases (token in $SHIPIT_SERVER_TOKEN)
+    Server {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Git remote deploys fetch their ref from
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+
+    /// Deploy to one stage the commit (and local-build images) live on another
+    Promote {
+        /// Stage whose live release is promoted
+        #[arg(long)]
+        from: String,
+        /// Stage to deploy it to
+        #[arg(long)]
+        to: String,
     },
 
     /// Rollback to a previous release
     Rollback {
-        /// Target stage
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
         #[arg(short, long)]
-        stage: String,
+        stage: Option<String>,
         /// Specific release to rollback to (e.g. 20250219-120000)
         #[arg(long)]
         release: Option<String>,
+        /// Pick the release from a list (the default on a terminal without --release)
+        #[arg(long, conflicts_with = "release")]
+        list: bool,
+    },
+
+    /// Undo the last rollback, returning to the release it replaced
+    Rollforward {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
     },
 
     /// List releases on VMs
     Releases {
-        /// Target stage
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
         #[arg(short, long)]
-        stage: String,
+        stage: Option<String>,
+    },
+
+    /// One-shot table of releases, containers, Traefik and disk per host (non-zero exit on problems)
+    Status {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+    },
+
+    /// ACME certificate status and days to expiry per domain
+    Certs {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+    },
+
+    /// Compare release, commit, secrets and override across the stage's hosts
+    Verify {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Re-deploy divergent hosts without asking
+        #[arg(long)]
+        redeploy: bool,
+    },
+
+    /// Prune dangling Docker images and old build cache on the stage's hosts
+    Gc {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+    },
+
+    /// Remove old releases and stopped app containers on every host
+    Prune {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Releases to keep (defaults to deploy.keep_releases)
+        #[arg(long)]
+        keep: Option<usize>,
+        /// Also remove dangling images and images of removed releases
+        #[arg(long)]
+        images: bool,
     },
 
     /// Tail logs from containers
     Logs {
-        /// Target stage
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
         #[arg(short, long)]
-        stage: String,
+        stage: Option<String>,
         /// Service name
         service: Option<String>,
         /// Number of lines to tail
@@ -84,14 +307,99 @@ pub enum Command {
 
     /// Execute a one-off command in the app container
     Run {
-        /// Target stage
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
         #[arg(short, long)]
-        stage: String,
+        stage: Option<String>,
+        /// Attach the local terminal, for interactive commands (consoles, REPLs)
+        #[arg(short, long)]
+        tty: bool,
         /// Command to run
         #[arg(trailing_var_arg = true)]
         cmd: Vec<String>,
     },
 
+    /// Open an interactive shell in a container of the current release
+    Shell {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Service to open the shell in (defaults to deploy.web_service)
+        #[arg(long)]
+        service: Option<String>,
+        /// Host to connect to (defaults to the first that runs the service)
+        #[arg(long)]
+        host: Option<String>,
+    },
+
+    /// Run a shell command on the hosts themselves: shipit exec -s prod -- "df -h"
+    Exec {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Only these hosts (repeatable or comma-separated)
+        #[arg(long = "host", value_delimiter = ',')]
+        hosts: Vec<String>,
+        /// Run the command with sudo
+        #[arg(long)]
+        sudo: bool,
+        /// Run on one host at a time instead of all at once
+        #[arg(long)]
+        serial: bool,
+        /// Command to run
+        #[arg(last = true, required = true)]
+        cmd: Vec<String>,
+    },
+
+    /// Run, list and restore [backups]
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+
+    /// Inspect the scheduled [cron] jobs
+    Cron {
+        #[command(subcommand)]
+        action: CronAction,
+    },
+
+    /// Create servers with a cloud provider and add them to a stage
+    Provision {
+        #[command(subcommand)]
+        provider: ProvisionProvider,
+    },
+
+    /// Manage a stage's host list
+    Hosts {
+        #[command(subcommand)]
+        action: HostsAction,
+    },
+
+    /// Check SSH access, OS, sudo and Docker on every host of a stage
+    Ping {
+        /// Target stage
+        #[arg(short, long)]
+        stage: String,
+    },
+
+    /// Record host keys into .shipit/known_hosts for strict verification
+    Trust {
+        /// Target stage
+        #[arg(short, long)]
+        stage: String,
+        /// Replace recorded keys that no longer match without asking
+        #[arg(long)]
+        replace: bool,
+    },
+
+    /// Start, stop, restart or inspect the live release's containers
+    App {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long, global = true)]
+        stage: Option<String>,
+        #[command(subcommand)]
+        action: AppAction,
+    },
+
     /// Manage remote environment variables (.env)
     Config {
         #[command(subcommand)]
@@ -110,7 +418,7 @@ pub enum Command {
         action: AccessoryAction,
     },
 
-    /// Manage local Multipass VM
+    /// Manage local VMs
     Local {
         #[command(subcommand)]
         action: LocalAction,
@@ -118,12 +426,40 @@ pub enum Command {
 
     /// Live TUI dashboard showing containers, resources, and disk usage
     Monitor {
-        /// Target stage
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
         #[arg(short, long)]
-        stage: String,
+        stage: Option<String>,
         /// Polling interval in seconds
         #[arg(short, long, default_value = "2")]
         interval: u64,
+        /// Poll once and print the result instead of starting the dashboard
+        #[arg(long)]
+        once: bool,
+        /// Print the --once snapshot as JSON
+        #[arg(long, requires = "once")]
+        json: bool,
+        /// Serve a read-only web dashboard and JSON API instead of the TUI
+        #[arg(long, conflicts_with = "once")]
+        web: bool,
+        /// Serve Prometheus metrics on /metrics instead of the TUI
+        #[arg(long, conflicts_with_all = ["once", "web"])]
+        exporter: bool,
+        /// Address to listen on (--web default 127.0.0.1:8787, --exporter default 127.0.0.1:9100)
+        #[arg(long)]
+        listen: Option<String>,
+    },
+
+    /// Show who ran which shipit command, when, and how it ended
+    Audit {
+        /// Only show entries for this stage
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Number of most recent entries to show
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+        /// Read the log kept on the stage's primary host (`audit.remote = true`)
+        #[arg(long)]
+        remote: bool,
     },
 
     /// LLM-readable documentation
@@ -131,6 +467,10 @@ pub enum Command {
         #[command(subcommand)]
         action: LlmsAction,
     },
+
+    /// Any other command runs the `shipit-<command>` plugin from PATH
+    #[command(external_subcommand)]
+    Plugin(Vec<String>),
 }
 
 #[derive(Subcommand)]
@@ -159,6 +499,137 @@ pub enum LlmsAction {
     Agents,
 }
 
+#[derive(Subcommand)]
+pub enum BackupAction {
+    /// Back up now (same script the timer runs)
+    Run {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Only this host
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// List the backups of each host, newest first
+    List {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+    },
+    /// Restore a backup over the current volumes and databases
+    Restore {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Backup id, as shown by `shipit backup list`
+        id: String,
+        /// Restore only these volumes/accessories (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+        /// Only this host
+        #[arg(long)]
+        host: Option<String>,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CronAction {
+    /// Show each job's schedule and its next and last run per host
+    List {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+    },
+    /// Show recent runs (and their output) from the hosts' journal
+    Runs {
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
+        #[arg(short, long)]
+        stage: Option<String>,
+        /// Only this job
+        job: Option<String>,
+        /// Number of journal lines per host
+        #[arg(short = 'n', long, default_value = "50")]
+        lines: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HostsAction {
+    /// Replace the stage's hosts with the addresses from a Terraform output
+    Import {
+        /// Target stage (created if missing)
+        #[arg(short, long)]
+        stage: String,
+        /// Terraform working directory to run `terraform output -json` in
+        #[arg(long, value_name = "DIR")]
+        terraform: PathBuf,
+        /// Output holding the addresses (string, list, or map of name to address)
+        #[arg(long, default_value = "shipit_hosts")]
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProvisionProvider {
+    /// Hetzner Cloud (API token in the stage secret HCLOUD_TOKEN)
+    Hetzner {
+        /// Stage to add the servers to (created if missing)
+        #[arg(short, long)]
+        stage: String,
+        /// Number of servers to create
+        #[arg(long, default_value = "1")]
+        count: usize,
+        /// Server type (e.g. cx22, cx32, cax11)
+        #[arg(long = "type", default_value = "cx22")]
+        server_type: String,
+        /// OS image
+        #[arg(long, default_value = "ubuntu-24.04")]
+        image: String,
+        /// Location (e.g. fsn1, nbg1, hel1, ash); Hetzner picks one if omitted
+        #[arg(long)]
+        location: Option<String>,
+        /// Stage secret holding the API token
+        #[arg(long, default_value = "HCLOUD_TOKEN")]
+        token_secret: String,
+        /// Run `shipit setup --bootstrap` once the servers are up
+        #[arg(long)]
+        setup: bool,
+    },
+    /// AWS EC2 (uses the aws CLI and its configured credentials)
+    Ec2 {
+        /// Stage to add the instances to (created if missing)
+        #[arg(short, long)]
+        stage: String,
+        /// Number of instances to launch
+        #[arg(long, default_value = "1")]
+        count: usize,
+        /// Instance type
+        #[arg(long = "type", default_value = "t3.small")]
+        instance_type: String,
+        /// AMI id (defaults to the region's current Ubuntu 24.04, amd64)
+        #[arg(long)]
+        ami: Option<String>,
+        /// AWS region (defaults to the aws CLI's)
+        #[arg(long)]
+        region: Option<String>,
+        /// Subnet to launch into (must assign public IPs)
+        #[arg(long)]
+        subnet: Option<String>,
+        /// VPC for the security group (defaults to the default VPC)
+        #[arg(long)]
+        vpc: Option<String>,
+        /// Login user of the AMI, used by --setup to bootstrap the stage user
+        #[arg(long, default_value = "ubuntu")]
+        admin_user: String,
+        /// Run `shipit setup --bootstrap` once the instances are up
+        #[arg(long)]
+        setup: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum ConfigAction {
     /// Set an environment variable
@@ -183,6 +654,20 @@ pub enum ConfigAction {
         #[arg(short, long)]
         stage: String,
     },
+    /// Fetch the remote .env → open in $EDITOR → push to every host
+    Edit {
+        /// Target stage
+        #[arg(short, long)]
+        stage: String,
+        /// Recreate the app containers afterwards so they pick up the change
+        #[arg(long)]
+        restart: bool,
+        /// Push without asking for confirmation after the diff
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Upgrade shipit.toml to the current config layout version
+    Migrate,
 }
 
 #[derive(Subcommand)]
@@ -220,6 +705,31 @@ pub enum SecretsAction {
         #[arg(short, long)]
         stage: String,
     },
+    /// Run a local command with the stage's secrets in its environment
+    Run {
+        /// Target stage
+        #[arg(short, long)]
+        stage: String,
+        /// Command to run (after --)
+        #[arg(last = true, required = true)]
+        cmd: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AppAction {
+    /// Start the app containers (`docker compose up -d`) on every host
+    Start,
+    /// Stop the app containers on every host, keeping them for `start`
+    Stop {
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Restart the app containers, one host at a time
+    Restart,
+    /// State and uptime of each service per host (non-zero exit if one is down)
+    Status,
 }
 
 #[derive(Subcommand)]
@@ -250,9 +760,9 @@ pub enum AccessoryAction {
     },
     /// Tail logs from an accessory container
     Logs {
-        /// Target stage
+        /// Target stage (defaults to $SHIPIT_STAGE, then default_stage)
         #[arg(short, long)]
-        stage: String,
+        stage: Option<String>,
         /// Accessory name (required)
         name: String,
         /// Follow log output
@@ -263,14 +773,133 @@ pub enum AccessoryAction {
 
 #[derive(Subcommand)]
 pub enum LocalAction {
-    /// Create a Multipass VM for local testing
-    Up,
+    /// Create a local VM for testing (Multipass or Vagrant, see [local])
+    Up {
+        /// Number of VMs, to simulate a multi-host stage (WireGuard mesh included)
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..=16))]
+        hosts: u16,
+    },
     /// Deploy to the local VM
-    Deploy,
+    Deploy {
+        /// Rsync the working tree into the current release and re-up compose,
+        /// skipping the git push and full pipeline
+        #[arg(long)]
+        sync: bool,
+    },
+    /// Mount the project directory into the local VM(s)
+    Mount {
+        /// Mount point inside the VM (default: /mnt/<app>)
+        #[arg(long)]
+        target: Option<String>,
+        /// Remove the mount instead
+        #[arg(long)]
+        unmount: bool,
+    },
     /// SSH into the local VM
-    Ssh,
+    Ssh {
+        /// Which VM to connect to (1-based, see `local status`)
+        #[arg(long, default_value = "1")]
+        host: usize,
+    },
+    /// Snapshot the local VM(s) so they can be reset later
+    Snapshot {
+        /// Snapshot name
+        #[arg(default_value = "base")]
+        name: String,
+    },
+    /// Reset the local VM(s) to a snapshot
+    Restore {
+        /// Snapshot name
+        #[arg(default_value = "base")]
+        name: String,
+    },
     /// Destroy the local VM
     Down,
     /// Show local VM status
-    Status,
+    Status {
+        /// Also show the app's containers on each VM
+        #[arg(long)]
+        app: bool,
+    },
+    /// Tail logs from containers on the local VM
+    Logs {
+        /// Service name
+        service: Option<String>,
+        /// Number of lines to tail
+        #[arg(short = 'n', long, default_value = "100")]
+        lines: usize,
+        /// Follow log output
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Execute a one-off command in the app container on the local VM
+    Run {
+        /// Command to run
+        #[arg(trailing_var_arg = true)]
+        cmd: Vec<String>,
+    },
+    /// List releases on the local VM
+    Releases,
+    /// Rollback the local VM to a previous release
+    Rollback {
+        /// Specific release to rollback to (e.g. 20250219-120000)
+        #[arg(long)]
+        release: Option<String>,
+    },
+}
+
+/// Pick the stage for commands where `-s` is optional: the flag, then
+/// $SHIPIT_STAGE, then `default_stage`. A protected stage reached without
+/// `-s` needs confirmation (and fails when nobody is there to confirm).
+pub fn resolve_stage(config: &ShipitConfig, stage: Option<String>) -> Result<String> {
+    if let Some(stage) = stage {
+        return Ok(stage);
+    }
+
+    let (name, source) = match std::env::var("SHIPIT_STAGE") {
+        Ok(name) if !name.is_empty() => (name, "SHIPIT_STAGE"),
+        _ => match &config.default_stage {
+            Some(name) => (name.clone(), "default_stage"),
+            None => bail!("No stage given. Pass -s <stage>, set SHIPIT_STAGE or default_stage in shipit.toml"),
+        },
+    };
+
+    if config.stage(&name)?.protected {
+        let confirmed = console::user_attended()
+            && dialoguer::Confirm::new()
+                .with_prompt(format!(
+                    "Stage '{}' (from {}) is protected. Continue?",
+                    name, source
+                ))
+                .default(false)
+                .interact()?;
+        if !confirmed {
+            bail!(
+                "Stage '{}' is protected; pass -s {} explicitly",
+                name,
+                name
+            );
+        }
+    }
+
+    Ok(name)
+}
+
+/// Load shipit.toml for a stage (picked as in `resolve_stage`), with the
+/// stage's `[stages.<name>.deploy]` overrides applied and its hosts discovered
+pub fn load_stage(
+    path: &Path,
+    stage: Option<String>,
+    project_root: &Path,
+) -> Result<(ShipitConfig, String)> {
+    let mut config = ShipitConfig::load(path)?;
+    let stage = resolve_stage(&config, stage)?;
+    config.apply_stage_overrides(&stage)?;
+    crate::provision::discovery::resolve(&mut config, &stage, project_root)?;
+    Ok((config, stage))
+}
+
+/// `load_stage` for a stage given by name
+pub fn load_for_stage(path: &Path, stage: &str, project_root: &Path) -> Result<ShipitConfig> {
+    load_stage(path, Some(stage.to_string()), project_root).map(|(config, _)| config)
 }
@@ -1,5 +1,7 @@
-use std::io;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use crossterm::{
@@ -12,14 +14,29 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table},
     Frame,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::config::ShipitConfig;
+use crate::config::{MonitorConfig, ShipitConfig};
+use crate::http::Response;
+use crate::output;
 use crate::ssh::SshSession;
 
+const DEFAULT_WEB_LISTEN: &str = "127.0.0.1:8787";
+const DEFAULT_EXPORTER_LISTEN: &str = "127.0.0.1:9100";
+
+const MONITOR_HTML: &str = include_str!("../../templates/monitor.html");
+
+/// Lines fetched for the log pane
+const LOG_TAIL_LINES: usize = 500;
+
+/// CPU/memory samples kept per container for the sparklines
+const HISTORY_SAMPLES: usize = 30;
+
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
 // --- Data structures ---
 
 #[derive(Debug, Deserialize)]
@@ -36,6 +53,8 @@ struct DockerPsEntry {
     ports: String,
     #[serde(alias = "State")]
     state: String,
+    #[serde(alias = "Labels", default)]
+    labels: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -50,7 +69,17 @@ struct DockerStatsEntry {
     mem_perc: String,
 }
 
-#[derive(Debug, Clone)]
+/// Where a container comes from, relative to the app's releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ContainerGroup {
+    Current,
+    Stale,
+    Accessory,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct ContainerInfo {
     name: String,
     image: String,
@@ -60,9 +89,13 @@ struct ContainerInfo {
     mem_usage: String,
     mem_perc: String,
     ports: String,
+    restarts: u64,
+    group: ContainerGroup,
+    /// Release directory name for current/stale containers
+    release: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 struct DiskInfo {
     size: String,
     used: String,
@@ -70,20 +103,293 @@ struct DiskInfo {
     use_percent: String,
 }
 
+/// State of a system service the app depends on (Traefik, Docker, WireGuard)
+#[derive(Debug, Clone, Serialize)]
+struct ServiceStatus {
+    name: String,
+    state: String,
+    healthy: bool,
+}
+
+/// Raw /proc counters; rates are computed against the previous poll
 #[derive(Debug, Clone)]
+struct HostCounters {
+    at: Instant,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    cpu_total: u64,
+    cpu_idle: u64,
+    cpu_steal: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HostMetrics {
+    load: [f64; 3],
+    mem_used_mb: u64,
+    mem_total_mb: u64,
+    swap_used_mb: u64,
+    /// Rates need two polls, so these are None on the first one
+    cpu_percent: Option<f64>,
+    steal_percent: Option<f64>,
+    rx_bytes_per_sec: Option<f64>,
+    tx_bytes_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct HostStatus {
     address: String,
+    metrics: Option<HostMetrics>,
+    #[serde(skip)]
+    counters: Option<HostCounters>,
+    services: Vec<ServiceStatus>,
     containers: Vec<ContainerInfo>,
     disk: Option<DiskInfo>,
     error: Option<String>,
 }
 
+/// Point-in-time view printed by `--once --json` and served by `--web`
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    app: &'a str,
+    stage: &'a str,
+    timestamp: String,
+    interval: u64,
+    hosts: &'a [HostStatus],
+    alerts: Vec<&'a str>,
+}
+
+impl<'a> Snapshot<'a> {
+    fn new(state: &'a AppState) -> Self {
+        let mut alerts: Vec<&str> = state.active_alerts.iter().map(String::as_str).collect();
+        alerts.sort();
+        Self {
+            app: &state.app_name,
+            stage: &state.stage_name,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            interval: state.interval,
+            hosts: &state.hosts,
+            alerts,
+        }
+    }
+}
+
+/// Rolling CPU/memory percentages for one container
+#[derive(Default)]
+struct ContainerHistory {
+    cpu: VecDeque<f64>,
+    mem: VecDeque<f64>,
+}
+
+impl ContainerHistory {
+    fn push(&mut self, cpu: f64, mem: f64) {
+        for (samples, value) in [(&mut self.cpu, cpu), (&mut self.mem, mem)] {
+            if samples.len() == HISTORY_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back(value);
+        }
+    }
+}
+
+/// Live log tail for one container, shown in a split pane
+struct LogPane {
+    host: usize,
+    container: String,
+    lines: Vec<String>,
+    /// Lines scrolled up from the bottom (0 = follow)
+    scroll: usize,
+}
+
+/// `docker inspect` highlights for one container, shown as an overlay
+struct ContainerDetails {
+    container: String,
+    host: String,
+    health: Option<String>,
+    health_log: Vec<String>,
+    restart_count: u64,
+    release_dir: Option<String>,
+    is_current: bool,
+    ports: Vec<String>,
+    mounts: Vec<String>,
+    /// Environment with values masked
+    env: Vec<String>,
+    error: Option<String>,
+    scroll: u16,
+}
+
+/// Which containers and hosts the dashboard shows
+#[derive(Default)]
+struct Filter {
+    /// Case-insensitive substring of name, image or status
+    query: String,
+    /// Typing into `query` (after `/`)
+    searching: bool,
+    /// Only containers that are not running, unhealthy or over a threshold
+    unhealthy_only: bool,
+    /// Only containers of the app's releases
+    app_only: bool,
+    /// Show a single host instead of all of them
+    host_tab: Option<usize>,
+}
+
 struct AppState {
     hosts: Vec<HostStatus>,
     app_name: String,
     stage_name: String,
     interval: u64,
     last_update: String,
+    /// (host index, container index) of the highlighted row
+    selected: (usize, usize),
+    logs: Option<LogPane>,
+    details: Option<ContainerDetails>,
+    /// Keyed by "<host>/<container>"
+    history: HashMap<String, ContainerHistory>,
+    /// Previous /proc counters per host address
+    counters: HashMap<String, HostCounters>,
+    thresholds: MonitorConfig,
+    /// Accessory container names ({app}-{accessory})
+    accessories: Vec<String>,
+    filter: Filter,
+    /// Alerts currently over threshold, so each crossing fires once
+    active_alerts: HashSet<String>,
+}
+
+impl AppState {
+    fn selected_container(&self) -> Option<&ContainerInfo> {
+        let (h, c) = self.selected;
+        self.hosts.get(h).and_then(|host| host.containers.get(c))
+    }
+
+    /// Hosts shown under the current host tab
+    fn visible_hosts(&self) -> Vec<usize> {
+        match self.filter.host_tab {
+            Some(tab) if tab < self.hosts.len() => vec![tab],
+            _ => (0..self.hosts.len()).collect(),
+        }
+    }
+
+    /// Indices of the host's containers that pass the filters
+    fn visible_containers(&self, host: usize) -> Vec<usize> {
+        let Some(host) = self.hosts.get(host) else {
+            return Vec::new();
+        };
+        let query = self.filter.query.to_lowercase();
+        host.containers
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                query.is_empty()
+                    || [&c.name, &c.image, &c.status]
+                        .iter()
+                        .any(|field| field.to_lowercase().contains(&query))
+            })
+            .filter(|(_, c)| {
+                !self.filter.unhealthy_only
+                    || c.state != "running"
+                    || c.status.contains("unhealthy")
+                    || !self.container_alerts(c).is_empty()
+            })
+            .filter(|(_, c)| {
+                !self.filter.app_only
+                    || matches!(c.group, ContainerGroup::Current | ContainerGroup::Stale)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Cycle the host tab: all hosts -> host 1 -> ... -> host N -> all hosts
+    fn next_host_tab(&mut self, forward: bool) {
+        let count = self.hosts.len();
+        self.filter.host_tab = match (self.filter.host_tab, forward) {
+            (None, true) => Some(0),
+            (None, false) => count.checked_sub(1),
+            (Some(tab), true) if tab + 1 < count => Some(tab + 1),
+            (Some(tab), false) if tab > 0 => Some(tab - 1),
+            _ => None,
+        };
+        self.clamp_selection();
+    }
+
+    /// (host, container) of every visible row, top to bottom
+    fn visible_rows(&self) -> Vec<(usize, usize)> {
+        self.visible_hosts()
+            .into_iter()
+            .flat_map(|h| self.visible_containers(h).into_iter().map(move |c| (h, c)))
+            .collect()
+    }
+
+    /// Keep the highlight on a visible row after the filters change
+    fn clamp_selection(&mut self) {
+        let rows = self.visible_rows();
+        if !rows.contains(&self.selected) {
+            if let Some(first) = rows.first() {
+                self.selected = *first;
+            }
+        }
+    }
+
+    /// Move the highlight one row down (or up) across the visible hosts
+    fn move_selection(&mut self, down: bool) {
+        let positions = self.visible_rows();
+        if positions.is_empty() {
+            return;
+        }
+        let current = positions
+            .iter()
+            .position(|p| *p == self.selected)
+            .unwrap_or(0);
+        let next = if down {
+            (current + 1).min(positions.len() - 1)
+        } else {
+            current.saturating_sub(1)
+        };
+        self.selected = positions[next];
+    }
+
+    /// Thresholds this container is currently over
+    fn container_alerts(&self, c: &ContainerInfo) -> Vec<String> {
+        let t = &self.thresholds;
+        let mut alerts = Vec::new();
+        if let Some(max) = t.cpu_percent {
+            let cpu = parse_percent(&c.cpu_perc);
+            if cpu > max {
+                alerts.push(format!("{} CPU {:.1}% > {}%", c.name, cpu, max));
+            }
+        }
+        if let Some(max) = t.memory_percent {
+            let mem = parse_percent(&c.mem_perc);
+            if mem > max {
+                alerts.push(format!("{} memory {:.1}% > {}%", c.name, mem, max));
+            }
+        }
+        if let Some(max) = t.restarts {
+            if c.restarts > max {
+                alerts.push(format!("{} restarted {} times (> {})", c.name, c.restarts, max));
+            }
+        }
+        alerts
+    }
+
+    fn disk_alert(&self, host: &HostStatus) -> Option<String> {
+        let max = self.thresholds.disk_percent?;
+        let used = parse_percent(&host.disk.as_ref()?.use_percent);
+        (used > max).then(|| format!("disk {:.0}% > {}%", used, max))
+    }
+
+    fn toggle_logs(&mut self) {
+        if self.logs.is_some() {
+            self.logs = None;
+            return;
+        }
+        if let Some(container) = self.selected_container() {
+            self.logs = Some(LogPane {
+                host: self.selected.0,
+                container: container.name.clone(),
+                lines: Vec::new(),
+                scroll: 0,
+            });
+        }
+    }
 }
 
 // --- Parsing ---
@@ -126,7 +432,7 @@ fn merge_ps_stats(
     ps_entries: Vec<DockerPsEntry>,
     stats_entries: Vec<DockerStatsEntry>,
 ) -> Vec<ContainerInfo> {
-    let stats_map: std::collections::HashMap<String, &DockerStatsEntry> = stats_entries
+    let stats_map: HashMap<String, &DockerStatsEntry> = stats_entries
         .iter()
         .map(|s| (s.name.clone(), s))
         .collect();
@@ -153,16 +459,310 @@ fn merge_ps_stats(
                     .unwrap_or_default(),
                 mem_perc: stats.map(|s| s.mem_perc.clone()).unwrap_or_default(),
                 ports: ps.ports,
+                restarts: 0,
+                group: ContainerGroup::Other,
+                release: compose_working_dir(&ps.labels),
             }
         })
         .collect()
 }
 
+fn parse_inspect(output: &str, details: &mut ContainerDetails) -> Result<()> {
+    let v: serde_json::Value = serde_json::from_str(output).context("Invalid docker inspect output")?;
+    let c = v.get(0).context("Container not found")?;
+
+    details.restart_count = c["RestartCount"].as_u64().unwrap_or(0);
+
+    let health = &c["State"]["Health"];
+    details.health = health["Status"].as_str().map(str::to_string);
+    details.health_log = health["Log"]
+        .as_array()
+        .map(|log| {
+            log.iter()
+                .rev()
+                .take(5)
+                .map(|entry| {
+                    format!(
+                        "exit {} — {}",
+                        entry["ExitCode"].as_i64().unwrap_or(-1),
+                        entry["Output"].as_str().unwrap_or("").trim().replace('\n', " ")
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    details.release_dir = c["Config"]["Labels"]["com.docker.compose.project.working_dir"]
+        .as_str()
+        .map(str::to_string);
+
+    details.ports = c["NetworkSettings"]["Ports"]
+        .as_object()
+        .map(|ports| {
+            ports
+                .iter()
+                .map(|(container_port, bindings)| {
+                    let published: Vec<String> = bindings
+                        .as_array()
+                        .map(|b| {
+                            b.iter()
+                                .map(|b| {
+                                    format!(
+                                        "{}:{}",
+                                        b["HostIp"].as_str().unwrap_or(""),
+                                        b["HostPort"].as_str().unwrap_or("")
+                                    )
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    if published.is_empty() {
+                        container_port.clone()
+                    } else {
+                        format!("{} -> {}", published.join(", "), container_port)
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    details.mounts = c["Mounts"]
+        .as_array()
+        .map(|mounts| {
+            mounts
+                .iter()
+                .map(|m| {
+                    let source = m["Name"]
+                        .as_str()
+                        .or_else(|| m["Source"].as_str())
+                        .unwrap_or("?");
+                    let mode = if m["RW"].as_bool().unwrap_or(true) { "rw" } else { "ro" };
+                    format!("{} -> {} ({})", source, m["Destination"].as_str().unwrap_or("?"), mode)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Values may hold secrets; only the keys are useful at a glance
+    details.env = c["Config"]["Env"]
+        .as_array()
+        .map(|env| {
+            env.iter()
+                .filter_map(|e| e.as_str())
+                .map(|e| match e.split_once('=') {
+                    Some((key, "")) => format!("{}=", key),
+                    Some((key, _)) => format!("{}=****", key),
+                    None => e.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(())
+}
+
+/// The compose project directory from a `docker ps` Labels string
+fn compose_working_dir(labels: &str) -> Option<String> {
+    labels
+        .split(',')
+        .find_map(|l| l.strip_prefix("com.docker.compose.project.working_dir="))
+        .map(str::to_string)
+}
+
+/// Classify containers as current/stale release, accessory or unrelated and
+/// order them by group
+fn group_containers(
+    containers: &mut [ContainerInfo],
+    releases_dir: &str,
+    current: Option<&str>,
+    accessories: &[String],
+) {
+    for c in containers.iter_mut() {
+        let working_dir = c.release.take();
+        match working_dir.as_deref().and_then(|d| d.strip_prefix(releases_dir)) {
+            Some(release) => {
+                c.group = if working_dir.as_deref() == current {
+                    ContainerGroup::Current
+                } else {
+                    ContainerGroup::Stale
+                };
+                c.release = Some(release.trim_start_matches('/').to_string());
+            }
+            None if accessories.contains(&c.name) => c.group = ContainerGroup::Accessory,
+            None => c.group = ContainerGroup::Other,
+        }
+    }
+    containers.sort_by_key(|c| c.group);
+}
+
+/// "/name 3" lines from docker inspect -> name => restart count
+fn parse_restarts(output: &str) -> HashMap<String, u64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, count) = line.trim().rsplit_once(' ')?;
+            Some((name.trim_start_matches('/').to_string(), count.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parse `name=state` lines printed by SERVICES_SCRIPT
+fn parse_services(output: &str, mesh: bool) -> Vec<ServiceStatus> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .filter_map(|(name, state)| {
+            let healthy = match name {
+                "traefik" => state == "active" || state == "container running",
+                "docker" => state == "active",
+                // Only multi-host stages are meshed
+                "wireguard" if !mesh && state == "absent" => return None,
+                "wireguard" => state == "up",
+                _ => return None,
+            };
+            Some(ServiceStatus {
+                name: name.to_string(),
+                state: state.to_string(),
+                healthy,
+            })
+        })
+        .collect()
+}
+
+/// Parse HOST_METRICS_SCRIPT output (loadavg, meminfo, stat, net/dev
+/// sections separated by "--")
+fn parse_host_metrics(output: &str) -> Option<(HostMetrics, HostCounters)> {
+    let mut sections = output.split("--\n");
+    let loadavg = sections.next()?;
+    let meminfo = sections.next()?;
+    let stat = sections.next()?;
+    let netdev = sections.next()?;
+
+    let load: Vec<f64> = loadavg
+        .split_whitespace()
+        .take(3)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    if load.len() != 3 {
+        return None;
+    }
+
+    let mem_kb = |key: &str| -> u64 {
+        meminfo
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    };
+    let mem_total = mem_kb("MemTotal:");
+    let mem_available = mem_kb("MemAvailable:");
+    let swap_used = mem_kb("SwapTotal:").saturating_sub(mem_kb("SwapFree:"));
+
+    // cpu  user nice system idle iowait irq softirq steal ...
+    let cpu: Vec<u64> = stat
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    let (cpu_total, cpu_idle, cpu_steal) = if cpu.len() == 8 {
+        (cpu.iter().sum(), cpu[3] + cpu[4], cpu[7])
+    } else {
+        (0, 0, 0)
+    };
+
+    // Physical interfaces only: bridge/veth/wg traffic would be counted twice
+    let (mut rx_bytes, mut tx_bytes) = (0, 0);
+    for line in netdev.lines() {
+        let Some((iface, counters)) = line.split_once(':') else {
+            continue;
+        };
+        let iface = iface.trim();
+        if iface == "lo" || ["docker", "br-", "veth", "wg"].iter().any(|p| iface.starts_with(p)) {
+            continue;
+        }
+        let fields: Vec<u64> = counters
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        if fields.len() >= 9 {
+            rx_bytes += fields[0];
+            tx_bytes += fields[8];
+        }
+    }
+
+    Some((
+        HostMetrics {
+            load: [load[0], load[1], load[2]],
+            mem_used_mb: mem_total.saturating_sub(mem_available) / 1024,
+            mem_total_mb: mem_total / 1024,
+            swap_used_mb: swap_used / 1024,
+            cpu_percent: None,
+            steal_percent: None,
+            rx_bytes_per_sec: None,
+            tx_bytes_per_sec: None,
+        },
+        HostCounters {
+            at: Instant::now(),
+            rx_bytes,
+            tx_bytes,
+            cpu_total,
+            cpu_idle,
+            cpu_steal,
+        },
+    ))
+}
+
+/// Fill in CPU, steal and network rates from the previous counters
+fn apply_rates(metrics: &mut HostMetrics, prev: &HostCounters, now: &HostCounters) {
+    let total = now.cpu_total.saturating_sub(prev.cpu_total);
+    if total > 0 {
+        let idle = now.cpu_idle.saturating_sub(prev.cpu_idle);
+        let steal = now.cpu_steal.saturating_sub(prev.cpu_steal);
+        metrics.cpu_percent = Some(100.0 * total.saturating_sub(idle) as f64 / total as f64);
+        metrics.steal_percent = Some(100.0 * steal as f64 / total as f64);
+    }
+
+    let secs = now.at.duration_since(prev.at).as_secs_f64();
+    if secs > 0.0 {
+        metrics.rx_bytes_per_sec = Some(now.rx_bytes.saturating_sub(prev.rx_bytes) as f64 / secs);
+        metrics.tx_bytes_per_sec = Some(now.tx_bytes.saturating_sub(prev.tx_bytes) as f64 / secs);
+    }
+}
+
 // --- SSH polling ---
 
-async fn poll_host(session: &SshSession, deploy_to: &str) -> HostStatus {
+const HOST_METRICS_SCRIPT: &str = "cat /proc/loadavg; echo --; \
+    grep -E '^(MemTotal|MemAvailable|SwapTotal|SwapFree):' /proc/meminfo; echo --; \
+    head -1 /proc/stat; echo --; \
+    tail -n +3 /proc/net/dev";
+
+/// Traefik runs as a systemd unit (or a container on custom setups), WireGuard as wg0
+const SERVICES_SCRIPT: &str = r#"t=$(systemctl is-active traefik 2>/dev/null)
+if [ "$t" != active ]; then c=$(docker inspect -f '{{.State.Status}}' traefik 2>/dev/null) && t="container $c"; fi
+echo "traefik=${t:-missing}"
+echo "docker=$(systemctl is-active docker 2>/dev/null || echo unknown)"
+if ip link show wg0 >/dev/null 2>&1; then echo wireguard=up; else echo wireguard=absent; fi"#;
+
+async fn poll_host(
+    session: &SshSession,
+    deploy_to: &str,
+    accessories: &[String],
+    mesh: bool,
+) -> HostStatus {
     let address = session.host().to_string();
 
+    let services = match session.exec(SERVICES_SCRIPT).await {
+        Ok(output) => parse_services(&output, mesh),
+        Err(_) => vec![],
+    };
+
+    let (metrics, counters) = match session.exec(HOST_METRICS_SCRIPT).await {
+        Ok(output) => parse_host_metrics(&output).unzip(),
+        Err(_) => (None, None),
+    };
+
     // docker ps
     let ps_result = session
         .exec("docker ps -a --format '{{json .}}'")
@@ -173,6 +773,9 @@ async fn poll_host(session: &SshSession, deploy_to: &str) -> HostStatus {
         Err(e) => {
             return HostStatus {
                 address,
+                metrics,
+                counters,
+                services,
                 containers: vec![],
                 disk: None,
                 error: Some(format!("docker ps failed: {}", e)),
@@ -211,10 +814,39 @@ async fn poll_host(session: &SshSession, deploy_to: &str) -> HostStatus {
         Err(_) => None,
     };
 
-    let containers = merge_ps_stats(ps_entries, stats_entries);
+    let mut containers = merge_ps_stats(ps_entries, stats_entries);
+
+    let current = session
+        .exec(&format!("readlink -f {}/current", deploy_to))
+        .await
+        .ok()
+        .map(|out| out.trim().to_string())
+        .filter(|out| !out.is_empty());
+    group_containers(
+        &mut containers,
+        &format!("{}/releases/", deploy_to),
+        current.as_deref(),
+        accessories,
+    );
+
+    // Restart counts are only exposed by docker inspect
+    if !containers.is_empty() {
+        if let Ok(output) = session
+            .exec("docker inspect --format '{{.Name}} {{.RestartCount}}' $(docker ps -aq)")
+            .await
+        {
+            let restarts = parse_restarts(&output);
+            for c in &mut containers {
+                c.restarts = restarts.get(&c.name).copied().unwrap_or(0);
+            }
+        }
+    }
 
     HostStatus {
         address,
+        metrics,
+        counters,
+        services,
         containers,
         disk,
         error: None,
@@ -222,16 +854,181 @@ async fn poll_host(session: &SshSession, deploy_to: &str) -> HostStatus {
 }
 
 async fn poll_all(sessions: &[SshSession], state: &mut AppState, deploy_to: &str) {
+    let mesh = sessions.len() > 1;
+    let accessories = state.accessories.clone();
     let mut futures = Vec::new();
     for session in sessions {
-        futures.push(poll_host(session, deploy_to));
+        futures.push(poll_host(session, deploy_to, &accessories, mesh));
     }
 
-    let results = futures::future::join_all(futures).await;
+    let mut results = futures::future::join_all(futures).await;
+    for host in &mut results {
+        let Some(now) = host.counters.clone() else {
+            continue;
+        };
+        if let (Some(metrics), Some(prev)) = (host.metrics.as_mut(), state.counters.get(&host.address)) {
+            apply_rates(metrics, prev, &now);
+        }
+        state.counters.insert(host.address.clone(), now);
+    }
     state.hosts = results;
+    record_history(state);
     state.last_update = chrono::Local::now().format("%H:%M:%S").to_string();
 }
 
+/// Append the latest stats sample of every running container and forget
+/// containers that are gone
+fn record_history(state: &mut AppState) {
+    let mut seen = std::collections::HashSet::new();
+    for host in &state.hosts {
+        for c in host.containers.iter().filter(|c| c.state == "running") {
+            let key = format!("{}/{}", host.address, c.name);
+            state
+                .history
+                .entry(key.clone())
+                .or_default()
+                .push(parse_percent(&c.cpu_perc), parse_percent(&c.mem_perc));
+            seen.insert(key);
+        }
+    }
+    state.history.retain(|key, _| seen.contains(key));
+}
+
+/// Re-evaluate thresholds, returning alerts that were not active on the
+/// previous poll
+fn check_alerts(state: &mut AppState) -> Vec<String> {
+    let mut active = HashSet::new();
+    for host in &state.hosts {
+        let alerts = host
+            .containers
+            .iter()
+            .flat_map(|c| state.container_alerts(c))
+            .chain(state.disk_alert(host))
+            .chain(
+                host.services
+                    .iter()
+                    .filter(|svc| !svc.healthy)
+                    .map(|svc| format!("{} is {}", svc.name, svc.state)),
+            )
+            .chain(stale_running(host).map(|c| {
+                format!(
+                    "{} from old release {} is still running",
+                    c.name,
+                    c.release.as_deref().unwrap_or("?")
+                )
+            }));
+        for alert in alerts {
+            active.insert(format!("{}: {}", host.address, alert));
+        }
+    }
+
+    let mut crossed: Vec<String> = active.difference(&state.active_alerts).cloned().collect();
+    crossed.sort();
+    state.active_alerts = active;
+    crossed
+}
+
+fn stale_running(host: &HostStatus) -> impl Iterator<Item = &ContainerInfo> {
+    host.containers
+        .iter()
+        .filter(|c| c.group == ContainerGroup::Stale && c.state == "running")
+}
+
+/// Ring the bell and post newly crossed alerts to the configured webhook
+fn fire_alerts(state: &AppState, crossed: &[String]) {
+    if crossed.is_empty() {
+        return;
+    }
+
+    if state.thresholds.bell {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x07");
+        let _ = stdout.flush();
+    }
+
+    if let Some(url) = &state.thresholds.webhook_url {
+        let url = url.clone();
+        let payload = serde_json::json!({
+            "event": "monitor.alert",
+            "app": state.app_name,
+            "stage": state.stage_name,
+            "alerts": crossed,
+        });
+        // Fire and forget: a slow webhook must not stall the dashboard
+        tokio::task::spawn_blocking(move || {
+            let _ = crate::notify::post_json(&url, &payload);
+        });
+    }
+}
+
+/// "12.34%" -> 12.34
+fn parse_percent(s: &str) -> f64 {
+    s.trim().trim_end_matches('%').parse().unwrap_or(0.0)
+}
+
+/// Refresh the open log pane with the container's latest lines
+async fn poll_logs(sessions: &[SshSession], state: &mut AppState) {
+    let Some(pane) = state.logs.as_mut() else {
+        return;
+    };
+    let Some(session) = sessions.get(pane.host) else {
+        return;
+    };
+
+    pane.lines = match session
+        .exec(&format!("docker logs --tail {} {} 2>&1", LOG_TAIL_LINES, pane.container))
+        .await
+    {
+        Ok(output) => output.lines().map(str::to_string).collect(),
+        Err(e) => vec![format!("Failed to read logs: {}", e)],
+    };
+}
+
+/// Inspect the selected container and open the details overlay
+async fn inspect_selected(sessions: &[SshSession], state: &mut AppState, deploy_to: &str) {
+    let host_index = state.selected.0;
+    let Some(container) = state.selected_container().map(|c| c.name.clone()) else {
+        return;
+    };
+    let (Some(session), Some(host)) = (sessions.get(host_index), state.hosts.get(host_index)) else {
+        return;
+    };
+
+    let mut details = ContainerDetails {
+        container: container.clone(),
+        host: host.address.clone(),
+        health: None,
+        health_log: Vec::new(),
+        restart_count: 0,
+        release_dir: None,
+        is_current: false,
+        ports: Vec::new(),
+        mounts: Vec::new(),
+        env: Vec::new(),
+        error: None,
+        scroll: 0,
+    };
+
+    let result = match session.exec(&format!("docker inspect {}", container)).await {
+        Ok(output) => parse_inspect(&output, &mut details),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        details.error = Some(format!("docker inspect failed: {}", e));
+    }
+
+    if let Some(dir) = &details.release_dir {
+        if let Ok(current) = session
+            .exec(&format!("readlink -f {}/current", deploy_to))
+            .await
+        {
+            details.is_current = current.trim() == dir;
+        }
+    }
+
+    state.details = Some(details);
+}
+
 // --- TUI rendering ---
 
 fn ui(frame: &mut Frame, state: &AppState) {
@@ -252,35 +1049,207 @@ fn ui(frame: &mut Frame, state: &AppState) {
     let inner = outer_block.inner(area);
     frame.render_widget(outer_block, area);
 
-    let num_hosts = state.hosts.len().max(1);
+    // Split off the log pane (bottom 40%) when open
+    let (hosts_area, logs_area) = match &state.logs {
+        Some(_) => {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .split(inner);
+            (split[0], Some(split[1]))
+        }
+        None => (inner, None),
+    };
+
+    let visible_hosts = state.visible_hosts();
+    let num_hosts = visible_hosts.len().max(1);
 
     // Split inner area: host sections + footer
     // Use Fill(1) for each host so they share remaining space equally after the footer
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
-            std::iter::repeat(Constraint::Fill(1))
-                .take(num_hosts)
+            std::iter::repeat_n(Constraint::Fill(1), num_hosts)
                 .chain(std::iter::once(Constraint::Length(1)))
                 .collect::<Vec<_>>(),
         )
-        .split(inner);
+        .split(hosts_area);
 
     // Render each host
-    for (i, host) in state.hosts.iter().enumerate() {
-        render_host(frame, chunks[i], host);
+    for (slot, &i) in visible_hosts.iter().enumerate() {
+        render_host(frame, chunks[slot], state, i);
+    }
+
+    if let (Some(pane), Some(area)) = (&state.logs, logs_area) {
+        render_logs(frame, area, pane, &state.hosts);
+    }
+
+    if let Some(details) = &state.details {
+        render_details(frame, inner, details);
     }
 
     // Footer
-    let footer = Paragraph::new(Line::from(vec![
-        Span::styled("q", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-        Span::raw(" = quit │ refreshing every "),
-        Span::styled(format!("{}s", state.interval), Style::default().fg(Color::Yellow)),
-    ]));
-    frame.render_widget(footer, chunks[num_hosts]);
+    let key = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let footer = if state.filter.searching {
+        Line::from(vec![
+            Span::styled("/", key),
+            Span::raw(state.filter.query.clone()),
+            Span::styled("█", Style::default().fg(Color::DarkGray)),
+            Span::raw("  (enter = apply, esc = clear)"),
+        ])
+    } else {
+        let mut spans = vec![
+            Span::styled("q", key),
+            Span::raw(" quit │ "),
+            Span::styled("↑↓", key),
+            Span::raw(" select │ "),
+            Span::styled("enter", key),
+            Span::raw(" inspect │ "),
+            Span::styled("l", key),
+            Span::raw(" logs │ "),
+            Span::styled("/", key),
+            Span::raw(" search │ "),
+            Span::styled("u", key),
+            Span::raw(" unhealthy │ "),
+            Span::styled("a", key),
+            Span::raw(" app only │ "),
+            Span::styled("tab", key),
+            Span::raw(" hosts │ every "),
+            Span::styled(format!("{}s", state.interval), Style::default().fg(Color::Yellow)),
+        ];
+        let filter = filter_summary(state);
+        if !filter.is_empty() {
+            spans.push(Span::raw(" │ "));
+            spans.push(Span::styled(filter, Style::default().fg(Color::Cyan)));
+        }
+        Line::from(spans)
+    };
+    frame.render_widget(Paragraph::new(footer), chunks[num_hosts]);
+}
+
+/// Active filters, e.g. "host 2/3 · /web · unhealthy"
+fn filter_summary(state: &AppState) -> String {
+    let f = &state.filter;
+    let mut parts = Vec::new();
+    if let Some(tab) = f.host_tab {
+        parts.push(format!("host {}/{}", tab + 1, state.hosts.len()));
+    }
+    if !f.query.is_empty() {
+        parts.push(format!("/{}", f.query));
+    }
+    if f.unhealthy_only {
+        parts.push("unhealthy".to_string());
+    }
+    if f.app_only {
+        parts.push("app only".to_string());
+    }
+    parts.join(" · ")
+}
+
+fn render_logs(frame: &mut Frame, area: Rect, pane: &LogPane, hosts: &[HostStatus]) {
+    let host = hosts.get(pane.host).map(|h| h.address.as_str()).unwrap_or("?");
+    let follow = if pane.scroll == 0 { "following" } else { "scrolled" };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(vec![Span::styled(
+            format!(" logs: {} @ {} ", pane.container, host),
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        )]))
+        .title(
+            Line::from(vec![Span::styled(
+                format!(" {} ", follow),
+                Style::default().fg(Color::DarkGray),
+            )])
+            .alignment(ratatui::layout::Alignment::Right),
+        );
+
+    // Show the window ending `scroll` lines above the bottom
+    let height = block.inner(area).height as usize;
+    let end = pane.lines.len().saturating_sub(pane.scroll);
+    let start = end.saturating_sub(height);
+    let lines: Vec<Line> = pane.lines[start..end]
+        .iter()
+        .map(|l| Line::from(l.as_str()))
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
 }
 
-fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
+fn render_details(frame: &mut Frame, area: Rect, details: &ContainerDetails) {
+    // Centered overlay covering most of the dashboard
+    let popup = Rect {
+        x: area.x + area.width / 10,
+        y: area.y + area.height / 10,
+        width: area.width * 4 / 5,
+        height: area.height * 4 / 5,
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(vec![Span::styled(
+            format!(" {} @ {} ", details.container, details.host),
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        )]));
+
+    let heading = |text: &str| {
+        Line::from(Span::styled(
+            text.to_string(),
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        ))
+    };
+    let item = |text: &str| Line::from(format!("  {}", text));
+
+    let mut lines = Vec::new();
+    if let Some(err) = &details.error {
+        lines.push(Line::from(Span::styled(err.clone(), Style::default().fg(Color::Red))));
+    } else {
+        let release = match &details.release_dir {
+            Some(dir) if details.is_current => format!("{} (current)", dir),
+            Some(dir) => dir.clone(),
+            None => "not part of a compose project".to_string(),
+        };
+        lines.push(Line::from(format!("Release:  {}", release)));
+        lines.push(Line::from(format!("Restarts: {}", details.restart_count)));
+        lines.push(Line::from(format!(
+            "Health:   {}",
+            details.health.as_deref().unwrap_or("no healthcheck")
+        )));
+        for entry in &details.health_log {
+            lines.push(item(entry));
+        }
+
+        for (title, entries) in [
+            ("Ports", &details.ports),
+            ("Mounts", &details.mounts),
+            ("Env", &details.env),
+        ] {
+            lines.push(Line::from(""));
+            lines.push(heading(title));
+            if entries.is_empty() {
+                lines.push(item("(none)"));
+            }
+            for entry in entries {
+                lines.push(item(entry));
+            }
+        }
+    }
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(block).scroll((details.scroll, 0)),
+        popup,
+    );
+}
+
+fn render_host(frame: &mut Frame, area: Rect, state: &AppState, index: usize) {
+    let host = &state.hosts[index];
+    let selected = (state.selected.0 == index).then_some(state.selected.1);
+    let disk_color = if state.disk_alert(host).is_some() {
+        Color::Red
+    } else {
+        Color::DarkGray
+    };
+
     let disk_info = host
         .disk
         .as_ref()
@@ -288,15 +1257,32 @@ fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
         .unwrap_or_else(|| "Disk: N/A".to_string());
 
     let title = format!(" {} ", host.address);
+    let mut title_spans = vec![Span::styled(
+        title,
+        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+    )];
+    for svc in &host.services {
+        let (color, label) = if svc.healthy {
+            (Color::Green, svc.name.clone())
+        } else {
+            (Color::Red, format!("{}: {}", svc.name, svc.state))
+        };
+        title_spans.push(Span::styled("● ", Style::default().fg(color)));
+        title_spans.push(Span::styled(format!("{} ", label), Style::default().fg(color)));
+    }
+    if stale_running(host).next().is_some() {
+        title_spans.push(Span::styled(
+            "⚠ old release still running ",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
     let block = Block::default()
         .borders(Borders::ALL)
-        .title(Line::from(vec![
-            Span::styled(title, Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-        ]))
+        .title(Line::from(title_spans))
         .title(Line::from(vec![
             Span::styled(
                 format!(" {} ", disk_info),
-                Style::default().fg(Color::DarkGray),
+                Style::default().fg(disk_color),
             ),
         ]).alignment(ratatui::layout::Alignment::Right));
 
@@ -313,9 +1299,34 @@ fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
     let inner = block.inner(area);
     frame.render_widget(block, area);
 
-    if host.containers.is_empty() {
+    // Host metrics line above the container table
+    let inner = match &host.metrics {
+        Some(metrics) => {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(inner);
+            frame.render_widget(
+                Paragraph::new(Span::styled(
+                    format_host_metrics(metrics),
+                    Style::default().fg(Color::DarkGray),
+                )),
+                split[0],
+            );
+            split[1]
+        }
+        None => inner,
+    };
+
+    let visible = state.visible_containers(index);
+    if visible.is_empty() {
+        let message = if host.containers.is_empty() {
+            "  No containers found"
+        } else {
+            "  No containers match the filters"
+        };
         let empty = Paragraph::new(Span::styled(
-            "  No containers found",
+            message,
             Style::default().fg(Color::DarkGray),
         ));
         frame.render_widget(empty, inner);
@@ -324,11 +1335,14 @@ fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
 
     // Table header
     let header = Row::new(vec![
+        Cell::from("GROUP"),
         Cell::from("NAME"),
         Cell::from("IMAGE"),
         Cell::from("STATUS"),
         Cell::from("CPU%"),
+        Cell::from("CPU HISTORY"),
         Cell::from("MEM"),
+        Cell::from("MEM HISTORY"),
     ])
     .style(
         Style::default()
@@ -337,10 +1351,10 @@ fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
     );
 
     // Table rows
-    let rows: Vec<Row> = host
-        .containers
+    let rows: Vec<Row> = visible
         .iter()
-        .map(|c| {
+        .map(|&i| (i, &host.containers[i]))
+        .map(|(i, c)| {
             let state_color = match c.state.as_str() {
                 "running" => Color::Green,
                 "exited" => Color::Red,
@@ -357,23 +1371,52 @@ fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
             let name_display = truncate(&c.name, 24);
             let image_display = truncate(&c.image, 26);
 
-            Row::new(vec![
+            let samples = state.history.get(&format!("{}/{}", host.address, c.name));
+            let cpu_spark = samples.map(|h| sparkline(&h.cpu)).unwrap_or_default();
+            let mem_spark = samples.map(|h| sparkline(&h.mem)).unwrap_or_default();
+
+            let (group, group_color) = match c.group {
+                ContainerGroup::Current => ("current".to_string(), Color::Green),
+                ContainerGroup::Stale => (
+                    format!("stale {}", c.release.as_deref().unwrap_or("")),
+                    Color::Red,
+                ),
+                ContainerGroup::Accessory => ("accessory".to_string(), Color::Blue),
+                ContainerGroup::Other => ("other".to_string(), Color::DarkGray),
+            };
+
+            let row = Row::new(vec![
+                Cell::from(truncate(&group, 20)).style(Style::default().fg(group_color)),
                 Cell::from(name_display),
                 Cell::from(image_display),
                 Cell::from(status_display).style(Style::default().fg(state_color)),
                 Cell::from(c.cpu_perc.clone()),
+                Cell::from(cpu_spark).style(Style::default().fg(Color::Cyan)),
                 Cell::from(c.mem_usage.clone()),
-            ])
+                Cell::from(mem_spark).style(Style::default().fg(Color::Magenta)),
+            ]);
+
+            let mut style = Style::default();
+            if !state.container_alerts(c).is_empty() {
+                style = style.fg(Color::Red).add_modifier(Modifier::BOLD);
+            }
+            if selected == Some(i) {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            row.style(style)
         })
         .collect();
 
     let table = Table::new(
         rows,
         [
+            Constraint::Length(21),
             Constraint::Length(25),
             Constraint::Length(27),
             Constraint::Length(16),
             Constraint::Length(8),
+            Constraint::Length(HISTORY_SAMPLES as u16 + 1),
+            Constraint::Length(11),
             Constraint::Min(10),
         ],
     )
@@ -382,6 +1425,43 @@ fn render_host(frame: &mut Frame, area: Rect, host: &HostStatus) {
     frame.render_widget(table, inner);
 }
 
+/// One-line summary: load, memory, swap, CPU/steal and network rates
+fn format_host_metrics(m: &HostMetrics) -> String {
+    let mut parts = vec![
+        format!("load {:.2} {:.2} {:.2}", m.load[0], m.load[1], m.load[2]),
+        format!("mem {}/{} MB", m.mem_used_mb, m.mem_total_mb),
+        format!("swap {} MB", m.swap_used_mb),
+    ];
+    if let (Some(cpu), Some(steal)) = (m.cpu_percent, m.steal_percent) {
+        parts.push(format!("cpu {:.0}% steal {:.1}%", cpu, steal));
+    }
+    if let (Some(rx), Some(tx)) = (m.rx_bytes_per_sec, m.tx_bytes_per_sec) {
+        parts.push(format!("net ↓{} ↑{}", format_rate(rx), format_rate(tx)));
+    }
+    parts.join(" │ ")
+}
+
+fn format_rate(bytes_per_sec: f64) -> String {
+    if bytes_per_sec >= 1024.0 * 1024.0 {
+        format!("{:.1} MB/s", bytes_per_sec / (1024.0 * 1024.0))
+    } else {
+        format!("{:.0} KB/s", bytes_per_sec / 1024.0)
+    }
+}
+
+/// Render percentages as a block sparkline, scaled to 100% (or the peak
+/// when CPU exceeds one core)
+fn sparkline(samples: &VecDeque<f64>) -> String {
+    let max = samples.iter().copied().fold(100.0, f64::max);
+    samples
+        .iter()
+        .map(|v| {
+            let level = ((v / max) * (SPARK_BLOCKS.len() - 1) as f64).round() as usize;
+            SPARK_BLOCKS[level.min(SPARK_BLOCKS.len() - 1)]
+        })
+        .collect()
+}
+
 fn truncate(s: &str, max: usize) -> String {
     if s.len() > max {
         format!("{}...", &s[..max.saturating_sub(3)])
@@ -390,9 +1470,303 @@ fn truncate(s: &str, max: usize) -> String {
     }
 }
 
+/// Print the state after a single poll, as JSON or a plain summary
+fn print_once(state: &AppState, json: bool) -> Result<()> {
+    let snapshot = Snapshot::new(state);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&snapshot)?);
+        return Ok(());
+    }
+
+    output::header(&format!("{} ({})", state.app_name, state.stage_name));
+    for host in &state.hosts {
+        let disk = host
+            .disk
+            .as_ref()
+            .map(|d| format!("disk {}/{} ({})", d.used, d.size, d.use_percent))
+            .unwrap_or_else(|| "disk N/A".to_string());
+        output::info(&format!("{} — {}", host.address, disk));
+        if let Some(metrics) = &host.metrics {
+            println!("  {}", format_host_metrics(metrics));
+        }
+        let services: Vec<String> = host
+            .services
+            .iter()
+            .map(|svc| format!("{}: {}", svc.name, svc.state))
+            .collect();
+        if !services.is_empty() {
+            println!("  {}", services.join(" │ "));
+        }
+
+        if let Some(err) = &host.error {
+            output::error(err);
+            continue;
+        }
+        for c in &host.containers {
+            println!(
+                "  {:<10} {:<25} {:<28} {:>8} {:>10}",
+                format!("{:?}", c.group).to_lowercase(),
+                truncate(&c.name, 24),
+                truncate(&c.status, 27),
+                c.cpu_perc,
+                c.mem_usage
+            );
+        }
+    }
+    for alert in snapshot.alerts {
+        output::warning(alert);
+    }
+    Ok(())
+}
+
+/// What `serve_http` exposes
+#[derive(Clone, Copy)]
+enum ServeMode {
+    /// Read-only web UI (`/`) and JSON API (`/api/status`)
+    Web,
+    /// Prometheus text format on `/metrics`
+    Exporter,
+}
+
+impl ServeMode {
+    fn render(self, state: &AppState) -> String {
+        match self {
+            ServeMode::Web => serde_json::to_string(&Snapshot::new(state)).unwrap_or_default(),
+            ServeMode::Exporter => prometheus_metrics(state),
+        }
+    }
+
+    fn respond(self, path: &str, latest: String) -> Response {
+        match (self, path) {
+            (ServeMode::Web, "/") => Response::ok("text/html; charset=utf-8", MONITOR_HTML),
+            (ServeMode::Web, "/api/status") => Response::ok("application/json", latest),
+            (ServeMode::Exporter, "/metrics") => {
+                Response::ok("text/plain; version=0.0.4", latest)
+            }
+            _ => Response::not_found(),
+        }
+    }
+}
+
+/// Prometheus label value escaping
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render the current state in the Prometheus text exposition format
+fn prometheus_metrics(state: &AppState) -> String {
+    let mut out = String::new();
+    let stage = label(&state.stage_name);
+    let mut metric = |name: &str, help: &str, kind: &str, samples: Vec<(String, f64)>| {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+        for (labels, value) in samples {
+            out.push_str(&format!("{}{{stage=\"{}\",{}}} {}\n", name, stage, labels, value));
+        }
+    };
+
+    let hosts = &state.hosts;
+    let host_label = |h: &HostStatus| format!("host=\"{}\"", label(&h.address));
+    let container_label = |h: &HostStatus, c: &ContainerInfo| {
+        format!(
+            "host=\"{}\",container=\"{}\",group=\"{}\"",
+            label(&h.address),
+            label(&c.name),
+            format!("{:?}", c.group).to_lowercase()
+        )
+    };
+    let per_host = |f: &dyn Fn(&HostStatus) -> Option<f64>| -> Vec<(String, f64)> {
+        hosts
+            .iter()
+            .filter_map(|h| f(h).map(|v| (host_label(h), v)))
+            .collect()
+    };
+    let per_container = |f: &dyn Fn(&ContainerInfo) -> f64| -> Vec<(String, f64)> {
+        hosts
+            .iter()
+            .flat_map(|h| h.containers.iter().map(move |c| (h, c)))
+            .map(|(h, c)| (container_label(h, c), f(c)))
+            .collect()
+    };
+
+    metric(
+        "shipit_host_up",
+        "Whether the host could be polled",
+        "gauge",
+        per_host(&|h| Some(if h.error.is_none() { 1.0 } else { 0.0 })),
+    );
+    metric(
+        "shipit_host_disk_used_percent",
+        "Disk use of the filesystem holding deploy_to",
+        "gauge",
+        per_host(&|h| h.disk.as_ref().map(|d| parse_percent(&d.use_percent))),
+    );
+    for (i, period) in ["1", "5", "15"].iter().enumerate() {
+        metric(
+            &format!("shipit_host_load{}", period),
+            &format!("{}-minute load average", period),
+            "gauge",
+            per_host(&|h| h.metrics.as_ref().map(|m| m.load[i])),
+        );
+    }
+    metric(
+        "shipit_host_memory_used_bytes",
+        "Host memory in use (total minus available)",
+        "gauge",
+        per_host(&|h| h.metrics.as_ref().map(|m| (m.mem_used_mb * 1024 * 1024) as f64)),
+    );
+    metric(
+        "shipit_host_memory_total_bytes",
+        "Host memory",
+        "gauge",
+        per_host(&|h| h.metrics.as_ref().map(|m| (m.mem_total_mb * 1024 * 1024) as f64)),
+    );
+    metric(
+        "shipit_host_swap_used_bytes",
+        "Host swap in use",
+        "gauge",
+        per_host(&|h| h.metrics.as_ref().map(|m| (m.swap_used_mb * 1024 * 1024) as f64)),
+    );
+    metric(
+        "shipit_host_cpu_percent",
+        "Host CPU busy percentage since the previous poll",
+        "gauge",
+        per_host(&|h| h.metrics.as_ref().and_then(|m| m.cpu_percent)),
+    );
+    metric(
+        "shipit_host_cpu_steal_percent",
+        "Host CPU steal percentage since the previous poll",
+        "gauge",
+        per_host(&|h| h.metrics.as_ref().and_then(|m| m.steal_percent)),
+    );
+    metric(
+        "shipit_host_network_receive_bytes_total",
+        "Bytes received on physical interfaces",
+        "counter",
+        per_host(&|h| h.counters.as_ref().map(|c| c.rx_bytes as f64)),
+    );
+    metric(
+        "shipit_host_network_transmit_bytes_total",
+        "Bytes sent on physical interfaces",
+        "counter",
+        per_host(&|h| h.counters.as_ref().map(|c| c.tx_bytes as f64)),
+    );
+    metric(
+        "shipit_service_up",
+        "Whether Traefik, the Docker daemon and WireGuard are healthy",
+        "gauge",
+        hosts
+            .iter()
+            .flat_map(|h| {
+                h.services.iter().map(move |svc| {
+                    (
+                        format!("{},service=\"{}\"", host_label(h), label(&svc.name)),
+                        if svc.healthy { 1.0 } else { 0.0 },
+                    )
+                })
+            })
+            .collect(),
+    );
+    metric(
+        "shipit_container_running",
+        "Whether the container is running",
+        "gauge",
+        per_container(&|c| if c.state == "running" { 1.0 } else { 0.0 }),
+    );
+    metric(
+        "shipit_container_cpu_percent",
+        "Container CPU percentage",
+        "gauge",
+        per_container(&|c| parse_percent(&c.cpu_perc)),
+    );
+    metric(
+        "shipit_container_memory_percent",
+        "Container memory percentage of its limit",
+        "gauge",
+        per_container(&|c| parse_percent(&c.mem_perc)),
+    );
+    metric(
+        "shipit_container_restarts",
+        "Container restart count",
+        "gauge",
+        per_container(&|c| c.restarts as f64),
+    );
+
+    out
+}
+
+/// Poll on the interval and serve the latest state over HTTP until Ctrl-C
+async fn serve_http(
+    sessions: &[SshSession],
+    state: &mut AppState,
+    deploy_to: &str,
+    listen: &str,
+    mode: ServeMode,
+) -> Result<()> {
+    poll_all(sessions, state, deploy_to).await;
+    check_alerts(state);
+    let latest = Arc::new(RwLock::new(mode.render(state)));
+
+    let shared = latest.clone();
+    let server = crate::http::serve(listen, move |req| {
+        let shared = shared.clone();
+        async move {
+            if req.method != "GET" {
+                return Response::method_not_allowed();
+            }
+            let body = shared.read().unwrap_or_else(|e| e.into_inner()).clone();
+            mode.respond(&req.path, body)
+        }
+    });
+
+    let poller = async {
+        let mut poll_interval = tokio::time::interval(Duration::from_secs(state.interval));
+        poll_interval.tick().await;
+        loop {
+            poll_interval.tick().await;
+            poll_all(sessions, state, deploy_to).await;
+            let crossed = check_alerts(state);
+            fire_alerts(state, &crossed);
+            let body = mode.render(state);
+            *latest.write().unwrap_or_else(|e| e.into_inner()) = body;
+        }
+    };
+
+    output::success(&format!(
+        "Serving monitor on http://{} (Ctrl-C to stop)",
+        crate::http::listen_addr(listen)
+    ));
+
+    tokio::select! {
+        result = server => result,
+        _ = poller => Ok(()),
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    }
+}
+
 // --- Entry point ---
 
-pub async fn run(config: ShipitConfig, stage_name: &str, interval: u64) -> Result<()> {
+/// Flags of `shipit monitor`
+pub struct MonitorOptions {
+    /// Polling interval in seconds
+    pub interval: u64,
+    /// Poll once and print instead of starting the TUI
+    pub once: bool,
+    /// With `once`, print JSON
+    pub json: bool,
+    /// Serve a web dashboard instead of the TUI
+    pub web: bool,
+    /// Serve Prometheus metrics instead of the TUI
+    pub exporter: bool,
+    /// Address for `web` (default 127.0.0.1:8787) or `exporter` (default 127.0.0.1:9100)
+    pub listen: Option<String>,
+}
+
+pub async fn run(config: ShipitConfig, stage_name: &str, opts: &MonitorOptions) -> Result<()> {
+    let interval = opts.interval;
     let stage = config.stage(stage_name)?;
     let user = stage.user.as_deref().unwrap_or("root");
     let port = stage.port;
@@ -401,7 +1775,7 @@ pub async fn run(config: ShipitConfig, stage_name: &str, interval: u64) -> Resul
     // Connect SSH sessions
     let mut sessions = Vec::new();
     for host in &stage.hosts {
-        let session = SshSession::connect(user, &host.address, port, stage.proxy.as_deref())
+        let session = SshSession::connect(user, &host.address, port, stage.proxy.as_deref(), &stage.ssh)
             .await
             .with_context(|| format!("Failed to connect to {}", host.address))?;
         sessions.push(session);
@@ -413,6 +1787,9 @@ pub async fn run(config: ShipitConfig, stage_name: &str, interval: u64) -> Resul
             .iter()
             .map(|h| HostStatus {
                 address: h.address.clone(),
+                metrics: None,
+                counters: None,
+                services: vec![],
                 containers: vec![],
                 disk: None,
                 error: None,
@@ -422,8 +1799,44 @@ pub async fn run(config: ShipitConfig, stage_name: &str, interval: u64) -> Resul
         stage_name: stage_name.to_string(),
         interval,
         last_update: "...".to_string(),
+        selected: (0, 0),
+        logs: None,
+        details: None,
+        history: HashMap::new(),
+        counters: HashMap::new(),
+        thresholds: config.monitor.clone(),
+        accessories: config
+            .accessories
+            .keys()
+            .map(|name| format!("{}-{}", config.app.name, name))
+            .collect(),
+        filter: Filter::default(),
+        active_alerts: HashSet::new(),
     };
 
+    if opts.once {
+        poll_all(&sessions, &mut state, &deploy_to).await;
+        check_alerts(&mut state);
+        for session in sessions {
+            let _ = session.close().await;
+        }
+        return print_once(&state, opts.json);
+    }
+
+    if opts.web || opts.exporter {
+        let (mode, default_listen) = if opts.web {
+            (ServeMode::Web, DEFAULT_WEB_LISTEN)
+        } else {
+            (ServeMode::Exporter, DEFAULT_EXPORTER_LISTEN)
+        };
+        let listen = opts.listen.as_deref().unwrap_or(default_listen);
+        let result = serve_http(&sessions, &mut state, &deploy_to, listen, mode).await;
+        for session in sessions {
+            let _ = session.close().await;
+        }
+        return result;
+    }
+
     // Setup terminal with panic hook
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
@@ -438,11 +1851,15 @@ pub async fn run(config: ShipitConfig, stage_name: &str, interval: u64) -> Resul
 
     // Initial poll
     poll_all(&sessions, &mut state, &deploy_to).await;
+    let crossed = check_alerts(&mut state);
+    fire_alerts(&state, &crossed);
 
     // Event loop
     let mut event_stream = EventStream::new();
     let mut poll_interval = tokio::time::interval(Duration::from_secs(interval));
     poll_interval.tick().await; // consume the first immediate tick
+    // The log pane refreshes faster than the dashboard so it reads as a live tail
+    let mut logs_interval = tokio::time::interval(Duration::from_secs(1));
 
     loop {
         terminal.draw(|f| ui(f, &state))?;
@@ -450,12 +1867,77 @@ pub async fn run(config: ShipitConfig, stage_name: &str, interval: u64) -> Resul
         tokio::select! {
             _ = poll_interval.tick() => {
                 poll_all(&sessions, &mut state, &deploy_to).await;
+                let crossed = check_alerts(&mut state);
+                fire_alerts(&state, &crossed);
+                state.clamp_selection();
+            }
+            _ = logs_interval.tick(), if state.logs.is_some() => {
+                poll_logs(&sessions, &mut state).await;
             }
             Some(Ok(event)) = event_stream.next() => {
                 if let Event::Key(key) = event {
-                    if key.kind == KeyEventKind::Press {
+                    if key.kind == KeyEventKind::Press && state.filter.searching {
+                        match key.code {
+                            KeyCode::Enter => state.filter.searching = false,
+                            KeyCode::Esc => {
+                                state.filter.searching = false;
+                                state.filter.query.clear();
+                            }
+                            KeyCode::Backspace => {
+                                state.filter.query.pop();
+                            }
+                            KeyCode::Char(c) => state.filter.query.push(c),
+                            _ => {}
+                        }
+                        state.clamp_selection();
+                    } else if key.kind == KeyEventKind::Press {
                         match key.code {
-                            KeyCode::Char('q') | KeyCode::Esc => break,
+                            KeyCode::Char('q') => break,
+                            KeyCode::Esc | KeyCode::Enter if state.details.is_some() => {
+                                state.details = None;
+                            }
+                            KeyCode::PageUp if state.details.is_some() => {
+                                if let Some(details) = state.details.as_mut() {
+                                    details.scroll = details.scroll.saturating_sub(10);
+                                }
+                            }
+                            KeyCode::PageDown if state.details.is_some() => {
+                                if let Some(details) = state.details.as_mut() {
+                                    details.scroll = details.scroll.saturating_add(10);
+                                }
+                            }
+                            KeyCode::Enter => {
+                                inspect_selected(&sessions, &mut state, &deploy_to).await;
+                            }
+                            KeyCode::Esc if state.logs.is_some() => state.logs = None,
+                            KeyCode::Esc => break,
+                            KeyCode::Char('/') => state.filter.searching = true,
+                            KeyCode::Char('u') => {
+                                state.filter.unhealthy_only = !state.filter.unhealthy_only;
+                                state.clamp_selection();
+                            }
+                            KeyCode::Char('a') => {
+                                state.filter.app_only = !state.filter.app_only;
+                                state.clamp_selection();
+                            }
+                            KeyCode::Tab => state.next_host_tab(true),
+                            KeyCode::BackTab => state.next_host_tab(false),
+                            KeyCode::Down | KeyCode::Char('j') => state.move_selection(true),
+                            KeyCode::Up | KeyCode::Char('k') => state.move_selection(false),
+                            KeyCode::Char('l') => {
+                                state.toggle_logs();
+                                poll_logs(&sessions, &mut state).await;
+                            }
+                            KeyCode::PageUp => {
+                                if let Some(pane) = state.logs.as_mut() {
+                                    pane.scroll = (pane.scroll + 10).min(pane.lines.len());
+                                }
+                            }
+                            KeyCode::PageDown => {
+                                if let Some(pane) = state.logs.as_mut() {
+                                    pane.scroll = pane.scroll.saturating_sub(10);
+                                }
+                            }
                             _ => {}
                         }
                     }
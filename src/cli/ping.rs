@@ -0,0 +1,189 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::config::{HostConfig, ShipitConfig, StageConfig};
+use crate::output;
+use crate::ssh::SshSession;
+
+/// One round trip that reports everything setup needs to know up front
+const PROBE_SCRIPT: &str = r#". /etc/os-release 2>/dev/null
+echo "id=$ID"
+echo "version=$VERSION_ID"
+echo "uid=$(id -u)"
+if sudo -n true 2>/dev/null; then echo "sudo=yes"; else echo "sudo=no"; fi
+echo "docker=$(docker --version 2>/dev/null)"
+if docker info >/dev/null 2>&1; then echo "docker_access=yes"; else echo "docker_access=no"; fi"#;
+
+/// What a host reported back
+struct Probe {
+    os_id: String,
+    os_version: String,
+    root: bool,
+    sudo: bool,
+    docker: Option<String>,
+    /// The daemon answers this user (not just the CLI being installed)
+    docker_access: bool,
+    millis: u128,
+}
+
+impl Probe {
+    fn parse(output: &str, millis: u128) -> Self {
+        let field = |key: &str| {
+            output
+                .lines()
+                .find_map(|l| l.strip_prefix(&format!("{}=", key)))
+                .map(|v| v.trim().trim_matches('"').to_string())
+                .unwrap_or_default()
+        };
+        let docker = field("docker");
+        Self {
+            os_id: field("id"),
+            os_version: field("version"),
+            root: field("uid") == "0",
+            sudo: field("sudo") == "yes",
+            docker: (!docker.is_empty()).then_some(docker),
+            docker_access: field("docker_access") == "yes",
+            millis,
+        }
+    }
+
+    fn privileges(&self) -> &'static str {
+        if self.root {
+            "root"
+        } else if self.sudo {
+            "passwordless sudo"
+        } else {
+            "no passwordless sudo"
+        }
+    }
+}
+
+/// Connect to every host of the stage and report SSH auth, OS, sudo and
+/// Docker. When the stage has no `os` yet and all hosts agree, the detected
+/// one is written into shipit.toml.
+pub async fn run(config: ShipitConfig, stage_name: &str, config_path: &Path) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let user = stage.user.as_deref().unwrap_or("deploy");
+
+    output::header(&format!("Checking {} host(s) of {}", stage.hosts.len(), stage_name));
+
+    let results =
+        futures::future::join_all(stage.hosts.iter().map(|host| probe(stage, host, user))).await;
+
+    let mut failed = 0;
+    let mut os_ids: Vec<String> = Vec::new();
+    for (host, result) in stage.hosts.iter().zip(results) {
+        match result {
+            Ok(probe) => {
+                output::success(&format!(
+                    "{}@{} ({} ms)",
+                    user, host.address, probe.millis
+                ));
+                let os = if probe.os_id.is_empty() {
+                    "unknown"
+                } else {
+                    probe.os_id.as_str()
+                };
+                println!("    os: {} {}", os, probe.os_version);
+                println!("    privileges: {}", probe.privileges());
+                println!(
+                    "    docker: {}",
+                    probe.docker.as_deref().unwrap_or("not installed (setup installs it)")
+                );
+                if probe.docker.is_some() && !probe.docker_access {
+                    output::warning(&format!(
+                        "{}: {} cannot reach the Docker daemon (not in the docker group, or the daemon is stopped)",
+                        host.address, user
+                    ));
+                }
+                if !probe.root && !probe.sudo {
+                    output::warning(&format!(
+                        "{}: setup needs root or passwordless sudo for {}",
+                        host.address, user
+                    ));
+                }
+                if crate::os::HostOs::from_config(&probe.os_id).is_err() {
+                    output::warning(&format!(
+                        "{}: OS '{}' is not supported by setup",
+                        host.address, probe.os_id
+                    ));
+                } else if !os_ids.contains(&probe.os_id) {
+                    os_ids.push(probe.os_id);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                output::error(&format!("{}: {:#}", host.address, e));
+            }
+        }
+    }
+
+    if stage.os.is_none() && failed == 0 {
+        match os_ids.as_slice() {
+            [os] => write_stage_os(config_path, stage_name, os)?,
+            [] => {}
+            _ => output::warning(&format!(
+                "Hosts run different OSes ({}); leaving stages.{}.os unset",
+                os_ids.join(", "),
+                stage_name
+            )),
+        }
+    }
+
+    println!();
+    if failed > 0 {
+        bail!("{} of {} host(s) unreachable", failed, stage.hosts.len());
+    }
+    output::success("All hosts reachable");
+    Ok(())
+}
+
+async fn probe(stage: &StageConfig, host: &HostConfig, user: &str) -> Result<Probe> {
+    let started = Instant::now();
+    let session = SshSession::connect(
+        user,
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+    let millis = started.elapsed().as_millis();
+    let output = session.exec(PROBE_SCRIPT).await;
+    session.close().await?;
+    Ok(Probe::parse(&output?, millis))
+}
+
+/// Insert `os = "<id>"` right below the `[stages.<name>]` header, leaving the
+/// rest of the file untouched
+fn write_stage_os(config_path: &Path, stage_name: &str, os: &str) -> Result<()> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let header = format!("[stages.{}]", stage_name);
+
+    let mut lines: Vec<&str> = content.lines().collect();
+    let Some(index) = lines.iter().position(|l| l.trim() == header) else {
+        output::info(&format!(
+            "Detected OS: {}. Add `os = \"{}\"` to stages.{} to skip detection",
+            os, os, stage_name
+        ));
+        return Ok(());
+    };
+    let line = format!("os = \"{}\"", os);
+    lines.insert(index + 1, &line);
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    std::fs::write(config_path, updated)
+        .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    output::info(&format!(
+        "Wrote os = \"{}\" to stages.{} in {}",
+        os,
+        stage_name,
+        config_path.display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::release::lock::ShipitLock;
+use crate::ssh::SshSession;
+
+/// Deploy to `to` the commit that is live on `from`. With local and registry
+/// builds the images `from` runs are retagged instead of rebuilt, so both
+/// stages run the same image IDs.
+pub async fn run(
+    from_config: ShipitConfig,
+    from: &str,
+    to_config: ShipitConfig,
+    to: &str,
+    project_root: PathBuf,
+) -> Result<()> {
+    if from == to {
+        bail!("--from and --to are the same stage");
+    }
+    let (release, sha) = live_release(&from_config, from).await?;
+
+    let exists = Command::new("git")
+        .args(["cat-file", "-e", &format!("{}^{{commit}}", sha)])
+        .current_dir(&project_root)
+        .status()
+        .context("Failed to run git cat-file")?;
+    if !exists.success() {
+        bail!(
+            "Commit {} (live on {}) is not in the local repository; fetch it first",
+            sha,
+            from
+        );
+    }
+
+    let images_from = match to_config.deploy.build.as_str() {
+        "local" => local_images_of(&to_config.app.name, &release, &sha, &project_root)?,
+        // Pulled from the registry and retagged
+        "registry" => Some(release.clone()),
+        _ => None,
+    };
+
+    output::header(&format!(
+        "Promoting {} ({}) from {} to {}",
+        &sha[..7.min(sha.len())],
+        release,
+        from,
+        to
+    ));
+    super::deploy::promote(to_config, to, project_root, sha, images_from).await
+}
+
+/// The release and commit every host of the stage runs
+async fn live_release(config: &ShipitConfig, stage_name: &str) -> Result<(String, String)> {
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+    if stage.hosts.is_empty() {
+        bail!("No hosts configured for stage '{}'", stage_name);
+    }
+
+    let mut live: Option<(String, String)> = None;
+    for host in &stage.hosts {
+        let session = SshSession::connect(
+            stage.user.as_deref().unwrap_or("deploy"),
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+        let lock = ShipitLock::read(&session, &app_path).await;
+        session.close().await?;
+        let lock = lock?
+            .with_context(|| format!("Nothing deployed on {} ({})", host.address, stage_name))?;
+
+        let current = (lock.current_release, lock.git_sha);
+        match &live {
+            None => live = Some(current),
+            Some(first) if first.1 != current.1 => bail!(
+                "Hosts of {} run different commits ({} and {}); run `shipit verify -s {}` first",
+                stage_name,
+                &first.1[..7.min(first.1.len())],
+                &current.1[..7.min(current.1.len())],
+                stage_name
+            ),
+            Some(_) => {}
+        }
+    }
+    live.context("No hosts to read the live release from")
+}
+
+/// For local builds: the release whose images to retag, when this machine
+/// still has them. Without them the commit is only rebuilt when it is HEAD,
+/// since local builds use the working tree.
+fn local_images_of(
+    app_name: &str,
+    release: &str,
+    sha: &str,
+    project_root: &Path,
+) -> Result<Option<String>> {
+    let output = Command::new("docker")
+        .args([
+            "images",
+            "-q",
+            "--filter",
+            &format!("reference={}-*:{}", app_name, release),
+        ])
+        .output()
+        .context("Failed to run docker images")?;
+    if output.status.success() && !String::from_utf8_lossy(&output.stdout).trim().is_empty() {
+        return Ok(Some(release.to_string()));
+    }
+
+    if crate::deploy::context::head_sha(project_root).as_deref() != Some(sha) {
+        bail!(
+            "The images of release {} are not in the local Docker and HEAD is not {}; check out {} to rebuild it",
+            release,
+            &sha[..7.min(sha.len())],
+            sha
+        );
+    }
+    output::warning(&format!(
+        "The images of release {} are not in the local Docker; rebuilding {} from the working tree",
+        release,
+        &sha[..7.min(sha.len())]
+    ));
+    Ok(None)
+}
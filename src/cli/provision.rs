@@ -0,0 +1,273 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::provision::{self, ec2, hetzner};
+
+/// How long a new server may take to boot and start sshd
+const BOOT_TIMEOUT: Duration = Duration::from_secs(300);
+
+pub struct HetznerOptions {
+    pub count: usize,
+    pub server_type: String,
+    pub image: String,
+    pub location: Option<String>,
+    /// Stage secret holding the Hetzner Cloud API token
+    pub token_secret: String,
+    /// Run `shipit setup --bootstrap` on the stage afterwards
+    pub setup: bool,
+}
+
+pub struct Ec2Options {
+    pub count: usize,
+    pub instance_type: String,
+    /// Defaults to the region's current Ubuntu LTS
+    pub ami: Option<String>,
+    pub region: Option<String>,
+    pub subnet_id: Option<String>,
+    pub vpc_id: Option<String>,
+    /// Login user of the AMI, used to bootstrap the stage user
+    pub admin_user: String,
+    pub setup: bool,
+}
+
+/// Create servers in Hetzner Cloud, wait for them to boot and add them to the stage
+pub async fn hetzner(
+    config_path: &Path,
+    project_root: &Path,
+    stage_name: &str,
+    opts: &HetznerOptions,
+) -> Result<()> {
+    // The stage may not exist yet (or have no hosts), so skip validation
+    let config = ShipitConfig::parse(config_path)?;
+    check_stage_name(stage_name)?;
+
+    let token = provision::stage_secret(&config, project_root, stage_name, &opts.token_secret)?;
+    let public_key = crate::ssh::local_public_key()?.context(
+        "No SSH public key found (~/.ssh/id_ed25519.pub or id_rsa.pub). Generate one with: ssh-keygen",
+    )?;
+    let api = hetzner::Hetzner::new(token);
+
+    output::header(&format!(
+        "Provisioning {} Hetzner server(s) ({}) for {}",
+        opts.count, opts.server_type, stage_name
+    ));
+
+    let key_id = api.ensure_ssh_key(
+        &public_key,
+        &format!(
+            "shipit-{}-{}",
+            config.app.name,
+            chrono::Utc::now().timestamp()
+        ),
+    )?;
+
+    let mut addresses = existing_addresses(&config, stage_name);
+    let prefix = format!("{}-{}", config.app.name, stage_name).replace('_', "-");
+    let mut created = Vec::new();
+    let mut failure = None;
+    for i in 0..opts.count {
+        let spec = hetzner::ServerSpec {
+            name: format!("{}-{}", prefix, addresses.len() + created.len() + 1),
+            server_type: &opts.server_type,
+            image: &opts.image,
+            location: opts.location.as_deref(),
+            ssh_key_id: key_id,
+            labels: vec![
+                ("app".to_string(), config.app.name.clone()),
+                ("stage".to_string(), stage_name.to_string()),
+            ],
+        };
+        output::step(i + 1, opts.count, &format!("Creating {}", spec.name));
+        match api.create_server(&spec) {
+            Ok(server) => created.push(server),
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+    }
+
+    for server in &created {
+        let spinner = output::create_spinner(&format!("Waiting for {} to boot ...", server.name));
+        let booted = match api.wait_running(server.id, BOOT_TIMEOUT).await {
+            Ok(running) => provision::wait_for_ssh(&running.address, 22, BOOT_TIMEOUT)
+                .await
+                .map(|_| running),
+            Err(e) => Err(e),
+        };
+        spinner.finish_and_clear();
+        match booted {
+            Ok(running) => {
+                output::success(&format!("{} is up at {}", running.name, running.address));
+                addresses.push(running.address);
+            }
+            Err(e) => {
+                output::error(&format!("{}: {:#}", server.name, e));
+                failure.get_or_insert(e);
+            }
+        }
+    }
+
+    finish(
+        config_path,
+        project_root,
+        stage_name,
+        &addresses,
+        failure,
+        opts.setup,
+        "root",
+    )
+    .await
+}
+
+/// Record the new hosts, then set them up or say how to. Hosts that came up
+/// are recorded even if others failed, so they aren't orphaned.
+async fn finish(
+    config_path: &Path,
+    project_root: &Path,
+    stage_name: &str,
+    addresses: &[String],
+    failure: Option<anyhow::Error>,
+    setup: bool,
+    root_user: &str,
+) -> Result<()> {
+    let config = ShipitConfig::parse(config_path)?;
+    let discovered = config
+        .stages
+        .get(stage_name)
+        .is_some_and(|stage| stage.discovery.is_some());
+    if discovered {
+        output::info(&format!(
+            "stages.{} uses discovery; the new servers are found through their labels/tags",
+            stage_name
+        ));
+    } else if addresses.len() > existing_addresses(&config, stage_name).len() {
+        provision::write_stage_hosts(config_path, stage_name, addresses)?;
+    }
+    if let Some(e) = failure {
+        return Err(e).context("Provisioning did not complete");
+    }
+
+    if setup {
+        let config = super::load_for_stage(config_path, stage_name, project_root)?;
+        let setup_opts = super::setup::SetupOptions {
+            bootstrap_as: Some(root_user.to_string()),
+            only: Vec::new(),
+            skip: Vec::new(),
+        };
+        super::setup::run(config, stage_name, &setup_opts).await?;
+    } else {
+        println!();
+        output::info(&format!(
+            "Next: shipit setup -s {} --bootstrap --root-user {}",
+            stage_name, root_user
+        ));
+    }
+    Ok(())
+}
+
+/// Launch EC2 instances (via the aws CLI), wait for them to boot and add them to the stage
+pub async fn ec2(
+    config_path: &Path,
+    project_root: &Path,
+    stage_name: &str,
+    opts: &Ec2Options,
+) -> Result<()> {
+    let config = ShipitConfig::parse(config_path)?;
+    check_stage_name(stage_name)?;
+
+    let public_key = crate::ssh::local_public_key()?.context(
+        "No SSH public key found (~/.ssh/id_ed25519.pub or id_rsa.pub). Generate one with: ssh-keygen",
+    )?;
+    let api = ec2::Ec2::new(opts.region.clone());
+    let name = format!("shipit-{}-{}", config.app.name, stage_name);
+
+    output::header(&format!(
+        "Provisioning {} EC2 instance(s) ({}) for {}",
+        opts.count, opts.instance_type, stage_name
+    ));
+
+    output::step(1, 4, "Preparing key pair and security group");
+    let ami = match &opts.ami {
+        Some(ami) => ami.clone(),
+        None => api.default_ami()?,
+    };
+    let key_name = format!("shipit-{}", config.app.name);
+    api.ensure_key_pair(&key_name, &public_key)?;
+    let security_group_id =
+        api.ensure_security_group(&name, opts.vpc_id.as_deref(), crate::wireguard::WG_PORT)?;
+
+    output::step(
+        2,
+        4,
+        &format!("Launching {} instance(s) from {}", opts.count, ami),
+    );
+    let ids = api.run_instances(&ec2::InstanceSpec {
+        count: opts.count,
+        instance_type: &opts.instance_type,
+        ami: &ami,
+        key_name: &key_name,
+        security_group_id: &security_group_id,
+        subnet_id: opts.subnet_id.as_deref(),
+        tags: vec![
+            ("Name".to_string(), name.clone()),
+            ("app".to_string(), config.app.name.clone()),
+            ("stage".to_string(), stage_name.to_string()),
+        ],
+    })?;
+
+    output::step(3, 4, "Waiting for instances to run");
+    let instances = api.wait_running(&ids)?;
+
+    output::step(4, 4, "Waiting for SSH");
+    let mut addresses = existing_addresses(&config, stage_name);
+    let mut failure = None;
+    for instance in instances {
+        match provision::wait_for_ssh(&instance.address, 22, BOOT_TIMEOUT).await {
+            Ok(()) => {
+                output::success(&format!("{} is up at {}", instance.id, instance.address));
+                addresses.push(instance.address);
+            }
+            Err(e) => {
+                output::error(&format!("{}: {:#}", instance.id, e));
+                failure.get_or_insert(e);
+            }
+        }
+    }
+
+    finish(
+        config_path,
+        project_root,
+        stage_name,
+        &addresses,
+        failure,
+        opts.setup,
+        &opts.admin_user,
+    )
+    .await
+}
+
+fn existing_addresses(config: &ShipitConfig, stage_name: &str) -> Vec<String> {
+    config
+        .stages
+        .get(stage_name)
+        .map(|stage| stage.hosts.iter().map(|h| h.address.clone()).collect())
+        .unwrap_or_default()
+}
+
+fn check_stage_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "Invalid stage name '{}' (use letters, digits, '-' and '_')",
+            name
+        );
+    }
+    Ok(())
+}
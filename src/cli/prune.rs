@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::release;
+use crate::ssh::SshSession;
+
+/// Remove old releases and stopped app containers (plus, with `images`,
+/// dangling images and images of releases that are gone) on every host of
+/// the stage, outside of a deploy
+pub async fn run(
+    config: ShipitConfig,
+    stage_name: &str,
+    keep: Option<usize>,
+    images: bool,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+    // An explicit --keep is taken literally; otherwise keep_releases_days applies too
+    let (keep, keep_days) = match keep {
+        Some(keep) => (keep, None),
+        None => (
+            config.deploy.keep_releases,
+            config.deploy.keep_releases_days,
+        ),
+    };
+    // Local and registry builds tag their images themselves (compose sees `image:`)
+    let rmi = if config.deploy.build == "remote" {
+        "local"
+    } else {
+        "all"
+    };
+
+    output::header(&format!(
+        "Pruning {} on {} (keeping {} release(s))",
+        config.app.name, stage_name, keep
+    ));
+
+    let mut total = 0;
+    for host in &stage.hosts {
+        output::info(&format!("Host: {}", host.address));
+        let session = SshSession::connect(
+            stage.user.as_deref().unwrap_or("deploy"),
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+        let result = prune_host(&session, &config, &app_path, keep, keep_days, rmi, images).await;
+        session.close().await?;
+        let reclaimed = result.with_context(|| format!("Prune failed on {}", host.address))?;
+        output::success(&format!(
+            "{}: {} reclaimed",
+            host.address,
+            HumanBytes(reclaimed)
+        ));
+        total += reclaimed;
+    }
+
+    output::done(&format!(
+        "Reclaimed {} on {} host(s)",
+        HumanBytes(total),
+        stage.hosts.len()
+    ));
+    Ok(())
+}
+
+/// Prune one host, returning the bytes freed
+async fn prune_host(
+    session: &SshSession,
+    config: &ShipitConfig,
+    app_path: &str,
+    keep: usize,
+    keep_days: Option<u64>,
+    rmi: &str,
+    images: bool,
+) -> Result<u64> {
+    let before = free_bytes(session, app_path).await?;
+
+    let removed = release::remove_old(session, app_path, keep, keep_days, rmi).await?;
+    output::success(&format!("Removed {} old release(s)", removed.len()));
+
+    // Compose labels each container with the release directory it came from
+    let stopped = session
+        .query(&format!(
+            "docker ps -a --filter status=exited --filter status=created --filter status=dead \
+             --format '{{{{.ID}}}} {{{{.Label \"com.docker.compose.project.working_dir\"}}}}' \
+             | awk 'index($2, \"{}/releases/\") == 1 {{ print $1 }}'",
+            app_path
+        ))
+        .await
+        .context("Failed to list stopped containers")?;
+    let stopped: Vec<&str> = stopped.split_whitespace().collect();
+    if !stopped.is_empty() {
+        session
+            .exec(&format!("docker rm {}", stopped.join(" ")))
+            .await
+            .context("Failed to remove stopped containers")?;
+    }
+    output::success(&format!("Removed {} stopped container(s)", stopped.len()));
+
+    if images {
+        prune_images(session, config, app_path).await?;
+    }
+
+    let after = free_bytes(session, app_path).await?;
+    Ok(after.saturating_sub(before))
+}
+
+/// Remove dangling images and `<app>-<service>:<release>` images whose
+/// release directory is gone. Images still in use are left alone.
+async fn prune_images(session: &SshSession, config: &ShipitConfig, app_path: &str) -> Result<()> {
+    let kept = session
+        .query(&format!("ls -1 {}/releases 2>/dev/null || true", app_path))
+        .await?;
+    let kept: Vec<&str> = kept.lines().map(str::trim).collect();
+
+    let listing = session
+        .query("docker images --format '{{.Repository}}:{{.Tag}}'")
+        .await
+        .context("Failed to list images")?;
+    // Exact `<app>-<service>` names, so another app named `<app>-something`
+    // on the same host keeps its images
+    let services = session
+        .query(&format!(
+            "cd \"$(readlink -f {}/current)\" 2>/dev/null && docker compose config --services 2>/dev/null || true",
+            app_path
+        ))
+        .await?;
+    let names: Vec<String> = services
+        .lines()
+        .map(|service| format!("{}-{}", config.app.name, service.trim()))
+        .collect();
+    let stale: Vec<&str> = listing
+        .lines()
+        .map(str::trim)
+        .filter(|image| {
+            let Some((repository, tag)) = image.rsplit_once(':') else {
+                return false;
+            };
+            // Registry builds qualify the name with the registry repository
+            let name = repository.rsplit('/').next().unwrap_or(repository);
+            names.iter().any(|n| n == name) && !kept.contains(&tag)
+        })
+        .collect();
+    for image in &stale {
+        let _ = session.exec(&format!("docker rmi {}", image)).await;
+    }
+
+    session
+        .exec("docker image prune -f")
+        .await
+        .context("Failed to prune dangling images")?;
+    output::success(&format!(
+        "Removed dangling images and {} image(s) of removed releases",
+        stale.len()
+    ));
+    Ok(())
+}
+
+/// Free bytes on the filesystems holding Docker's data and the app, each
+/// counted once
+async fn free_bytes(session: &SshSession, app_path: &str) -> Result<u64> {
+    let out = session
+        .query(&format!(
+            "df -Pk \"$(docker info -f '{{{{.DockerRootDir}}}}')\" {} \
+             | awk 'NR > 1 && !seen[$1]++ {{ sum += $4 }} END {{ print sum }}'",
+            app_path
+        ))
+        .await
+        .context("Failed to read free disk space")?;
+    Ok(out.trim().parse::<u64>().unwrap_or_default() * 1024)
+}
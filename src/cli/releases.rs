@@ -18,7 +18,7 @@ pub async fn run(config: ShipitConfig, stage_name: &str) -> Result<()> {
     for host in &stage.hosts {
         output::info(&format!("Host: {}", host.address));
 
-        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
 
         let releases_dir = format!("{}/releases", app_path);
 
@@ -28,25 +28,33 @@ pub async fn run(config: ShipitConfig, stage_name: &str) -> Result<()> {
             continue;
         }
 
-        let output_str = session
-            .exec(&format!("ls -1 {} 2>/dev/null | sort -r", releases_dir))
-            .await?;
+        let releases = ShipitLock::read_release_records(&session, &app_path).await?;
 
         let lock = ShipitLock::read(&session, &app_path).await?;
         let current = lock.as_ref().map(|l| l.current_release.as_str());
 
-        if output_str.trim().is_empty() {
+        if releases.is_empty() {
             output::warning("No releases found");
         } else {
-            for line in output_str.lines() {
-                let line = line.trim();
-                if line.is_empty() {
-                    continue;
-                }
-                if Some(line) == current {
-                    println!("  {} ← current", line);
+            for (name, record) in &releases {
+                let marker = if Some(name.as_str()) == current {
+                    " ← current"
                 } else {
-                    println!("  {}", line);
+                    ""
+                };
+                match record {
+                    Some(record) => println!(
+                        "  {}  {}  {}{}",
+                        name,
+                        &record.git_sha[..7.min(record.git_sha.len())],
+                        record
+                            .deployed_by
+                            .as_ref()
+                            .map(|d| d.to_string())
+                            .unwrap_or_default(),
+                        marker
+                    ),
+                    None => println!("  {}{}", name, marker),
                 }
             }
         }
@@ -1,29 +1,156 @@
 use anyhow::{bail, Context, Result};
+use std::path::Path;
 use tracing::debug;
 
-use crate::config::ShipitConfig;
+use crate::config::{DeployConfig, HostConfig, ShipitConfig};
 use crate::output;
 use crate::release::lock::ShipitLock;
 use crate::ssh::SshSession;
 
+/// Which release a rollback switches to
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    /// `previous_release` from shipit.lock
+    Previous,
+    Release(&'a str),
+    /// The release that was live before the last rollback
+    Undo,
+}
+
+/// Roll back to `release_name`. Without one, a terminal gets a picker with
+/// the previous release preselected; scripts and CI get the previous release.
 pub async fn run(
     config: ShipitConfig,
     stage_name: &str,
+    project_root: &Path,
     release_name: Option<&str>,
+    list: bool,
 ) -> Result<()> {
+    let picked;
+    let target = match release_name {
+        Some(name) => Target::Release(name),
+        None if list || console::user_attended() => {
+            picked = pick_release(&config, stage_name).await?;
+            Target::Release(&picked)
+        }
+        None => Target::Previous,
+    };
+    switch(config, stage_name, project_root, target).await
+}
+
+/// Choose among the releases on the stage's first host, newest first, each
+/// with its git SHA and who deployed it when
+async fn pick_release(config: &ShipitConfig, stage_name: &str) -> Result<String> {
+    if !console::user_attended() {
+        bail!("Picking a release needs an interactive terminal; pass --release instead");
+    }
+    let stage = config.stage(stage_name)?;
+    let host = stage
+        .hosts
+        .first()
+        .with_context(|| format!("No hosts configured for stage '{}'", stage_name))?;
+    let app_path = config.app_path();
+
+    let session = SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+    let lock = ShipitLock::read(&session, &app_path).await;
+    let releases = ShipitLock::read_release_records(&session, &app_path).await;
+    session.close().await?;
+
+    let lock = lock?.context("No shipit.lock found — has a deploy been done?")?;
+    let releases: Vec<(String, Option<ShipitLock>)> = releases?
+        .into_iter()
+        .filter(|(name, _)| *name != lock.current_release)
+        .collect();
+    if releases.is_empty() {
+        bail!("No other release on {} to roll back to", host.address);
+    }
+
+    let items: Vec<String> = releases
+        .iter()
+        .map(|(name, record)| match record {
+            Some(record) => format!(
+                "{}  {}  {}",
+                name,
+                &record.git_sha[..7.min(record.git_sha.len())],
+                record.origin()
+            ),
+            None => name.clone(),
+        })
+        .collect();
+    let default = releases
+        .iter()
+        .position(|(name, _)| lock.previous_release.as_ref() == Some(name))
+        .unwrap_or(0);
+    let choice = dialoguer::Select::new()
+        .with_prompt(format!(
+            "Roll {} on {} back to (live: {})",
+            config.app.name, stage_name, lock.current_release
+        ))
+        .items(&items)
+        .default(default)
+        .interact()?;
+
+    Ok(releases
+        .into_iter()
+        .nth(choice)
+        .map(|(name, _)| name)
+        .unwrap_or_default())
+}
+
+/// Undo the last rollback: switch back to the release it replaced
+pub async fn rollforward(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: &Path,
+) -> Result<()> {
+    switch(config, stage_name, project_root, Target::Undo).await
+}
+
+async fn switch(
+    config: ShipitConfig,
+    stage_name: &str,
+    project_root: &Path,
+    target: Target<'_>,
+) -> Result<()> {
+    let started = std::time::Instant::now();
+    let result = rollback(&config, stage_name, target).await;
+    crate::notify::rollback_finished(
+        &config,
+        project_root,
+        stage_name,
+        &result,
+        started.elapsed(),
+    )
+    .await;
+    result.map(|_| ())
+}
+
+/// Roll every host back, returning the release that is live afterwards
+async fn rollback(config: &ShipitConfig, stage_name: &str, target: Target<'_>) -> Result<String> {
     let stage = config.stage(stage_name)?;
     let user = stage.user.as_deref().unwrap_or("deploy");
     let app_path = config.app_path();
+    let undo = matches!(target, Target::Undo);
+    let verb = if undo {
+        "Rolling forward"
+    } else {
+        "Rolling back"
+    };
 
-    output::header(&format!(
-        "Rolling back {} on {}",
-        config.app.name, stage_name
-    ));
+    output::header(&format!("{} {} on {}", verb, config.app.name, stage_name));
 
+    let mut live = String::new();
     for host in &stage.hosts {
-        output::info(&format!("Rolling back on {}", host.address));
+        output::info(&format!("{} on {}", verb, host.address));
 
-        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
 
         // Read current lock
         let lock = ShipitLock::read(&session, &app_path)
@@ -31,12 +158,19 @@ pub async fn run(
             .context("No shipit.lock found — has a deploy been done?")?;
 
         // Determine target release
-        let target = match release_name {
-            Some(name) => name.to_string(),
-            None => match &lock.previous_release {
+        let target = match target {
+            Target::Release(name) => name.to_string(),
+            Target::Previous => match &lock.previous_release {
                 Some(prev) => prev.clone(),
                 None => bail!("No previous release found to rollback to"),
             },
+            Target::Undo => match &lock.rolled_back_from {
+                Some(release) => release.clone(),
+                None => bail!(
+                    "The last change on {} was not a rollback; nothing to roll forward to",
+                    host.address
+                ),
+            },
         };
 
         let target_path = format!("{}/releases/{}", app_path, target);
@@ -46,6 +180,27 @@ pub async fn run(
         if !session.path_exists(&target_path).await? {
             bail!("Release directory not found: {}", target_path);
         }
+        output::info(&format!(
+            "Live: {} (deployed by {})",
+            lock.current_release,
+            lock.origin()
+        ));
+        let target_record = ShipitLock::read_release_records(&session, &app_path)
+            .await?
+            .into_iter()
+            .find(|(name, _)| *name == target)
+            .and_then(|(_, record)| record);
+        match &target_record {
+            Some(record) => output::info(&format!(
+                "Target: {} (deployed by {})",
+                target,
+                record.origin()
+            )),
+            None => output::warning(&format!(
+                "{} has no release record; the lock will not name its commit",
+                target
+            )),
+        }
 
         // Stop current
         output::step(1, 5, "Stopping current release");
@@ -67,82 +222,152 @@ pub async fn run(
 
         // Health check (same approach as deploy: poll Docker health status)
         output::step(3, 5, "Running health check");
-        let hc = &config.deploy.health_check;
-        let web_service = config
-            .deploy
-            .web_service
-            .as_deref()
-            .unwrap_or("web");
-
-        let container_id = session
-            .exec(&format!(
-                "cd {} && docker compose ps -q {}",
-                target_path, web_service
-            ))
-            .await
-            .context("Failed to get container ID for health check")?
-            .trim()
-            .to_string();
-
-        let spinner = output::create_spinner(&format!(
-            "Waiting for container {} to become healthy ...",
-            &container_id[..12.min(container_id.len())]
-        ));
-
-        let mut healthy = false;
-        for attempt in 1..=hc.retries {
-            debug!("Health check attempt {}/{}", attempt, hc.retries);
-
-            let status = session
-                .exec(&format!(
-                    "docker inspect --format='{{{{.State.Health.Status}}}}' {}",
-                    container_id
-                ))
-                .await
-                .unwrap_or_default()
-                .trim()
-                .to_string();
-
-            match status.as_str() {
-                "healthy" => {
-                    healthy = true;
-                    break;
-                }
-                "unhealthy" => {
-                    break;
-                }
-                _ => {
-                    debug!("Container status: {} (attempt {}/{})", status, attempt, hc.retries);
-                }
+        match checked_service(&config.deploy, host) {
+            Some(web_service) => {
+                wait_healthy(&session, config, &target_path, web_service)
+                    .await
+                    .with_context(|| format!("Health check failed after rollback to {}", target))?;
+                output::success("Health check passed");
             }
-
-            tokio::time::sleep(std::time::Duration::from_secs(hc.interval)).await;
+            // Hosts whose roles leave out the web service run it scaled to 0
+            None => output::info(&format!(
+                "{} does not run the web service (roles: {})",
+                host.address,
+                host.role.join(", ")
+            )),
         }
 
-        spinner.finish_and_clear();
-
-        if !healthy {
-            bail!("Health check failed after rollback to {}", target);
-        }
-        output::success("Health check passed");
-
         // Update symlink
         output::step(4, 5, "Updating symlink");
         session.atomic_symlink(&target_path, &current_path).await?;
 
         // Update lock
         output::step(5, 5, "Updating lock file");
-        let new_lock = ShipitLock::new(
+        // The SHA is the target's own, not that of the release being left
+        let mut new_lock = ShipitLock::new(
             target.clone(),
             Some(lock.current_release.clone()),
-            lock.git_sha.clone(),
+            target_record.map(|r| r.git_sha).unwrap_or_default(),
             lock.secrets_hash.clone(),
         );
+        if !undo {
+            new_lock.rolled_back_from = Some(lock.current_release.clone());
+        }
         new_lock.write(&session, &app_path).await?;
 
         session.close().await?;
-        output::success(&format!("Rolled back to {}", target));
+        if undo {
+            output::done(&format!("Rolled forward to {}", target));
+        } else {
+            output::done(&format!("Rolled back to {}", target));
+        }
+        live = target;
+    }
+
+    Ok(live)
+}
+
+/// The service whose health gates the switch on `host`: the web service,
+/// unless the host's roles leave it out
+fn checked_service<'a>(deploy: &'a DeployConfig, host: &HostConfig) -> Option<&'a str> {
+    let web_service = deploy.web_service.as_deref().unwrap_or("web");
+    deploy.runs_on(web_service, host).then_some(web_service)
+}
+
+/// Poll the target release's web container until Docker reports it healthy
+async fn wait_healthy(
+    session: &SshSession,
+    config: &ShipitConfig,
+    target_path: &str,
+    web_service: &str,
+) -> Result<()> {
+    let hc = &config.deploy.health_check;
+    let container_id = session
+        .exec(&format!(
+            "cd {} && docker compose ps -q {}",
+            target_path, web_service
+        ))
+        .await
+        .context("Failed to get container ID for health check")?
+        .trim()
+        .to_string();
+
+    let spinner = output::create_spinner(&format!(
+        "Waiting for container {} to become healthy ...",
+        &container_id[..12.min(container_id.len())]
+    ));
+
+    let mut healthy = false;
+    for attempt in 1..=hc.retries {
+        debug!("Health check attempt {}/{}", attempt, hc.retries);
+
+        let status = session
+            .exec(&format!(
+                "docker inspect --format='{{{{.State.Health.Status}}}}' {}",
+                container_id
+            ))
+            .await
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        match status.as_str() {
+            "healthy" => {
+                healthy = true;
+                break;
+            }
+            "unhealthy" => {
+                break;
+            }
+            _ => {
+                debug!(
+                    "Container status: {} (attempt {}/{})",
+                    status, attempt, hc.retries
+                );
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(hc.interval)).await;
     }
 
+    spinner.finish_and_clear();
+
+    if !healthy {
+        bail!("{} did not become healthy", web_service);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::checked_service;
+    use crate::config::{DeployConfig, HostConfig};
+
+    fn host(roles: &[&str]) -> HostConfig {
+        HostConfig {
+            address: "10.0.0.1".to_string(),
+            role: roles.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn skips_the_health_check_on_worker_only_hosts() {
+        let deploy: DeployConfig = toml::from_str(
+            r#"
+            web_service = "app"
+
+            [services.app]
+            roles = ["web"]
+
+            [services.worker]
+            roles = ["worker"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(checked_service(&deploy, &host(&["web"])), Some("app"));
+        assert_eq!(checked_service(&deploy, &host(&["worker"])), None);
+        // Hosts without roles run everything
+        assert_eq!(checked_service(&deploy, &host(&[])), Some("app"));
+    }
+}
@@ -1,10 +1,12 @@
 use anyhow::{bail, Context, Result};
 
 use crate::config::ShipitConfig;
-use crate::output;
 use crate::ssh::SshSession;
 
-pub async fn run(config: ShipitConfig, stage_name: &str, cmd: &[String]) -> Result<()> {
+/// Run a one-off command in the web container of the current release.
+/// Output is streamed as it arrives (or, with `tty`, the command gets the
+/// local terminal) and the command's exit code becomes shipit's.
+pub async fn run(config: ShipitConfig, stage_name: &str, cmd: &[String], tty: bool) -> Result<()> {
     if cmd.is_empty() {
         bail!("No command specified");
     }
@@ -15,28 +17,53 @@ pub async fn run(config: ShipitConfig, stage_name: &str, cmd: &[String]) -> Resu
 
     let web_service = config.deploy.web_service.as_deref().unwrap_or("web");
 
-    let host = &stage.hosts[0];
-    let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+    // With host roles, only some hosts run the web service
+    let host = stage
+        .hosts
+        .iter()
+        .find(|h| config.deploy.runs_on(web_service, h))
+        .with_context(|| format!("No host of stage '{}' runs {}", stage_name, web_service))?;
+    let session = SshSession::connect(
+        user,
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?
+    // The command runs as long as it needs, like it would in a shell
+    .without_command_timeout();
 
     if !session.path_exists(&current_path).await? {
-        output::error("No current release found. Deploy first.");
         session.close().await?;
-        return Ok(());
+        bail!("No current release found. Deploy first.");
     }
 
     let command_str = cmd.join(" ");
+    // Without a terminal, compose must not allocate a TTY either
     let remote_cmd = format!(
-        "cd $(readlink -f {}) && docker compose exec {} {}",
-        current_path, web_service, command_str
+        "cd $(readlink -f {}) && docker compose exec {}{} {}",
+        current_path,
+        if tty { "" } else { "-T " },
+        web_service,
+        command_str
     );
 
-    let result = session
-        .exec(&remote_cmd)
-        .await
-        .context("Failed to run command")?;
-
-    print!("{}", result);
-
+    let status = if tty {
+        session.exec_interactive(&remote_cmd).await
+    } else {
+        session
+            .exec_streamed_status(&remote_cmd, |line| println!("{}", line))
+            .await
+    };
     session.close().await?;
+
+    let status = status.context("Failed to run command")?;
+    if !status.success() {
+        match status.code() {
+            Some(code) => std::process::exit(code),
+            None => bail!("'{}' was killed by a signal", command_str),
+        }
+    }
     Ok(())
 }
@@ -142,6 +142,38 @@ pub fn edit(config: &ShipitConfig, stage: &str, project_root: &Path) -> Result<(
     Ok(())
 }
 
+/// Run a local command with the decrypted secrets added to its environment.
+/// The plaintext only ever lives in this process and the child's env; the
+/// command's exit code becomes shipit's.
+pub fn run(config: &ShipitConfig, stage: &str, cmd: &[String], project_root: &Path) -> Result<()> {
+    let (program, args) = cmd.split_first().context("No command specified")?;
+
+    let identity = key::load_identity(&config.app.name)?;
+    if !store::secrets_path(project_root, stage).exists() {
+        bail!(
+            "No secrets for stage '{}'. Add some with `shipit secrets set -s {} KEY=VALUE`.",
+            stage,
+            stage
+        );
+    }
+    let secrets = store::read_secrets(project_root, stage, &identity)?;
+
+    let status = std::process::Command::new(program)
+        .args(args)
+        .envs(&secrets)
+        .env("SHIPIT_STAGE", stage)
+        .status()
+        .with_context(|| format!("Failed to run '{}'", program))?;
+
+    if !status.success() {
+        match status.code() {
+            Some(code) => std::process::exit(code),
+            None => bail!("'{}' was killed by a signal", program),
+        }
+    }
+    Ok(())
+}
+
 /// Mask a value: show first 4 chars + "****"
 fn mask_value(value: &str) -> String {
     if value.len() <= 4 {
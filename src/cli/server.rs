@@ -0,0 +1,476 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc;
+
+use crate::config::ShipitConfig;
+use crate::http::{Request, Response};
+use crate::output;
+use crate::release::lock::ShipitLock;
+use crate::ssh::SshSession;
+
+/// Bearer token API clients must send
+const TOKEN_ENV: &str = "SHIPIT_SERVER_TOKEN";
+/// Finished jobs kept for `/api/jobs`
+const KEEP_JOBS: usize = 50;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A deploy or rollback started through the API, run as a `shipit`
+/// subprocess whose output is kept for the events stream
+#[derive(Serialize)]
+struct Job {
+    id: u64,
+    action: &'static str,
+    stage: String,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    git_ref: Option<String>,
+    started_at: String,
+    finished_at: Option<String>,
+    status: JobStatus,
+    #[serde(skip)]
+    lines: Vec<String>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    list: Vec<Job>,
+}
+
+struct Server {
+    config_path: PathBuf,
+    project_root: PathBuf,
+    remote: String,
+    token: String,
+    jobs: Mutex<Jobs>,
+}
+
+/// Serve the HTTP API until Ctrl-C
+pub async fn run(
+    config_path: &Path,
+    project_root: PathBuf,
+    listen: &str,
+    remote: String,
+) -> Result<()> {
+    let token = std::env::var(TOKEN_ENV)
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+        .with_context(|| format!("Set {} to the token API clients must send", TOKEN_ENV))?;
+    ShipitConfig::load(config_path)?;
+    let dirty = super::watch::git(
+        &project_root,
+        &["status", "--porcelain", "--untracked-files=no"],
+    )?;
+    if !dirty.is_empty() {
+        bail!(
+            "{} has uncommitted changes; run `shipit server` from a dedicated clone",
+            project_root.display()
+        );
+    }
+
+    let server = Arc::new(Server {
+        config_path: config_path.to_path_buf(),
+        project_root,
+        remote,
+        token: token.trim().to_string(),
+        jobs: Mutex::default(),
+    });
+
+    output::success(&format!(
+        "Serving the shipit API on http://{} (Ctrl-C to stop)",
+        crate::http::listen_addr(listen)
+    ));
+
+    let shared = server.clone();
+    let api = crate::http::serve(listen, move |req| {
+        let server = shared.clone();
+        async move { server.handle(req).await }
+    });
+    tokio::select! {
+        result = api => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    }
+}
+
+impl Server {
+    async fn handle(self: Arc<Self>, req: Request) -> Response {
+        if !self.authorized(&req) {
+            return error(401, "Missing or invalid bearer token");
+        }
+
+        let segments: Vec<&str> = req.path.trim_matches('/').split('/').collect();
+        match (req.method.as_str(), segments.as_slice()) {
+            ("GET", ["api", "stages"]) => self.stages(),
+            ("GET", ["api", "stages", stage, "status"]) => self.status(stage).await,
+            ("GET", ["api", "stages", stage, "releases"]) => self.releases(stage).await,
+            ("POST", ["api", "stages", stage, "deploy"]) => {
+                let git_ref = req.query_param("ref");
+                self.start("deploy", stage, git_ref, None)
+            }
+            ("POST", ["api", "stages", stage, "rollback"]) => {
+                let release = req.query_param("release");
+                self.start("rollback", stage, None, release)
+            }
+            ("GET", ["api", "jobs"]) => {
+                let jobs = self.jobs();
+                json_response(200, &jobs.list.iter().rev().collect::<Vec<_>>())
+            }
+            ("GET", ["api", "jobs", id]) => self.job(id),
+            ("GET", ["api", "jobs", id, "events"]) => self.events(id),
+            (_, ["api", ..]) => error(404, "No such endpoint"),
+            _ => Response::not_found(),
+        }
+    }
+
+    fn authorized(&self, req: &Request) -> bool {
+        let Some(given) = req
+            .header("authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        // Compare digests so timing doesn't reveal how much of the token matched
+        Sha256::digest(given.trim().as_bytes()) == Sha256::digest(self.token.as_bytes())
+    }
+
+    fn jobs(&self) -> MutexGuard<'_, Jobs> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The config with the stage's overrides applied, or the error response
+    fn stage_config(&self, stage_name: &str) -> Result<ShipitConfig, Response> {
+        let config = ShipitConfig::load(&self.config_path).map_err(|e| error(500, &e))?;
+        if !config.stages.contains_key(stage_name) {
+            return Err(error(404, format!("No stage named '{}'", stage_name)));
+        }
+        super::load_for_stage(&self.config_path, stage_name, &self.project_root)
+            .map_err(|e| error(500, &e))
+    }
+
+    fn stages(&self) -> Response {
+        let config = match ShipitConfig::load(&self.config_path) {
+            Ok(config) => config,
+            Err(e) => return error(500, &e),
+        };
+        let mut stages: Vec<_> = config
+            .stages
+            .iter()
+            .map(|(name, stage)| {
+                json!({
+                    "name": name,
+                    "hosts": stage.hosts.iter().map(|h| &h.address).collect::<Vec<_>>(),
+                    "protected": stage.protected,
+                })
+            })
+            .collect();
+        stages.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        json_response(200, &stages)
+    }
+
+    /// The live release of every host, from its shipit.lock
+    async fn status(&self, stage_name: &str) -> Response {
+        let config = match self.stage_config(stage_name) {
+            Ok(config) => config,
+            Err(response) => return response,
+        };
+        let mut hosts = Vec::new();
+        for (address, result) in read_hosts(&config, stage_name).await {
+            hosts.push(match result {
+                Ok((lock, _)) => json!({ "host": address, "lock": lock }),
+                Err(e) => json!({ "host": address, "error": format!("{:#}", e) }),
+            });
+        }
+        json_response(
+            200,
+            &json!({ "app": config.app.name, "stage": stage_name, "hosts": hosts }),
+        )
+    }
+
+    /// The releases on every host, newest first
+    async fn releases(&self, stage_name: &str) -> Response {
+        let config = match self.stage_config(stage_name) {
+            Ok(config) => config,
+            Err(response) => return response,
+        };
+        let mut hosts = Vec::new();
+        for (address, result) in read_hosts(&config, stage_name).await {
+            hosts.push(match result {
+                Ok((lock, releases)) => json!({
+                    "host": address,
+                    "current": lock.map(|l| l.current_release),
+                    "releases": releases,
+                }),
+                Err(e) => json!({ "host": address, "error": format!("{:#}", e) }),
+            });
+        }
+        json_response(
+            200,
+            &json!({ "app": config.app.name, "stage": stage_name, "hosts": hosts }),
+        )
+    }
+
+    fn start(
+        self: &Arc<Self>,
+        action: &'static str,
+        stage_name: &str,
+        git_ref: Option<String>,
+        release: Option<String>,
+    ) -> Response {
+        let config = match self.stage_config(stage_name) {
+            Ok(config) => config,
+            Err(response) => return response,
+        };
+        let git_ref = match action {
+            "deploy" => Some(git_ref.unwrap_or(config.app.branch)),
+            _ => None,
+        };
+        if let Some(git_ref) = &git_ref {
+            if let Err(e) = super::watch::check_ref(&self.project_root, git_ref) {
+                return error(400, format!("{:#}", e));
+            }
+        }
+
+        let mut args = vec![action.to_string(), "-s".to_string(), stage_name.to_string()];
+        if let Some(release) = release {
+            args.push(format!("--release={}", release));
+        }
+
+        let mut jobs = self.jobs();
+        // Jobs share the checkout, so only one runs at a time
+        if let Some(running) = jobs.list.iter().find(|j| j.status == JobStatus::Running) {
+            return error(
+                409,
+                format!(
+                    "Job {} ({} of {}) is still running",
+                    running.id, running.action, running.stage
+                ),
+            );
+        }
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.list.push(Job {
+            id,
+            action,
+            stage: stage_name.to_string(),
+            git_ref: git_ref.clone(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            finished_at: None,
+            status: JobStatus::Running,
+            lines: Vec::new(),
+        });
+        let excess = jobs.list.len().saturating_sub(KEEP_JOBS);
+        jobs.list.drain(..excess);
+        drop(jobs);
+
+        output::info(&format!("Job {}: {} {}", id, action, stage_name));
+        tokio::spawn(self.clone().run_job(id, args, git_ref));
+
+        json_response(
+            202,
+            &json!({ "id": id, "events": format!("/api/jobs/{}/events", id) }),
+        )
+    }
+
+    async fn run_job(self: Arc<Self>, id: u64, args: Vec<String>, git_ref: Option<String>) {
+        let result = async {
+            if let Some(git_ref) = &git_ref {
+                let sha = super::watch::poll(&self.project_root, &self.remote, git_ref)?;
+                super::watch::git(
+                    &self.project_root,
+                    &["checkout", "--quiet", "--detach", &sha],
+                )?;
+                self.log(id, format!("Checked out {} at {}", git_ref, sha));
+            }
+            self.exec(id, &args).await
+        }
+        .await;
+
+        if let Err(e) = &result {
+            self.log(id, format!("Error: {:#}", e));
+        }
+        let status = if result.is_ok() {
+            JobStatus::Succeeded
+        } else {
+            JobStatus::Failed
+        };
+        if let Some(job) = self.jobs().list.iter_mut().find(|j| j.id == id) {
+            job.status = status;
+            job.finished_at = Some(chrono::Local::now().to_rfc3339());
+        }
+        match status {
+            JobStatus::Succeeded => output::success(&format!("Job {} succeeded", id)),
+            _ => output::error(&format!("Job {} failed", id)),
+        }
+    }
+
+    /// Run `shipit <args>` for the job, recording its output
+    async fn exec(&self, id: u64, args: &[String]) -> Result<()> {
+        let exe = std::env::current_exe().context("Failed to locate the shipit binary")?;
+        let mut child = tokio::process::Command::new(exe)
+            .arg("--config")
+            .arg(&self.config_path)
+            .args(["--plain", "--no-color"])
+            .args(args)
+            .current_dir(&self.project_root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to start shipit")?;
+
+        tokio::join!(
+            self.pipe(id, child.stdout.take()),
+            self.pipe(id, child.stderr.take())
+        );
+        let status = child.wait().await?;
+        if !status.success() {
+            bail!("shipit {} exited with {}", args.join(" "), status);
+        }
+        Ok(())
+    }
+
+    async fn pipe(&self, id: u64, stream: Option<impl AsyncRead + Unpin>) {
+        let Some(stream) = stream else {
+            return;
+        };
+        let mut lines = BufReader::new(stream).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            self.log(id, line);
+        }
+    }
+
+    fn log(&self, id: u64, line: String) {
+        if let Some(job) = self.jobs().list.iter_mut().find(|j| j.id == id) {
+            job.lines.push(line);
+        }
+    }
+
+    /// A job with its output so far
+    fn job(&self, id: &str) -> Response {
+        let jobs = self.jobs();
+        let Some(job) = id
+            .parse::<u64>()
+            .ok()
+            .and_then(|id| jobs.list.iter().find(|j| j.id == id))
+        else {
+            return error(404, format!("No job {}", id));
+        };
+        let mut value = serde_json::to_value(job).unwrap_or_default();
+        value["log"] = json!(job.lines);
+        json_response(200, &value)
+    }
+
+    /// Server-sent events: one `data:` event per output line (including
+    /// those already printed), then a `done` event with the final status
+    fn events(self: &Arc<Self>, id: &str) -> Response {
+        let Some(id) = id
+            .parse::<u64>()
+            .ok()
+            .filter(|id| self.jobs().list.iter().any(|j| j.id == *id))
+        else {
+            return error(404, format!("No job {}", id));
+        };
+
+        let (tx, rx) = mpsc::channel(64);
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut sent = 0;
+            loop {
+                let Some((lines, status)) = server
+                    .jobs()
+                    .list
+                    .iter()
+                    .find(|j| j.id == id)
+                    .map(|j| (j.lines[sent..].to_vec(), j.status))
+                else {
+                    return;
+                };
+                sent += lines.len();
+                for line in lines {
+                    if tx.send(format!("data: {}\n\n", line)).await.is_err() {
+                        return;
+                    }
+                }
+                if status != JobStatus::Running {
+                    let done = json!({ "status": status });
+                    let _ = tx.send(format!("event: done\ndata: {}\n\n", done)).await;
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+        });
+        Response::event_stream(rx)
+    }
+}
+
+/// shipit.lock and releases (newest first) of every host of the stage
+async fn read_hosts(
+    config: &ShipitConfig,
+    stage_name: &str,
+) -> Vec<(String, Result<(Option<ShipitLock>, Vec<String>)>)> {
+    let Ok(stage) = config.stage(stage_name) else {
+        return Vec::new();
+    };
+    let app_path = config.app_path();
+
+    let mut hosts = Vec::new();
+    for host in &stage.hosts {
+        let result = async {
+            let session = SshSession::connect(
+                stage.user.as_deref().unwrap_or("deploy"),
+                &host.address,
+                stage.port,
+                stage.proxy.as_deref(),
+                &stage.ssh,
+            )
+            .await?;
+            let result = async {
+                let lock = ShipitLock::read(&session, &app_path).await?;
+                let listing = session
+                    .exec(&format!(
+                        "ls -1 {}/releases 2>/dev/null | sort -r",
+                        app_path
+                    ))
+                    .await?;
+                let releases: Vec<String> = listing
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                Ok::<_, anyhow::Error>((lock, releases))
+            }
+            .await;
+            session.close().await?;
+            result
+        }
+        .await;
+        hosts.push((host.address.clone(), result));
+    }
+    hosts
+}
+
+fn json_response(status: u16, value: &impl Serialize) -> Response {
+    Response::with_status(
+        status,
+        "application/json",
+        serde_json::to_string(value).unwrap_or_default(),
+    )
+}
+
+fn error(status: u16, message: impl std::fmt::Display) -> Response {
+    json_response(status, &json!({ "error": format!("{:#}", message) }))
+}
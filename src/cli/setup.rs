@@ -1,74 +1,598 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
-use crate::config::ShipitConfig;
+use crate::backup;
+use crate::config::{HostConfig, ShipitConfig, StageConfig};
+use crate::deploy::steps;
+use crate::gc;
+use crate::hardening;
 use crate::nixos;
 use crate::os::HostOs;
 use crate::output;
+use crate::registry;
 use crate::ssh::SshSession;
 use crate::traefik;
 use crate::wireguard;
 
-pub async fn run(config: ShipitConfig, stage_name: &str) -> Result<()> {
+/// An individually selectable setup step (for `--only` / `--skip`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SetupStep {
+    Docker,
+    DockerGroup,
+    Traefik,
+    DeployDir,
+    Git,
+    Directories,
+    Env,
+    Time,
+    Fail2ban,
+    HardenSsh,
+    Registry,
+    Wireguard,
+    Backups,
+    Gpu,
+    Gc,
+}
+
+pub struct SetupOptions {
+    /// Create the stage user first, connecting as this administrative user
+    pub bootstrap_as: Option<String>,
+    /// Run only these steps (empty = all)
+    pub only: Vec<SetupStep>,
+    /// Steps to leave out
+    pub skip: Vec<SetupStep>,
+}
+
+impl SetupOptions {
+    fn runs(&self, step: SetupStep) -> bool {
+        (self.only.is_empty() || self.only.contains(&step)) && !self.skip.contains(&step)
+    }
+}
+
+pub async fn run(config: ShipitConfig, stage_name: &str, opts: &SetupOptions) -> Result<()> {
     let stage = config.stage(stage_name)?;
     let user = stage.user.as_deref().unwrap_or("deploy");
-    let app_path = config.app_path();
 
     output::header(&format!("Setting up {} for {}", stage_name, config.app.name));
+    let dry_run = crate::ssh::is_dry_run();
+    if dry_run {
+        output::info("Dry run: checks still run, changes are printed instead");
+    }
 
-    for host in &stage.hosts {
-        output::info(&format!("Setting up {}", host.address));
+    if let Some(root_user) = opts.bootstrap_as.as_deref() {
+        for host in &stage.hosts {
+            bootstrap_user(stage, host, root_user, user).await?;
+        }
+    }
 
-        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
+    // Decrypt once up front so a missing key fails before touching any host
+    let project_root = std::env::current_dir()?;
+    let registry_password = if opts.runs(SetupStep::Registry) {
+        registry::password(&config, &project_root, stage_name)?
+    } else {
+        None
+    };
+    let registry_password = registry_password.as_deref();
+    let backup_credentials = if opts.runs(SetupStep::Backups) {
+        backup::credentials(&config, &project_root, stage_name)?
+    } else {
+        None
+    };
+
+    // Hosts are independent until the WireGuard mesh, so set them up
+    // concurrently. Sessions stay open so the WireGuard phase reuses them.
+    let results = if stage.hosts.len() > 1 {
+        futures::future::join_all(stage.hosts.iter().map(|host| {
+            output::with_host_prefix(&host.address, setup_host(&config, stage, host, user, opts, registry_password))
+        }))
+        .await
+    } else {
+        let mut results = Vec::new();
+        for host in &stage.hosts {
+            results.push(setup_host(&config, stage, host, user, opts, registry_password).await);
+        }
+        results
+    };
+
+    let mut sessions = Vec::new();
+    let mut failed = Vec::new();
+    for (host, result) in stage.hosts.iter().zip(results) {
+        match result {
+            Ok(session) => sessions.push(session),
+            Err(e) => failed.push((host.address.as_str(), e)),
+        }
+    }
 
-        // Detect host OS (config override or auto-detect)
-        let host_os = HostOs::resolve(stage.os.as_deref(), &session).await?;
-        output::info(&format!("Detected OS: {:?}", host_os));
+    if !failed.is_empty() {
+        for session in sessions {
+            let _ = session.close().await;
+        }
+        println!();
+        for (address, e) in &failed {
+            output::error(&format!("{}: {:#}", address, e));
+        }
+        bail!(
+            "Setup failed on {} of {} host(s)",
+            failed.len(),
+            stage.hosts.len()
+        );
+    }
+
+    // Step 8: Setup WireGuard mesh between hosts
+    if opts.runs(SetupStep::Wireguard) {
+        wireguard::setup(&stage.hosts, &sessions, stage.os.as_deref()).await?;
+    }
 
-        // NixOS: apply unified module (Docker + Traefik + WireGuard) before other steps
-        if host_os.needs_unified_module() {
-            nixos::apply_module(&session, user).await?;
+    // Step 9: Backup timers
+    if config.backups.is_some() && opts.runs(SetupStep::Backups) {
+        for (index, (host, session)) in stage.hosts.iter().zip(&sessions).enumerate() {
+            let items = backup::install(
+                session,
+                &config,
+                stage_name,
+                &host.address,
+                index,
+                backup_credentials.as_ref(),
+            )
+            .await?;
+            if items > 0 {
+                output::success(&format!(
+                    "Backups of {} item(s) scheduled on {}",
+                    items, host.address
+                ));
+            }
         }
+    }
+
+    for session in sessions {
+        session.close().await?;
+    }
+
+    println!();
+    if dry_run {
+        output::done("Dry run complete; nothing was changed");
+        return Ok(());
+    }
+    output::done("Setup complete! You can now deploy with: shipit deploy");
+    Ok(())
+}
 
-        // Step 1: Install Docker if not present (no-op on NixOS)
+/// Set up a single host that was added to an existing stage (used by
+/// `deploy --auto-setup`). The WireGuard mesh is left to a full `shipit setup`;
+/// registry login happens as part of the deploy itself.
+pub async fn setup_new_host(
+    config: &ShipitConfig,
+    stage: &StageConfig,
+    host: &HostConfig,
+) -> Result<()> {
+    let user = stage.user.as_deref().unwrap_or("deploy");
+    let opts = SetupOptions {
+        bootstrap_as: None,
+        only: Vec::new(),
+        skip: vec![SetupStep::Wireguard],
+    };
+
+    let session = setup_host(config, stage, host, user, &opts, None).await?;
+    session.close().await?;
+
+    if stage.hosts.len() > 1 {
+        output::warning(&format!(
+            "{} is not part of the WireGuard mesh yet; run `shipit setup` to add it",
+            host.address
+        ));
+    }
+    Ok(())
+}
+
+/// Run setup steps 1-7 (plus optional hardening) on a single host, returning
+/// the open session for the WireGuard phase.
+async fn setup_host(
+    config: &ShipitConfig,
+    stage: &StageConfig,
+    host: &HostConfig,
+    user: &str,
+    opts: &SetupOptions,
+    registry_password: Option<&str>,
+) -> Result<SshSession> {
+    let app_path = config.app_path();
+
+    output::info(&format!("Setting up {}", host.address));
+
+    let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref(), &stage.ssh).await?;
+
+    // Detect host OS (config override or auto-detect)
+    let host_os = HostOs::resolve(stage.os.as_deref(), &session).await?;
+    output::info(&format!("Detected OS: {:?}", host_os));
+
+    // NixOS: apply unified module (Docker + Traefik + WireGuard) before other steps
+    let needs_module = [SetupStep::Docker, SetupStep::Traefik, SetupStep::Wireguard]
+        .into_iter()
+        .any(|step| opts.runs(step));
+    if host_os.needs_unified_module() && needs_module {
+        nixos::apply_module(&session, user).await?;
+    }
+
+    // Step 1: Install Docker if not present (no-op on NixOS)
+    if opts.runs(SetupStep::Docker) {
         install_docker(&session, host_os).await?;
+    }
 
-        // Step 2: Add user to docker group
+    // Step 2: Add user to docker group
+    if opts.runs(SetupStep::DockerGroup) {
         add_docker_group(&session, user, host_os).await?;
+    }
+
+    // Services reserving GPUs need the NVIDIA container runtime
+    if stage.uses_gpus() && opts.runs(SetupStep::Gpu) {
+        verify_gpu(&session).await?;
+    }
 
-        // Step 3: Install Traefik
+    // Step 3: Install Traefik
+    if opts.runs(SetupStep::Traefik) {
         let acme_email = stage
             .traefik
             .as_ref()
             .and_then(|t| t.acme_email.as_deref());
         traefik::install(&session, acme_email, host_os).await?;
+    }
 
-        // Step 4: Create deploy directory with correct ownership
+    // Step 4: Create deploy directory with correct ownership
+    if opts.runs(SetupStep::DeployDir) {
         create_deploy_dir(&session, user, &app_path).await?;
+    }
 
-        // Step 5: Create bare git repo
+    // Step 5: Create bare git repo
+    if opts.runs(SetupStep::Git) {
         setup_git_repo(&session, &app_path).await?;
+    }
 
-        // Step 6: Create directories
-        setup_directories(&session, &app_path).await?;
+    // Step 6: Create directories
+    if opts.runs(SetupStep::Directories) {
+        setup_directories(&session, config, &app_path).await?;
+    }
 
-        // Step 7: Create initial .env
+    // Step 7: Create initial .env
+    if opts.runs(SetupStep::Env) {
         setup_env(&session, &app_path).await?;
+    }
 
-        session.close().await?;
-        output::success(&format!("Host {} is ready", host.address));
+    // Optional timezone / NTP
+    if opts.runs(SetupStep::Time) {
+        configure_time(&session, config.setup.timezone.as_deref(), config.setup.time_sync, host_os).await?;
     }
 
-    // Step 8: Setup WireGuard mesh between hosts
-    wireguard::setup(stage, &stage.hosts, stage.os.as_deref()).await?;
+    // Optional hardening
+    if config.setup.fail2ban && opts.runs(SetupStep::Fail2ban) {
+        hardening::install_fail2ban(&session, stage.port, host_os).await?;
+    }
+    if config.setup.harden_ssh && opts.runs(SetupStep::HardenSsh) {
+        hardening::harden_ssh(&session, stage, host, host_os).await?;
+    }
+
+    // Private registry login
+    if let (Some(registry), Some(password)) = (&config.registry, registry_password) {
+        registry::login(&session, registry, password).await?;
+    }
+
+    // Optional Docker image / build cache GC timer
+    if let Some(gc_config) = &config.gc {
+        if opts.runs(SetupStep::Gc) {
+            gc::install(&session, config, gc_config, user, host_os).await?;
+            output::success(&format!("Docker GC scheduled ({})", gc_config.schedule));
+        }
+    }
+
+    output::success(&format!("Host {} is ready", host.address));
+    Ok(session)
+}
+
+/// Disk usage (percent) at which the audit reports a failure
+const MAX_DISK_USE_PERCENT: u32 = 90;
+
+struct Check {
+    name: &'static str,
+    ok: bool,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Non-mutating audit of what `setup` would configure. Fails if any check fails.
+pub async fn check(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let user = stage.user.as_deref().unwrap_or("deploy");
+    let app_path = config.app_path();
+    let multi_host = stage.hosts.len() > 1;
+
+    output::header(&format!("Checking {} for {}", stage_name, config.app.name));
+
+    let mut failures = 0;
+
+    for host in &stage.hosts {
+        output::info(&format!("Host {}", host.address));
+
+        let session = match SshSession::connect(
+            user,
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await
+        {
+            Ok(session) => session,
+            Err(e) => {
+                output::error(&format!("ssh: {:#}", e));
+                failures += 1;
+                continue;
+            }
+        };
+
+        let checks = audit_host(
+            &session,
+            user,
+            &app_path,
+            multi_host,
+            config.setup.max_clock_skew,
+        )
+        .await;
+        for c in &checks {
+            if c.ok {
+                output::success(&format!("{}: {}", c.name, c.detail));
+            } else {
+                output::error(&format!("{}: {}", c.name, c.detail));
+                failures += 1;
+            }
+        }
+
+        session.close().await?;
+    }
 
     println!();
-    output::success("Setup complete! You can now deploy with: shipit deploy");
+    if failures > 0 {
+        bail!("Setup check failed: {} problem(s) found", failures);
+    }
+
+    output::success("All checks passed");
+    Ok(())
+}
+
+async fn audit_host(
+    session: &SshSession,
+    user: &str,
+    app_path: &str,
+    multi_host: bool,
+    max_clock_skew: u64,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    // Docker
+    let docker = session
+        .query("docker version --format '{{.Server.Version}}'")
+        .await;
+    checks.push(match docker {
+        Ok(v) => Check::new("docker", true, format!("server {}", v.trim())),
+        Err(_) => Check::new("docker", false, "not installed or not accessible by this user"),
+    });
+
+    // Traefik
+    let traefik = session
+        .query("systemctl is-active traefik 2>/dev/null || true")
+        .await
+        .unwrap_or_default();
+    let traefik = traefik.trim();
+    checks.push(Check::new(
+        "traefik",
+        traefik == "active",
+        format!("systemd unit {}", if traefik.is_empty() { "missing" } else { traefik }),
+    ));
+
+    // Directories and ownership
+    let dirs_ok = session
+        .exec_ok(&format!(
+            "test -d {}/releases && test -d {}/shared && test -f {}/shared/.env",
+            app_path, app_path, app_path
+        ))
+        .await
+        .unwrap_or(false);
+    let owner = session
+        .query(&format!("stat -c %U {} 2>/dev/null", app_path))
+        .await
+        .map(|o| o.trim().to_string())
+        .unwrap_or_default();
+    checks.push(Check::new(
+        "directories",
+        dirs_ok && owner == user,
+        if !dirs_ok {
+            format!("{}/releases, shared/ or shared/.env missing", app_path)
+        } else if owner != user {
+            format!("{} owned by '{}', expected '{}'", app_path, owner, user)
+        } else {
+            format!("{} owned by {}", app_path, owner)
+        },
+    ));
+
+    // Bare repo
+    let repo_ok = session
+        .exec_ok(&format!("test -f {}/repo/HEAD", app_path))
+        .await
+        .unwrap_or(false);
+    checks.push(Check::new(
+        "git repo",
+        repo_ok,
+        if repo_ok { "bare repo present" } else { "bare repo missing" },
+    ));
+
+    // WireGuard (only meaningful with peers)
+    if multi_host {
+        let wg_ok = session
+            .exec_ok("sudo wg show wg0 >/dev/null 2>&1")
+            .await
+            .unwrap_or(false);
+        checks.push(Check::new(
+            "wireguard",
+            wg_ok,
+            if wg_ok { "wg0 up" } else { "wg0 down or not configured" },
+        ));
+    }
+
+    // Disk space
+    let disk = session
+        .query(&format!("df -P {} | tail -1 | awk '{{print $5}}'", app_path))
+        .await
+        .ok()
+        .and_then(|o| o.trim().trim_end_matches('%').parse::<u32>().ok());
+    checks.push(match disk {
+        Some(pct) => Check::new(
+            "disk",
+            pct < MAX_DISK_USE_PERCENT,
+            format!("{}% used", pct),
+        ),
+        None => Check::new("disk", false, "could not read disk usage"),
+    });
+
+    // Clock skew
+    checks.push(match session.clock_skew().await {
+        Ok(skew) => Check::new(
+            "clock",
+            skew.unsigned_abs() <= max_clock_skew,
+            format!("{}s skew from local clock", skew.abs()),
+        ),
+        Err(_) => Check::new("clock", false, "could not read remote time"),
+    });
+
+    checks
+}
+
+/// Connect as an administrative user and create the deploy user with the
+/// local SSH public key and passwordless sudo.
+async fn bootstrap_user(
+    stage: &StageConfig,
+    host: &HostConfig,
+    root_user: &str,
+    user: &str,
+) -> Result<()> {
+    output::info(&format!(
+        "Bootstrapping user '{}' on {} as {}",
+        user, host.address, root_user
+    ));
+
+    if root_user == user {
+        bail!("--root-user must differ from the stage user '{}'", user);
+    }
+
+    let pub_key = crate::ssh::local_public_key()?
+        .context("No SSH public key found (~/.ssh/id_ed25519.pub or id_rsa.pub). Generate one with: ssh-keygen")?;
+
+    let session = SshSession::connect(
+        root_user,
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+
+    let sudoers = format!("/etc/sudoers.d/90-shipit-{}", user);
+    let script = format!(
+        "set -e\n\
+         command -v sudo >/dev/null || (apt-get update -qq && apt-get install -y -qq sudo) || dnf -y -q install sudo\n\
+         id -u {user} >/dev/null 2>&1 || useradd -m -s /bin/bash {user}\n\
+         home=$(getent passwd {user} | cut -d: -f6)\n\
+         mkdir -p \"$home/.ssh\"\n\
+         touch \"$home/.ssh/authorized_keys\"\n\
+         grep -qxF '{key}' \"$home/.ssh/authorized_keys\" || echo '{key}' >> \"$home/.ssh/authorized_keys\"\n\
+         chmod 700 \"$home/.ssh\" && chmod 600 \"$home/.ssh/authorized_keys\"\n\
+         chown -R {user}: \"$home/.ssh\"\n\
+         echo '{user} ALL=(ALL) NOPASSWD:ALL' > {sudoers}.tmp\n\
+         chmod 440 {sudoers}.tmp && visudo -cf {sudoers}.tmp && mv {sudoers}.tmp {sudoers}\n",
+        user = user,
+        key = pub_key,
+        sudoers = sudoers,
+    );
+
+    // Root can run it directly; any other admin user goes through sudo
+    let result = if root_user == "root" {
+        session.exec(&script).await
+    } else {
+        session.sudo_exec(&script).await
+    };
+    result.with_context(|| format!("Failed to bootstrap user '{}' on {}", user, host.address))?;
+
+    session.close().await?;
+    output::success(&format!("User '{}' ready on {}", user, host.address));
+    Ok(())
+}
+
+/// Set the host timezone and enable NTP sync, as configured in `[setup]`
+async fn configure_time(
+    session: &SshSession,
+    timezone: Option<&str>,
+    time_sync: bool,
+    host_os: HostOs,
+) -> Result<()> {
+    if timezone.is_none() && !time_sync {
+        return Ok(());
+    }
+
+    if host_os.needs_unified_module() {
+        output::warning(
+            "NixOS: set time.timeZone and services.timesyncd in configuration.nix instead (skipped)",
+        );
+        return Ok(());
+    }
+
+    if let Some(tz) = timezone {
+        let known = session
+            .exec_ok(&format!("timedatectl list-timezones | grep -qxF '{}'", tz))
+            .await?;
+        if !known {
+            bail!("Unknown timezone '{}' (see `timedatectl list-timezones`)", tz);
+        }
+        session
+            .sudo_exec(&format!("timedatectl set-timezone {}", tz))
+            .await
+            .context("Failed to set timezone")?;
+        output::success(&format!("Timezone set to {}", tz));
+    }
+
+    if time_sync {
+        let has_timesyncd = session
+            .exec_ok("systemctl list-unit-files systemd-timesyncd.service | grep -q timesyncd")
+            .await?;
+        if has_timesyncd {
+            session
+                .sudo_exec("timedatectl set-ntp true")
+                .await
+                .context("Failed to enable systemd-timesyncd")?;
+            output::success("NTP sync enabled (systemd-timesyncd)");
+        } else {
+            let spinner = output::create_spinner("Installing chrony...");
+            session
+                .sudo_exec(&format!(
+                    "(command -v chronyd || ({})) \
+                     && (systemctl enable --now chrony || systemctl enable --now chronyd)",
+                    host_os.install_packages_cmd("chrony")
+                ))
+                .await
+                .context("Failed to install chrony")?;
+            spinner.finish_and_clear();
+            output::success("NTP sync enabled (chrony)");
+        }
+    }
+
     Ok(())
 }
 
-pub async fn install_docker_on(session: &SshSession) -> Result<()> {
-    // Local VMs are always Ubuntu
-    install_docker(session, HostOs::Ubuntu).await
+pub async fn install_docker_on(session: &SshSession, host_os: HostOs) -> Result<()> {
+    install_docker(session, host_os).await
 }
 
 async fn install_docker(session: &SshSession, host_os: HostOs) -> Result<()> {
@@ -101,6 +625,44 @@ async fn add_docker_group(session: &SshSession, user: &str, host_os: HostOs) ->
     Ok(())
 }
 
+/// The NVIDIA driver and container toolkit depend on the GPU and distro, so
+/// they are verified rather than installed
+async fn verify_gpu(session: &SshSession) -> Result<()> {
+    output::info("Checking NVIDIA GPU support...");
+
+    let gpus = session
+        .exec("nvidia-smi -L")
+        .await
+        .context("nvidia-smi failed; install the NVIDIA driver on this host")?;
+
+    if !session
+        .exec_ok("command -v nvidia-ctk || command -v nvidia-container-runtime")
+        .await?
+    {
+        bail!(
+            "nvidia-container-toolkit is not installed. Install it \
+             (https://docs.nvidia.com/datacenter/cloud-native/container-toolkit/latest/install-guide.html), \
+             then run: sudo nvidia-ctk runtime configure --runtime=docker && sudo systemctl restart docker"
+        );
+    }
+
+    if !session
+        .exec_ok("docker info --format '{{json .Runtimes}}' | grep -q nvidia")
+        .await?
+    {
+        bail!(
+            "Docker has no nvidia runtime. Run: sudo nvidia-ctk runtime configure --runtime=docker \
+             && sudo systemctl restart docker"
+        );
+    }
+
+    output::success(&format!(
+        "{} GPU(s) available to Docker",
+        gpus.lines().filter(|l| !l.trim().is_empty()).count()
+    ));
+    Ok(())
+}
+
 async fn setup_git_repo(session: &SshSession, app_path: &str) -> Result<()> {
     output::info("Setting up bare git repository...");
 
@@ -120,7 +682,11 @@ async fn setup_git_repo(session: &SshSession, app_path: &str) -> Result<()> {
     Ok(())
 }
 
-async fn setup_directories(session: &SshSession, app_path: &str) -> Result<()> {
+async fn setup_directories(
+    session: &SshSession,
+    config: &ShipitConfig,
+    app_path: &str,
+) -> Result<()> {
     output::info("Creating directories...");
 
     session
@@ -131,6 +697,14 @@ async fn setup_directories(session: &SshSession, app_path: &str) -> Result<()> {
         .await
         .context("Failed to create directories")?;
 
+    let shared_path = format!("{}/shared", app_path);
+    if let Some(cmd) = steps::create_shared_paths_cmd(&config.deploy.shared, &shared_path) {
+        session
+            .exec(&cmd)
+            .await
+            .context("Failed to create shared paths")?;
+    }
+
     output::success("Directories created");
     Ok(())
 }
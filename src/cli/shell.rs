@@ -0,0 +1,68 @@
+use anyhow::{bail, Context, Result};
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::ssh::{shell_quote, SshSession};
+
+/// Open an interactive shell in a service container of the current release:
+/// bash when the image has it, sh otherwise. The shell's exit code becomes
+/// shipit's.
+pub async fn run(
+    config: ShipitConfig,
+    stage_name: &str,
+    service: Option<&str>,
+    host: Option<&str>,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let user = stage.user.as_deref().unwrap_or("deploy");
+    let current_path = format!("{}/current", config.app_path());
+    let service = service
+        .or(config.deploy.web_service.as_deref())
+        .unwrap_or("web");
+
+    // The first host that runs the service, unless one is picked
+    let host = match host {
+        Some(address) => stage
+            .hosts
+            .iter()
+            .find(|h| h.address == address)
+            .with_context(|| format!("No host {} in stage '{}'", address, stage_name))?,
+        None => stage
+            .hosts
+            .iter()
+            .find(|h| config.deploy.runs_on(service, h))
+            .with_context(|| format!("No host of stage '{}' runs {}", stage_name, service))?,
+    };
+    let session = SshSession::connect(
+        user,
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+
+    if !session.path_exists(&current_path).await? {
+        session.close().await?;
+        bail!("No current release found. Deploy first.");
+    }
+
+    output::info(&format!("{} on {} (exit to leave)", service, host.address));
+    let cmd = format!(
+        "cd $(readlink -f {}) && docker compose exec {} sh -c {}",
+        current_path,
+        service,
+        shell_quote("if command -v bash >/dev/null; then exec bash; else exec sh; fi")
+    );
+    let status = session.exec_interactive(&cmd).await;
+    session.close().await?;
+
+    let status = status?;
+    if !status.success() {
+        match status.code() {
+            Some(code) => std::process::exit(code),
+            None => bail!("The shell was killed by a signal"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use anyhow::{bail, Result};
+use console::style;
+
+use crate::config::{HostConfig, ShipitConfig, StageConfig};
+use crate::output;
+use crate::release::lock::ShipitLock;
+use crate::ssh::SshSession;
+
+/// What a host reported, in one round trip
+struct HostReport {
+    /// Release the `current` symlink points at
+    linked: Option<String>,
+    lock: Option<ShipitLock>,
+    traefik: String,
+    disk: String,
+    /// (service, state, health)
+    containers: Vec<(String, String, String)>,
+}
+
+impl HostReport {
+    fn parse(output: &str) -> Self {
+        let (head, containers) = output.split_once("\n--\n").unwrap_or((output, ""));
+        let field = |key: &str| {
+            head.lines()
+                .find_map(|l| l.strip_prefix(&format!("{}=", key)))
+                .map(|v| v.trim().to_string())
+                .unwrap_or_default()
+        };
+        let linked = field("current");
+        Self {
+            linked: (!linked.is_empty())
+                .then(|| linked.rsplit('/').next().unwrap_or(&linked).to_string()),
+            lock: serde_json::from_str(&field("lock")).ok(),
+            traefik: field("traefik"),
+            disk: field("disk"),
+            containers: containers
+                .lines()
+                .filter_map(|line| {
+                    let mut parts = line.trim().splitn(3, '|');
+                    let service = parts.next().filter(|s| !s.is_empty())?;
+                    Some((
+                        service.to_string(),
+                        parts.next().unwrap_or_default().to_string(),
+                        parts.next().unwrap_or_default().to_string(),
+                    ))
+                })
+                .collect(),
+        }
+    }
+
+    /// Everything that needs attention on this host
+    fn problems(&self, routed: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+        match &self.lock {
+            None => problems.push("nothing deployed (no shipit.lock)".to_string()),
+            Some(lock) => {
+                if self.linked.as_deref() != Some(lock.current_release.as_str()) {
+                    problems.push(format!(
+                        "current → {} but shipit.lock says {}",
+                        self.linked.as_deref().unwrap_or("nothing"),
+                        lock.current_release
+                    ));
+                }
+            }
+        }
+        if routed && self.traefik != "active" && self.traefik != "container running" {
+            problems.push(format!("traefik is {}", self.traefik));
+        }
+        for (service, state, health) in &self.containers {
+            if state != "running" || health == "unhealthy" {
+                problems.push(format!("{} is {}", service, describe(state, health)));
+            }
+        }
+        problems
+    }
+}
+
+fn describe(state: &str, health: &str) -> String {
+    if health.is_empty() {
+        state.to_string()
+    } else {
+        format!("{} ({})", state, health)
+    }
+}
+
+fn script(app_path: &str) -> String {
+    format!(
+        r#"echo "current=$(readlink {app}/current 2>/dev/null)"
+echo "lock=$(tr -d '\n' < {app}/shipit.lock 2>/dev/null)"
+t=$(systemctl is-active traefik 2>/dev/null)
+if [ "$t" != active ]; then c=$(docker inspect -f '{{{{.State.Status}}}}' traefik 2>/dev/null) && t="container $c"; fi
+echo "traefik=${{t:-missing}}"
+echo "disk=$(df -Ph {app} 2>/dev/null | awk 'NR==2 {{print $5 " used, " $4 " free"}}')"
+echo --
+test -e {app}/current && cd "$(readlink -f {app}/current)" && docker compose ps -a --format '{{{{.Service}}}}|{{{{.State}}}}|{{{{.Health}}}}' 2>/dev/null
+true"#,
+        app = app_path
+    )
+}
+
+/// One table of the stage: per host the live release and commit, whether
+/// `current` matches shipit.lock, Traefik, disk and the app's containers.
+/// Exits non-zero when anything needs attention, for CI post-deploy checks.
+pub async fn run(config: ShipitConfig, stage_name: &str) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+    let routed = stage.traefik.is_some();
+
+    output::header(&format!("Status of {} on {}", config.app.name, stage_name));
+
+    let reports =
+        futures::future::join_all(stage.hosts.iter().map(|host| probe(stage, host, &app_path)))
+            .await;
+
+    println!(
+        "  {:<22} {:<17} {:<8} {:<9} {:<22} {}",
+        style("HOST").bold(),
+        style("RELEASE").bold(),
+        style("SHA").bold(),
+        style("TRAEFIK").bold(),
+        style("DISK").bold(),
+        style("CONTAINERS").bold()
+    );
+
+    let mut problems = Vec::new();
+    for (host, report) in stage.hosts.iter().zip(reports) {
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                println!("  {:<22} {}", host.address, style("unreachable").red());
+                problems.push(format!("{}: {:#}", host.address, e));
+                continue;
+            }
+        };
+
+        let (release, sha) = match &report.lock {
+            Some(lock) => (
+                lock.current_release.clone(),
+                lock.git_sha[..7.min(lock.git_sha.len())].to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let containers: Vec<String> = report
+            .containers
+            .iter()
+            .map(|(service, state, health)| {
+                let text = format!("{}: {}", service, describe(state, health));
+                if state == "running" && health != "unhealthy" {
+                    style(text).green().to_string()
+                } else {
+                    style(text).red().to_string()
+                }
+            })
+            .collect();
+        println!(
+            "  {:<22} {:<17} {:<8} {:<9} {:<22} {}",
+            host.address,
+            release,
+            sha,
+            report.traefik,
+            report.disk,
+            if containers.is_empty() {
+                "-".to_string()
+            } else {
+                containers.join(", ")
+            }
+        );
+        if let Some(by) = report.lock.as_ref().and_then(|l| l.deployed_by.as_ref()) {
+            println!(
+                "  {:<22} {}",
+                "",
+                style(format!("deployed by {}", by)).dim()
+            );
+        }
+
+        problems.extend(
+            report
+                .problems(routed)
+                .into_iter()
+                .map(|p| format!("{}: {}", host.address, p)),
+        );
+    }
+
+    println!();
+    if problems.is_empty() {
+        output::done("All hosts healthy");
+        return Ok(());
+    }
+    for problem in &problems {
+        output::warning(problem);
+    }
+    bail!("{} problem(s) on {}", problems.len(), stage_name);
+}
+
+async fn probe(stage: &StageConfig, host: &HostConfig, app_path: &str) -> Result<HostReport> {
+    let session = SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+    let result = session.exec(&script(app_path)).await;
+    session.close().await?;
+    Ok(HostReport::parse(&result?))
+}
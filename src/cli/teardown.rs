@@ -0,0 +1,193 @@
+use anyhow::{bail, Context, Result};
+use dialoguer::{Confirm, Input};
+
+use crate::accessory;
+use crate::backup;
+use crate::config::ShipitConfig;
+use crate::cron;
+use crate::gc;
+use crate::os::HostOs;
+use crate::output;
+use crate::ssh::pool::SessionPool;
+use crate::ssh::SshSession;
+
+pub struct TeardownOptions {
+    pub remove_traefik: bool,
+    pub remove_docker: bool,
+    pub yes: bool,
+}
+
+pub async fn run(config: ShipitConfig, stage_name: &str, opts: TeardownOptions) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let user = stage.user.as_deref().unwrap_or("deploy");
+    let app_path = config.app_path();
+
+    output::header(&format!("Tearing down {} on {}", config.app.name, stage_name));
+    output::warning(&format!(
+        "This stops the app and accessories and permanently deletes {} on {} host(s):",
+        app_path,
+        stage.hosts.len()
+    ));
+    for host in &stage.hosts {
+        println!("  {}", host.address);
+    }
+
+    if !opts.yes {
+        let typed: String = Input::new()
+            .with_prompt(format!("Type the stage name ({}) to confirm", stage_name))
+            .interact_text()?;
+        if typed != stage_name {
+            bail!("Confirmation did not match, aborting");
+        }
+    }
+
+    let remove_traefik = opts.remove_traefik && confirm(opts.yes, "Also remove Traefik?")?;
+    let remove_docker = opts.remove_docker
+        && confirm(opts.yes, "Also uninstall Docker and delete all images and volumes?")?;
+
+    // Accessories (only those placed on this stage's hosts)
+    let mut pool = SessionPool::new();
+    for (name, acc) in &config.accessories {
+        if !accessory::runs_on_stage(stage, acc) {
+            continue;
+        }
+        if let Err(e) = accessory::stop_accessory(&mut pool, &config, stage, name, acc).await {
+            output::warning(&format!("Accessory '{}': {:#}", name, e));
+        }
+    }
+    pool.close_all().await?;
+
+    for host in &stage.hosts {
+        output::info(&format!("Tearing down {}", host.address));
+
+        let session = SshSession::connect(
+            user,
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+
+        let host_os = HostOs::resolve(stage.os.as_deref(), &session).await?;
+
+        // Timers would otherwise keep firing into the deleted release
+        cron::remove_all(&session, &config, host_os).await?;
+        if config.backups.is_some() {
+            backup::remove(&session, &config, host_os).await?;
+        }
+        if config.gc.is_some() {
+            gc::remove(&session, &config, host_os).await?;
+        }
+        stop_releases(&session, &app_path).await?;
+        remove_app_dir(&session, &app_path).await?;
+        teardown_wireguard(&session).await?;
+
+        if host_os.needs_unified_module() && (remove_traefik || remove_docker) {
+            output::warning(
+                "NixOS: remove the ./shipit.nix import from /etc/nixos/configuration.nix \
+                 and run `nixos-rebuild switch` to uninstall Traefik/Docker",
+            );
+        } else {
+            if remove_traefik {
+                remove_traefik_service(&session).await?;
+            }
+            if remove_docker {
+                uninstall_docker(&session, host_os).await?;
+            }
+        }
+
+        session.close().await?;
+        output::success(&format!("Host {} torn down", host.address));
+    }
+
+    println!();
+    output::done(&format!("Stage {} decommissioned", stage_name));
+    Ok(())
+}
+
+fn confirm(yes: bool, prompt: &str) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    Ok(Confirm::new().with_prompt(prompt).default(false).interact()?)
+}
+
+async fn stop_releases(session: &SshSession, app_path: &str) -> Result<()> {
+    output::info("Stopping app containers...");
+
+    // Every release, not just current, in case an old one was left running
+    session
+        .exec(&format!(
+            "for d in {}/releases/*/; do [ -d \"$d\" ] && (cd \"$d\" && docker compose down --remove-orphans 2>/dev/null); done; true",
+            app_path
+        ))
+        .await
+        .context("Failed to stop app containers")?;
+
+    output::success("App containers stopped");
+    Ok(())
+}
+
+async fn remove_app_dir(session: &SshSession, app_path: &str) -> Result<()> {
+    session
+        .sudo_exec(&format!("rm -rf {}", app_path))
+        .await
+        .with_context(|| format!("Failed to remove {}", app_path))?;
+
+    output::success(&format!("Removed {}", app_path));
+    Ok(())
+}
+
+async fn teardown_wireguard(session: &SshSession) -> Result<()> {
+    if !session.exec_ok("test -f /etc/wireguard/wg0.conf").await? {
+        return Ok(());
+    }
+
+    session
+        .sudo_exec(
+            "wg-quick down wg0 2>/dev/null; systemctl disable wg-quick@wg0 2>/dev/null; \
+             rm -f /etc/wireguard/wg0.conf /etc/wireguard/privatekey /etc/wireguard/publickey",
+        )
+        .await
+        .context("Failed to tear down WireGuard")?;
+
+    output::success("WireGuard removed");
+    Ok(())
+}
+
+async fn remove_traefik_service(session: &SshSession) -> Result<()> {
+    session
+        .sudo_exec(
+            "systemctl disable --now traefik 2>/dev/null; \
+             rm -f /etc/systemd/system/traefik.service && systemctl daemon-reload && \
+             rm -rf /etc/traefik && (docker network rm traefik 2>/dev/null || true)",
+        )
+        .await
+        .context("Failed to remove Traefik")?;
+
+    output::success("Traefik removed");
+    Ok(())
+}
+
+async fn uninstall_docker(session: &SshSession, host_os: HostOs) -> Result<()> {
+    let spinner = output::create_spinner("Uninstalling Docker...");
+
+    let remove = match host_os {
+        HostOs::Rhel => "dnf -y -q remove",
+        _ => "apt-get purge -y -qq",
+    };
+    session
+        .sudo_exec(&format!(
+            "{} docker-ce docker-ce-cli containerd.io docker-buildx-plugin \
+             docker-compose-plugin docker-ce-rootless-extras && \
+             rm -rf /var/lib/docker /var/lib/containerd",
+            remove
+        ))
+        .await
+        .context("Failed to uninstall Docker")?;
+
+    spinner.finish_and_clear();
+    output::success("Docker uninstalled");
+    Ok(())
+}
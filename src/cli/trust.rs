@@ -0,0 +1,99 @@
+use anyhow::{bail, Context, Result};
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::ssh::{known_hosts, SshSession};
+
+/// Record the host keys of every host of the stage. Keys already recorded
+/// are kept; one that no longer matches is only replaced with `replace` or
+/// after confirming the old and new fingerprints.
+pub async fn run(config: ShipitConfig, stage_name: &str, replace: bool) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let user = stage.user.as_deref().unwrap_or("deploy");
+
+    let file = known_hosts::path()?;
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).context("Failed to create .shipit directory")?;
+    }
+
+    output::header(&format!("Recording host keys for {}", stage_name));
+
+    for host in &stage.hosts {
+        let recorded = known_hosts::fingerprints(&file, &host.address, stage.port)?;
+        if !recorded.is_empty() {
+            let presented = known_hosts::scan(
+                user,
+                &host.address,
+                stage.port,
+                stage.proxy.as_deref(),
+                stage.ssh.connect_timeout,
+            )?;
+            let changed = presented.iter().any(|fp| {
+                !recorded
+                    .iter()
+                    .any(|r| known_hosts::key_of(r) == known_hosts::key_of(fp))
+            });
+            if changed {
+                output::warning(&format!("The host key of {} has changed", host.address));
+                println!("  Recorded:");
+                for fp in &recorded {
+                    println!("    {}", fp);
+                }
+                println!("  Presented:");
+                for fp in &presented {
+                    println!("    {}", fp);
+                }
+                if !replace && !confirm_replace(&host.address)? {
+                    bail!(
+                        "Kept the recorded key of {}. Re-run with --replace if the host was \
+                         legitimately rebuilt.",
+                        host.address
+                    );
+                }
+                known_hosts::forget(&file, &host.address, stage.port)?;
+            }
+        }
+
+        let session = SshSession::connect_trusting(
+            user,
+            &host.address,
+            stage.port,
+            stage.proxy.as_deref(),
+            &stage.ssh,
+        )
+        .await?;
+        session.close().await?;
+
+        let fingerprints = known_hosts::fingerprints(&file, &host.address, stage.port)?;
+        if fingerprints.is_empty() {
+            output::warning(&format!("No key recorded for {}", host.address));
+            continue;
+        }
+
+        output::success(&format!("Trusted {}", host.address));
+        for fp in fingerprints {
+            println!("  {}", fp);
+        }
+    }
+
+    println!();
+    output::info(&format!("Host keys saved to {}", file.display()));
+    if !stage.ssh.strict_host_keys {
+        output::info(&format!(
+            "Enable verification with `strict_host_keys = true` under [stages.{}.ssh]",
+            stage_name
+        ));
+    }
+
+    Ok(())
+}
+
+fn confirm_replace(address: &str) -> Result<bool> {
+    if !console::user_attended() {
+        return Ok(false);
+    }
+    Ok(dialoguer::Confirm::new()
+        .with_prompt(format!("Replace the recorded key of {}?", address))
+        .default(false)
+        .interact()?)
+}
@@ -0,0 +1,164 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::ShipitConfig;
+use crate::output;
+
+/// Check shipit.toml and the files it relies on without connecting to any
+/// host. Every problem is listed before exiting non-zero.
+pub fn run(config_path: &Path, project_root: &Path) -> Result<()> {
+    let config = ShipitConfig::parse(config_path)?;
+
+    let mut problems = config.problems();
+    problems.extend(check_compose(&config, project_root));
+    problems.extend(check_secrets(&config));
+    problems.extend(check_accessories(&config));
+
+    if problems.is_empty() {
+        output::done(&format!(
+            "{} is valid ({} stage(s), {} accessory(ies))",
+            config_path.display(),
+            config.stages.len(),
+            config.accessories.len()
+        ));
+        return Ok(());
+    }
+
+    for problem in &problems {
+        output::error(problem);
+    }
+    println!();
+    bail!(
+        "{} problem(s) found in {}",
+        problems.len(),
+        config_path.display()
+    );
+}
+
+/// The compose file must exist and define the web service and every
+/// service named in deploy.health_check.services, deploy.services and
+/// stage resources
+fn check_compose(config: &ShipitConfig, project_root: &Path) -> Vec<String> {
+    let Some(path) = crate::compose::COMPOSE_FILES
+        .iter()
+        .map(|f| project_root.join(f))
+        .find(|p| p.exists())
+    else {
+        return vec![format!(
+            "No compose file found (expected one of: {})",
+            crate::compose::COMPOSE_FILES.join(", ")
+        )];
+    };
+
+    let services = match compose_services(&path) {
+        Ok(services) => services,
+        Err(e) => return vec![format!("{:#}", e)],
+    };
+    let mut names: Vec<&str> = services.iter().map(String::as_str).collect();
+    names.sort();
+    let available = if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    };
+
+    let web_service = config.deploy.web_service.as_deref().unwrap_or("web");
+    let mut problems = Vec::new();
+    if !services.contains(web_service) {
+        problems.push(format!(
+            "{} has no '{}' service (deploy.web_service); services: {}",
+            path.display(),
+            web_service,
+            available
+        ));
+    }
+    for service in &config.deploy.health_check.services {
+        if !services.contains(service) {
+            problems.push(format!(
+                "{} has no '{}' service (deploy.health_check.services); services: {}",
+                path.display(),
+                service,
+                available
+            ));
+        }
+    }
+    let mut configured: Vec<&String> = config.deploy.services.keys().collect();
+    configured.sort();
+    for service in configured {
+        if !services.contains(service) {
+            problems.push(format!(
+                "{} has no '{}' service (deploy.services); services: {}",
+                path.display(),
+                service,
+                available
+            ));
+        }
+    }
+    let mut stages: Vec<_> = config.stages.iter().collect();
+    stages.sort_by_key(|(name, _)| name.as_str());
+    for (stage_name, stage) in stages {
+        let mut limited: Vec<&String> = stage.resources.keys().collect();
+        limited.sort();
+        for service in limited {
+            if !services.contains(service) {
+                problems.push(format!(
+                    "{} has no '{}' service (stages.{}.resources); services: {}",
+                    path.display(),
+                    service,
+                    stage_name,
+                    available
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Service names from the compose file, read offline (no `docker compose config`)
+pub(super) fn compose_services(path: &Path) -> Result<HashSet<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    crate::compose::service_names(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn check_secrets(config: &ShipitConfig) -> Vec<String> {
+    match crate::secrets::key::load_recipients(&config.secrets) {
+        Ok(_) => Vec::new(),
+        Err(e) => vec![format!("secrets.recipients: {:#}", e)],
+    }
+}
+
+/// Accessories run on a host of some stage, so their address must appear in one
+fn check_accessories(config: &ShipitConfig) -> Vec<String> {
+    // Discovered hosts are only known at runtime
+    if config
+        .stages
+        .values()
+        .any(|stage| stage.discovery.is_some())
+    {
+        return Vec::new();
+    }
+
+    let addresses: HashSet<&str> = config
+        .stages
+        .values()
+        .flat_map(|stage| stage.hosts.iter().map(|h| h.address.as_str()))
+        .collect();
+
+    let mut names: Vec<_> = config.accessories.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let host = &config.accessories[name].host;
+            (!host.is_empty() && !addresses.contains(host.as_str())).then(|| {
+                format!(
+                    "Accessory '{}' host {} is not a host of any stage",
+                    name, host
+                )
+            })
+        })
+        .collect()
+}
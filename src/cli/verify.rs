@@ -0,0 +1,226 @@
+use anyhow::{bail, Result};
+use console::style;
+use std::path::PathBuf;
+
+use crate::config::{HostConfig, ShipitConfig, StageConfig};
+use crate::output;
+use crate::release::lock::ShipitLock;
+use crate::ssh::SshSession;
+
+/// What must be identical on every host of a stage
+struct Fingerprint {
+    /// Release the `current` symlink points at
+    release: String,
+    git_sha: String,
+    secrets_hash: String,
+    /// sha256 of the live docker-compose.override.yml
+    override_sum: String,
+}
+
+impl Fingerprint {
+    fn parse(output: &str) -> Self {
+        let field = |key: &str| {
+            output
+                .lines()
+                .find_map(|l| l.strip_prefix(&format!("{}=", key)))
+                .map(|v| v.trim().to_string())
+                .unwrap_or_default()
+        };
+        let lock: Option<ShipitLock> = serde_json::from_str(&field("lock")).ok();
+        let current = field("current");
+        Self {
+            release: current.rsplit('/').next().unwrap_or_default().to_string(),
+            git_sha: lock.as_ref().map(|l| l.git_sha.clone()).unwrap_or_default(),
+            secrets_hash: lock.and_then(|l| l.secrets_hash).unwrap_or_default(),
+            override_sum: field("override"),
+        }
+    }
+
+    fn fields(&self) -> [(&'static str, &str); 4] {
+        [
+            ("release", &self.release),
+            ("sha", &self.git_sha),
+            ("secrets", &self.secrets_hash),
+            ("override", &self.override_sum),
+        ]
+    }
+}
+
+fn script(app_path: &str) -> String {
+    format!(
+        r#"echo "current=$(readlink {app}/current 2>/dev/null)"
+echo "lock=$(tr -d '\n' < {app}/shipit.lock 2>/dev/null)"
+echo "override=$(sha256sum {app}/current/docker-compose.override.yml 2>/dev/null | cut -d' ' -f1)"
+true"#,
+        app = app_path
+    )
+}
+
+/// The value most hosts agree on (ties go to the first host)
+fn majority<'a>(values: impl Iterator<Item = &'a str>) -> &'a str {
+    let values: Vec<&str> = values.collect();
+    values
+        .iter()
+        .copied()
+        .max_by_key(|v| {
+            let count = values.iter().filter(|other| *other == v).count();
+            let first = values.iter().position(|other| other == v).unwrap_or(0);
+            (count, std::cmp::Reverse(first))
+        })
+        .unwrap_or_default()
+}
+
+/// Compare the live release, commit, secrets hash and override file across
+/// the hosts of a stage. Hosts that disagree with the majority can be
+/// re-deployed on their own (`--redeploy`, or after a prompt).
+pub async fn run(
+    mut config: ShipitConfig,
+    stage_name: &str,
+    project_root: PathBuf,
+    redeploy: bool,
+) -> Result<()> {
+    let stage = config.stage(stage_name)?;
+    let app_path = config.app_path();
+
+    output::header(&format!("Verifying {} on {}", config.app.name, stage_name));
+
+    let reports =
+        futures::future::join_all(stage.hosts.iter().map(|host| probe(stage, host, &app_path)))
+            .await;
+
+    let mut unreachable = Vec::new();
+    let mut hosts: Vec<(&HostConfig, Fingerprint)> = Vec::new();
+    for (host, report) in stage.hosts.iter().zip(reports) {
+        match report {
+            Ok(fingerprint) => hosts.push((host, fingerprint)),
+            Err(e) => unreachable.push(format!("{}: {:#}", host.address, e)),
+        }
+    }
+
+    let expected = Fingerprint {
+        release: majority(hosts.iter().map(|(_, f)| f.release.as_str())).to_string(),
+        git_sha: majority(hosts.iter().map(|(_, f)| f.git_sha.as_str())).to_string(),
+        secrets_hash: majority(hosts.iter().map(|(_, f)| f.secrets_hash.as_str())).to_string(),
+        override_sum: majority(hosts.iter().map(|(_, f)| f.override_sum.as_str())).to_string(),
+    };
+
+    println!(
+        "  {:<22} {:<24} {:<8} {:<8} {}",
+        style("HOST").bold(),
+        style("RELEASE").bold(),
+        style("SHA").bold(),
+        style("SECRETS").bold(),
+        style("OVERRIDE").bold()
+    );
+    let mut divergent = Vec::new();
+    for (host, fingerprint) in &hosts {
+        let mut differs = Vec::new();
+        let cells: Vec<String> = fingerprint
+            .fields()
+            .iter()
+            .zip(expected.fields())
+            .map(|((name, value), (_, want))| {
+                let short = if *name == "release" {
+                    value.to_string()
+                } else {
+                    value[..7.min(value.len())].to_string()
+                };
+                let short = if short.is_empty() {
+                    "-".to_string()
+                } else {
+                    short
+                };
+                if *value == want {
+                    short
+                } else {
+                    differs.push(*name);
+                    style(short).red().to_string()
+                }
+            })
+            .collect();
+        // `{:<N}` would count a red cell's escape codes as width
+        let pad = |cell: &str, width: usize| {
+            let len = console::measure_text_width(cell);
+            format!("{}{}", cell, " ".repeat(width.saturating_sub(len)))
+        };
+        println!(
+            "  {:<22} {} {} {} {}",
+            host.address,
+            pad(&cells[0], 24),
+            pad(&cells[1], 8),
+            pad(&cells[2], 8),
+            cells[3]
+        );
+        if !differs.is_empty() {
+            divergent.push((host.address.clone(), differs));
+        }
+    }
+    for problem in &unreachable {
+        println!("  {}", style(problem).red());
+    }
+
+    println!();
+    if divergent.is_empty() {
+        if !unreachable.is_empty() {
+            bail!("{} host(s) unreachable", unreachable.len());
+        }
+        output::done(&format!("All {} host(s) in sync", hosts.len()));
+        return Ok(());
+    }
+    for (address, differs) in &divergent {
+        output::warning(&format!("{} differs in {}", address, differs.join(", ")));
+    }
+
+    let confirmed = redeploy
+        || (console::user_attended()
+            && dialoguer::Confirm::new()
+                .with_prompt(format!("Re-deploy {} divergent host(s)?", divergent.len()))
+                .default(false)
+                .interact()?);
+    if !confirmed {
+        bail!(
+            "{} of {} host(s) out of sync",
+            divergent.len(),
+            stage.hosts.len()
+        );
+    }
+
+    // A re-deploy ships local HEAD; it must be what the in-sync hosts run
+    let head = crate::deploy::context::head_sha(&project_root);
+    if !expected.git_sha.is_empty() && head.as_deref() != Some(expected.git_sha.as_str()) {
+        bail!(
+            "Local HEAD is {}, but the in-sync hosts run {}. Check out {} to re-deploy the divergent hosts.",
+            head.as_deref().map_or("unknown", |h| &h[..7.min(h.len())]),
+            &expected.git_sha[..7.min(expected.git_sha.len())],
+            expected.git_sha
+        );
+    }
+
+    // Reuse the in-sync release name unless a divergent host already runs
+    // it (its directory would be rebuilt under the running containers)
+    let release_name = (!expected.release.is_empty()
+        && !hosts.iter().any(|(host, f)| {
+            f.release == expected.release && divergent.iter().any(|(a, _)| *a == host.address)
+        }))
+    .then(|| expected.release.clone());
+
+    let addresses: Vec<String> = divergent.into_iter().map(|(address, _)| address).collect();
+    if let Some(stage) = config.stages.get_mut(stage_name) {
+        stage.hosts.retain(|h| addresses.contains(&h.address));
+    }
+    crate::cli::deploy::redeploy(config, stage_name, project_root, release_name).await
+}
+
+async fn probe(stage: &StageConfig, host: &HostConfig, app_path: &str) -> Result<Fingerprint> {
+    let session = SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await?;
+    let result = session.exec(&script(app_path)).await;
+    session.close().await?;
+    Ok(Fingerprint::parse(&result?))
+}
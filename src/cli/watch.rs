@@ -0,0 +1,175 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::release::lock::ShipitLock;
+use crate::ssh::SshSession;
+
+pub struct WatchOptions {
+    /// Seconds between polls of the branch
+    pub interval: u64,
+    /// Minimum seconds between two deploys
+    pub cooldown: u64,
+    pub remote: String,
+}
+
+/// Poll the configured branch and deploy each new commit. Runs until
+/// interrupted; failed deploys are reported and retried only when the
+/// branch moves again.
+pub async fn run(
+    config_path: &Path,
+    project_root: PathBuf,
+    stage_name: &str,
+    opts: &WatchOptions,
+) -> Result<()> {
+    let dirty = git(
+        &project_root,
+        &["status", "--porcelain", "--untracked-files=no"],
+    )?;
+    if !dirty.is_empty() {
+        bail!(
+            "{} has uncommitted changes; run `shipit watch` from a dedicated clone",
+            project_root.display()
+        );
+    }
+
+    let config = super::load_for_stage(config_path, stage_name, &project_root)?;
+    let branch = config.app.branch.clone();
+    let mut last_seen = deployed_sha(&config, stage_name).await;
+
+    output::header(&format!(
+        "Watching {}/{} for {} (every {}s, cooldown {}s)",
+        opts.remote, branch, stage_name, opts.interval, opts.cooldown
+    ));
+    match &last_seen {
+        Some(sha) => output::info(&format!("Live on {}: {}", stage_name, short(sha))),
+        None => output::info(&format!("Nothing deployed on {} yet", stage_name)),
+    }
+
+    let mut last_deploy: Option<Instant> = None;
+    loop {
+        match poll(&project_root, &opts.remote, &branch) {
+            Ok(sha) if last_seen.as_deref() != Some(sha.as_str()) => {
+                if let Some(wait) = last_deploy
+                    .map(|at| Duration::from_secs(opts.cooldown).saturating_sub(at.elapsed()))
+                    .filter(|wait| !wait.is_zero())
+                {
+                    log(&format!(
+                        "New commit {}, deploying after the cooldown ({}s)",
+                        short(&sha),
+                        wait.as_secs()
+                    ));
+                    if sleep_or_exit(wait).await {
+                        break;
+                    }
+                }
+
+                log(&format!("Deploying {} to {}", short(&sha), stage_name));
+                last_deploy = Some(Instant::now());
+                // The commit is only tried once; a fix means a new commit
+                last_seen = Some(sha.clone());
+                match deploy(config_path, &project_root, stage_name, &sha).await {
+                    Ok(()) => log(&format!("Deployed {}", short(&sha))),
+                    Err(e) => output::error(&format!("Deploy of {} failed: {:#}", short(&sha), e)),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => output::warning(&format!("Polling {} failed: {:#}", branch, e)),
+        }
+
+        if sleep_or_exit(Duration::from_secs(opts.interval)).await {
+            break;
+        }
+    }
+
+    output::info("Stopped watching");
+    Ok(())
+}
+
+/// Fetch the branch and return the commit it points at
+pub(super) fn poll(project_root: &Path, remote: &str, branch: &str) -> Result<String> {
+    check_ref(project_root, branch)?;
+    git(project_root, &["fetch", "--quiet", "--", remote, branch])?;
+    git(project_root, &["rev-parse", "FETCH_HEAD"])
+}
+
+/// Check out `sha` and deploy it with the config from that commit
+async fn deploy(
+    config_path: &Path,
+    project_root: &Path,
+    stage_name: &str,
+    sha: &str,
+) -> Result<()> {
+    git(project_root, &["checkout", "--quiet", "--detach", sha])?;
+    let config = super::load_for_stage(config_path, stage_name, project_root)?;
+    super::deploy::run(config, stage_name, project_root.to_path_buf(), false).await
+}
+
+/// The commit live on the stage's first host, from its shipit.lock
+async fn deployed_sha(config: &ShipitConfig, stage_name: &str) -> Option<String> {
+    let stage = config.stage(stage_name).ok()?;
+    let host = stage.hosts.first()?;
+    let session = SshSession::connect(
+        stage.user.as_deref().unwrap_or("deploy"),
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await
+    .ok()?;
+    let lock = ShipitLock::read(&session, &config.app_path()).await;
+    let _ = session.close().await;
+    lock.ok().flatten().map(|l| l.git_sha)
+}
+
+/// Reject anything that is not a plain branch or tag name, so a ref from
+/// the API can't pass options or a refspec to `git fetch`
+pub(super) fn check_ref(project_root: &Path, git_ref: &str) -> Result<()> {
+    if git_ref.starts_with('-') || git_ref.contains(':') {
+        bail!("Invalid ref '{}'", git_ref);
+    }
+    git(project_root, &["check-ref-format", "--branch", git_ref])
+        .with_context(|| format!("Invalid ref '{}'", git_ref))?;
+    Ok(())
+}
+
+pub(super) fn git(project_root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(project_root)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Sleep, returning true if interrupted with Ctrl-C (SIGINT). A deploy in
+/// progress is never interrupted; the watcher stops at the next wait.
+async fn sleep_or_exit(duration: Duration) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => false,
+        _ = tokio::signal::ctrl_c() => true,
+    }
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+fn log(msg: &str) {
+    output::info(&format!(
+        "[{}] {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        msg
+    ));
+}
@@ -1,24 +1,92 @@
 use anyhow::{Context, Result};
 use minijinja::Environment;
 use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
-use crate::config::{ShipitConfig, TraefikConfig};
+use crate::config::{HostConfig, LoggingConfig, ResourceLimits, ShipitConfig, StageConfig};
 
 const OVERRIDE_TEMPLATE: &str = include_str!("../../templates/docker-compose.override.yml.j2");
 
+/// File names `docker compose` picks up by default
+pub const COMPOSE_FILES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// Service names defined in a compose file's YAML
+pub fn service_names(yaml: &str) -> Result<HashSet<String>> {
+    let doc: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+    Ok(doc["services"]
+        .as_mapping()
+        .map(|services| {
+            services
+                .keys()
+                .filter_map(|k| k.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ImageService {
     pub name: String,
     pub image: String,
 }
 
+/// A non-web service the override touches: a pre-built image, limits,
+/// networks or just the logging settings every service gets
+#[derive(Debug, Serialize)]
+struct OverrideService<'a> {
+    name: &'a str,
+    image: Option<&'a str>,
+    resources: Option<&'a ResourceLimits>,
+    /// Networks to join (empty leaves the compose file's as they are)
+    networks: Vec<&'a str>,
+    aliases: &'a [String],
+    /// Not for this host's roles: scaled to zero so `up` doesn't start it
+    off: bool,
+}
+
+/// `logging:` of every service, with max_size/max_file as driver options
+#[derive(Debug, Serialize)]
+struct Logging<'a> {
+    driver: &'a str,
+    options: BTreeMap<&'a str, String>,
+}
+
+impl<'a> Logging<'a> {
+    fn new(config: &'a LoggingConfig) -> Self {
+        let mut options: BTreeMap<&str, String> = config
+            .options
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
+        if let Some(size) = &config.max_size {
+            options.insert("max-size", size.clone());
+        }
+        if let Some(files) = config.max_file {
+            options.insert("max-file", files.to_string());
+        }
+        Self {
+            driver: &config.driver,
+            options,
+        }
+    }
+}
+
 pub fn generate_override(
     config: &ShipitConfig,
-    traefik: &TraefikConfig,
+    stage: &StageConfig,
+    host: &HostConfig,
     shared_path: &str,
     web_image: Option<&str>,
     image_services: &[ImageService],
+    compose_services: &[String],
 ) -> Result<String> {
+    let traefik = stage.traefik.as_ref();
+    let resources = &stage.resources;
     let web_service = config
         .deploy
         .web_service
@@ -27,6 +95,34 @@ pub fn generate_override(
 
     let hc = &config.deploy.health_check;
 
+    // Logging applies to every service of the compose file; each service may
+    // appear only once in the override
+    let logging = config.deploy.logging.as_ref().map(Logging::new);
+    let mut names: BTreeSet<&str> = image_services
+        .iter()
+        .map(|s| s.name.as_str())
+        .chain(resources.keys().map(String::as_str))
+        .chain(config.deploy.services.keys().map(String::as_str))
+        .collect();
+    if logging.is_some() {
+        names.extend(compose_services.iter().map(String::as_str));
+    }
+    names.remove(web_service);
+    let services: Vec<OverrideService> = names
+        .into_iter()
+        .map(|name| OverrideService {
+            name,
+            image: image_services
+                .iter()
+                .find(|s| s.name == name)
+                .map(|s| s.image.as_str()),
+            resources: resources.get(name),
+            networks: networks(config, name, &["default"]),
+            aliases: aliases(config, name),
+            off: !config.deploy.runs_on(name, host),
+        })
+        .collect();
+
     let mut env = Environment::new();
     env.add_template("override", OVERRIDE_TEMPLATE)
         .context("Failed to load override template")?;
@@ -35,19 +131,50 @@ pub fn generate_override(
     let rendered = tmpl
         .render(minijinja::context! {
             web_service => web_service,
+            web_off => !config.deploy.runs_on(web_service, host),
             app_name => &config.app.name,
-            domain => &traefik.domain,
+            traefik => traefik.is_some(),
+            domain => traefik.map(|t| t.domain.as_str()),
             port => hc.port,
             health_path => &hc.path,
             health_interval => hc.interval,
             health_retries => hc.retries,
             health_cmd => &hc.cmd,
-            tls => traefik.tls,
+            tls => traefik.is_some_and(|t| t.tls),
             shared_path => shared_path,
             web_image => web_image,
-            image_services => image_services,
+            web_resources => resources.get(web_service),
+            web_networks => networks(config, web_service, &["traefik", "default"]),
+            web_aliases => aliases(config, web_service),
+            extra_networks => config.deploy.extra_networks(),
+            services => services,
+            logging => logging,
         })
         .context("Failed to render override template")?;
 
     Ok(rendered)
 }
+
+/// Networks a service joins: `base` plus its configured extras. Services
+/// without extras keep their compose networks (empty list).
+fn networks<'a>(config: &'a ShipitConfig, service: &str, base: &[&'a str]) -> Vec<&'a str> {
+    match config.deploy.services.get(service) {
+        Some(settings) if !settings.networks.is_empty() || !settings.aliases.is_empty() => base
+            .iter()
+            .copied()
+            .chain(settings.networks.iter().map(String::as_str))
+            .collect(),
+        // The web service always joins Traefik's network and its own
+        _ if base.contains(&"traefik") => base.to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+fn aliases<'a>(config: &'a ShipitConfig, service: &str) -> &'a [String] {
+    config
+        .deploy
+        .services
+        .get(service)
+        .map(|s| s.aliases.as_slice())
+        .unwrap_or_default()
+}
@@ -0,0 +1,79 @@
+use anyhow::{bail, Result};
+use toml::{Table, Value};
+
+/// Layout version written by this shipit. Files without `version` are 1.
+pub const CURRENT_VERSION: i64 = 2;
+
+/// `MIGRATIONS[n]` upgrades a version n+1 table to n+2 and describes what it changed
+const MIGRATIONS: [fn(&mut Table) -> Vec<String>; 1] = [v1_to_v2];
+
+/// The file's `version` (1 when absent)
+pub fn version(table: &Table) -> Result<i64> {
+    match table.get("version") {
+        None => Ok(1),
+        Some(Value::Integer(v)) if *v >= 1 => Ok(*v),
+        Some(other) => bail!("version must be a positive integer, got {}", other),
+    }
+}
+
+/// Upgrade `table` in place to CURRENT_VERSION, returning the changes made
+pub fn upgrade(table: &mut Table) -> Result<Vec<String>> {
+    let from = version(table)?;
+    if from > CURRENT_VERSION {
+        bail!(
+            "shipit.toml is config version {}, but this shipit only understands up to {}. Upgrade shipit.",
+            from,
+            CURRENT_VERSION
+        );
+    }
+
+    let mut changes = Vec::new();
+    for migration in &MIGRATIONS[(from - 1) as usize..] {
+        changes.extend(migration(table));
+    }
+    table.insert("version".to_string(), Value::Integer(CURRENT_VERSION));
+    Ok(changes)
+}
+
+/// Version 2 only adds the `version` key itself; the layout is unchanged
+fn v1_to_v2(_table: &mut Table) -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{upgrade, version, CURRENT_VERSION};
+    use toml::Table;
+
+    #[test]
+    fn stamps_the_version_on_v1_files() {
+        let mut table: Table =
+            toml::from_str("[stages.production]\nhosts = [{ address = \"10.0.0.1\" }]\n").unwrap();
+        let mut expected = table.clone();
+        expected.insert("version".to_string(), CURRENT_VERSION.into());
+
+        assert!(upgrade(&mut table).unwrap().is_empty());
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn leaves_current_files_alone() {
+        let mut table: Table = toml::from_str(&format!(
+            "version = {}\n[stages.production]\nhosts = [{{ address = \"10.0.0.1\" }}]\n",
+            CURRENT_VERSION
+        ))
+        .unwrap();
+        let before = table.clone();
+
+        assert!(upgrade(&mut table).unwrap().is_empty());
+        assert_eq!(table, before);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let newer: Table = toml::from_str(&format!("version = {}", CURRENT_VERSION + 1)).unwrap();
+        assert!(upgrade(&mut newer.clone()).is_err());
+        assert!(version(&toml::from_str("version = 0").unwrap()).is_err());
+        assert_eq!(version(&Table::new()).unwrap(), 1);
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
 
+pub mod migrate;
 mod validate;
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -11,6 +12,402 @@ pub struct SecretsConfig {
     pub recipients: Vec<String>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetupConfig {
+    /// Disable password auth and root login in sshd
+    #[serde(default)]
+    pub harden_ssh: bool,
+    /// Install fail2ban with an sshd jail
+    #[serde(default)]
+    pub fail2ban: bool,
+    /// IANA timezone to set on every host (e.g. "UTC")
+    pub timezone: Option<String>,
+    /// Enable NTP time sync (systemd-timesyncd, falling back to chrony)
+    #[serde(default)]
+    pub time_sync: bool,
+    /// Seconds of host clock skew tolerated before deploy warns / setup --check fails
+    #[serde(default = "default_max_clock_skew")]
+    pub max_clock_skew: u64,
+}
+
+impl Default for SetupConfig {
+    fn default() -> Self {
+        Self {
+            harden_ssh: false,
+            fail2ban: false,
+            timezone: None,
+            time_sync: false,
+            max_clock_skew: default_max_clock_skew(),
+        }
+    }
+}
+
+fn default_max_clock_skew() -> u64 {
+    10
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct HooksConfig {
+    /// Commands run on the machine running shipit, once per deploy
+    #[serde(default)]
+    pub local: LocalHooks,
+    /// Commands run on each host during deploy
+    #[serde(default)]
+    pub remote: RemoteHooks,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct LocalHooks {
+    /// Before anything changes on the hosts; a failure aborts the deploy
+    #[serde(default)]
+    pub before_deploy: Vec<String>,
+    /// Once every host runs the new release
+    #[serde(default)]
+    pub after_deploy: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct RemoteHooks {
+    /// After the images are built, before the new release starts (migrations)
+    #[serde(default)]
+    pub after_build: Vec<RemoteHook>,
+    /// After the new release passes its health check, before traffic switches
+    #[serde(default, alias = "before_symlink")]
+    pub after_start: Vec<RemoteHook>,
+    /// After `current` points at the new release
+    #[serde(default, alias = "after_deploy")]
+    pub after_switch: Vec<RemoteHook>,
+}
+
+/// A remote hook: a shell command run in the release directory, or
+/// `{ service, cmd }` to run it inside one of the release's containers
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum RemoteHook {
+    Host(String),
+    Container { service: String, cmd: String },
+}
+
+/// Local VM settings for `shipit local`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LocalConfig {
+    /// VM backend: "multipass" or "vagrant"
+    #[serde(default = "default_local_provider")]
+    pub provider: String,
+    /// Multipass image (default "24.04")
+    pub image: Option<String>,
+    /// Vagrant box (default "bento/ubuntu-24.04"), e.g. "generic/rocky9"
+    #[serde(rename = "box")]
+    pub vagrant_box: Option<String>,
+    /// Vagrant provider to use (e.g. "libvirt" for QEMU/KVM); Vagrant's default if unset
+    pub vagrant_provider: Option<String>,
+    #[serde(default = "default_local_cpus")]
+    pub cpus: u32,
+    #[serde(default = "default_local_memory_mb")]
+    pub memory_mb: u32,
+    #[serde(default = "default_local_disk_gb")]
+    pub disk_gb: u32,
+    /// Route the local app through Traefik; when false the compose ports are published as-is
+    #[serde(default = "default_true")]
+    pub traefik: bool,
+    /// Command run inside the web container after a successful `local deploy`
+    pub seed_cmd: Option<String>,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            provider: default_local_provider(),
+            image: None,
+            vagrant_box: None,
+            vagrant_provider: None,
+            cpus: default_local_cpus(),
+            memory_mb: default_local_memory_mb(),
+            disk_gb: default_local_disk_gb(),
+            traefik: true,
+            seed_cmd: None,
+        }
+    }
+}
+
+fn default_local_provider() -> String {
+    "multipass".to_string()
+}
+
+fn default_local_cpus() -> u32 {
+    2
+}
+
+fn default_local_memory_mb() -> u32 {
+    2048
+}
+
+fn default_local_disk_gb() -> u32 {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Alert thresholds for `shipit monitor` (unset thresholds are not checked)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MonitorConfig {
+    /// Container CPU % (can exceed 100 on multi-core hosts)
+    pub cpu_percent: Option<f64>,
+    /// Container memory % of its limit
+    pub memory_percent: Option<f64>,
+    /// Disk use % of the filesystem holding deploy_to
+    pub disk_percent: Option<f64>,
+    /// Container restart count
+    pub restarts: Option<u64>,
+    /// Ring the terminal bell when a threshold is crossed
+    #[serde(default = "default_true")]
+    pub bell: bool,
+    /// URL that receives a JSON POST for every newly crossed threshold
+    pub webhook_url: Option<String>,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            cpu_percent: None,
+            memory_percent: None,
+            disk_percent: None,
+            restarts: None,
+            bell: true,
+            webhook_url: None,
+        }
+    }
+}
+
+/// Where deploy outcomes are reported
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct NotifyConfig {
+    /// Healthchecks.io-style check URL, pinged at deploy start (/start),
+    /// success and failure (/fail)
+    pub healthchecks_url: Option<String>,
+    /// Create a Sentry release and deploy after each successful deploy
+    pub sentry: Option<SentryConfig>,
+    /// Email deploy and rollback outcomes over SMTP
+    pub email: Option<EmailConfig>,
+    /// Receive JSON events (see `WEBHOOK_EVENTS`)
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+/// Events a `[[notify.webhooks]]` entry can subscribe to
+pub const WEBHOOK_EVENTS: [&str; 5] = [
+    "deploy.started",
+    "deploy.step",
+    "deploy.finished",
+    "rollback.finished",
+    "accessory.booted",
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Stage secret holding the HMAC-SHA256 signing key
+    pub secret: Option<String>,
+    /// Events to send (all when empty)
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Body of the request: the JSON event, or a chat message for a Slack or
+    /// Discord incoming webhook (see `WEBHOOK_FORMATS`)
+    #[serde(default = "default_webhook_format")]
+    pub format: String,
+}
+
+/// `format` values of a `[[notify.webhooks]]` entry
+pub const WEBHOOK_FORMATS: [&str; 3] = ["json", "slack", "discord"];
+
+fn default_webhook_format() -> String {
+    "json".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SentryConfig {
+    pub org: String,
+    pub project: String,
+    /// Sentry base URL (self-hosted installs)
+    #[serde(default = "default_sentry_url")]
+    pub url: String,
+    /// Stage secret holding the auth token (needs project:releases scope)
+    #[serde(default = "default_sentry_token_secret")]
+    pub token_secret: String,
+}
+
+fn default_sentry_url() -> String {
+    "https://sentry.io".to_string()
+}
+
+fn default_sentry_token_secret() -> String {
+    "SENTRY_AUTH_TOKEN".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmailConfig {
+    /// SMTP server
+    pub host: String,
+    /// 465 uses implicit TLS, anything else STARTTLS (unless `tls = false`)
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default = "default_true")]
+    pub tls: bool,
+    pub from: String,
+    pub to: Vec<String>,
+    /// SMTP login; no authentication when unset
+    pub username: Option<String>,
+    /// Stage secret holding the SMTP password
+    #[serde(default = "default_smtp_password_secret")]
+    pub password_secret: String,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_password_secret() -> String {
+    "SMTP_PASSWORD".to_string()
+}
+
+/// Command audit log (always kept locally in .shipit/audit.log)
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct AuditConfig {
+    /// Also append each entry to `<app path>/audit.log` on the stage's hosts
+    #[serde(default)]
+    pub remote: bool,
+}
+
+/// A recurring job, run on the hosts by a systemd timer in the current release
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CronJob {
+    /// Cron expression ("0 3 * * *") or @hourly/@daily/@weekly/@monthly
+    pub schedule: String,
+    /// Compose service the command runs in
+    pub service: String,
+    pub cmd: String,
+    /// "exec" into the running container, or "run" a one-off container
+    #[serde(default = "default_cron_mode")]
+    pub mode: String,
+    /// Run on every host of the stage instead of only the first one
+    #[serde(default)]
+    pub all_hosts: bool,
+}
+
+fn default_cron_mode() -> String {
+    "exec".to_string()
+}
+
+/// Dangling image layers and build cache outlive the releases that made them
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GcConfig {
+    /// Cron expression or @daily-style shorthand
+    #[serde(default = "default_gc_schedule")]
+    pub schedule: String,
+    /// Only prune what is older than this Docker duration, e.g. "168h"
+    #[serde(default = "default_gc_keep")]
+    pub keep: String,
+    /// Also prune unused tagged images, including those of old releases
+    /// (a rollback to them then has to rebuild or pull)
+    #[serde(default)]
+    pub all_images: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            schedule: default_gc_schedule(),
+            keep: default_gc_keep(),
+            all_images: false,
+        }
+    }
+}
+
+fn default_gc_schedule() -> String {
+    "@daily".to_string()
+}
+
+fn default_gc_keep() -> String {
+    "168h".to_string()
+}
+
+/// Scheduled backups of named volumes and accessory databases
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BackupsConfig {
+    /// "s3://bucket/prefix" (also S3-compatible stores via `endpoint`) or an
+    /// absolute path on the hosts
+    pub target: String,
+    /// Cron expression or @daily-style shorthand
+    #[serde(default = "default_backup_schedule")]
+    pub schedule: String,
+    /// Backups kept per host; older ones are deleted after each run
+    #[serde(default = "default_backup_retention")]
+    pub retention: usize,
+    /// Docker volume names (as in `docker volume ls`)
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// Postgres/MySQL/MariaDB accessories to dump
+    #[serde(default)]
+    pub accessories: Vec<String>,
+    /// S3 API endpoint for non-AWS stores (B2, R2, MinIO)
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    /// Stage secrets holding the S3 credentials
+    #[serde(default = "default_backup_access_key_secret")]
+    pub access_key_secret: String,
+    #[serde(default = "default_backup_secret_key_secret")]
+    pub secret_key_secret: String,
+}
+
+fn default_backup_schedule() -> String {
+    "@daily".to_string()
+}
+
+fn default_backup_retention() -> usize {
+    7
+}
+
+fn default_backup_access_key_secret() -> String {
+    "BACKUP_ACCESS_KEY_ID".to_string()
+}
+
+fn default_backup_secret_key_secret() -> String {
+    "BACKUP_SECRET_ACCESS_KEY".to_string()
+}
+
+/// Private registry that hosts log in to during setup and deploy
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegistryConfig {
+    /// Registry host (e.g. "ghcr.io")
+    #[serde(alias = "url")]
+    pub server: String,
+    pub username: String,
+    /// Key in the stage's encrypted secrets holding the password/token
+    #[serde(default = "default_registry_password_secret")]
+    pub password_secret: String,
+    /// Path under the server that `deploy.build = "registry"` pushes to
+    /// (defaults to the username)
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+impl RegistryConfig {
+    /// Prefix of pushed images, e.g. "ghcr.io/acme"
+    pub fn repository(&self) -> String {
+        format!(
+            "{}/{}",
+            self.server.trim_end_matches('/'),
+            self.namespace.as_deref().unwrap_or(&self.username)
+        )
+    }
+}
+
+fn default_registry_password_secret() -> String {
+    "REGISTRY_PASSWORD".to_string()
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ShipitConfig {
     pub app: AppConfig,
@@ -18,6 +415,29 @@ pub struct ShipitConfig {
     #[serde(default)]
     pub secrets: SecretsConfig,
     #[serde(default)]
+    pub setup: SetupConfig,
+    pub registry: Option<RegistryConfig>,
+    #[serde(default)]
+    pub local: LocalConfig,
+    #[serde(default)]
+    pub monitor: MonitorConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default, alias = "notifications")]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub cron: HashMap<String, CronJob>,
+    pub backups: Option<BackupsConfig>,
+    /// Scheduled Docker image and build cache cleanup on the hosts
+    pub gc: Option<GcConfig>,
+    /// Plugins (`shipit-<name>` executables on PATH) that receive lifecycle events
+    #[serde(default)]
+    pub plugins: Vec<String>,
+    /// Stage used when -s and $SHIPIT_STAGE are not given
+    pub default_stage: Option<String>,
+    #[serde(default)]
     pub stages: HashMap<String, StageConfig>,
     #[serde(default)]
     pub accessories: HashMap<String, AccessoryConfig>,
@@ -41,11 +461,100 @@ pub struct DeployConfig {
     pub deploy_to: String,
     #[serde(default = "default_keep_releases")]
     pub keep_releases: usize,
+    /// Also keep releases younger than this many days, beyond `keep_releases`
+    pub keep_releases_days: Option<u64>,
+    /// strftime format of release names, with `{sha}` and `{short_sha}`
+    /// (default `%Y%m%d-%H%M%S`)
+    pub release_format: Option<String>,
     #[serde(default = "default_build")]
     pub build: String,
+    /// "sequential" (one host after another) or "rolling"
+    #[serde(default = "default_strategy")]
+    pub strategy: String,
+    /// Rolling deploys: hosts deployed at once, per batch
+    #[serde(default = "default_max_unavailable")]
+    pub max_unavailable: usize,
+    /// Rolling deploys: seconds to watch a batch before the next one starts
+    #[serde(default)]
+    pub bake_time: u64,
     #[serde(default)]
     pub health_check: HealthCheckConfig,
     pub web_service: Option<String>,
+    #[serde(default)]
+    pub preflight: PreflightConfig,
+    /// Accessories that must be running (and healthy, when their image has a
+    /// healthcheck) before the new release starts
+    #[serde(default)]
+    pub depends_on_accessories: Vec<String>,
+    /// Boot stopped `depends_on_accessories` instead of failing the deploy
+    #[serde(default)]
+    pub boot_accessories: bool,
+    /// Logging driver and rotation applied to every service
+    pub logging: Option<LoggingConfig>,
+    /// Extra networks and aliases per compose service
+    #[serde(default)]
+    pub services: HashMap<String, ServiceConfig>,
+    /// Request the stage's domain through Traefik once every host is switched
+    pub public_check: Option<PublicCheckConfig>,
+    /// Paths kept in `shared/` across releases and symlinked into each one
+    #[serde(default)]
+    pub shared: SharedConfig,
+}
+
+/// Directories and files (relative to the release) that outlive releases,
+/// e.g. user uploads. `.env` is always shared.
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct SharedConfig {
+    #[serde(default)]
+    pub dirs: Vec<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+impl SharedConfig {
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty() && self.files.is_empty()
+    }
+}
+
+/// Settings for one compose service rendered into the override
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ServiceConfig {
+    /// Docker networks to join besides the app's own (created if missing),
+    /// e.g. a backend network shared with accessories or other apps
+    #[serde(default)]
+    pub networks: Vec<String>,
+    /// Other names the service answers to on its default and extra networks
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Host roles the service runs on (empty runs it on every host)
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl DeployConfig {
+    /// Whether `service` runs on `host`: services without roles run on every
+    /// host, and hosts without roles run every service
+    pub fn runs_on(&self, service: &str, host: &HostConfig) -> bool {
+        match self.services.get(service) {
+            Some(settings) if !settings.roles.is_empty() && !host.role.is_empty() => {
+                settings.roles.iter().any(|role| host.role.contains(role))
+            }
+            _ => true,
+        }
+    }
+
+    /// Every extra network some service joins, sorted and deduplicated
+    pub fn extra_networks(&self) -> Vec<&str> {
+        let mut networks: Vec<&str> = self
+            .services
+            .values()
+            .flat_map(|s| s.networks.iter().map(String::as_str))
+            .collect();
+        networks.sort();
+        networks.dedup();
+        networks
+    }
 }
 
 fn default_deploy_to() -> String {
@@ -60,6 +569,107 @@ fn default_build() -> String {
     "remote".to_string()
 }
 
+fn default_strategy() -> String {
+    "sequential".to_string()
+}
+
+fn default_max_unavailable() -> usize {
+    1
+}
+
+/// Resource checks run on each host before anything is built or transferred
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PreflightConfig {
+    /// Minimum free space (MB) on deploy_to and the Docker data root (0 disables)
+    #[serde(default = "default_min_disk_mb")]
+    pub min_disk_mb: u64,
+    /// Minimum available memory (MB) (0 disables)
+    #[serde(default = "default_min_memory_mb")]
+    pub min_memory_mb: u64,
+    /// Prune dangling images and build cache when disk is low, then re-check
+    #[serde(default)]
+    pub auto_prune: bool,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self {
+            min_disk_mb: default_min_disk_mb(),
+            min_memory_mb: default_min_memory_mb(),
+            auto_prune: false,
+        }
+    }
+}
+
+fn default_min_disk_mb() -> u64 {
+    2048
+}
+
+fn default_min_memory_mb() -> u64 {
+    256
+}
+
+/// What users see after a deploy: the health path answered through the
+/// public domain, and (with TLS) a certificate that isn't about to expire
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PublicCheckConfig {
+    /// Path to request (defaults to health_check.path)
+    pub path: Option<String>,
+    #[serde(default = "default_public_status")]
+    pub expect_status: u16,
+    /// Attempts while Traefik picks up the new containers (and certificate)
+    #[serde(default = "default_public_retries")]
+    pub retries: u32,
+    /// Seconds between attempts
+    #[serde(default = "default_public_interval")]
+    pub interval: u64,
+    /// Certificates expiring in fewer days are reported
+    #[serde(default = "default_min_cert_days")]
+    pub min_cert_days: i64,
+    /// "warn" or "fail" (the deploy exits non-zero; the release stays live)
+    #[serde(default = "default_public_on_failure")]
+    pub on_failure: String,
+}
+
+fn default_public_status() -> u16 {
+    200
+}
+
+fn default_public_retries() -> u32 {
+    10
+}
+
+fn default_public_interval() -> u64 {
+    3
+}
+
+pub fn default_min_cert_days() -> i64 {
+    14
+}
+
+fn default_public_on_failure() -> String {
+    "warn".to_string()
+}
+
+/// Container logs grow without bound by default; rotate them
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LoggingConfig {
+    /// json-file, local, journald, syslog, ...
+    #[serde(default = "default_logging_driver")]
+    pub driver: String,
+    /// Size a log file may reach before it is rotated, e.g. "10m"
+    pub max_size: Option<String>,
+    /// Rotated files to keep
+    pub max_file: Option<u32>,
+    /// Other driver options (e.g. syslog-address, tag)
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+}
+
+fn default_logging_driver() -> String {
+    "json-file".to_string()
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct HealthCheckConfig {
     #[serde(default = "default_health_path")]
@@ -73,6 +683,10 @@ pub struct HealthCheckConfig {
     #[serde(default = "default_health_retries")]
     pub retries: u32,
     pub cmd: Option<String>,
+    /// Other services that must come up too: healthy when they define a
+    /// healthcheck of their own, otherwise running without restarting
+    #[serde(default)]
+    pub services: Vec<String>,
 }
 
 impl Default for HealthCheckConfig {
@@ -84,6 +698,7 @@ impl Default for HealthCheckConfig {
             interval: default_health_interval(),
             retries: default_health_retries(),
             cmd: None,
+            services: Vec::new(),
         }
     }
 }
@@ -115,11 +730,115 @@ pub struct StageConfig {
     #[serde(default)]
     pub env: HashMap<String, String>,
     pub traefik: Option<TraefikConfig>,
+    #[serde(default)]
+    pub ssh: SshConfig,
+    /// Require `-s` (or confirmation) when selected via default_stage / $SHIPIT_STAGE
+    #[serde(default)]
+    pub protected: bool,
+    /// Overrides for `[deploy]` keys (keep_releases, build, health_check, ...)
+    pub deploy: Option<toml::Table>,
+    /// Look hosts up through a cloud provider's API instead of listing them
+    pub discovery: Option<DiscoveryConfig>,
+    /// Per-service limits rendered into the compose override
+    #[serde(default)]
+    pub resources: HashMap<String, ResourceLimits>,
+}
+
+/// Limits for one service, so a leaking service can't take the host down,
+/// plus the GPUs it reserves
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResourceLimits {
+    /// Memory limit in compose notation, e.g. "512m" or "2g"
+    pub memory: Option<String>,
+    /// CPUs the service may use, e.g. 0.5
+    pub cpus: Option<f64>,
+    /// Maximum number of processes/threads
+    pub pids: Option<u32>,
+    /// NVIDIA GPUs: "all", a count, or a list of device IDs
+    pub gpus: Option<Gpus>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Gpus {
+    /// "all"
+    All(String),
+    Count(u32),
+    Devices(Vec<String>),
+}
+
+impl StageConfig {
+    /// Whether any service reserves a GPU, so hosts need the NVIDIA runtime
+    pub fn uses_gpus(&self) -> bool {
+        self.resources.values().any(|r| r.gpus.is_some())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DiscoveryConfig {
+    /// "hetzner" or "ec2"
+    pub provider: String,
+    /// Labels/tags every host must carry, e.g. "app=myapp,stage=prod"
+    pub tag: String,
+    /// Stage secret holding the Hetzner API token
+    #[serde(default = "default_hcloud_token_secret")]
+    pub token_secret: String,
+    /// AWS region (defaults to the aws CLI's)
+    pub region: Option<String>,
+}
+
+fn default_hcloud_token_secret() -> String {
+    "HCLOUD_TOKEN".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SshConfig {
+    /// Seconds to wait for the SSH connection to be established
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// Seconds a single remote command may run before it is aborted (unlimited if unset)
+    pub command_timeout: Option<u64>,
+    /// Verify host keys against the project's .shipit/known_hosts (see `shipit trust`)
+    #[serde(default)]
+    pub strict_host_keys: bool,
+    /// Forward the local SSH agent to remote builds (also enables BuildKit `--ssh default`)
+    #[serde(default)]
+    pub forward_agent: bool,
+    /// Seconds between keepalive probes on idle connections (0 disables)
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval: u64,
+    /// Unanswered keepalives tolerated before the connection is dropped (ssh default: 3)
+    pub keepalive_count_max: Option<u32>,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: default_connect_timeout(),
+            command_timeout: None,
+            strict_host_keys: false,
+            forward_agent: false,
+            keepalive_interval: default_keepalive_interval(),
+            keepalive_count_max: None,
+        }
+    }
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_keepalive_interval() -> u64 {
+    15
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct HostConfig {
     pub address: String,
+    /// Roles of the host, e.g. `["web"]` or `["worker"]`; services with
+    /// `roles` only run on hosts sharing one of them
+    #[serde(default)]
+    pub role: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -142,17 +861,143 @@ pub struct AccessoryConfig {
     pub volumes: Vec<String>,
 }
 
+/// Fill in keys a stage doesn't set from `[defaults]`. Nested tables (env,
+/// ssh, traefik) are merged key by key; `traefik` is only completed for
+/// stages that define their own, since the domain is always per stage.
+fn merge_defaults(stage: &mut toml::Table, defaults: &toml::Table) {
+    for (key, value) in defaults {
+        match (stage.get_mut(key), value) {
+            (Some(toml::Value::Table(own)), toml::Value::Table(inherited)) => {
+                merge_defaults(own, inherited);
+            }
+            (Some(_), _) => {}
+            (None, _) if key == "traefik" => {}
+            (None, value) => {
+                stage.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Overlay `overrides` on `base`; nested tables (health_check, preflight)
+/// are overlaid key by key
+fn merge_overrides(base: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(own)), toml::Value::Table(inner)) => {
+                merge_overrides(own, inner);
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Move a `[deploy.hooks]` table into `[hooks]`: `before_deploy` and
+/// `after_deploy` run locally, `after_build` and `before_symlink` on the
+/// hosts. Returns whether there was one.
+fn hoist_deploy_hooks(table: &mut toml::Table) -> Result<bool> {
+    let Some(toml::Value::Table(deploy)) = table.get_mut("deploy") else {
+        return Ok(false);
+    };
+    let hooks = match deploy.remove("hooks") {
+        Some(toml::Value::Table(hooks)) => hooks,
+        Some(_) => anyhow::bail!("[deploy.hooks] must be a table"),
+        None => return Ok(false),
+    };
+
+    for (key, value) in hooks {
+        let (side, name) = match key.as_str() {
+            "before_deploy" | "after_deploy" => ("local", key.as_str()),
+            "after_build" => ("remote", "after_build"),
+            "before_symlink" => ("remote", "after_start"),
+            _ => anyhow::bail!(
+                "Unknown hook deploy.hooks.{}. Supported: before_deploy, after_build, \
+                 before_symlink, after_deploy (or see [hooks.local] and [hooks.remote])",
+                key
+            ),
+        };
+        let toml::Value::Array(commands) = value else {
+            anyhow::bail!("deploy.hooks.{} must be a list", key);
+        };
+        let hooks = table
+            .entry("hooks")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        let side = hooks
+            .as_table_mut()
+            .context("[hooks] must be a table")?
+            .entry(side)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("[hooks.{}] must be a table", side))?;
+        match side
+            .entry(name)
+            .or_insert_with(|| toml::Value::Array(Vec::new()))
+        {
+            toml::Value::Array(existing) => existing.extend(commands),
+            _ => anyhow::bail!("hooks.{} must be a list", name),
+        }
+    }
+    Ok(true)
+}
+
 impl ShipitConfig {
     pub fn load(path: &Path) -> Result<Self> {
+        let config = Self::parse(path)?;
+        validate::validate(&config)?;
+        Ok(config)
+    }
+
+    /// Read and deserialize shipit.toml without validating it
+    pub fn parse(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-        let config: Self = toml::from_str(&content)
+        let mut table: toml::Table = toml::from_str(&content)
             .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
 
-        validate::validate(&config)?;
+        let changes = migrate::upgrade(&mut table)
+            .with_context(|| format!("Failed to load config file: {}", path.display()))?;
+        if !changes.is_empty() {
+            crate::output::warning(&format!(
+                "{} uses an older config layout (upgraded in memory). Run `shipit config migrate` to update the file.",
+                path.display()
+            ));
+        }
 
-        Ok(config)
+        let defaults = match table.remove("defaults") {
+            Some(toml::Value::Table(defaults)) => Some(defaults),
+            Some(_) => anyhow::bail!("[defaults] must be a table"),
+            None => None,
+        };
+
+        let hoisted = hoist_deploy_hooks(&mut table)
+            .with_context(|| format!("Failed to load config file: {}", path.display()))?;
+
+        // Untouched files are deserialized from the text itself so errors keep line numbers
+        if changes.is_empty() && defaults.is_none() && !hoisted {
+            return toml::from_str(&content)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()));
+        }
+
+        if let (Some(defaults), Some(toml::Value::Table(stages))) =
+            (&defaults, table.get_mut("stages"))
+        {
+            for (_, stage) in stages.iter_mut() {
+                if let toml::Value::Table(stage) = stage {
+                    merge_defaults(stage, defaults);
+                }
+            }
+        }
+        toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
+
+    /// All validation problems, instead of failing on the first
+    pub fn problems(&self) -> Vec<String> {
+        validate::problems(self)
     }
 
     pub fn stage(&self, name: &str) -> Result<&StageConfig> {
@@ -161,7 +1006,162 @@ impl ShipitConfig {
             .with_context(|| format!("Stage '{}' not found in config", name))
     }
 
+    /// `[deploy]` with the stage's `[stages.<name>.deploy]` overrides applied
+    pub fn stage_deploy(&self, name: &str) -> Result<DeployConfig> {
+        let toml::Value::Table(mut deploy) =
+            toml::Value::try_from(&self.deploy).context("Failed to serialize [deploy]")?
+        else {
+            anyhow::bail!("[deploy] did not serialize to a table");
+        };
+        if let Some(overrides) = self.stages.get(name).and_then(|s| s.deploy.as_ref()) {
+            if overrides.contains_key("hooks") {
+                anyhow::bail!(
+                    "[stages.{}.deploy.hooks] is not supported; hooks apply to every stage ([deploy.hooks])",
+                    name
+                );
+            }
+            merge_overrides(&mut deploy, overrides);
+        }
+        toml::Value::Table(deploy)
+            .try_into()
+            .with_context(|| format!("Invalid [stages.{}.deploy]", name))
+    }
+
+    /// Make the stage's deploy overrides the effective `[deploy]` for this run
+    pub fn apply_stage_overrides(&mut self, name: &str) -> Result<()> {
+        if self.stages.get(name).is_some_and(|s| s.deploy.is_some()) {
+            self.deploy = self.stage_deploy(name)?;
+            validate::validate(self)?;
+        }
+        Ok(())
+    }
+
     pub fn app_path(&self) -> String {
         format!("{}/{}", self.deploy.deploy_to, self.app.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{hoist_deploy_hooks, merge_defaults, merge_overrides};
+
+    fn table(text: &str) -> toml::Table {
+        toml::from_str(text).expect("valid TOML")
+    }
+
+    #[test]
+    fn stages_inherit_missing_defaults() {
+        let defaults = table(
+            r#"
+            user = "deploy"
+            port = 2222
+            env = { RUST_LOG = "info", TZ = "UTC" }
+            traefik = { acme_email = "ops@example.com" }
+            "#,
+        );
+        let mut stage = table(
+            r#"
+            port = 22
+            env = { TZ = "Europe/Lisbon" }
+            "#,
+        );
+
+        merge_defaults(&mut stage, &defaults);
+        assert_eq!(
+            stage,
+            table(
+                r#"
+                user = "deploy"
+                port = 22
+                env = { RUST_LOG = "info", TZ = "Europe/Lisbon" }
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn traefik_defaults_only_complete_a_stage_traefik() {
+        let defaults = table(r#"traefik = { acme_email = "ops@example.com" }"#);
+        let mut stage = table(r#"traefik = { domain = "example.com" }"#);
+
+        merge_defaults(&mut stage, &defaults);
+        assert_eq!(
+            stage,
+            table(r#"traefik = { domain = "example.com", acme_email = "ops@example.com" }"#)
+        );
+    }
+
+    #[test]
+    fn overrides_replace_values_and_overlay_tables() {
+        let mut deploy = table(
+            r#"
+            strategy = "rolling"
+            keep_releases = 5
+            health_check = { path = "/up", timeout = 30 }
+            "#,
+        );
+        let overrides = table(
+            r#"
+            keep_releases = 2
+            health_check = { timeout = 90 }
+            "#,
+        );
+
+        merge_overrides(&mut deploy, &overrides);
+        assert_eq!(
+            deploy,
+            table(
+                r#"
+                strategy = "rolling"
+                keep_releases = 2
+                health_check = { path = "/up", timeout = 90 }
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn deploy_hooks_move_into_hooks() {
+        let mut config = table(
+            r#"
+            [deploy]
+            build = "local"
+
+            [deploy.hooks]
+            before_deploy = ["npm run lint"]
+            after_build = [{ service = "web", cmd = "bin/rails db:migrate" }]
+            before_symlink = ["./warm.sh"]
+            after_deploy = ["./notify.sh"]
+
+            [hooks.local]
+            before_deploy = ["make check"]
+            "#,
+        );
+
+        assert!(hoist_deploy_hooks(&mut config).unwrap());
+        assert_eq!(
+            config,
+            table(
+                r#"
+                [deploy]
+                build = "local"
+
+                [hooks.local]
+                before_deploy = ["make check", "npm run lint"]
+                after_deploy = ["./notify.sh"]
+
+                [hooks.remote]
+                after_build = [{ service = "web", cmd = "bin/rails db:migrate" }]
+                after_start = ["./warm.sh"]
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_deploy_hooks_are_rejected() {
+        let mut config = table("[deploy.hooks]\nafter_migrate = [\"x\"]");
+        assert!(hoist_deploy_hooks(&mut config).is_err());
+        assert!(!hoist_deploy_hooks(&mut table("[deploy]\nbuild = \"local\"")).unwrap());
+    }
+}
@@ -1,67 +1,524 @@
 use anyhow::{bail, Result};
 
-use super::ShipitConfig;
+use super::{Gpus, ShipitConfig};
 
 pub fn validate(config: &ShipitConfig) -> Result<()> {
+    let problems = problems(config);
+    if !problems.is_empty() {
+        bail!("{}", problems.join("\n"));
+    }
+    Ok(())
+}
+
+/// Every problem in the config, so `shipit validate` can list them all at once
+pub fn problems(config: &ShipitConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+
     if config.app.name.is_empty() {
-        bail!("app.name cannot be empty");
+        problems.push("app.name cannot be empty".to_string());
     }
 
     if config.app.repository.is_empty() {
-        bail!("app.repository cannot be empty");
+        problems.push("app.repository cannot be empty".to_string());
     }
 
     match config.deploy.build.as_str() {
         "remote" | "local" => {}
-        other => bail!(
-            "deploy.build has invalid value '{}'. Supported: remote, local",
+        "registry" if config.registry.is_none() => {
+            problems.push("deploy.build = \"registry\" requires a [registry] section".to_string())
+        }
+        "registry" => {}
+        other => problems.push(format!(
+            "deploy.build has invalid value '{}'. Supported: remote, local, registry",
+            other
+        )),
+    }
+
+    if !matches!(config.deploy.strategy.as_str(), "sequential" | "rolling") {
+        problems.push(format!(
+            "deploy.strategy has invalid value '{}'. Supported: sequential, rolling",
+            config.deploy.strategy
+        ));
+    }
+    if config.deploy.max_unavailable == 0 {
+        problems.push("deploy.max_unavailable must be greater than 0".to_string());
+    }
+
+    if let Some(format) = &config.deploy.release_format {
+        // Cleanup and rollback find the newest releases by sorting their names
+        if !crate::release::is_sortable(format) {
+            problems.push(format!(
+                "deploy.release_format '{}' must start with a full timestamp (%Y%m%d%H%M%S, \
+                 optionally separated by '-', '_' or '.') so releases sort by deploy time",
+                format
+            ));
+        } else if let Err(e) = crate::release::render(
+            format,
+            chrono::Local::now(),
+            Some("0123456789abcdef0123456789abcdef01234567"),
+        ) {
+            problems.push(format!("deploy.release_format: {:#}", e));
+        }
+    }
+
+    if let Some(check) = &config.deploy.public_check {
+        if !matches!(check.on_failure.as_str(), "warn" | "fail") {
+            problems.push(format!(
+                "deploy.public_check.on_failure has invalid value '{}'. Supported: warn, fail",
+                check.on_failure
+            ));
+        }
+        if check.path.as_ref().is_some_and(|p| !p.starts_with('/')) {
+            problems.push("deploy.public_check.path must start with /".to_string());
+        }
+        if check.retries == 0 {
+            problems.push("deploy.public_check.retries must be at least 1".to_string());
+        }
+    }
+
+    if let Some(tz) = &config.setup.timezone {
+        if tz.is_empty() || tz.contains(char::is_whitespace) {
+            problems.push(format!("setup.timezone '{}' is not a valid timezone name", tz));
+        }
+    }
+
+    if let Some(email) = &config.notify.email {
+        if email.host.is_empty() || email.from.is_empty() {
+            problems.push("notify.email.host and notify.email.from cannot be empty".to_string());
+        }
+        if email.to.is_empty() {
+            problems.push("notify.email.to needs at least one recipient".to_string());
+        }
+    }
+
+    for webhook in &config.notify.webhooks {
+        if webhook.url.is_empty() {
+            problems.push("notify.webhooks: url cannot be empty".to_string());
+        }
+        if !super::WEBHOOK_FORMATS.contains(&webhook.format.as_str()) {
+            problems.push(format!(
+                "notify.webhooks: unknown format '{}'. Supported: {}",
+                webhook.format,
+                super::WEBHOOK_FORMATS.join(", ")
+            ));
+        }
+        for event in &webhook.events {
+            if !super::WEBHOOK_EVENTS.contains(&event.as_str()) {
+                problems.push(format!(
+                    "notify.webhooks: unknown event '{}'. Supported: {}",
+                    event,
+                    super::WEBHOOK_EVENTS.join(", ")
+                ));
+            }
+        }
+    }
+
+    for plugin in &config.plugins {
+        if plugin.is_empty()
+            || !plugin
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            problems.push(format!(
+                "plugins: '{}' is not a valid plugin name (letters, digits, - and _)",
+                plugin
+            ));
+        }
+    }
+
+    if let Some(registry) = &config.registry {
+        if registry.server.is_empty() || registry.username.is_empty() {
+            problems.push("registry.server and registry.username cannot be empty".to_string());
+        }
+    }
+
+    match config.local.provider.as_str() {
+        "multipass" | "vagrant" => {}
+        other => problems.push(format!(
+            "local.provider has invalid value '{}'. Supported: multipass, vagrant",
             other
-        ),
+        )),
+    }
+
+    for (key, value) in [
+        ("monitor.memory_percent", config.monitor.memory_percent),
+        ("monitor.disk_percent", config.monitor.disk_percent),
+    ] {
+        if let Some(percent) = value {
+            if !(0.0..=100.0).contains(&percent) {
+                problems.push(format!("{} must be between 0 and 100", key));
+            }
+        }
+    }
+
+    if config.monitor.cpu_percent.is_some_and(|p| p < 0.0) {
+        problems.push("monitor.cpu_percent cannot be negative".to_string());
     }
 
-    for (name, stage) in &config.stages {
+    if let Some(stage) = &config.default_stage {
+        if !config.stages.contains_key(stage) {
+            problems.push(format!("default_stage '{}' is not a defined stage", stage));
+        }
+    }
+
+    let mut stages: Vec<_> = config.stages.iter().collect();
+    stages.sort_by_key(|(name, _)| name.as_str());
+    for (name, stage) in stages {
         if let Some(ref os) = stage.os {
             match os.as_str() {
-                "ubuntu" | "debian" | "nixos" => {}
-                other => bail!(
-                    "Stage '{}' has invalid os '{}'. Supported: ubuntu, debian, nixos",
+                "ubuntu" | "debian" | "rocky" | "almalinux" | "rhel" | "centos" | "fedora"
+                | "nixos" => {}
+                other => problems.push(format!(
+                    "Stage '{}' has invalid os '{}'. Supported: ubuntu, debian, rocky, almalinux, rhel, centos, fedora, nixos",
                     name,
                     other
-                ),
+                )),
+            }
+        }
+
+        // Discovered stages get their hosts at runtime, from the provider
+        if let Some(discovery) = &stage.discovery {
+            if !stage.hosts.is_empty() {
+                problems.push(format!(
+                    "Stage '{}' sets both hosts and discovery; use one",
+                    name
+                ));
+            }
+            if !matches!(discovery.provider.as_str(), "hetzner" | "ec2") {
+                problems.push(format!(
+                    "Stage '{}' discovery.provider '{}' is not supported. Supported: hetzner, ec2",
+                    name, discovery.provider
+                ));
             }
+            if discovery.tag.split(',').any(|pair| {
+                !pair
+                    .split_once('=')
+                    .is_some_and(|(k, v)| !k.is_empty() && !v.is_empty())
+            }) {
+                problems.push(format!(
+                    "Stage '{}' discovery.tag must be key=value pairs separated by commas",
+                    name
+                ));
+            }
+        } else if stage.hosts.is_empty() && name != "local" {
+            // [stages.local] only overrides the synthesized `shipit local` stage,
+            // whose hosts come from the local VMs
+            problems.push(format!("Stage '{}' has no hosts defined", name));
+        }
+
+        if stage.hosts.iter().any(|host| host.address.is_empty()) {
+            problems.push(format!("Stage '{}' has a host with empty address", name));
+        }
+
+        if stage
+            .hosts
+            .iter()
+            .any(|host| host.role.iter().any(|role| role.is_empty()))
+        {
+            problems.push(format!("Stage '{}' has a host with an empty role", name));
+        }
+
+        if stage.ssh.connect_timeout == 0 {
+            problems.push(format!(
+                "Stage '{}' ssh.connect_timeout must be greater than 0",
+                name
+            ));
+        }
+
+        if stage.ssh.command_timeout == Some(0) {
+            problems.push(format!(
+                "Stage '{}' ssh.command_timeout must be greater than 0",
+                name
+            ));
         }
 
-        if stage.hosts.is_empty() {
-            bail!("Stage '{}' has no hosts defined", name);
+        if stage.ssh.keepalive_count_max == Some(0) {
+            problems.push(format!(
+                "Stage '{}' ssh.keepalive_count_max must be greater than 0",
+                name
+            ));
         }
 
-        for host in &stage.hosts {
-            if host.address.is_empty() {
-                bail!("Stage '{}' has a host with empty address", name);
+        if stage.deploy.is_some() {
+            match config.stage_deploy(name) {
+                Ok(deploy) if !matches!(deploy.build.as_str(), "remote" | "local" | "registry") => {
+                    problems.push(format!(
+                        "Stage '{}' deploy.build has invalid value '{}'. Supported: remote, local, registry",
+                        name, deploy.build
+                    ))
+                }
+                Ok(deploy) if deploy.build == "registry" && config.registry.is_none() => {
+                    problems.push(format!(
+                        "Stage '{}' deploy.build = \"registry\" requires a [registry] section",
+                        name
+                    ))
+                }
+                Ok(_) => {}
+                Err(e) => problems.push(format!("{:#}", e)),
+            }
+        }
+
+        let mut services: Vec<_> = stage.resources.iter().collect();
+        services.sort_by_key(|(service, _)| service.as_str());
+        for (service, limits) in services {
+            if limits.memory.is_none()
+                && limits.cpus.is_none()
+                && limits.pids.is_none()
+                && limits.gpus.is_none()
+            {
+                problems.push(format!(
+                    "Stage '{}' resources.{} sets nothing (memory, cpus, pids or gpus)",
+                    name, service
+                ));
+            }
+            match &limits.gpus {
+                Some(Gpus::All(all)) if all != "all" => problems.push(format!(
+                    "Stage '{}' resources.{}.gpus must be \"all\", a count or a list of device IDs",
+                    name, service
+                )),
+                Some(Gpus::Count(0)) => problems.push(format!(
+                    "Stage '{}' resources.{}.gpus must be at least 1",
+                    name, service
+                )),
+                Some(Gpus::Devices(ids)) if ids.is_empty() => problems.push(format!(
+                    "Stage '{}' resources.{}.gpus lists no devices",
+                    name, service
+                )),
+                _ => {}
+            }
+            if let Some(memory) = &limits.memory {
+                let digits = memory.trim_end_matches(|c: char| "bBkKmMgG".contains(c));
+                if digits.is_empty() || digits.parse::<f64>().is_err() {
+                    problems.push(format!(
+                        "Stage '{}' resources.{}.memory '{}' is not a size like 512m or 2g",
+                        name, service, memory
+                    ));
+                }
+            }
+            if limits.cpus.is_some_and(|cpus| cpus <= 0.0) {
+                problems.push(format!(
+                    "Stage '{}' resources.{}.cpus must be greater than 0",
+                    name, service
+                ));
+            }
+            if limits.pids == Some(0) {
+                problems.push(format!(
+                    "Stage '{}' resources.{}.pids must be greater than 0",
+                    name, service
+                ));
             }
         }
 
         if let Some(traefik) = &stage.traefik {
             if traefik.domain.is_empty() {
-                bail!("Stage '{}' traefik.domain cannot be empty", name);
+                problems.push(format!("Stage '{}' traefik.domain cannot be empty", name));
             }
             if traefik.tls && traefik.acme_email.is_none() {
-                bail!(
+                problems.push(format!(
                     "Stage '{}' has TLS enabled but no acme_email configured",
                     name
-                );
+                ));
+            }
+        }
+    }
+
+    let mut jobs: Vec<_> = config.cron.iter().collect();
+    jobs.sort_by_key(|(name, _)| name.as_str());
+    for (name, job) in jobs {
+        // The name becomes part of systemd unit and script file names
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            problems.push(format!(
+                "Cron job '{}' has an invalid name (use letters, digits, '-' and '_')",
+                name
+            ));
+        }
+        if job.service.is_empty() || job.cmd.is_empty() {
+            problems.push(format!("Cron job '{}' needs a service and a cmd", name));
+        }
+        if !matches!(job.mode.as_str(), "exec" | "run") {
+            problems.push(format!(
+                "Cron job '{}' has invalid mode '{}'. Supported: exec, run",
+                name, job.mode
+            ));
+        }
+        if let Err(e) = crate::cron::on_calendar(&job.schedule) {
+            problems.push(format!("Cron job '{}': {:#}", name, e));
+        }
+    }
+
+    if let Some(backups) = &config.backups {
+        if !backups.target.starts_with("s3://") && !backups.target.starts_with('/') {
+            problems.push(format!(
+                "backups.target '{}' must be an s3:// URL or an absolute path",
+                backups.target
+            ));
+        }
+        if backups.retention == 0 {
+            problems.push("backups.retention must be at least 1".to_string());
+        }
+        if backups.volumes.is_empty() && backups.accessories.is_empty() {
+            problems.push("backups needs at least one volume or accessory".to_string());
+        }
+        if let Err(e) = crate::cron::on_calendar(&backups.schedule) {
+            problems.push(format!("backups.schedule: {:#}", e));
+        }
+        for name in &backups.accessories {
+            match config.accessories.get(name) {
+                None => problems.push(format!(
+                    "backups.accessories: no accessory named '{}'",
+                    name
+                )),
+                Some(acc) if crate::backup::database_kind(&acc.image).is_none() => {
+                    problems.push(format!(
+                        "backups.accessories: '{}' ({}) is not a Postgres, MySQL or MariaDB \
+                         database; back up its volume instead",
+                        name, acc.image
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    if let Some(logging) = &config.deploy.logging {
+        if logging.driver.is_empty() {
+            problems.push("deploy.logging.driver cannot be empty".to_string());
+        }
+        if (logging.max_size.is_some() || logging.max_file.is_some())
+            && !matches!(logging.driver.as_str(), "json-file" | "local")
+        {
+            problems.push(format!(
+                "deploy.logging: max_size and max_file only apply to the json-file and local drivers, not '{}'",
+                logging.driver
+            ));
+        }
+        if logging.max_file == Some(0) {
+            problems.push("deploy.logging.max_file must be at least 1".to_string());
+        }
+    }
+
+    let mut services: Vec<_> = config.deploy.services.iter().collect();
+    services.sort_by_key(|(name, _)| name.as_str());
+    for (service, settings) in services {
+        for network in &settings.networks {
+            if network == "traefik" || network == "default" {
+                problems.push(format!(
+                    "deploy.services.{}.networks: '{}' is always joined; list only extra networks",
+                    service, network
+                ));
+            } else if !valid_docker_name(network) {
+                problems.push(format!(
+                    "deploy.services.{}.networks: '{}' is not a valid network name",
+                    service, network
+                ));
+            }
+        }
+        for alias in &settings.aliases {
+            if !valid_docker_name(alias) {
+                problems.push(format!(
+                    "deploy.services.{}.aliases: '{}' is not a valid alias",
+                    service, alias
+                ));
+            }
+        }
+        if settings.roles.iter().any(|role| role.is_empty()) {
+            problems.push(format!(
+                "deploy.services.{}.roles has an empty role",
+                service
+            ));
+        }
+        // A service no host of a stage runs would silently go missing
+        if !settings.roles.is_empty() {
+            let mut stages: Vec<_> = config.stages.iter().collect();
+            stages.sort_by_key(|(name, _)| name.as_str());
+            for (name, stage) in stages {
+                if !stage.hosts.is_empty()
+                    && !stage
+                        .hosts
+                        .iter()
+                        .any(|host| config.deploy.runs_on(service, host))
+                {
+                    problems.push(format!(
+                        "deploy.services.{}.roles: no host of stage '{}' has role {}",
+                        service,
+                        name,
+                        settings.roles.join(" or ")
+                    ));
+                }
             }
         }
     }
 
-    for (name, accessory) in &config.accessories {
+    let shared = &config.deploy.shared;
+    for (key, path) in shared
+        .dirs
+        .iter()
+        .map(|p| ("dirs", p))
+        .chain(shared.files.iter().map(|p| ("files", p)))
+    {
+        let trimmed = path.trim_end_matches('/');
+        if trimmed.is_empty()
+            || trimmed.starts_with('/')
+            || trimmed.split('/').any(|part| part == ".." || part == ".")
+        {
+            problems.push(format!(
+                "deploy.shared.{}: '{}' must be a path inside the release, without '.' or '..'",
+                key, path
+            ));
+        } else if trimmed == ".env" {
+            problems.push(format!(
+                "deploy.shared.{}: .env is always shared; remove it",
+                key
+            ));
+        }
+    }
+
+    for name in &config.deploy.depends_on_accessories {
+        if !config.accessories.contains_key(name) {
+            problems.push(format!(
+                "deploy.depends_on_accessories: no accessory named '{}'",
+                name
+            ));
+        }
+    }
+
+    if let Some(gc) = &config.gc {
+        if let Err(e) = crate::cron::on_calendar(&gc.schedule) {
+            problems.push(format!("gc.schedule: {:#}", e));
+        }
+        let digits = gc.keep.trim_end_matches(|c: char| "hms".contains(c));
+        if digits.is_empty() || digits.len() == gc.keep.len() || digits.parse::<u64>().is_err() {
+            problems.push(format!(
+                "gc.keep '{}' is not a duration like 168h or 30m",
+                gc.keep
+            ));
+        }
+    }
+
+    let mut accessories: Vec<_> = config.accessories.iter().collect();
+    accessories.sort_by_key(|(name, _)| name.as_str());
+    for (name, accessory) in accessories {
         if accessory.image.is_empty() {
-            bail!("Accessory '{}' has no image defined", name);
+            problems.push(format!("Accessory '{}' has no image defined", name));
         }
         if accessory.host.is_empty() {
-            bail!("Accessory '{}' has no host defined", name);
+            problems.push(format!("Accessory '{}' has no host defined", name));
         }
     }
 
-    Ok(())
+    problems
+}
+
+/// Docker network and alias names: alphanumeric first, then [a-zA-Z0-9_.-]
+fn valid_docker_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
 }
@@ -0,0 +1,385 @@
+use anyhow::{bail, Context, Result};
+
+use crate::config::{CronJob, ShipitConfig};
+use crate::os::HostOs;
+use crate::ssh::{shell_quote, SshSession};
+
+const WEEKDAYS: [&str; 8] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// systemd unit name (without suffix) of a job
+pub fn unit_name(app: &str, job: &str) -> String {
+    format!("shipit-{}-cron-{}", app, job)
+}
+
+/// Directory the units are written to. NixOS keeps /etc/systemd/system in
+/// the store, so units go to the persistent system.control directory there.
+fn unit_dir(os: HostOs) -> &'static str {
+    match os {
+        HostOs::NixOs => "/etc/systemd/system.control",
+        HostOs::Ubuntu | HostOs::Rhel => "/etc/systemd/system",
+    }
+}
+
+/// Translate a cron expression into a systemd `OnCalendar=` spec
+pub fn on_calendar(schedule: &str) -> Result<String> {
+    let shorthand = match schedule.trim() {
+        "@hourly" => Some("hourly"),
+        "@daily" | "@midnight" => Some("daily"),
+        "@weekly" => Some("weekly"),
+        "@monthly" => Some("monthly"),
+        "@yearly" | "@annually" => Some("yearly"),
+        _ => None,
+    };
+    if let Some(spec) = shorthand {
+        return Ok(spec.to_string());
+    }
+
+    let fields: Vec<&str> = schedule.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else {
+        bail!(
+            "'{}' is not a cron expression (minute hour day month weekday)",
+            schedule
+        );
+    };
+    // cron runs when *either* day field matches, systemd needs both to
+    if day != "*" && weekday != "*" {
+        bail!(
+            "'{}' restricts both day of month and day of week; use one of them",
+            schedule
+        );
+    }
+
+    let weekdays = if weekday == "*" {
+        String::new()
+    } else {
+        format!("{} ", weekdays(weekday)?)
+    };
+    Ok(format!(
+        "{}*-{}-{} {}:{}:00",
+        weekdays,
+        field(month, 1, 12)?,
+        field(day, 1, 31)?,
+        field(hour, 0, 23)?,
+        field(minute, 0, 59)?
+    ))
+}
+
+/// One numeric field: `*`, `n`, `a-b`, `*/n`, `a/n` and lists of those
+fn field(spec: &str, min: u32, max: u32) -> Result<String> {
+    let number = |s: &str| -> Result<u32> {
+        match s.parse::<u32>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => bail!("'{}' is out of range in '{}' ({}-{})", s, spec, min, max),
+        }
+    };
+
+    let items = spec
+        .split(',')
+        .map(|item| {
+            if item == "*" {
+                return Ok("*".to_string());
+            }
+            if let Some((start, step)) = item.split_once('/') {
+                let start = if start == "*" { min } else { number(start)? };
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .with_context(|| format!("Invalid step in '{}'", spec))?;
+                return Ok(format!("{}/{}", start, step));
+            }
+            if let Some((start, end)) = item.split_once('-') {
+                return Ok(format!("{}..{}", number(start)?, number(end)?));
+            }
+            number(item).map(|n| n.to_string())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(items.join(","))
+}
+
+/// The weekday field, as systemd day names (0 and 7 are Sunday)
+fn weekdays(spec: &str) -> Result<String> {
+    let day = |s: &str| -> Result<usize> {
+        if let Ok(n) = s.parse::<usize>() {
+            if n < WEEKDAYS.len() {
+                return Ok(n);
+            }
+        }
+        WEEKDAYS
+            .iter()
+            .position(|d| d.eq_ignore_ascii_case(s))
+            .with_context(|| format!("'{}' is not a weekday in '{}'", s, spec))
+    };
+
+    let items = spec
+        .split(',')
+        .map(|item| match item.split_once('-') {
+            // systemd weeks start on Monday, so a range from Sunday is split
+            Some((start, end)) => match (day(start)?, day(end)?) {
+                (0, end) if end != 0 => Ok(format!("Sun,Mon..{}", WEEKDAYS[end])),
+                (start, end) => Ok(format!("{}..{}", WEEKDAYS[start], WEEKDAYS[end])),
+            },
+            None => day(item).map(|d| WEEKDAYS[d].to_string()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(items.join(","))
+}
+
+/// Shell script the service runs: the job's command in the current release
+fn script(config: &ShipitConfig, name: &str, job: &CronJob) -> String {
+    let compose = if job.mode == "run" {
+        format!("docker compose run --rm -T {}", shell_quote(&job.service))
+    } else {
+        format!("docker compose exec -T {}", shell_quote(&job.service))
+    };
+    format!(
+        "#!/bin/sh\n# Managed by shipit: cron job {}\ncd \"$(readlink -f {}/current)\" || exit 1\nexec {} sh -c {}\n",
+        name,
+        config.app_path(),
+        compose,
+        shell_quote(&job.cmd)
+    )
+}
+
+/// A systemd timer (and its oneshot service) that runs a script as `user`
+pub struct Timer<'a> {
+    pub unit: &'a str,
+    pub description: &'a str,
+    pub user: &'a str,
+    pub script_path: &'a str,
+    pub on_calendar: &'a str,
+}
+
+impl Timer<'_> {
+    fn service_unit(&self) -> String {
+        format!(
+            "[Unit]\n\
+             Description={}\n\
+             After=docker.service\n\
+             \n\
+             [Service]\n\
+             Type=oneshot\n\
+             User={}\n\
+             Environment=PATH=/run/current-system/sw/bin:/usr/local/bin:/usr/bin:/bin\n\
+             ExecStart=/bin/sh {}\n",
+            self.description, self.user, self.script_path
+        )
+    }
+
+    fn timer_unit(&self) -> String {
+        format!(
+            "[Unit]\n\
+             Description={}\n\
+             \n\
+             [Timer]\n\
+             OnCalendar={}\n\
+             Persistent=true\n\
+             \n\
+             [Install]\n\
+             WantedBy=timers.target\n",
+            self.description, self.on_calendar
+        )
+    }
+
+    /// Write both units and enable the timer. The caller reloads systemd and
+    /// (re)starts the timer.
+    pub async fn write(&self, session: &SshSession, os: HostOs) -> Result<()> {
+        let dir = unit_dir(os);
+        let unit = self.unit;
+        session
+            .sudo_write_file(&format!("{}/{}.service", dir, unit), &self.service_unit())
+            .await?;
+        session
+            .sudo_write_file(&format!("{}/{}.timer", dir, unit), &self.timer_unit())
+            .await?;
+        // Link by hand: `systemctl enable` can't write to /etc/systemd/system on NixOS
+        session
+            .sudo_exec(&format!(
+                "mkdir -p {dir}/timers.target.wants && \
+                 ln -sfn {dir}/{unit}.timer {dir}/timers.target.wants/{unit}.timer"
+            ))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Shell command that stops a timer and deletes its units
+pub fn remove_timer_cmd(os: HostOs, unit: &str) -> String {
+    let dir = unit_dir(os);
+    format!(
+        "systemctl stop {unit}.timer 2>/dev/null; \
+         rm -f {dir}/{unit}.timer {dir}/{unit}.service {dir}/timers.target.wants/{unit}.timer"
+    )
+}
+
+/// Job names whose timers are installed on the host
+pub async fn installed(
+    session: &SshSession,
+    config: &ShipitConfig,
+    os: HostOs,
+) -> Result<Vec<String>> {
+    let prefix = unit_name(&config.app.name, "");
+    let listing = session
+        .query(&format!("ls -1 {} 2>/dev/null || true", unit_dir(os)))
+        .await?;
+    Ok(listing
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix(&prefix))
+        .filter_map(|rest| rest.strip_suffix(".timer"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Make the host's timers match `jobs`: write and (re)start the units of
+/// configured jobs and remove those of jobs that are gone. Returns how many
+/// jobs are scheduled.
+pub async fn sync(
+    session: &SshSession,
+    config: &ShipitConfig,
+    os_config: Option<&str>,
+    user: &str,
+    jobs: &[(&String, &CronJob)],
+) -> Result<usize> {
+    let os = HostOs::resolve(os_config, session).await?;
+    let existing = installed(session, config, os).await?;
+    if jobs.is_empty() && existing.is_empty() {
+        return Ok(0);
+    }
+
+    let scripts_dir = format!("{}/cron", config.app_path());
+    session
+        .exec(&format!("mkdir -p {}", scripts_dir))
+        .await
+        .context("Failed to create the cron scripts directory")?;
+
+    for name in existing
+        .iter()
+        .filter(|name| !jobs.iter().any(|(job, _)| job == name))
+    {
+        let unit = unit_name(&config.app.name, name);
+        session
+            .sudo_exec(&remove_timer_cmd(os, &unit))
+            .await
+            .with_context(|| format!("Failed to remove cron job {}", name))?;
+        session
+            .exec(&format!("rm -f {}/{}.sh", scripts_dir, name))
+            .await?;
+    }
+
+    let mut timers = Vec::new();
+    for (name, job) in jobs {
+        let unit = unit_name(&config.app.name, name);
+        let spec = on_calendar(&job.schedule)
+            .with_context(|| format!("Invalid schedule for cron job {}", name))?;
+        let script_path = format!("{}/{}.sh", scripts_dir, name);
+
+        session
+            .write_file(&script_path, &script(config, name, job))
+            .await?;
+        let description = format!("shipit cron job {} ({})", name, config.app.name);
+        Timer {
+            unit: &unit,
+            description: &description,
+            user,
+            script_path: &script_path,
+            on_calendar: &spec,
+        }
+        .write(session, os)
+        .await?;
+        timers.push(format!("{}.timer", unit));
+    }
+
+    session
+        .sudo_exec(&format!(
+            "systemctl daemon-reload{}",
+            if timers.is_empty() {
+                String::new()
+            } else {
+                format!(" && systemctl restart {}", timers.join(" "))
+            }
+        ))
+        .await
+        .context("Failed to start cron timers")?;
+
+    Ok(timers.len())
+}
+
+/// Remove every timer of the app from the host (teardown)
+pub async fn remove_all(session: &SshSession, config: &ShipitConfig, os: HostOs) -> Result<()> {
+    let existing = installed(session, config, os).await?;
+    if existing.is_empty() {
+        return Ok(());
+    }
+    let mut cmd = String::new();
+    for name in &existing {
+        cmd.push_str(&remove_timer_cmd(os, &unit_name(&config.app.name, name)));
+        cmd.push_str("; ");
+    }
+    cmd.push_str("systemctl daemon-reload");
+    session
+        .sudo_exec(&cmd)
+        .await
+        .context("Failed to remove cron timers")?;
+    Ok(())
+}
+
+/// Jobs that run on the host at `index` of its stage: the first host runs
+/// every job, the others only those with `all_hosts`
+pub fn jobs_for_host(config: &ShipitConfig, index: usize) -> Vec<(&String, &CronJob)> {
+    let mut jobs: Vec<_> = config
+        .cron
+        .iter()
+        .filter(|(_, job)| index == 0 || job.all_hosts)
+        .collect();
+    jobs.sort_by_key(|(name, _)| name.as_str());
+    jobs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field, on_calendar, weekdays};
+
+    #[test]
+    fn translates_cron_expressions() {
+        assert_eq!(on_calendar("*/15 * * * *").unwrap(), "*-*-* *:0/15:00");
+        assert_eq!(on_calendar("30 2 * * *").unwrap(), "*-*-* 2:30:00");
+        assert_eq!(on_calendar("0 9 1 1,7 *").unwrap(), "*-1,7-1 9:0:00");
+        assert_eq!(on_calendar("0 8 * * 1-5").unwrap(), "Mon..Fri *-*-* 8:0:00");
+        assert_eq!(on_calendar(" @daily ").unwrap(), "daily");
+        assert_eq!(on_calendar("@midnight").unwrap(), "daily");
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(on_calendar("* * * *").is_err());
+        assert!(on_calendar("0 0 1 * 1").is_err());
+        assert!(on_calendar("60 * * * *").is_err());
+        assert!(on_calendar("@reboot").is_err());
+    }
+
+    #[test]
+    fn translates_numeric_fields() {
+        assert_eq!(field("*", 0, 59).unwrap(), "*");
+        assert_eq!(field("5", 0, 59).unwrap(), "5");
+        assert_eq!(field("1-5", 0, 59).unwrap(), "1..5");
+        assert_eq!(field("*/10", 0, 59).unwrap(), "0/10");
+        assert_eq!(field("5/10", 0, 59).unwrap(), "5/10");
+        assert_eq!(field("*/2", 1, 12).unwrap(), "1/2");
+        assert_eq!(field("0,30", 0, 59).unwrap(), "0,30");
+        assert!(field("0", 1, 31).is_err());
+        assert!(field("*/0", 0, 59).is_err());
+        assert!(field("a", 0, 59).is_err());
+    }
+
+    #[test]
+    fn translates_weekdays() {
+        assert_eq!(weekdays("0").unwrap(), "Sun");
+        assert_eq!(weekdays("7").unwrap(), "Sun");
+        assert_eq!(weekdays("mon,FRI").unwrap(), "Mon,Fri");
+        assert_eq!(weekdays("1-5").unwrap(), "Mon..Fri");
+        // systemd weeks start on Monday
+        assert_eq!(weekdays("0-3").unwrap(), "Sun,Mon..Wed");
+        assert!(weekdays("8").is_err());
+        assert!(weekdays("Funday").is_err());
+    }
+}
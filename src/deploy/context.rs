@@ -1,6 +1,9 @@
 use crate::config::{ShipitConfig, StageConfig};
+use crate::notify::webhook::Webhook;
 use crate::release::Release;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 pub struct DeployContext {
     pub config: ShipitConfig,
@@ -8,6 +11,24 @@ pub struct DeployContext {
     pub stage: StageConfig,
     pub release: Release,
     pub project_root: PathBuf,
+    /// Run setup inline on hosts that were never set up (`deploy --auto-setup`)
+    pub auto_setup: bool,
+    /// Webhooks that receive deploy events (empty unless the CLI loads them)
+    pub webhooks: Vec<Webhook>,
+    /// Commit to deploy instead of local HEAD (`shipit promote`)
+    pub commit: Option<String>,
+    /// Retag the local images of this release instead of building
+    /// (`shipit promote` with local builds)
+    pub images_from: Option<String>,
+    pub started: Instant,
+    /// Wall-clock time of each step on each host, in the order they ran
+    pub timings: Mutex<Vec<StepTiming>>,
+}
+
+pub struct StepTiming {
+    pub host: String,
+    pub step: String,
+    pub elapsed: Duration,
 }
 
 impl DeployContext {
@@ -17,12 +38,22 @@ impl DeployContext {
         stage: StageConfig,
         project_root: PathBuf,
     ) -> Self {
+        let release = Release::new(
+            config.deploy.release_format.as_deref(),
+            head_sha(&project_root).as_deref(),
+        );
         Self {
             config,
             stage_name,
             stage,
-            release: Release::new(),
+            release,
             project_root,
+            auto_setup: false,
+            webhooks: Vec::new(),
+            commit: None,
+            images_from: None,
+            started: Instant::now(),
+            timings: Mutex::new(Vec::new()),
         }
     }
 
@@ -66,7 +97,56 @@ impl DeployContext {
         self.config.deploy.build == "local"
     }
 
+    pub fn is_registry_build(&self) -> bool {
+        self.config.deploy.build == "registry"
+    }
+
+    /// Images are built on this machine (local and registry builds)
+    pub fn builds_locally(&self) -> bool {
+        self.is_local_build() || self.is_registry_build()
+    }
+
+    /// SHA that `push_code` deploys: the promoted commit, else local HEAD
+    pub fn git_sha(&self) -> Option<String> {
+        self.commit.clone().or_else(|| head_sha(&self.project_root))
+    }
+
+    pub fn record_step(&self, host: &str, step: &str, elapsed: Duration) {
+        if let Ok(mut timings) = self.timings.lock() {
+            timings.push(StepTiming {
+                host: host.to_string(),
+                step: step.to_string(),
+                elapsed,
+            });
+        }
+    }
+
     pub fn image_name_for(&self, service: &str) -> String {
-        format!("{}-{}:{}", self.config.app.name, service, self.release.name)
+        self.image_name(service, &self.release.name)
+    }
+
+    /// Tag of a service's image for `release`, qualified with the registry
+    /// repository for registry builds
+    pub fn image_name(&self, service: &str, release: &str) -> String {
+        let name = format!("{}-{}:{}", self.config.app.name, service, release);
+        match &self.config.registry {
+            Some(registry) if self.is_registry_build() => {
+                format!("{}/{}", registry.repository(), name)
+            }
+            _ => name,
+        }
     }
 }
+
+/// SHA of HEAD in the local checkout
+pub fn head_sha(project_root: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(project_root)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -1,12 +1,17 @@
 pub mod context;
 pub mod steps;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use std::future::Future;
+use std::time::{Duration, Instant};
 
+use crate::cli::setup;
 use crate::compose::ImageService;
 use crate::config::HostConfig;
 use crate::output;
-use crate::ssh::SshSession;
+use crate::registry;
+use crate::release::lock::Deployer;
+use crate::ssh::{self, shell_quote, SshSession};
 
 use context::DeployContext;
 
@@ -16,19 +21,121 @@ pub async fn run(ctx: &DeployContext) -> Result<()> {
         ctx.config.app.name, ctx.stage_name, ctx.release.name
     ));
 
-    // For local builds, parse built services once (shared across hosts)
-    let built_services = if ctx.is_local_build() {
+    let dry_run = ssh::is_dry_run();
+    if dry_run {
+        output::info("Dry run: checks still run, changes are printed instead");
+    } else {
+        crate::notify::before_deploy(ctx).await?;
+    }
+    steps::run_local_hooks(ctx, "before_deploy", &ctx.config.hooks.local.before_deploy)?;
+
+    let registry_password =
+        registry::password(&ctx.config, &ctx.project_root, &ctx.stage_name)?;
+
+    // Fail before anything changes when a database or cache the app needs is down
+    if !ctx.config.deploy.depends_on_accessories.is_empty() {
+        crate::accessory::ensure_running(
+            &ctx.config,
+            &ctx.stage_name,
+            &ctx.stage,
+            &ctx.config.deploy.depends_on_accessories,
+            ctx.config.deploy.boot_accessories,
+        )
+        .await?;
+    }
+
+    // For local and registry builds, parse built services once (shared across hosts)
+    let built_services = if ctx.builds_locally() {
         steps::parse_built_services(ctx)?
     } else {
         Vec::new()
     };
 
-    for host in &ctx.stage.hosts {
-        deploy_to_host(ctx, host, &built_services).await?;
+    let locked = acquire_lock(ctx).await?;
+    // Registry builds are built and pushed once, then pulled by each host
+    let mut result = if ctx.is_registry_build() {
+        steps::publish_images(ctx, &built_services, registry_password.as_deref()).await
+    } else {
+        Ok(())
+    };
+
+    // Sequential deploys are rolling deploys of one host without a bake time
+    let rolling = ctx.config.deploy.strategy == "rolling";
+    let batch_size = if rolling {
+        ctx.config.deploy.max_unavailable.max(1)
+    } else {
+        1
+    };
+    let batches: Vec<&[HostConfig]> = ctx.stage.hosts.chunks(batch_size).collect();
+    for (i, batch) in batches.iter().enumerate() {
+        if result.is_err() {
+            break;
+        }
+        if rolling && batches.len() > 1 {
+            let addresses: Vec<&str> = batch.iter().map(|h| h.address.as_str()).collect();
+            output::info(&format!(
+                "Batch {}/{}: {}",
+                i + 1,
+                batches.len(),
+                addresses.join(", ")
+            ));
+        }
+
+        // Hosts in one batch deploy concurrently, so tag their output
+        let concurrent = batch.len() > 1;
+        let deploys = batch.iter().map(|host| async {
+            let deploy = deploy_to_host(ctx, host, &built_services, registry_password.as_deref());
+            if concurrent {
+                output::with_host_prefix(&host.address, deploy).await
+            } else {
+                deploy.await
+            }
+        });
+        result = futures::future::join_all(deploys)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<()>>>()
+            .map(|_| ());
+
+        let remaining: usize = batches[i + 1..].iter().map(|b| b.len()).sum();
+        let baking = rolling && remaining > 0 && ctx.config.deploy.bake_time > 0;
+        if result.is_ok() && baking && !dry_run {
+            result = bake(ctx, batch).await;
+        }
+        if rolling && remaining > 0 {
+            result = result.with_context(|| {
+                format!(
+                    "Rolling deploy stopped; {} host(s) still run the previous release",
+                    remaining
+                )
+            });
+        }
+    }
+    if locked {
+        if let Err(e) = release_lock(ctx).await {
+            output::warning(&format!("Failed to release the deploy lock: {:#}", e));
+        }
+    }
+    result?;
+
+    steps::run_local_hooks(ctx, "after_deploy", &ctx.config.hooks.local.after_deploy)
+        .with_context(|| {
+            format!(
+                "Release {} is live but its after_deploy hooks failed",
+                ctx.release.name
+            )
+        })?;
+
+    if let Some(check) = ctx.config.deploy.public_check.as_ref().filter(|_| !dry_run) {
+        steps::check_public(ctx, check).await?;
     }
 
     println!();
-    output::success(&format!(
+    if dry_run {
+        output::done("Dry run complete; nothing was changed");
+        return Ok(());
+    }
+    output::done(&format!(
         "Deploy complete! Release {} is live.",
         ctx.release.name
     ));
@@ -39,12 +146,32 @@ async fn deploy_to_host(
     ctx: &DeployContext,
     host: &HostConfig,
     built_services: &[(String, String)],
+    registry_password: Option<&str>,
 ) -> Result<()> {
     output::info(&format!("Deploying to {}", host.address));
 
-    let session = SshSession::connect(ctx.user(), &host.address, ctx.stage.port, ctx.stage.proxy.as_deref())
-        .await
-        .with_context(|| format!("Failed to connect to {}", host.address))?;
+    let mut session = connect(ctx, host).await?;
+
+    // A host that was never set up would otherwise fail at mkdir/git push
+    let repo_head = format!("{}/HEAD", ctx.remote_repo_path());
+    if !session.path_exists(&repo_head).await? {
+        if !ctx.auto_setup {
+            bail!(
+                "{} has not been set up ({} is missing). Run `shipit setup -s {}` first, \
+                 or deploy with --auto-setup",
+                host.address,
+                ctx.remote_repo_path(),
+                ctx.stage_name
+            );
+        }
+
+        output::warning(&format!("{} has not been set up; running setup first", host.address));
+        setup::setup_new_host(&ctx.config, &ctx.stage, host).await?;
+
+        // Reconnect so the new docker group membership applies
+        session.close().await?;
+        session = connect(ctx, host).await?;
+    }
 
     // Compute image overrides for local builds
     let web_service_name = ctx.web_service();
@@ -62,51 +189,331 @@ async fn deploy_to_host(
         })
         .collect();
 
+    steps::check_clock_skew(&session, ctx).await;
+    steps::preflight(&session, ctx).await?;
+
+    // Refresh registry credentials so private images can be pulled
+    if let (Some(registry), Some(password)) = (&ctx.config.registry, registry_password) {
+        registry::login(&session, registry, password).await?;
+    }
+
     // Step 1: Create release directory
-    steps::create_release_dir(&session, ctx).await?;
+    step(
+        ctx,
+        host,
+        "create_release_dir",
+        steps::create_release_dir(&session, ctx),
+    )
+    .await?;
 
-    // Step 2: Push code (runs locally, not via SSH)
-    steps::push_code(ctx, host)?;
+    // Step 2: Push code (runs locally, multiplexed over the session)
+    step(ctx, host, "push_code", steps::push_code(&session, ctx, host)).await?;
 
     // Step 3: Checkout code
-    steps::checkout_code(&session, ctx).await?;
+    step(
+        ctx,
+        host,
+        "checkout_code",
+        steps::checkout_code(&session, ctx),
+    )
+    .await?;
 
     // Step 4: Generate docker-compose.override.yml
-    steps::generate_override(
-        &session,
+    step(
         ctx,
-        web_image.as_deref(),
-        &image_services,
+        host,
+        "generate_override",
+        steps::generate_override(&session, ctx, host, web_image.as_deref(), &image_services),
     )
     .await?;
 
     // Step 5: Link shared .env
-    steps::link_shared_env(&session, ctx).await?;
+    step(
+        ctx,
+        host,
+        "link_shared_env",
+        steps::link_shared_env(&session, ctx),
+    )
+    .await?;
+    steps::link_shared_paths(&session, ctx).await?;
 
     // Step 6: Build images
-    steps::build_images(&session, ctx, host).await?;
+    step(
+        ctx,
+        host,
+        "build_images",
+        steps::build_images(&session, ctx, host),
+    )
+    .await?;
+
+    let hooks = &ctx.config.hooks.remote;
+    steps::run_remote_hooks(&session, ctx, "after_build", &hooks.after_build)
+        .await
+        .context(
+            "Deploy failed: after_build hook did not pass; the previous release keeps running",
+        )?;
 
     // Step 7: Start new release (previous keeps running)
-    steps::start_new(&session, ctx).await?;
+    step(ctx, host, "start_new", steps::start_new(&session, ctx)).await?;
 
     // Step 8: Health check via container IP (with auto-rollback on failure)
-    if let Err(e) = steps::health_check(&session, ctx).await {
+    if let Err(e) = step(
+        ctx,
+        host,
+        "health_check",
+        steps::health_check(&session, ctx, host),
+    )
+    .await
+    {
         steps::rollback_on_failure(&session, ctx).await?;
         return Err(e).context("Deploy failed: health check did not pass");
     }
 
+    if let Err(e) = steps::run_remote_hooks(&session, ctx, "after_start", &hooks.after_start).await
+    {
+        steps::rollback_on_failure(&session, ctx).await?;
+        return Err(e).context("Deploy failed: after_start hook did not pass");
+    }
+
     // Step 9: Stop previous release (only after health check passes)
-    steps::stop_previous(&session, ctx).await?;
+    step(
+        ctx,
+        host,
+        "stop_previous",
+        steps::stop_previous(&session, ctx),
+    )
+    .await?;
 
     // Step 10: Update symlink
-    steps::update_symlink(&session, ctx).await?;
+    step(
+        ctx,
+        host,
+        "update_symlink",
+        steps::update_symlink(&session, ctx),
+    )
+    .await?;
+
+    // The release is already live, so a failing hook can't roll back anymore
+    steps::run_remote_hooks(&session, ctx, "after_switch", &hooks.after_switch)
+        .await
+        .with_context(|| {
+            format!(
+                "Release {} is live but its after_switch hooks failed",
+                ctx.release.name
+            )
+        })?;
+
+    steps::sync_cron(&session, ctx, host)
+        .await
+        .with_context(|| {
+            format!(
+                "Release {} is live but its cron jobs could not be scheduled",
+                ctx.release.name
+            )
+        })?;
 
     // Step 11: Update lock file
-    steps::update_lock(&session, ctx).await?;
+    step(ctx, host, "update_lock", steps::update_lock(&session, ctx)).await?;
 
     // Step 12: Cleanup old releases
-    steps::cleanup_old_releases(&session, ctx).await?;
+    step(
+        ctx,
+        host,
+        "cleanup_old_releases",
+        steps::cleanup_old_releases(&session, ctx),
+    )
+    .await?;
 
     session.close().await?;
     Ok(())
 }
+
+/// Rolling deploys: wait `bake_time` after a batch went live, then make
+/// sure its containers are still running and not unhealthy
+async fn bake(ctx: &DeployContext, hosts: &[HostConfig]) -> Result<()> {
+    let bake_time = ctx.config.deploy.bake_time;
+    let spinner = output::create_spinner(&format!(
+        "Baking for {}s before the next batch...",
+        bake_time
+    ));
+    tokio::time::sleep(Duration::from_secs(bake_time)).await;
+    spinner.finish_and_clear();
+
+    for host in hosts {
+        let session = connect(ctx, host).await?;
+        let result = session
+            .exec(&format!(
+                "cd {} && docker compose ps -a --format '{{{{.Service}}}}|{{{{.State}}}}|{{{{.Health}}}}'",
+                ctx.remote_release_path()
+            ))
+            .await;
+        session.close().await?;
+
+        let down: Vec<String> = result?
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.trim().splitn(3, '|');
+                let service = parts.next().filter(|s| !s.is_empty())?;
+                let state = parts.next().unwrap_or_default();
+                let health = parts.next().unwrap_or_default();
+                (state != "running" || health == "unhealthy").then(|| {
+                    format!("{} is {} {}", service, state, health)
+                        .trim()
+                        .to_string()
+                })
+            })
+            .collect();
+        if !down.is_empty() {
+            bail!(
+                "{} failed during the bake time ({}); it runs release {}, roll it back with `shipit rollback -s {}`",
+                host.address,
+                down.join(", "),
+                ctx.release.name,
+                ctx.stage_name
+            );
+        }
+    }
+    output::success(&format!("Batch healthy after {}s", bake_time));
+    Ok(())
+}
+
+/// Run one deploy step and report it, with its duration, as a `deploy.step` event
+async fn step<T>(
+    ctx: &DeployContext,
+    host: &HostConfig,
+    name: &str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let started = Instant::now();
+    let result = fut.await;
+    let elapsed = started.elapsed();
+    ctx.record_step(&host.address, name, elapsed);
+    crate::notify::deploy_step(ctx, &host.address, name, elapsed, result.is_ok()).await;
+    result
+}
+
+/// Print how long each step took per host, so slow builds, transfers or
+/// health checks stand out
+pub fn print_step_timings(ctx: &DeployContext) {
+    let Ok(timings) = ctx.timings.lock() else {
+        return;
+    };
+    if timings.is_empty() {
+        return;
+    }
+
+    let mut hosts: Vec<&str> = Vec::new();
+    let mut steps: Vec<&str> = Vec::new();
+    for t in timings.iter() {
+        if !hosts.contains(&t.host.as_str()) {
+            hosts.push(&t.host);
+        }
+        if !steps.contains(&t.step.as_str()) {
+            steps.push(&t.step);
+        }
+    }
+    let cell = |step: &str, host: &str| {
+        timings
+            .iter()
+            .find(|t| t.step == step && t.host == host)
+            .map(|t| format!("{:.1}s", t.elapsed.as_secs_f64()))
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    let width = hosts.iter().map(|h| h.len()).max().unwrap_or(0).max(8);
+    let mut head = format!("  {:<22}", "step");
+    for host in &hosts {
+        head.push_str(&format!(" {:>width$}", host, width = width));
+    }
+    let mut rows: Vec<String> = steps
+        .iter()
+        .map(|step| {
+            let mut line = format!("  {:<22}", step);
+            for host in &hosts {
+                line.push_str(&format!(" {:>width$}", cell(step, host), width = width));
+            }
+            line
+        })
+        .collect();
+    rows.push(format!(
+        "  {:<22} {:.1}s",
+        "total",
+        ctx.started.elapsed().as_secs_f64()
+    ));
+    output::table("Step timings", &head, &rows);
+}
+
+/// Marks a deploy in progress on the stage's first host
+fn lock_path(ctx: &DeployContext) -> String {
+    format!("{}/deploy.lock", ctx.remote_app_path())
+}
+
+/// Take the stage's deploy lock so two deploys (people, CI, `shipit watch`)
+/// can't interleave. Returns false when the first host was never set up,
+/// since there is nothing to protect yet.
+async fn acquire_lock(ctx: &DeployContext) -> Result<bool> {
+    let Some(host) = ctx.stage.hosts.first().filter(|_| !ssh::is_dry_run()) else {
+        return Ok(false);
+    };
+    let session = connect(ctx, host).await?;
+    let path = lock_path(ctx);
+
+    let result = async {
+        if !session.path_exists(&ctx.remote_app_path()).await? {
+            return Ok(false);
+        }
+        let holder = format!(
+            "{} since {} (release {})",
+            Deployer::current(),
+            chrono::Local::now().to_rfc3339(),
+            ctx.release.name
+        );
+        let taken = session
+            .exec_ok(&format!(
+                "mkdir {} 2>/dev/null && printf '%s\\n' {} > {}/holder",
+                path,
+                shell_quote(&holder),
+                path
+            ))
+            .await?;
+        if !taken {
+            let holder = session
+                .exec(&format!("cat {}/holder 2>/dev/null || true", path))
+                .await
+                .unwrap_or_default();
+            bail!(
+                "Another deploy of {} is in progress: {}. If it is not, remove {} on {}",
+                ctx.stage_name,
+                holder.trim(),
+                path,
+                host.address
+            );
+        }
+        Ok(true)
+    }
+    .await;
+    session.close().await?;
+    result
+}
+
+async fn release_lock(ctx: &DeployContext) -> Result<()> {
+    let Some(host) = ctx.stage.hosts.first() else {
+        return Ok(());
+    };
+    let session = connect(ctx, host).await?;
+    let result = session.exec(&format!("rm -rf {}", lock_path(ctx))).await;
+    session.close().await?;
+    result.map(|_| ())
+}
+
+async fn connect(ctx: &DeployContext, host: &HostConfig) -> Result<SshSession> {
+    SshSession::connect(
+        ctx.user(),
+        &host.address,
+        ctx.stage.port,
+        ctx.stage.proxy.as_deref(),
+        &ctx.stage.ssh,
+    )
+    .await
+    .with_context(|| format!("Failed to connect to {}", host.address))
+}
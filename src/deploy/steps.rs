@@ -1,19 +1,112 @@
 use anyhow::{bail, Context, Result};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar};
+use std::collections::HashMap;
 use std::process::{Command, Stdio};
 use std::time::Duration;
+use tokio::io::AsyncReadExt;
 use tracing::debug;
 
 use crate::compose::{self, ImageService};
-use crate::config::HostConfig;
+use crate::config::{HostConfig, PublicCheckConfig, RemoteHook, SharedConfig};
+use crate::cron;
 use crate::output;
-use crate::release::lock::ShipitLock;
+use crate::registry;
+use crate::release::{self, lock::ShipitLock};
 use crate::secrets::{key, store as secrets_store};
-use crate::ssh::SshSession;
+use crate::ssh::{self, shell_quote, SshSession};
+use crate::traefik;
 
 use super::context::DeployContext;
 
 const TOTAL_STEPS: usize = 12;
 
+/// Warn (without failing) when the host clock drifts from the local clock:
+/// release names and health-check timing assume they agree.
+pub async fn check_clock_skew(session: &SshSession, ctx: &DeployContext) {
+    let max = ctx.config.setup.max_clock_skew;
+    match session.clock_skew().await {
+        Ok(skew) if skew.unsigned_abs() > max => output::warning(&format!(
+            "Clock on {} is {}s {} the local clock (max {}s); consider `[setup] time_sync = true`",
+            session.host(),
+            skew.abs(),
+            if skew > 0 { "ahead of" } else { "behind" },
+            max
+        )),
+        Ok(_) => {}
+        Err(e) => debug!("Could not read clock on {}: {:#}", session.host(), e),
+    }
+}
+
+/// Abort before building or transferring images when the host is short on
+/// disk (deploy_to and the Docker data root) or memory.
+pub async fn preflight(session: &SshSession, ctx: &DeployContext) -> Result<()> {
+    let preflight = &ctx.config.deploy.preflight;
+
+    if preflight.min_disk_mb > 0 {
+        let mut low = low_disk(session, ctx).await?;
+        if !low.is_empty() && preflight.auto_prune {
+            output::warning(&format!(
+                "Low disk space on {}; pruning dangling images and build cache",
+                session.host()
+            ));
+            session
+                .exec("docker image prune -f >/dev/null && docker builder prune -f >/dev/null")
+                .await
+                .context("Failed to prune Docker images")?;
+            low = low_disk(session, ctx).await?;
+        }
+        if !low.is_empty() {
+            bail!(
+                "Not enough disk space on {}: {} (need {} MB free; see deploy.preflight)",
+                session.host(),
+                low.join(", "),
+                preflight.min_disk_mb
+            );
+        }
+    }
+
+    if preflight.min_memory_mb > 0 {
+        let available: u64 = session
+            .query("awk '/^MemAvailable:/ {print int($2 / 1024)}' /proc/meminfo")
+            .await?
+            .trim()
+            .parse()
+            .context("Failed to read available memory")?;
+        if available < preflight.min_memory_mb {
+            bail!(
+                "Not enough memory on {}: {} MB available (need {} MB; see deploy.preflight)",
+                session.host(),
+                available,
+                preflight.min_memory_mb
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Mount points (with free MB) below the disk threshold
+async fn low_disk(session: &SshSession, ctx: &DeployContext) -> Result<Vec<String>> {
+    let output = session
+        .query(&format!(
+            "df -Pm {} \"$(docker info -f '{{{{.DockerRootDir}}}}' 2>/dev/null || echo /var/lib/docker)\" \
+             2>/dev/null | awk 'NR > 1 {{print $6, $4}}' | sort -u",
+            ctx.remote_app_path()
+        ))
+        .await
+        .context("Failed to check disk space")?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let (mount, free) = line.split_once(' ')?;
+            let free: u64 = free.trim().parse().ok()?;
+            (free < ctx.config.deploy.preflight.min_disk_mb)
+                .then(|| format!("{} has {} MB free", mount, free))
+        })
+        .collect())
+}
+
 pub async fn create_release_dir(session: &SshSession, ctx: &DeployContext) -> Result<()> {
     output::step(1, TOTAL_STEPS, "Creating release directory");
 
@@ -26,32 +119,87 @@ pub async fn create_release_dir(session: &SshSession, ctx: &DeployContext) -> Re
     Ok(())
 }
 
-pub fn push_code(ctx: &DeployContext, host: &HostConfig) -> Result<()> {
+pub async fn push_code(session: &SshSession, ctx: &DeployContext, host: &HostConfig) -> Result<()> {
     output::step(2, TOTAL_STEPS, "Pushing code to remote");
 
     let user = ctx.user();
     let repo_path = ctx.remote_repo_path();
     let remote_url = format!("ssh://{}@{}{}", user, host.address, repo_path);
     let branch = &ctx.config.app.branch;
+    let commit = ctx.commit.as_deref().unwrap_or("HEAD");
+
+    if ssh::is_dry_run() {
+        output::planned(
+            "local",
+            &format!(
+                "git push {} {}:refs/heads/{} --force",
+                remote_url, commit, branch
+            ),
+        );
+        return Ok(());
+    }
 
-    let mut cmd = Command::new("git");
+    let mut cmd = tokio::process::Command::new("git");
     cmd.args([
         "push",
         &remote_url,
-        &format!("HEAD:refs/heads/{}", branch),
+        &format!("{}:refs/heads/{}", commit, branch),
         "--force",
     ])
-    .current_dir(&ctx.project_root);
+    .current_dir(&ctx.project_root)
+    // Reuse the deploy session's master connection (already through any proxy)
+    .env("GIT_SSH_COMMAND", session.ssh_command());
 
-    if let Some(proxy) = &ctx.stage.proxy {
-        cmd.env("GIT_SSH_COMMAND", format!("ssh -J {}", proxy));
+    let mut child = cmd
+        .arg("--progress")
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git push")?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .context("Failed to capture git push output")?;
+
+    // git redraws its progress with \r; show it on the spinner and keep the
+    // other lines for the error message
+    let label = format!("Pushing to {}", host.address);
+    let spinner = output::create_spinner(&format!("{}...", label));
+    let mut messages = Vec::new();
+    let mut segment = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stderr
+            .read(&mut buf)
+            .await
+            .context("Failed to read git push output")?;
+        if n == 0 {
+            break;
+        }
+        for &byte in &buf[..n] {
+            if byte != b'\r' && byte != b'\n' {
+                segment.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&segment).trim().to_string();
+            segment.clear();
+            if line.contains('%') {
+                spinner.set_message(format!("{}: {}", label, line));
+            } else if !line.is_empty() {
+                messages.push(line);
+            }
+        }
+    }
+    if !segment.is_empty() {
+        messages.push(String::from_utf8_lossy(&segment).trim().to_string());
     }
 
-    let status = cmd.status().context("Failed to run git push")?;
+    let status = child.wait().await.context("Failed to wait for git push")?;
+    spinner.finish_and_clear();
 
     if !status.success() {
-        bail!("git push failed");
+        bail!("git push failed:\n{}", messages.join("\n"));
     }
+    debug!("git push: {}", messages.join(" | "));
 
     output::success("Code pushed");
     Ok(())
@@ -79,20 +227,67 @@ pub async fn checkout_code(session: &SshSession, ctx: &DeployContext) -> Result<
 pub async fn generate_override(
     session: &SshSession,
     ctx: &DeployContext,
+    host: &HostConfig,
     web_image: Option<&str>,
     image_services: &[ImageService],
 ) -> Result<()> {
     output::step(4, TOTAL_STEPS, "Generating docker-compose.override.yml");
 
-    let traefik = ctx
-        .stage
-        .traefik
-        .as_ref()
-        .context("Traefik config not found for this stage")?;
+    // Without Traefik the web service keeps its own published ports
+    let traefik = ctx.stage.traefik.as_ref();
+    if traefik.is_none() {
+        output::warning("No traefik config for this stage; the web service is not routed through Traefik");
+    }
+
+    // Logging settings go on every service, so list them from the release's
+    // compose file (`docker compose config` would need the .env linked first)
+    let compose_services: Vec<String> = if ctx.config.deploy.logging.is_some() {
+        let files = compose::COMPOSE_FILES.join(" ");
+        // A dry run checks nothing out, so read the local compose file instead
+        let content = if ssh::is_dry_run() {
+            compose::COMPOSE_FILES
+                .iter()
+                .find_map(|f| std::fs::read_to_string(ctx.project_root.join(f)).ok())
+                .context("No compose file found")?
+        } else {
+            session
+                .exec(&format!(
+                    "cd {} && for f in {}; do if [ -f $f ]; then cat $f; break; fi; done",
+                    ctx.remote_release_path(),
+                    files
+                ))
+                .await
+                .context("Failed to read the compose file")?
+        };
+        compose::service_names(&content)
+            .context("Failed to parse the compose file")?
+            .into_iter()
+            .collect()
+    } else {
+        Vec::new()
+    };
 
     let shared_path = ctx.remote_shared_path();
-    let override_content =
-        compose::generate_override(&ctx.config, traefik, &shared_path, web_image, image_services)?;
+    let override_content = compose::generate_override(
+        &ctx.config,
+        &ctx.stage,
+        host,
+        &shared_path,
+        web_image,
+        image_services,
+        &compose_services,
+    )?;
+
+    // Extra networks are declared external, so they must exist on the host
+    for network in ctx.config.deploy.extra_networks() {
+        session
+            .exec(&format!(
+                "docker network inspect {0} >/dev/null 2>&1 || docker network create {0}",
+                network
+            ))
+            .await
+            .with_context(|| format!("Failed to create network {}", network))?;
+    }
 
     let override_path = format!("{}/docker-compose.override.yml", ctx.remote_release_path());
     session
@@ -164,6 +359,68 @@ pub async fn link_shared_env(session: &SshSession, ctx: &DeployContext) -> Resul
     Ok(())
 }
 
+/// Shell command creating the missing `[deploy.shared]` directories and
+/// (empty) files under `shared_path`, or None when nothing is shared
+pub fn create_shared_paths_cmd(shared: &SharedConfig, shared_path: &str) -> Option<String> {
+    if shared.is_empty() {
+        return None;
+    }
+    let dirs = shared.dirs.iter().map(|dir| {
+        format!(
+            "mkdir -p {}",
+            shell_quote(&format!("{}/{}", shared_path, dir))
+        )
+    });
+    let files = shared.files.iter().map(|file| {
+        let path = shell_quote(&format!("{}/{}", shared_path, file));
+        format!("mkdir -p \"$(dirname {0})\" && touch {0}", path)
+    });
+    Some(dirs.chain(files).collect::<Vec<_>>().join(" && "))
+}
+
+/// Replace each `[deploy.shared]` path in the release with a symlink into
+/// `shared/`, so uploads and other state survive the switch to a new release
+pub async fn link_shared_paths(session: &SshSession, ctx: &DeployContext) -> Result<()> {
+    let shared = &ctx.config.deploy.shared;
+    let shared_path = ctx.remote_shared_path();
+    let Some(create) = create_shared_paths_cmd(shared, &shared_path) else {
+        return Ok(());
+    };
+
+    let release_path = ctx.remote_release_path();
+    let links: Vec<String> = shared
+        .dirs
+        .iter()
+        .chain(&shared.files)
+        .map(|path| {
+            // `ln -s` would take a trailing slash for an existing directory
+            let path = path.trim_end_matches('/');
+            let link = shell_quote(&format!("{}/{}", release_path, path));
+            format!(
+                "rm -rf {0} && mkdir -p \"$(dirname {0})\" && ln -s {1} {0}",
+                link,
+                shell_quote(&format!("{}/{}", shared_path, path))
+            )
+        })
+        .collect();
+    session
+        .exec(&format!("{} && {}", create, links.join(" && ")))
+        .await
+        .context("Failed to link shared paths")?;
+
+    output::success(&format!(
+        "Shared paths linked: {}",
+        shared
+            .dirs
+            .iter()
+            .chain(&shared.files)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    Ok(())
+}
+
 pub async fn build_images(
     session: &SshSession,
     ctx: &DeployContext,
@@ -174,13 +431,25 @@ pub async fn build_images(
     let spinner = output::create_spinner("Building...");
 
     if ctx.is_local_build() {
-        build_images_local(ctx, host)?;
+        build_images_local(session, ctx, host).await?;
+    } else if ctx.is_registry_build() {
+        pull_images(session, ctx, host, &spinner).await?;
     } else {
+        // With agent forwarding, let Dockerfiles use `RUN --mount=type=ssh`
+        let ssh_flag = if ctx.stage.ssh.forward_agent {
+            " --ssh default"
+        } else {
+            ""
+        };
         session
-            .exec(&format!(
-                "cd {} && docker compose build",
-                ctx.remote_release_path()
-            ))
+            .exec_streamed_with_agent(
+                &format!(
+                    "cd {} && docker compose build{}",
+                    ctx.remote_release_path(),
+                    ssh_flag
+                ),
+                |line| spinner.suspend(|| output::remote_line(line)),
+            )
             .await
             .context("Failed to build Docker images")?;
     }
@@ -223,9 +492,11 @@ pub fn parse_built_services(ctx: &DeployContext) -> Result<Vec<(String, String)>
     Ok(built)
 }
 
-fn build_images_local(ctx: &DeployContext, host: &HostConfig) -> Result<()> {
-    let app_name = &ctx.config.app.name;
-
+async fn build_images_local(
+    session: &SshSession,
+    ctx: &DeployContext,
+    host: &HostConfig,
+) -> Result<()> {
     // 1. Parse compose config to find built services
     let built_services = parse_built_services(ctx)?;
     if built_services.is_empty() {
@@ -236,25 +507,171 @@ fn build_images_local(ctx: &DeployContext, host: &HostConfig) -> Result<()> {
     let image_names: Vec<&str> = built_services.iter().map(|(_, img)| img.as_str()).collect();
     debug!("Built services: {:?}", built_services);
 
-    // 2. Build locally with COMPOSE_PROJECT_NAME set
-    output::info("Building images locally...");
-    let status = Command::new("docker")
-        .args(["compose", "build"])
-        .env("COMPOSE_PROJECT_NAME", app_name)
-        .current_dir(&ctx.project_root)
-        .status()
-        .context("Failed to run local docker compose build")?;
+    if ssh::is_dry_run() {
+        output::planned("local", "docker compose build");
+        output::planned(
+            "local",
+            &format!(
+                "docker save {} | gzip -1 | ssh {} docker load",
+                image_names.join(" "),
+                host.address
+            ),
+        );
+        return Ok(());
+    }
 
-    if !status.success() {
-        bail!("Local docker compose build failed");
+    // 2-3. Build and tag with the release name
+    build_and_tag(ctx, &built_services).await?;
+
+    // 4. Transfer via docker save | gzip | docker load over the existing session,
+    //    counting the uncompressed bytes against the images' size
+    let bar = output::create_transfer_bar(
+        &format!("Transferring images to {}", host.address),
+        image_size(&image_names).await,
+    );
+
+    let mut save_child = tokio::process::Command::new("docker")
+        .arg("save")
+        .args(&image_names)
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn docker save")?;
+    let mut gzip_child = tokio::process::Command::new("gzip")
+        .arg("-1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn gzip")?;
+
+    let save_stdout = save_child
+        .stdout
+        .take()
+        .context("Failed to capture docker save stdout")?;
+    let mut gzip_stdin = gzip_child
+        .stdin
+        .take()
+        .context("Failed to open gzip stdin")?;
+    let mut gzip_stdout = gzip_child
+        .stdout
+        .take()
+        .context("Failed to capture gzip stdout")?;
+
+    let mut reader = output::ProgressReader::new(save_stdout, bar.clone());
+    let feed = async move {
+        let copied = tokio::io::copy(&mut reader, &mut gzip_stdin).await;
+        // Closing gzip's stdin lets it flush and end the stream
+        drop(gzip_stdin);
+        copied
+    };
+    let mut feed = std::pin::pin!(feed);
+    let (fed, load_result) = {
+        let mut load = std::pin::pin!(session.exec_with_input("docker load", &mut gzip_stdout));
+        tokio::select! {
+            fed = &mut feed => (Some(fed), load.await),
+            loaded = &mut load => (None, loaded),
+        }
+    };
+    drop(gzip_stdout);
+    let fed = match fed {
+        Some(fed) => fed,
+        // docker load gave up mid-stream: nothing reads gzip's output any
+        // more, so stop the local side or the feed blocks on a full pipe
+        None if load_result.is_err() => {
+            let _ = save_child.start_kill();
+            let _ = gzip_child.start_kill();
+            let _ = feed.await;
+            let _ = save_child.wait().await;
+            let _ = gzip_child.wait().await;
+            bar.finish_and_clear();
+            return load_result.with_context(|| {
+                format!(
+                    "Image transfer failed (docker save | docker load) to {}",
+                    host.address
+                )
+            });
+        }
+        None => feed.await,
+    };
+    bar.finish_and_clear();
+
+    let save_status = save_child
+        .wait()
+        .await
+        .context("Failed to wait for docker save")?;
+    if !save_status.success() {
+        bail!("docker save failed for {}", image_names.join(", "));
+    }
+    let gzip_status = gzip_child
+        .wait()
+        .await
+        .context("Failed to wait for gzip")?;
+
+    load_result.with_context(|| {
+        format!(
+            "Image transfer failed (docker save | docker load) to {}",
+            host.address
+        )
+    })?;
+    fed.context("Failed to compress images")?;
+    if !gzip_status.success() {
+        bail!("gzip failed while compressing images");
+    }
+
+    let elapsed = bar.elapsed().as_secs_f64().max(0.001);
+    output::success(&format!(
+        "Transferred {} in {} ({}/s)",
+        HumanBytes(bar.position()),
+        HumanDuration(bar.elapsed()),
+        HumanBytes((bar.position() as f64 / elapsed) as u64)
+    ));
+
+    Ok(())
+}
+
+/// Build the images locally with COMPOSE_PROJECT_NAME set and tag them with
+/// the release name. When promoting, the images of `ctx.images_from` are
+/// retagged instead (pulled first for registry builds).
+async fn build_and_tag(ctx: &DeployContext, built_services: &[(String, String)]) -> Result<()> {
+    let app_name = &ctx.config.app.name;
+
+    match &ctx.images_from {
+        Some(release) => output::info(&format!("Reusing the images of release {}", release)),
+        None => {
+            output::info("Building images locally...");
+            let status = tokio::process::Command::new("docker")
+                .args(["compose", "build"])
+                .env("COMPOSE_PROJECT_NAME", app_name)
+                .current_dir(&ctx.project_root)
+                .status()
+                .await
+                .context("Failed to run local docker compose build")?;
+
+            if !status.success() {
+                bail!("Local docker compose build failed");
+            }
+        }
     }
 
     // 3. Tag images with release name
-    for (svc_name, tagged) in &built_services {
-        let source = format!("{}-{}:latest", app_name, svc_name);
-        let tag_status = Command::new("docker")
+    for (svc_name, tagged) in built_services {
+        let source = match &ctx.images_from {
+            Some(release) => ctx.image_name(svc_name, release),
+            None => format!("{}-{}:latest", app_name, svc_name),
+        };
+        if ctx.images_from.is_some() && ctx.is_registry_build() {
+            let pull_status = tokio::process::Command::new("docker")
+                .args(["pull", "-q", &source])
+                .status()
+                .await
+                .with_context(|| format!("Failed to pull {}", source))?;
+            if !pull_status.success() {
+                bail!("docker pull {} failed", source);
+            }
+        }
+        let tag_status = tokio::process::Command::new("docker")
             .args(["tag", &source, tagged])
             .status()
+            .await
             .with_context(|| format!("Failed to tag {} as {}", source, tagged))?;
 
         if !tag_status.success() {
@@ -263,51 +680,86 @@ fn build_images_local(ctx: &DeployContext, host: &HostConfig) -> Result<()> {
         debug!("Tagged {} → {}", source, tagged);
     }
 
-    // 4. Transfer via docker save | ssh docker load
-    output::info(&format!(
-        "Transferring images to {}...",
-        host.address
-    ));
-
-    let mut save_cmd = Command::new("docker");
-    save_cmd.arg("save").args(&image_names).stdout(Stdio::piped());
-
-    let mut save_child = save_cmd
-        .spawn()
-        .context("Failed to spawn docker save")?;
-
-    let save_stdout = save_child
-        .stdout
-        .take()
-        .context("Failed to capture docker save stdout")?;
+    Ok(())
+}
 
-    let mut ssh_args = vec!["-C".to_string()];
-    if let Some(proxy) = &ctx.stage.proxy {
-        ssh_args.extend(["-J".to_string(), proxy.clone()]);
-    }
-    if let Some(port) = ctx.stage.port {
-        ssh_args.extend(["-p".to_string(), port.to_string()]);
+/// Registry builds: build once on this machine and push the release's
+/// images, so every host pulls the same ones
+pub async fn publish_images(
+    ctx: &DeployContext,
+    built_services: &[(String, String)],
+    registry_password: Option<&str>,
+) -> Result<()> {
+    let (Some(registry), Some(password)) = (&ctx.config.registry, registry_password) else {
+        bail!("deploy.build = \"registry\" requires a [registry] section");
+    };
+    if built_services.is_empty() {
+        output::info("No services with build directives found");
+        return Ok(());
     }
-    ssh_args.push(format!("{}@{}", ctx.user(), host.address));
-    ssh_args.push("docker".to_string());
-    ssh_args.push("load".to_string());
 
-    let load_status = Command::new("ssh")
-        .args(&ssh_args)
-        .stdin(save_stdout)
-        .status()
-        .context("Failed to run ssh docker load")?;
+    output::header(&format!("Publishing images to {}", registry.repository()));
+    if ssh::is_dry_run() {
+        output::planned("local", "docker compose build");
+        for (_, image) in built_services {
+            output::planned("local", &format!("docker push {}", image));
+        }
+        return Ok(());
+    }
+    build_and_tag(ctx, built_services).await?;
+    registry::login_local(registry, password)?;
 
-    if !load_status.success() {
-        bail!(
-            "Image transfer failed (docker save | ssh docker load) to {}",
-            host.address
-        );
+    for (_, image) in built_services {
+        output::info(&format!("Pushing {}", image));
+        let status = tokio::process::Command::new("docker")
+            .args(["push", "-q", image])
+            .status()
+            .await
+            .with_context(|| format!("Failed to run docker push {}", image))?;
+        if !status.success() {
+            bail!("docker push {} failed", image);
+        }
     }
+    output::success(&format!("Pushed {} image(s)", built_services.len()));
+    Ok(())
+}
 
+/// Registry builds: pull the images `publish_images` pushed
+async fn pull_images(
+    session: &SshSession,
+    ctx: &DeployContext,
+    host: &HostConfig,
+    spinner: &ProgressBar,
+) -> Result<()> {
+    let built_services = parse_built_services(ctx)?;
+    for (_, image) in &built_services {
+        session
+            .exec_streamed(&format!("docker pull -q {}", shell_quote(image)), |line| {
+                spinner.suspend(|| output::remote_line(line))
+            })
+            .await
+            .with_context(|| format!("Failed to pull {} on {}", image, host.address))?;
+    }
     Ok(())
 }
 
+/// Uncompressed size of the images, what `docker save` will stream
+async fn image_size(images: &[&str]) -> Option<u64> {
+    let output = tokio::process::Command::new("docker")
+        .args(["image", "inspect", "--format", "{{.Size}}"])
+        .args(images)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().parse::<u64>().ok())
+        .sum()
+}
+
 pub async fn start_new(session: &SshSession, ctx: &DeployContext) -> Result<()> {
     output::step(7, TOTAL_STEPS, "Starting new release");
 
@@ -323,13 +775,33 @@ pub async fn start_new(session: &SshSession, ctx: &DeployContext) -> Result<()>
     Ok(())
 }
 
-pub async fn health_check(session: &SshSession, ctx: &DeployContext) -> Result<()> {
+pub async fn health_check(
+    session: &SshSession,
+    ctx: &DeployContext,
+    host: &HostConfig,
+) -> Result<()> {
     output::step(8, TOTAL_STEPS, "Running health check");
 
+    if ssh::is_dry_run() {
+        output::info("Dry run: nothing was started, so there is nothing to check");
+        return Ok(());
+    }
+
     let hc = &ctx.config.deploy.health_check;
     let web_service = ctx.web_service();
     let release_path = ctx.remote_release_path();
 
+    // Hosts whose roles leave out the web service only run the other services
+    if !ctx.config.deploy.runs_on(web_service, host) {
+        output::info(&format!(
+            "{} does not run {} (roles: {})",
+            host.address,
+            web_service,
+            host.role.join(", ")
+        ));
+        return check_services(session, ctx, host).await;
+    }
+
     let container_id = session
         .exec(&format!(
             "cd {} && docker compose ps -q {}",
@@ -362,7 +834,7 @@ pub async fn health_check(session: &SshSession, ctx: &DeployContext) -> Result<(
             "healthy" => {
                 spinner.finish_and_clear();
                 output::success("Health check passed");
-                return Ok(());
+                return check_services(session, ctx, host).await;
             }
             "unhealthy" => {
                 spinner.finish_and_clear();
@@ -385,6 +857,109 @@ pub async fn health_check(session: &SshSession, ctx: &DeployContext) -> Result<(
     );
 }
 
+/// Wait for `health_check.services`: healthy when the service defines a
+/// healthcheck, otherwise running with the same restart count on two checks
+/// in a row. A container that exits or restarts fails the deploy right away,
+/// so a crash-looping worker doesn't pass as a successful deploy.
+async fn check_services(
+    session: &SshSession,
+    ctx: &DeployContext,
+    host: &HostConfig,
+) -> Result<()> {
+    let hc = &ctx.config.deploy.health_check;
+    // Only the services this host's roles run
+    let services: Vec<&str> = hc
+        .services
+        .iter()
+        .map(String::as_str)
+        .filter(|service| ctx.config.deploy.runs_on(service, host))
+        .collect();
+    if services.is_empty() {
+        return Ok(());
+    }
+    let release_path = ctx.remote_release_path();
+
+    let spinner = output::create_spinner(&format!(
+        "Waiting for {} to come up ...",
+        services.join(", ")
+    ));
+
+    let mut pending = services.clone();
+    // Last state seen while every container of the service was running
+    let mut running: HashMap<&str, String> = HashMap::new();
+
+    for attempt in 1..=hc.retries {
+        let mut waiting = Vec::new();
+        for service in pending {
+            // One line per container: "<health or state> <restart count>"
+            let state = session
+                .exec(&format!(
+                    "cd {} && docker inspect --format '{{{{if .State.Health}}}}{{{{.State.Health.Status}}}}{{{{else}}}}{{{{.State.Status}}}}{{{{end}}}} {{{{.RestartCount}}}}' $(docker compose ps -aq {})",
+                    release_path, service
+                ))
+                .await
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            let statuses: Vec<&str> = state
+                .lines()
+                .map(|l| l.split_whitespace().next().unwrap_or_default())
+                .collect();
+            debug!(
+                "{}: {:?} (attempt {}/{})",
+                service, statuses, attempt, hc.retries
+            );
+
+            if let Some(bad) = statuses
+                .iter()
+                .find(|s| matches!(**s, "unhealthy" | "restarting" | "exited" | "dead"))
+            {
+                spinner.finish_and_clear();
+                let logs = session
+                    .exec(&format!(
+                        "cd {} && docker compose logs --tail 20 {} 2>&1",
+                        release_path, service
+                    ))
+                    .await
+                    .unwrap_or_default();
+                bail!(
+                    "Service {} is {} (deploy.health_check.services)\n{}",
+                    service,
+                    bad,
+                    logs.trim_end()
+                );
+            }
+
+            let up =
+                !statuses.is_empty() && statuses.iter().all(|s| *s == "healthy" || *s == "running");
+            let settled =
+                statuses.iter().all(|s| *s == "healthy") || running.get(service) == Some(&state);
+            if up && settled {
+                continue;
+            }
+            if up {
+                running.insert(service, state);
+            }
+            waiting.push(service);
+        }
+
+        pending = waiting;
+        if pending.is_empty() {
+            spinner.finish_and_clear();
+            output::success(&format!("{} up", services.join(", ")));
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(hc.interval)).await;
+    }
+
+    spinner.finish_and_clear();
+    bail!(
+        "Timed out after {} attempts waiting for {} (deploy.health_check.services)",
+        hc.retries,
+        pending.join(", ")
+    );
+}
+
 pub async fn stop_previous(session: &SshSession, ctx: &DeployContext) -> Result<()> {
     output::step(9, TOTAL_STEPS, "Stopping previous release");
 
@@ -441,6 +1016,7 @@ pub async fn update_lock(session: &SshSession, ctx: &DeployContext) -> Result<()
     );
 
     lock.write(session, &app_path).await?;
+    lock.write_release_record(session, &app_path).await?;
 
     output::success("Lock file updated");
     Ok(())
@@ -449,50 +1025,209 @@ pub async fn update_lock(session: &SshSession, ctx: &DeployContext) -> Result<()
 pub async fn cleanup_old_releases(session: &SshSession, ctx: &DeployContext) -> Result<()> {
     output::step(12, TOTAL_STEPS, "Cleaning up old releases");
 
-    let releases_dir = format!("{}/releases", ctx.remote_app_path());
-    let keep = ctx.config.deploy.keep_releases;
+    // Use --rmi all for local builds (compose sees `image:` not `build:`)
+    let rmi_flag = if ctx.builds_locally() { "all" } else { "local" };
+    let removed = release::remove_old(
+        session,
+        &ctx.remote_app_path(),
+        ctx.config.deploy.keep_releases,
+        ctx.config.deploy.keep_releases_days,
+        rmi_flag,
+    )
+    .await?;
+
+    if removed.is_empty() {
+        output::success("Nothing to clean up");
+    } else {
+        output::success(&format!("Removed {} old release(s)", removed.len()));
+    }
+    Ok(())
+}
 
-    let output_str = session
-        .exec(&format!("ls -1 {} | sort -r", releases_dir))
-        .await?;
+/// Request the health path through the public domain once every host runs
+/// the new release, and check the served certificate. Problems are warnings
+/// unless `on_failure = "fail"`; either way the release stays live.
+pub async fn check_public(ctx: &DeployContext, check: &PublicCheckConfig) -> Result<()> {
+    let Some(traefik) = &ctx.stage.traefik else {
+        output::warning("deploy.public_check is set but the stage has no traefik domain; skipping");
+        return Ok(());
+    };
+    let path = check
+        .path
+        .as_deref()
+        .unwrap_or(&ctx.config.deploy.health_check.path);
+    let scheme = if traefik.tls { "https" } else { "http" };
+    let url = format!("{}://{}{}", scheme, traefik.domain, path);
+
+    let spinner = output::create_spinner(&format!("Requesting {} ...", url));
+    let mut last = String::new();
+    let mut reachable = false;
+    for attempt in 1..=check.retries {
+        match traefik::public::http_status(&url) {
+            Ok(status) if status == check.expect_status => {
+                reachable = true;
+                break;
+            }
+            Ok(status) => last = format!("HTTP {}", status),
+            Err(e) => last = format!("{:#}", e),
+        }
+        debug!(
+            "Public check attempt {}/{}: {}",
+            attempt, check.retries, last
+        );
+        if attempt < check.retries {
+            tokio::time::sleep(Duration::from_secs(check.interval)).await;
+        }
+    }
+    spinner.finish_and_clear();
 
-    let releases: Vec<&str> = output_str.lines().collect();
+    let mut problems = Vec::new();
+    if reachable {
+        output::success(&format!("{} answers {}", url, check.expect_status));
+    } else {
+        problems.push(format!(
+            "{} did not answer {} after {} attempt(s): {}",
+            url, check.expect_status, check.retries, last
+        ));
+    }
 
-    if releases.len() <= keep {
-        output::success("Nothing to clean up");
+    if traefik.tls {
+        match traefik::public::served(&traefik.domain) {
+            Ok(cert) if cert.days_left() < check.min_cert_days => problems.push(format!(
+                "The certificate of {} expires in {} day(s) ({})",
+                traefik.domain,
+                cert.days_left(),
+                cert.expires.format("%Y-%m-%d")
+            )),
+            Ok(cert) => output::success(&format!(
+                "Certificate of {} valid for {} more day(s)",
+                traefik.domain,
+                cert.days_left()
+            )),
+            Err(e) => problems.push(format!("{:#}", e)),
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if check.on_failure == "fail" {
+        bail!(
+            "Release {} is live but the public check failed:\n{}",
+            ctx.release.name,
+            problems.join("\n")
+        );
+    }
+    for problem in &problems {
+        output::warning(problem);
+    }
+    Ok(())
+}
+
+/// Run `[hooks.local]` commands in the project directory with SHIPIT_*
+/// variables set, stopping at the first failure
+pub fn run_local_hooks(ctx: &DeployContext, point: &str, hooks: &[String]) -> Result<()> {
+    if hooks.is_empty() {
         return Ok(());
     }
+    output::info(&format!("Running local {} hook(s)", point));
 
-    let to_remove = &releases[keep..];
-    let mut removed = 0;
+    for hook in hooks {
+        if ssh::is_dry_run() {
+            output::planned("local", hook);
+            continue;
+        }
+        let status = Command::new("sh")
+            .args(["-c", hook])
+            .current_dir(&ctx.project_root)
+            .env("SHIPIT_STAGE", &ctx.stage_name)
+            .env("SHIPIT_RELEASE", &ctx.release.name)
+            .env("SHIPIT_SHA", ctx.git_sha().unwrap_or_default())
+            .status()
+            .with_context(|| format!("Failed to run {} hook: {}", point, hook))?;
+        if !status.success() {
+            bail!("{} hook failed ({}): {}", point, status, hook);
+        }
+    }
 
-    for release in to_remove {
-        let release_path = format!("{}/{}", releases_dir, release);
+    output::success(&format!("Local {} hooks done", point));
+    Ok(())
+}
 
-        // Stop containers and remove images
-        // Use --rmi all for local builds (compose sees `image:` not `build:`)
-        let rmi_flag = if ctx.is_local_build() { "all" } else { "local" };
-        let _ = session
-            .exec(&format!(
-                "cd {} && docker compose down --rmi {} 2>/dev/null || true",
-                release_path, rmi_flag
-            ))
-            .await;
+/// Run `[hooks.remote]` commands for one point of the pipeline. Host hooks
+/// run in the release directory with SHIPIT_* variables set; container hooks
+/// go through `docker compose exec`, or a one-off `docker compose run` for
+/// `after_build`, when the release isn't running yet.
+pub async fn run_remote_hooks(
+    session: &SshSession,
+    ctx: &DeployContext,
+    point: &str,
+    hooks: &[RemoteHook],
+) -> Result<()> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    output::info(&format!("Running {} hook(s)", point));
 
-        // Remove directory
+    let release_path = ctx.remote_release_path();
+    for hook in hooks {
+        let cmd = match hook {
+            RemoteHook::Host(cmd) => format!(
+                "cd {} && SHIPIT_STAGE={} SHIPIT_RELEASE={} SHIPIT_RELEASE_PATH={} bash -c {}",
+                release_path,
+                shell_quote(&ctx.stage_name),
+                shell_quote(&ctx.release.name),
+                shell_quote(&release_path),
+                shell_quote(cmd)
+            ),
+            RemoteHook::Container { service, cmd } => format!(
+                "cd {} && docker compose {} -T {} sh -c {}",
+                release_path,
+                if point == "after_build" {
+                    "run --rm"
+                } else {
+                    "exec"
+                },
+                shell_quote(service),
+                shell_quote(cmd)
+            ),
+        };
         session
-            .exec(&format!("rm -rf {}", release_path))
-            .await?;
-
-        removed += 1;
+            .exec_streamed(&cmd, output::remote_line)
+            .await
+            .with_context(|| format!("{} hook failed", point))?;
     }
 
-    output::success(&format!("Removed {} old release(s)", removed));
+    output::success(&format!("{} hooks done", point));
+    Ok(())
+}
+
+/// Schedule the `[cron]` jobs that run on this host (the stage's first host
+/// runs them all, the others only `all_hosts` jobs) and drop removed ones
+pub async fn sync_cron(session: &SshSession, ctx: &DeployContext, host: &HostConfig) -> Result<()> {
+    let index = ctx
+        .stage
+        .hosts
+        .iter()
+        .position(|h| h.address == host.address)
+        .unwrap_or(0);
+    let jobs = cron::jobs_for_host(&ctx.config, index);
+    let scheduled = cron::sync(
+        session,
+        &ctx.config,
+        ctx.stage.os.as_deref(),
+        ctx.user(),
+        &jobs,
+    )
+    .await?;
+    if scheduled > 0 {
+        output::success(&format!("{} cron job(s) scheduled", scheduled));
+    }
     Ok(())
 }
 
 pub async fn rollback_on_failure(session: &SshSession, ctx: &DeployContext) -> Result<()> {
-    output::warning("Health check failed, rolling back...");
+    output::warning("New release failed, rolling back...");
 
     // Just stop the new release — the previous one was never touched
     let _ = session
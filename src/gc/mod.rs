@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+
+use crate::config::{GcConfig, ShipitConfig};
+use crate::cron;
+use crate::os::HostOs;
+use crate::ssh::SshSession;
+
+fn unit(config: &ShipitConfig) -> String {
+    format!("shipit-{}-gc", config.app.name)
+}
+
+/// Shell command reclaiming Docker disk space. Images of kept releases are
+/// still tagged, so only dangling ones go unless `all_images` is set.
+pub fn command(gc: &GcConfig) -> String {
+    format!(
+        "docker image prune -f{} --filter until={} && docker builder prune -f --filter until={}",
+        if gc.all_images { " -a" } else { "" },
+        gc.keep,
+        gc.keep
+    )
+}
+
+/// Install the GC script and its timer on a host (setup step)
+pub async fn install(
+    session: &SshSession,
+    config: &ShipitConfig,
+    gc: &GcConfig,
+    user: &str,
+    os: HostOs,
+) -> Result<()> {
+    let script_path = format!("{}/gc.sh", config.app_path());
+    session
+        .write_file(
+            &script_path,
+            &format!(
+                "#!/bin/sh\n# Docker image and build cache GC for {}, installed by shipit\nset -e\n{}\n",
+                config.app.name,
+                command(gc)
+            ),
+        )
+        .await?;
+
+    let unit = unit(config);
+    let on_calendar = cron::on_calendar(&gc.schedule).context("Invalid gc.schedule")?;
+    let description = format!("shipit Docker GC ({})", config.app.name);
+    cron::Timer {
+        unit: &unit,
+        description: &description,
+        user,
+        script_path: &script_path,
+        on_calendar: &on_calendar,
+    }
+    .write(session, os)
+    .await?;
+    session
+        .sudo_exec(&format!(
+            "systemctl daemon-reload && systemctl restart {}.timer",
+            unit
+        ))
+        .await
+        .context("Failed to start the GC timer")?;
+    Ok(())
+}
+
+/// Remove the GC timer (teardown)
+pub async fn remove(session: &SshSession, config: &ShipitConfig, os: HostOs) -> Result<()> {
+    session
+        .sudo_exec(&format!(
+            "{}; systemctl daemon-reload",
+            cron::remove_timer_cmd(os, &unit(config))
+        ))
+        .await
+        .context("Failed to remove the GC timer")?;
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use anyhow::{bail, Context, Result};
+
+use crate::config::{HostConfig, StageConfig};
+use crate::os::HostOs;
+use crate::output;
+use crate::ssh::SshSession;
+
+/// Sorts before distro drop-ins (e.g. 50-cloud-init.conf) so our values win
+const SSHD_DROPIN: &str = "/etc/ssh/sshd_config.d/00-shipit-hardening.conf";
+const FAIL2BAN_JAIL: &str = "/etc/fail2ban/jail.d/shipit.local";
+
+/// Disable password authentication and root login, then reload sshd.
+/// A fresh connection is opened afterwards; if it fails the change is reverted
+/// over the still-open session.
+pub async fn harden_ssh(
+    session: &SshSession,
+    stage: &StageConfig,
+    host: &HostConfig,
+    host_os: HostOs,
+) -> Result<()> {
+    output::info("Hardening sshd...");
+
+    if host_os.needs_unified_module() {
+        output::warning(
+            "NixOS: set services.openssh.settings in configuration.nix instead (skipped)",
+        );
+        return Ok(());
+    }
+
+    let user = stage.user.as_deref().unwrap_or("deploy");
+    // Never lock out a stage that deploys as root
+    let permit_root = if user == "root" {
+        "prohibit-password"
+    } else {
+        "no"
+    };
+
+    let dropin = format!(
+        "# Managed by shipit\n\
+         PasswordAuthentication no\n\
+         KbdInteractiveAuthentication no\n\
+         PermitRootLogin {}\n",
+        permit_root
+    );
+
+    session
+        .sudo_exec("mkdir -p /etc/ssh/sshd_config.d")
+        .await
+        .context("Failed to create sshd_config.d")?;
+    session
+        .sudo_write_file(SSHD_DROPIN, &dropin)
+        .await
+        .context("Failed to write sshd hardening config")?;
+
+    if let Err(e) = session.sudo_exec("sshd -t").await {
+        let _ = session.sudo_exec(&format!("rm -f {}", SSHD_DROPIN)).await;
+        return Err(e).context("sshd rejected the hardening config (reverted)");
+    }
+
+    reload_sshd(session).await?;
+
+    // Verify we can still get in before declaring success
+    let probe = SshSession::connect(
+        user,
+        &host.address,
+        stage.port,
+        stage.proxy.as_deref(),
+        &stage.ssh,
+    )
+    .await;
+
+    match probe {
+        Ok(probe) => {
+            probe.exec("true").await?;
+            probe.close().await?;
+        }
+        Err(e) => {
+            output::error("New SSH connection failed after hardening, reverting...");
+            session
+                .sudo_exec(&format!("rm -f {}", SSHD_DROPIN))
+                .await
+                .context("Failed to revert sshd hardening")?;
+            reload_sshd(session).await?;
+            bail!("SSH hardening reverted: {:#}", e);
+        }
+    }
+
+    output::success("sshd hardened (no passwords, no root login)");
+    Ok(())
+}
+
+async fn reload_sshd(session: &SshSession) -> Result<()> {
+    session
+        .sudo_exec("systemctl reload ssh 2>/dev/null || systemctl reload sshd")
+        .await
+        .context("Failed to reload sshd")?;
+    Ok(())
+}
+
+/// Install fail2ban with an sshd jail on the stage's SSH port
+pub async fn install_fail2ban(session: &SshSession, port: Option<u16>, host_os: HostOs) -> Result<()> {
+    output::info("Setting up fail2ban...");
+
+    if host_os.needs_unified_module() {
+        output::warning("NixOS: enable services.fail2ban in configuration.nix instead (skipped)");
+        return Ok(());
+    }
+
+    if !session.exec_ok("command -v fail2ban-client").await? {
+        let spinner = output::create_spinner("Installing fail2ban...");
+        session
+            .sudo_exec(&host_os.install_packages_cmd("fail2ban"))
+            .await
+            .context("Failed to install fail2ban")?;
+        spinner.finish_and_clear();
+    }
+
+    let jail = format!(
+        "# Managed by shipit\n\
+         [sshd]\n\
+         enabled = true\n\
+         port = {}\n\
+         backend = systemd\n\
+         maxretry = 5\n\
+         findtime = 10m\n\
+         bantime = 1h\n",
+        port.unwrap_or(22)
+    );
+
+    session
+        .sudo_write_file(FAIL2BAN_JAIL, &jail)
+        .await
+        .context("Failed to write fail2ban jail")?;
+
+    session
+        .sudo_exec("systemctl enable fail2ban && systemctl restart fail2ban")
+        .await
+        .context("Failed to start fail2ban")?;
+
+    output::success("fail2ban active (sshd jail)");
+    Ok(())
+}
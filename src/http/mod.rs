@@ -0,0 +1,233 @@
+use anyhow::{bail, Context, Result};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Longest request or header line accepted
+const MAX_LINE: u64 = 8 * 1024;
+/// Most header lines accepted in one request
+const MAX_HEADERS: usize = 64;
+/// Time a client gets to send the request head, before any auth check
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed HTTP/1.1 request (one request per connection). Bodies are not
+/// read; handlers take their parameters from the path and query string.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    /// Header names are lowercased
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The URL-decoded value of a query string parameter
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| percent_decode(key) == name)
+            .map(|(_, value)| percent_decode(value))
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+    /// Server-sent events written as they arrive, instead of `body`
+    pub events: Option<mpsc::Receiver<String>>,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: impl Into<String>) -> Self {
+        Self::with_status(200, content_type, body)
+    }
+
+    pub fn with_status(status: u16, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+            events: None,
+        }
+    }
+
+    /// A `text/event-stream` response; each received string is one
+    /// already-formatted event and the stream ends when the sender is dropped
+    pub fn event_stream(events: mpsc::Receiver<String>) -> Self {
+        Self {
+            events: Some(events),
+            ..Self::ok("text/event-stream", "")
+        }
+    }
+
+    pub fn method_not_allowed() -> Self {
+        Self::with_status(405, "text/plain", "Method not allowed\n")
+    }
+
+    pub fn not_found() -> Self {
+        Self::with_status(404, "text/plain", "Not found\n")
+    }
+}
+
+/// ":9100" -> "0.0.0.0:9100"
+pub fn listen_addr(listen: &str) -> String {
+    if listen.starts_with(':') {
+        format!("0.0.0.0{}", listen)
+    } else {
+        listen.to_string()
+    }
+}
+
+/// Accept connections forever, answering each request with `handler`
+pub async fn serve<F, Fut>(listen: &str, handler: F) -> Result<()>
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    let addr = listen_addr(listen);
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    let handler = Arc::new(handler);
+
+    loop {
+        let (stream, _) = listener.accept().await.context("Failed to accept connection")?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let _ = handle(stream, handler.as_ref()).await;
+        });
+    }
+}
+
+async fn handle<F, Fut>(stream: TcpStream, handler: &F) -> Result<()>
+where
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+
+    let request = tokio::time::timeout(READ_TIMEOUT, read_request(&mut reader))
+        .await
+        .context("Timed out reading the request")??;
+    let response = handler(request).await;
+
+    if let Some(mut events) = response.events {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            response.status,
+            reason(response.status),
+            response.content_type
+        );
+        write.write_all(head.as_bytes()).await?;
+        // A write error means the client went away; dropping `events` tells the sender
+        while let Some(event) = events.recv().await {
+            write.write_all(event.as_bytes()).await?;
+            write.flush().await?;
+        }
+        write.shutdown().await?;
+        return Ok(());
+    }
+
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    write.write_all(head.as_bytes()).await?;
+    write.write_all(response.body.as_bytes()).await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+/// Read the request line and headers, bounded by `MAX_LINE` and `MAX_HEADERS`
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Request> {
+    let request_line = read_line(reader).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader).await?;
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            bail!("Too many request headers");
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+    })
+}
+
+/// One line of the request head; empty at end of stream
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    let read = (&mut *reader).take(MAX_LINE).read_line(&mut line).await?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        bail!("Request line longer than {} bytes", MAX_LINE);
+    }
+    Ok(line)
+}
+
+/// Decode `%XX` escapes and `+` (space) in a query string component
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use toml::{Table, Value};
+
+use super::Import;
+
+/// Convert Capistrano's `config/deploy.rb` plus the stage files in
+/// `config/deploy/*.rb` into a shipit config. The Ruby is scanned line by
+/// line for literal `set`, `server` and `role` calls; anything computed is
+/// reported instead of guessed.
+pub fn convert(path: &Path) -> Result<Import> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut import = Import::default();
+
+    let application = setting(&content, "application")
+        .context("config/deploy.rb does not `set :application` to a literal name")?;
+    import.set("app.name", application.as_str());
+
+    let interpolate = |value: String| value.replace("#{fetch(:application)}", &application);
+
+    match setting(&content, "repo_url") {
+        Some(repo) => import.set("app.repository", interpolate(repo)),
+        None => {
+            import.set("app.repository", "CHANGE_ME");
+            import.todo.push("Set app.repository".to_string());
+        }
+    }
+    import.set(
+        "app.branch",
+        setting(&content, "branch").unwrap_or_else(|| "main".to_string()),
+    );
+
+    // shipit deploys to <deploy_to>/<app>, Capistrano to deploy_to itself
+    if let Some(deploy_to) = setting(&content, "deploy_to").map(interpolate) {
+        match deploy_to.strip_suffix(&format!("/{}", application)) {
+            Some(base) if !base.contains("#{") => import.set("deploy.deploy_to", base),
+            _ => import.todo.push(format!(
+                "Capistrano deployed to {}; shipit uses <deploy.deploy_to>/{} — set deploy.deploy_to accordingly",
+                deploy_to, application
+            )),
+        }
+    }
+    if let Some(keep) = setting(&content, "keep_releases").and_then(|k| k.parse::<i64>().ok()) {
+        import.set("deploy.keep_releases", keep);
+    }
+
+    for key in ["linked_files", "linked_dirs"] {
+        if content.contains(&format!("set :{}", key)) || content.contains(&format!("append :{}", key)) {
+            import.unsupported.push(format!(
+                "{}: shared files/dirs become compose volumes and shared/.env",
+                key
+            ));
+        }
+    }
+
+    let stages_dir = path.parent().map(|dir| dir.join("deploy"));
+    let mut stage_files: Vec<_> = match &stages_dir {
+        Some(dir) if dir.is_dir() => std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rb"))
+            .collect(),
+        _ => Vec::new(),
+    };
+    stage_files.sort();
+
+    if stage_files.is_empty() {
+        import
+            .todo
+            .push("No config/deploy/*.rb stage files found; add [stages.*] with hosts".to_string());
+    }
+
+    for file in stage_files {
+        let Some(name) = file.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let stage = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        convert_stage(&name, &stage, &mut import);
+    }
+
+    Ok(import)
+}
+
+fn convert_stage(name: &str, content: &str, import: &mut Import) {
+    let prefix = format!("stages.{}", name);
+    let mut hosts: Vec<String> = Vec::new();
+    let mut users: Vec<String> = Vec::new();
+    let mut port = None;
+
+    for line in content.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("server ") {
+            // server "1.2.3.4", user: "deploy", roles: %w{app web}, port: 2222
+            let Some(address) = quoted(rest).into_iter().next() else {
+                continue;
+            };
+            let (user, host) = split_user(&address);
+            push_unique(&mut hosts, host);
+            if let Some(user) = option(rest, "user").or(user) {
+                push_unique(&mut users, user);
+            }
+            if let Some(p) = option(rest, "port").and_then(|p| p.parse::<i64>().ok()) {
+                port = Some(p);
+            }
+        } else if let Some(rest) = line.strip_prefix("role ") {
+            // role :app, %w{deploy@1.2.3.4 deploy@5.6.7.8}
+            let Some((_, list)) = rest.split_once(',') else {
+                continue;
+            };
+            for entry in word_list(list) {
+                let (user, host) = split_user(&entry);
+                push_unique(&mut hosts, host);
+                if let Some(user) = user {
+                    push_unique(&mut users, user);
+                }
+            }
+        }
+    }
+
+    let hosts: Vec<Value> = hosts
+        .into_iter()
+        .map(|address| {
+            let mut host = Table::new();
+            host.insert("address".to_string(), Value::String(address));
+            Value::Table(host)
+        })
+        .collect();
+    if hosts.is_empty() {
+        import
+            .todo
+            .push(format!("Add hosts to {} (none were literal in the stage file)", prefix));
+    }
+    import.set(&format!("{}.hosts", prefix), hosts);
+
+    if let Some(user) = users.first() {
+        import.set(&format!("{}.user", prefix), user.as_str());
+    }
+    if users.len() > 1 {
+        import.unsupported.push(format!(
+            "{}: several SSH users ({}); shipit uses one per stage",
+            prefix,
+            users.join(", ")
+        ));
+    }
+    if let Some(port) = port {
+        import.set(&format!("{}.port", prefix), port);
+    }
+
+    if let Some(branch) = setting(content, "branch") {
+        import.unsupported.push(format!(
+            "{}: branch {} (shipit deploys app.branch for every stage)",
+            prefix, branch
+        ));
+    }
+}
+
+/// Literal value of `set :key, ...` (quoted string, symbol or number)
+fn setting(content: &str, key: &str) -> Option<String> {
+    let marker = format!("set :{},", key);
+    content.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix(&marker)?.trim();
+        if rest.starts_with(['"', '\'']) {
+            return quoted(rest).into_iter().next();
+        }
+        // Bare numbers and symbols; skip anything computed
+        let token = rest.split_whitespace().next()?.trim_start_matches(':');
+        token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            .then(|| token.to_string())
+    })
+}
+
+/// `key: "value"` or `key: value` inside a Ruby argument list
+fn option(args: &str, key: &str) -> Option<String> {
+    let (_, rest) = args.split_once(&format!("{}:", key))?;
+    let rest = rest.trim_start();
+    if rest.starts_with(['"', '\'']) {
+        return quoted(rest).into_iter().next();
+    }
+    rest.split([',', ' ']).next().map(str::to_string).filter(|v| !v.is_empty())
+}
+
+/// All single- or double-quoted strings, in order
+fn quoted(s: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' || c == '\'' {
+            let value: String = chars.by_ref().take_while(|&ch| ch != c).collect();
+            found.push(value);
+        }
+    }
+    found
+}
+
+/// `%w{a b}`, `%w[a b]` or `["a", "b"]`
+fn word_list(s: &str) -> Vec<String> {
+    let s = s.trim();
+    if let Some(rest) = s.strip_prefix("%w") {
+        return rest
+            .trim_matches(|c| matches!(c, '{' | '}' | '[' | ']' | '(' | ')'))
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+    }
+    quoted(s)
+}
+
+/// "deploy@1.2.3.4" -> (Some("deploy"), "1.2.3.4")
+fn split_user(address: &str) -> (Option<String>, String) {
+    match address.split_once('@') {
+        Some((user, host)) => (Some(user.to_string()), host.to_string()),
+        None => (None, address.to_string()),
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, setting, word_list};
+
+    const DEPLOY_RB: &str = r#"
+lock "~> 3.17.0"
+
+set :application, "myapp"
+set :repo_url, "git@github.com:acme/#{fetch(:application)}.git"
+set :deploy_to, "/var/www/#{fetch(:application)}"
+set :keep_releases, 3
+append :linked_dirs, "log", "tmp/pids"
+"#;
+
+    const PRODUCTION_RB: &str = r#"
+server "10.0.0.1", user: "deploy", roles: %w{app web}, port: 2222
+role :db, %w{deploy@10.0.0.2 deploy@10.0.0.1}
+set :branch, "release"
+"#;
+
+    #[test]
+    fn converts_deploy_rb_and_stage_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.rb");
+        std::fs::write(&path, DEPLOY_RB).unwrap();
+        std::fs::create_dir(dir.path().join("deploy")).unwrap();
+        std::fs::write(dir.path().join("deploy/production.rb"), PRODUCTION_RB).unwrap();
+        std::fs::write(
+            dir.path().join("deploy/staging.rb"),
+            "server 'admin@10.1.0.1', roles: [:app]\n",
+        )
+        .unwrap();
+
+        let import = convert(&path).unwrap();
+        let config = toml::Value::Table(import.config);
+
+        assert_eq!(config["app"]["name"].as_str(), Some("myapp"));
+        assert_eq!(
+            config["app"]["repository"].as_str(),
+            Some("git@github.com:acme/myapp.git")
+        );
+        assert_eq!(config["app"]["branch"].as_str(), Some("main"));
+        assert_eq!(config["deploy"]["deploy_to"].as_str(), Some("/var/www"));
+        assert_eq!(config["deploy"]["keep_releases"].as_integer(), Some(3));
+
+        let production = &config["stages"]["production"];
+        let hosts: Vec<&str> = production["hosts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|h| h["address"].as_str().unwrap())
+            .collect();
+        assert_eq!(hosts, ["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(production["user"].as_str(), Some("deploy"));
+        assert_eq!(production["port"].as_integer(), Some(2222));
+
+        let staging = &config["stages"]["staging"];
+        assert_eq!(staging["hosts"][0]["address"].as_str(), Some("10.1.0.1"));
+        assert_eq!(staging["user"].as_str(), Some("admin"));
+
+        assert!(import
+            .unsupported
+            .iter()
+            .any(|u| u.starts_with("linked_dirs")));
+        assert!(import
+            .unsupported
+            .iter()
+            .any(|u| u.starts_with("stages.production: branch release")));
+    }
+
+    #[test]
+    fn reads_only_literal_settings() {
+        let content = "set :stage, :production\nset :pty, true\nset :user, ENV['USER']\n";
+        assert_eq!(setting(content, "stage").as_deref(), Some("production"));
+        assert_eq!(setting(content, "pty").as_deref(), Some("true"));
+        assert_eq!(setting(content, "user"), None);
+        assert_eq!(setting(content, "missing"), None);
+    }
+
+    #[test]
+    fn parses_word_lists() {
+        assert_eq!(word_list(" %w{a b}"), ["a", "b"]);
+        assert_eq!(word_list("%w[a b]"), ["a", "b"]);
+        assert_eq!(word_list(r#"["a", 'b']"#), ["a", "b"]);
+    }
+}
@@ -0,0 +1,487 @@
+use anyhow::{Context, Result};
+use serde_yaml::Value as Yaml;
+use std::path::Path;
+use toml::{Table, Value};
+
+use super::Import;
+
+/// Top-level keys that are converted (fully or partially)
+const CONVERTED_KEYS: [&str; 13] = [
+    "service",
+    "image",
+    "servers",
+    "proxy",
+    "registry",
+    "env",
+    "ssh",
+    "accessories",
+    "healthcheck",
+    "retain_containers",
+    "deploy_timeout",
+    "builder",
+    "traefik",
+];
+
+/// Convert a Kamal `config/deploy.yml` (and any `deploy.<destination>.yml`
+/// next to it) into a shipit config
+pub fn convert(path: &Path, repository: &str) -> Result<Import> {
+    let doc = load(path)?;
+    let mut import = Import::default();
+
+    let service = doc["service"]
+        .as_str()
+        .context("Kamal config has no `service` name")?;
+    import.set("app.name", service);
+    import.set("app.repository", repository);
+    import.set("app.branch", "main");
+
+    import.todo.push(
+        "shipit deploys docker-compose.yml: create one with a `web` service built from your Dockerfile \
+         (the image name is not used)"
+            .to_string(),
+    );
+
+    if let Some(keep) = doc["retain_containers"].as_i64() {
+        import.set("deploy.keep_releases", keep);
+    }
+    if doc["builder"].is_mapping() {
+        import.unsupported.push(
+            "builder: images are built on the host (deploy.build = \"remote\") or locally (\"local\")"
+                .to_string(),
+        );
+    }
+
+    health_check(&doc, &mut import);
+    stage("production", &doc, &mut import);
+
+    // Destinations (deploy.staging.yml, ...) become stages, overriding the base keys
+    for (name, dest) in destinations(path)? {
+        let mut merged = doc.clone();
+        if let (Some(base), Some(overrides)) = (merged.as_mapping_mut(), dest.as_mapping()) {
+            for (key, value) in overrides {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+        stage(&name, &merged, &mut import);
+    }
+
+    registry(&doc, &mut import);
+    accessories(&doc, &mut import);
+
+    if let Some(map) = doc.as_mapping() {
+        for key in map.keys().filter_map(Yaml::as_str) {
+            if !CONVERTED_KEYS.contains(&key) {
+                import.unsupported.push(format!("{}: no shipit equivalent", key));
+            }
+        }
+    }
+
+    Ok(import)
+}
+
+fn load(path: &Path) -> Result<Yaml> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_yaml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// `deploy.<name>.yml` files in the same directory
+fn destinations(path: &Path) -> Result<Vec<(String, Yaml)>> {
+    let Some(dir) = path.parent() else {
+        return Ok(Vec::new());
+    };
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(name) = file_name
+            .strip_prefix("deploy.")
+            .and_then(|rest| rest.strip_suffix(".yml"))
+        {
+            found.push((name.to_string(), load(&dir.join(&file_name))?));
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(found)
+}
+
+fn health_check(doc: &Yaml, import: &mut Import) {
+    // Kamal 2 keeps these under `proxy`, Kamal 1 under `healthcheck`
+    let proxy = &doc["proxy"];
+    let legacy = &doc["healthcheck"];
+    if let Some(path) = proxy["healthcheck"]["path"].as_str().or(legacy["path"].as_str()) {
+        import.set("deploy.health_check.path", path);
+    }
+    if let Some(port) = proxy["app_port"].as_i64().or(legacy["port"].as_i64()) {
+        import.set("deploy.health_check.port", port);
+    }
+    if let Some(timeout) = doc["deploy_timeout"].as_i64() {
+        import.set("deploy.health_check.timeout", timeout);
+    }
+}
+
+fn stage(name: &str, doc: &Yaml, import: &mut Import) {
+    let prefix = format!("stages.{}", name);
+
+    let hosts: Vec<Value> = server_hosts(&doc["servers"], import)
+        .into_iter()
+        .map(|address| {
+            let mut host = Table::new();
+            host.insert("address".to_string(), Value::String(address));
+            Value::Table(host)
+        })
+        .collect();
+    import.set(&format!("{}.hosts", prefix), hosts);
+
+    // Kamal connects as root unless ssh.user is set
+    let ssh = &doc["ssh"];
+    import.set(
+        &format!("{}.user", prefix),
+        ssh["user"].as_str().unwrap_or("root"),
+    );
+    if let Some(port) = ssh["port"].as_i64() {
+        import.set(&format!("{}.port", prefix), port);
+    }
+    if let Some(proxy) = ssh["proxy"].as_str() {
+        import.set(&format!("{}.proxy", prefix), proxy.trim_start_matches("ssh://"));
+    }
+
+    let env = env_vars(&doc["env"], &format!("{} env", name), import);
+    if !env.is_empty() {
+        import.set(&format!("{}.env", prefix), env);
+    }
+    for key in secret_keys(&doc["env"]) {
+        import
+            .todo
+            .push(format!("shipit secrets set -s {} {}=...", name, key));
+    }
+
+    let proxy = &doc["proxy"];
+    let domain = proxy["host"]
+        .as_str()
+        .or_else(|| proxy["hosts"][0].as_str());
+    if let Some(domain) = domain {
+        import.set(&format!("{}.traefik.domain", prefix), domain);
+        let tls = proxy["ssl"].as_bool().unwrap_or(false);
+        import.set(&format!("{}.traefik.tls", prefix), tls);
+        if tls {
+            import.todo.push(format!(
+                "Set stages.{}.traefik.acme_email for Let's Encrypt",
+                name
+            ));
+        }
+        if proxy["hosts"].as_sequence().is_some_and(|h| h.len() > 1) {
+            import
+                .unsupported
+                .push(format!("{}: proxy.hosts beyond the first domain", name));
+        }
+    }
+    if doc["traefik"].is_mapping() {
+        import.unsupported.push(format!(
+            "{}: traefik options (shipit installs and configures Traefik itself)",
+            name
+        ));
+    }
+}
+
+/// Hosts of every role; roles other than `web` are reported since shipit
+/// runs the whole compose project on each host
+fn server_hosts(servers: &Yaml, import: &mut Import) -> Vec<String> {
+    let mut hosts = Vec::new();
+    match servers {
+        Yaml::Sequence(list) => hosts.extend(list.iter().filter_map(host_address)),
+        Yaml::Mapping(roles) => {
+            for (role, spec) in roles {
+                let role = role.as_str().unwrap_or("?");
+                let list = match spec {
+                    Yaml::Sequence(list) => list.clone(),
+                    _ => spec["hosts"].as_sequence().cloned().unwrap_or_default(),
+                };
+                for host in list.iter().filter_map(host_address) {
+                    if !hosts.contains(&host) {
+                        hosts.push(host);
+                    }
+                }
+                if role != "web" {
+                    import.unsupported.push(format!(
+                        "servers.{}: role hosts were merged into the stage; run the role as a compose service",
+                        role
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+    hosts
+}
+
+/// "1.2.3.4" or { "1.2.3.4": [tags] }
+fn host_address(host: &Yaml) -> Option<String> {
+    match host {
+        Yaml::String(address) => Some(address.clone()),
+        Yaml::Mapping(map) => map.keys().next()?.as_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Clear env vars: `env.clear` or a flat `env` mapping
+fn env_vars(env: &Yaml, what: &str, import: &mut Import) -> Table {
+    let clear = if env["clear"].is_mapping() || env["secret"].is_sequence() {
+        &env["clear"]
+    } else {
+        env
+    };
+    if env["tags"].is_mapping() {
+        import.unsupported.push(format!("{}: tags", what));
+    }
+
+    let mut vars = Table::new();
+    if let Some(map) = clear.as_mapping() {
+        for (key, value) in map {
+            let (Some(key), Some(value)) = (key.as_str(), yaml_scalar(value)) else {
+                continue;
+            };
+            vars.insert(key.to_string(), Value::String(value));
+        }
+    }
+    vars
+}
+
+fn secret_keys(env: &Yaml) -> Vec<String> {
+    env["secret"]
+        .as_sequence()
+        .map(|keys| {
+            keys.iter()
+                .filter_map(Yaml::as_str)
+                // KEY:ALIAS reads ALIAS from .kamal/secrets into KEY
+                .map(|k| k.split(':').next().unwrap_or(k).to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn yaml_scalar(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) => Some(s.clone()),
+        Yaml::Number(n) => Some(n.to_string()),
+        Yaml::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn registry(doc: &Yaml, import: &mut Import) {
+    let registry = &doc["registry"];
+    if !registry.is_mapping() {
+        return;
+    }
+
+    import.set(
+        "registry.server",
+        registry["server"].as_str().unwrap_or("docker.io"),
+    );
+    match &registry["username"] {
+        Yaml::String(username) => import.set("registry.username", username.as_str()),
+        _ => {
+            import.set("registry.username", "CHANGE_ME");
+            import
+                .todo
+                .push("Set registry.username (it was read from a Kamal secret)".to_string());
+        }
+    }
+
+    let password_secret = registry["password"][0]
+        .as_str()
+        .unwrap_or("REGISTRY_PASSWORD");
+    import.set("registry.password_secret", password_secret);
+    import.todo.push(format!(
+        "shipit secrets set -s <stage> {}=... (registry password)",
+        password_secret
+    ));
+}
+
+fn accessories(doc: &Yaml, import: &mut Import) {
+    let Some(accessories) = doc["accessories"].as_mapping() else {
+        return;
+    };
+
+    for (name, acc) in accessories {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let prefix = format!("accessories.{}", name);
+
+        if let Some(image) = acc["image"].as_str() {
+            import.set(&format!("{}.image", prefix), image);
+        }
+
+        let host = acc["host"]
+            .as_str()
+            .or_else(|| acc["hosts"][0].as_str());
+        match host {
+            Some(host) => import.set(&format!("{}.host", prefix), host),
+            None => {
+                import.set(&format!("{}.host", prefix), "CHANGE_ME");
+                import
+                    .todo
+                    .push(format!("Set {}.host (Kamal placed it by role)", prefix));
+            }
+        }
+        if acc["hosts"].as_sequence().is_some_and(|h| h.len() > 1) {
+            import
+                .unsupported
+                .push(format!("{}: multiple hosts (shipit runs one instance)", prefix));
+        }
+
+        if let Some(port) = yaml_scalar(&acc["port"]) {
+            import.set(&format!("{}.port", prefix), port);
+        }
+        if let Some(cmd) = acc["cmd"].as_str() {
+            import.set(&format!("{}.cmd", prefix), cmd);
+        }
+
+        let env = env_vars(&acc["env"], &prefix, import);
+        if !env.is_empty() {
+            import.set(&format!("{}.env", prefix), env);
+        }
+        for key in secret_keys(&acc["env"]) {
+            import.todo.push(format!(
+                "Add {} to {}.env (accessory secrets are plain env in shipit)",
+                key, prefix
+            ));
+        }
+
+        // Kamal's `directories` live under the accessory dir; keep them as bind/volume mounts
+        let volumes: Vec<Value> = ["volumes", "directories"]
+            .iter()
+            .filter_map(|key| acc[*key].as_sequence())
+            .flatten()
+            .filter_map(Yaml::as_str)
+            .map(|v| Value::String(v.to_string()))
+            .collect();
+        if !volumes.is_empty() {
+            import.set(&format!("{}.volumes", prefix), volumes);
+        }
+        if acc["directories"].is_sequence() {
+            import.todo.push(format!(
+                "Check {}.volumes: Kamal directories were copied as-is and may need absolute host paths",
+                prefix
+            ));
+        }
+
+        for key in ["files", "options", "labels", "roles"] {
+            if !acc[key].is_null() {
+                import.unsupported.push(format!("{}: {}", prefix, key));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::convert;
+
+    const DEPLOY: &str = r#"
+service: myapp
+image: acme/myapp
+servers:
+  web:
+    - 10.0.0.1
+    - 10.0.0.2
+  job:
+    hosts:
+      - 10.0.0.3
+proxy:
+  ssl: true
+  host: app.example.com
+  app_port: 3000
+  healthcheck:
+    path: /up
+ssh:
+  user: app
+env:
+  clear:
+    RAILS_ENV: production
+    WORKERS: 2
+  secret:
+    - RAILS_MASTER_KEY
+    - DATABASE_URL:PROD_DATABASE_URL
+registry:
+  username: acme
+  password:
+    - KAMAL_REGISTRY_PASSWORD
+accessories:
+  db:
+    image: postgres:16
+    host: 10.0.0.4
+    port: 5432
+    directories:
+      - data:/var/lib/postgresql/data
+retain_containers: 3
+asset_path: /rails/public/assets
+"#;
+
+    #[test]
+    fn converts_deploy_yml_and_destinations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.yml");
+        std::fs::write(&path, DEPLOY).unwrap();
+        std::fs::write(
+            dir.path().join("deploy.staging.yml"),
+            "servers:\n  - 10.1.0.1\nproxy:\n  host: staging.example.com\n",
+        )
+        .unwrap();
+
+        let import = convert(&path, "git@github.com:acme/myapp.git").unwrap();
+        let config = toml::Value::Table(import.config);
+
+        assert_eq!(config["app"]["name"].as_str(), Some("myapp"));
+        assert_eq!(config["deploy"]["keep_releases"].as_integer(), Some(3));
+        assert_eq!(
+            config["deploy"]["health_check"]["path"].as_str(),
+            Some("/up")
+        );
+        assert_eq!(
+            config["deploy"]["health_check"]["port"].as_integer(),
+            Some(3000)
+        );
+
+        let production = &config["stages"]["production"];
+        let hosts: Vec<&str> = production["hosts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|h| h["address"].as_str().unwrap())
+            .collect();
+        assert_eq!(hosts, ["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+        assert_eq!(production["user"].as_str(), Some("app"));
+        assert_eq!(production["env"]["WORKERS"].as_str(), Some("2"));
+        assert_eq!(
+            production["traefik"]["domain"].as_str(),
+            Some("app.example.com")
+        );
+        assert_eq!(production["traefik"]["tls"].as_bool(), Some(true));
+
+        let staging = &config["stages"]["staging"];
+        assert_eq!(staging["hosts"][0]["address"].as_str(), Some("10.1.0.1"));
+        assert_eq!(
+            staging["traefik"]["domain"].as_str(),
+            Some("staging.example.com")
+        );
+
+        assert_eq!(
+            config["registry"]["password_secret"].as_str(),
+            Some("KAMAL_REGISTRY_PASSWORD")
+        );
+        assert_eq!(config["accessories"]["db"]["port"].as_str(), Some("5432"));
+
+        assert!(import
+            .todo
+            .contains(&"shipit secrets set -s production DATABASE_URL=...".to_string()));
+        assert!(import
+            .unsupported
+            .contains(&"asset_path: no shipit equivalent".to_string()));
+        assert!(import
+            .unsupported
+            .iter()
+            .any(|u| u.starts_with("servers.job")));
+    }
+}
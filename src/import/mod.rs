@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use toml::{Table, Value};
+
+use crate::output;
+
+pub mod capistrano;
+pub mod kamal;
+
+/// A shipit.toml converted from another deploy tool's config
+#[derive(Default)]
+pub struct Import {
+    pub config: Table,
+    /// Options that have no shipit equivalent and were dropped
+    pub unsupported: Vec<String>,
+    /// Manual follow-ups (secrets to set, files to create)
+    pub todo: Vec<String>,
+}
+
+impl Import {
+    /// Insert `value` at a dotted path (e.g. "stages.production.user"),
+    /// creating intermediate tables
+    pub fn set(&mut self, path: &str, value: impl Into<Value>) {
+        let mut table = &mut self.config;
+        let mut keys = path.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                table.insert(key.to_string(), value.into());
+                return;
+            }
+            table = table
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .expect("import path crosses a non-table value");
+        }
+    }
+
+    /// Write shipit.toml (sections in the usual order) and print the report
+    pub fn write(&self, path: &Path, source: &str) -> Result<()> {
+        let mut content = format!(
+            "# Imported from {} by shipit init\nversion = {}\n",
+            source,
+            crate::config::migrate::CURRENT_VERSION
+        );
+        for section in ["app", "deploy", "registry", "stages", "accessories"] {
+            if let Some(value) = self.config.get(section) {
+                let mut table = Table::new();
+                table.insert(section.to_string(), value.clone());
+                content.push('\n');
+                content.push_str(&toml::to_string(&table).context("Failed to serialize config")?);
+            }
+        }
+        std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+        output::success(&format!("Created {} from {}", path.display(), source));
+
+        if !self.todo.is_empty() {
+            output::header("To do");
+            for item in unique(&self.todo) {
+                output::info(item);
+            }
+        }
+        if !self.unsupported.is_empty() {
+            output::header("Not converted");
+            for item in unique(&self.unsupported) {
+                output::warning(item);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Items in first-seen order without repeats (stages often report the same option)
+fn unique(items: &[String]) -> Vec<&str> {
+    let mut seen = Vec::new();
+    for item in items {
+        if !seen.contains(&item.as_str()) {
+            seen.push(item.as_str());
+        }
+    }
+    seen
+}
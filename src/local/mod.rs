@@ -1,19 +1,47 @@
-use anyhow::{bail, Context, Result};
+pub mod provider;
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::process::Command;
 
-use crate::config::{HostConfig, ShipitConfig, StageConfig, TraefikConfig};
+use crate::config::{HostConfig, ShipitConfig, SshConfig, StageConfig, TraefikConfig};
 use crate::output;
 
+use provider::Provider;
+
+/// Name of the synthesized stage used for the local VMs
+pub const STAGE_NAME: &str = "local";
+
 const LOCAL_STATE_DIR: &str = ".shipit";
 const LOCAL_STATE_FILE: &str = ".shipit/local.json";
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalVm {
+    pub name: String,
+    pub ip: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LocalState {
-    pub vm_name: String,
-    pub ip: String,
+    /// One VM per simulated host; the first is the primary
+    #[serde(default)]
+    pub vms: Vec<LocalVm>,
     pub app_name: String,
+    #[serde(default)]
+    pub provider: Provider,
+    /// SSH user inside the VM
+    #[serde(default = "default_vm_user")]
+    pub user: String,
+    // Single-VM state files written before `local up --hosts`
+    #[serde(default, skip_serializing)]
+    vm_name: Option<String>,
+    #[serde(default, skip_serializing)]
+    ip: Option<String>,
+}
+
+fn default_vm_user() -> String {
+    "ubuntu".to_string()
 }
 
 impl LocalState {
@@ -23,7 +51,12 @@ impl LocalState {
             return Ok(None);
         }
         let content = std::fs::read_to_string(&path)?;
-        let state: Self = serde_json::from_str(&content)?;
+        let mut state: Self = serde_json::from_str(&content)?;
+        if let (Some(name), Some(ip)) = (state.vm_name.take(), state.ip.take()) {
+            if state.vms.is_empty() {
+                state.vms.push(LocalVm { name, ip });
+            }
+        }
         Ok(Some(state))
     }
 
@@ -43,62 +76,73 @@ impl LocalState {
         }
         Ok(())
     }
+
+    /// The VM for host number `n` (1-based, as shown by `local status`)
+    pub fn vm(&self, n: usize) -> Result<&LocalVm> {
+        n.checked_sub(1)
+            .and_then(|i| self.vms.get(i))
+            .with_context(|| format!("No local VM #{} (there are {})", n, self.vms.len()))
+    }
 }
 
 pub fn vm_name(app_name: &str) -> String {
     format!("shipit-{}", app_name)
 }
 
-pub fn up(config: &ShipitConfig, project_root: &Path) -> Result<LocalState> {
-    let name = vm_name(&config.app.name);
+/// VM names for a stage of `hosts` VMs; the first keeps the single-VM name
+fn vm_names(app_name: &str, hosts: usize) -> Vec<String> {
+    let base = vm_name(app_name);
+    (1..=hosts)
+        .map(|n| if n == 1 { base.clone() } else { format!("{}-{}", base, n) })
+        .collect()
+}
 
-    // Check if Multipass is installed
-    if which::which("multipass").is_err() {
-        bail!("Multipass is not installed. Install it from https://multipass.run/");
-    }
+pub fn up(config: &ShipitConfig, project_root: &Path, hosts: usize) -> Result<LocalState> {
+    let provider = Provider::from_config(&config.local.provider)?;
+
+    provider.ensure_installed()?;
 
     // Check if VM already exists
     if let Some(state) = LocalState::load(project_root)? {
-        output::warning(&format!("VM '{}' already exists at {}", state.vm_name, state.ip));
+        if state.vms.len() != hosts {
+            output::warning(&format!(
+                "Local environment already has {} VM(s); run 'shipit local down' first to change --hosts",
+                state.vms.len()
+            ));
+        }
+        for vm in &state.vms {
+            output::warning(&format!("VM '{}' already exists at {}", vm.name, vm.ip));
+        }
         return Ok(state);
     }
 
-    output::info(&format!("Creating VM '{}'...", name));
-    let spinner = output::create_spinner("Launching Multipass VM...");
+    let mut vms = Vec::new();
+    for name in vm_names(&config.app.name, hosts) {
+        output::info(&format!("Creating VM '{}'...", name));
+        let spinner = output::create_spinner(&format!("Launching {:?} VM...", provider));
 
-    let status = Command::new("multipass")
-        .args([
-            "launch",
-            "24.04",
-            "--name",
-            &name,
-            "--cpus",
-            "2",
-            "--memory",
-            "2G",
-            "--disk",
-            "10G",
-        ])
-        .status()
-        .context("Failed to launch Multipass VM")?;
+        let launched = provider.launch(&name, &config.local, project_root);
 
-    spinner.finish_and_clear();
+        spinner.finish_and_clear();
+        launched?;
 
-    if !status.success() {
-        bail!("Failed to create Multipass VM");
-    }
+        // Get IP
+        let ip = provider.ip(&name, project_root)?;
+        output::success(&format!("VM created: {} ({})", name, ip));
 
-    // Get IP
-    let ip = get_vm_ip(&name)?;
-    output::success(&format!("VM created: {} ({})", name, ip));
+        // Setup SSH key
+        setup_ssh_key(provider, &name, project_root)?;
 
-    // Setup SSH key
-    setup_ssh_key(&name)?;
+        vms.push(LocalVm { name, ip });
+    }
 
     let state = LocalState {
-        vm_name: name,
-        ip,
+        vms,
         app_name: config.app.name.clone(),
+        provider,
+        user: provider.default_user().to_string(),
+        vm_name: None,
+        ip: None,
     };
     state.save(project_root)?;
 
@@ -109,15 +153,9 @@ pub fn down(project_root: &Path) -> Result<()> {
     let state = LocalState::load(project_root)?
         .context("No local VM found. Run 'shipit local up' first.")?;
 
-    output::info(&format!("Destroying VM '{}'...", state.vm_name));
-
-    let status = Command::new("multipass")
-        .args(["delete", "--purge", &state.vm_name])
-        .status()
-        .context("Failed to destroy VM")?;
-
-    if !status.success() {
-        bail!("Failed to destroy VM");
+    for vm in &state.vms {
+        output::info(&format!("Destroying VM '{}'...", vm.name));
+        state.provider.destroy(&vm.name, project_root)?;
     }
 
     LocalState::delete(project_root)?;
@@ -125,24 +163,84 @@ pub fn down(project_root: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn ssh(project_root: &Path) -> Result<()> {
+/// SSH into local VM number `host` (1-based)
+pub fn ssh(project_root: &Path, host: usize) -> Result<()> {
     let state = LocalState::load(project_root)?
         .context("No local VM found. Run 'shipit local up' first.")?;
+    let vm = state.vm(host)?;
 
     let status = Command::new("ssh")
         .args([
             "-o",
             "StrictHostKeyChecking=no",
-            &format!("ubuntu@{}", state.ip),
+            &format!("{}@{}", state.user, vm.ip),
         ])
         .status()
         .context("Failed to SSH into VM")?;
 
     if !status.success() {
-        // Fallback to multipass shell
-        let _ = Command::new("multipass")
-            .args(["shell", &state.vm_name])
-            .status();
+        // Fallback to the provider's own shell
+        let _ = state.provider.shell(&vm.name, project_root);
+    }
+
+    Ok(())
+}
+
+/// Snapshot every local VM under the same name
+pub fn snapshot(project_root: &Path, name: &str) -> Result<()> {
+    let state = LocalState::load(project_root)?
+        .context("No local VM found. Run 'shipit local up' first.")?;
+
+    for vm in &state.vms {
+        output::info(&format!("Snapshotting '{}' as '{}'...", vm.name, name));
+        state.provider.snapshot(&vm.name, name, project_root)?;
+    }
+
+    output::success(&format!("Snapshot '{}' saved", name));
+    Ok(())
+}
+
+/// Restore every local VM to a snapshot taken with `snapshot`
+pub fn restore(project_root: &Path, name: &str) -> Result<()> {
+    let mut state = LocalState::load(project_root)?
+        .context("No local VM found. Run 'shipit local up' first.")?;
+
+    for vm in &mut state.vms {
+        output::info(&format!("Restoring '{}' to '{}'...", vm.name, name));
+        state.provider.restore(&vm.name, name, project_root)?;
+
+        // DHCP may hand out a different address after the reboot
+        vm.ip = state.provider.ip(&vm.name, project_root)?;
+    }
+    state.save(project_root)?;
+
+    output::success(&format!("Restored snapshot '{}'", name));
+    Ok(())
+}
+
+/// Default mount point for `shipit local mount`
+pub fn default_mount_target(app_name: &str) -> String {
+    format!("/mnt/{}", app_name)
+}
+
+/// Mount (or unmount) the project directory into every local VM
+pub fn mount(project_root: &Path, target: &str, unmount: bool) -> Result<()> {
+    let state = LocalState::load(project_root)?
+        .context("No local VM found. Run 'shipit local up' first.")?;
+
+    for vm in &state.vms {
+        if unmount {
+            state.provider.unmount(&vm.name, target)?;
+            output::success(&format!("Unmounted {} from {}", target, vm.name));
+        } else {
+            state.provider.mount(&vm.name, project_root, target)?;
+            output::success(&format!(
+                "Mounted {} at {}:{}",
+                project_root.display(),
+                vm.name,
+                target
+            ));
+        }
     }
 
     Ok(())
@@ -154,28 +252,15 @@ pub fn status(project_root: &Path) -> Result<()> {
     match state {
         Some(state) => {
             output::header("Local VM Status");
-            println!("  VM Name: {}", state.vm_name);
-            println!("  IP:      {}", state.ip);
-            println!("  App:     {}", state.app_name);
-
-            // Get multipass info
-            let info = Command::new("multipass")
-                .args(["info", &state.vm_name])
-                .output();
-
-            if let Ok(out) = info {
-                if out.status.success() {
-                    let info_str = String::from_utf8_lossy(&out.stdout);
-                    for line in info_str.lines() {
-                        let line = line.trim();
-                        if line.starts_with("State:")
-                            || line.starts_with("CPU(s):")
-                            || line.starts_with("Memory usage:")
-                            || line.starts_with("Disk usage:")
-                        {
-                            println!("  {}", line);
-                        }
-                    }
+            println!("  App:      {}", state.app_name);
+            println!("  Provider: {:?}", state.provider);
+
+            for (i, vm) in state.vms.iter().enumerate() {
+                println!();
+                println!("  #{} {}", i + 1, vm.name);
+                println!("  IP:      {}", vm.ip);
+                for line in state.provider.info_lines(&vm.name, project_root) {
+                    println!("  {}", line);
                 }
             }
         }
@@ -187,58 +272,66 @@ pub fn status(project_root: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn local_stage_config(state: &LocalState) -> StageConfig {
-    StageConfig {
-        user: Some("ubuntu".to_string()),
+/// Stage config for the local VMs. A `[stages.local]` section in shipit.toml
+/// is merged over the synthesized defaults (hosts always come from the VMs).
+pub fn local_stage_config(config: &ShipitConfig, state: &LocalState) -> StageConfig {
+    let mut stage = StageConfig {
+        user: Some(state.user.clone()),
         port: None,
         os: None,
         proxy: None,
-        hosts: vec![HostConfig {
-            address: state.ip.clone(),
-        }],
+        hosts: state
+            .vms
+            .iter()
+            .map(|vm| HostConfig {
+                address: vm.ip.clone(),
+                role: Vec::new(),
+            })
+            .collect(),
         env: std::collections::HashMap::new(),
         traefik: Some(TraefikConfig {
             domain: format!("{}.local", state.app_name),
             tls: false,
             acme_email: None,
         }),
-    }
-}
-
-fn get_vm_ip(name: &str) -> Result<String> {
-    let output = Command::new("multipass")
-        .args(["info", name, "--format", "json"])
-        .output()
-        .context("Failed to get VM info")?;
+        ssh: SshConfig::default(),
+        protected: false,
+        deploy: None,
+        discovery: None,
+        resources: std::collections::HashMap::new(),
+    };
 
-    if !output.status.success() {
-        bail!("Failed to get VM info");
+    if let Some(overrides) = config.stages.get(STAGE_NAME) {
+        if overrides.user.is_some() {
+            stage.user = overrides.user.clone();
+        }
+        if overrides.port.is_some() {
+            stage.port = overrides.port;
+        }
+        if overrides.os.is_some() {
+            stage.os = overrides.os.clone();
+        }
+        if overrides.traefik.is_some() {
+            stage.traefik = overrides.traefik.clone();
+        }
+        stage.env.extend(overrides.env.clone());
+        stage.ssh = overrides.ssh.clone();
+        stage.deploy = overrides.deploy.clone();
+        stage.resources = overrides.resources.clone();
     }
 
-    let json_str = String::from_utf8_lossy(&output.stdout);
-    let v: serde_json::Value = serde_json::from_str(&json_str)?;
-
-    let ip = v["info"][name]["ipv4"][0]
-        .as_str()
-        .context("Failed to parse VM IP")?
-        .to_string();
+    if !config.local.traefik {
+        stage.traefik = None;
+    }
 
-    Ok(ip)
+    stage
 }
 
-fn setup_ssh_key(vm_name: &str) -> Result<()> {
+fn setup_ssh_key(provider: Provider, vm_name: &str, project_root: &Path) -> Result<()> {
     output::info("Setting up SSH access...");
 
     // Read local public key
-    let home = std::env::var("HOME").context("HOME not set")?;
-    let pub_key_path = format!("{}/.ssh/id_rsa.pub", home);
-    let ed_key_path = format!("{}/.ssh/id_ed25519.pub", home);
-
-    let pub_key = if Path::new(&ed_key_path).exists() {
-        std::fs::read_to_string(&ed_key_path)?
-    } else if Path::new(&pub_key_path).exists() {
-        std::fs::read_to_string(&pub_key_path)?
-    } else {
+    let Some(pub_key) = crate::ssh::local_public_key()? else {
         output::warning("No SSH public key found. Generate one with: ssh-keygen");
         return Ok(());
     };
@@ -246,22 +339,16 @@ fn setup_ssh_key(vm_name: &str) -> Result<()> {
     let pub_key = pub_key.trim();
 
     // Inject into VM
-    let status = Command::new("multipass")
-        .args([
-            "exec",
-            vm_name,
-            "--",
-            "bash",
-            "-c",
-            &format!(
-                "mkdir -p ~/.ssh && echo '{}' >> ~/.ssh/authorized_keys && chmod 600 ~/.ssh/authorized_keys",
-                pub_key
-            ),
-        ])
-        .status()
-        .context("Failed to inject SSH key")?;
-
-    if !status.success() {
+    let injected = provider.exec(
+        vm_name,
+        project_root,
+        &format!(
+            "mkdir -p ~/.ssh && echo '{}' >> ~/.ssh/authorized_keys && chmod 600 ~/.ssh/authorized_keys",
+            pub_key
+        ),
+    )?;
+
+    if !injected {
         output::warning("Failed to inject SSH key into VM");
     } else {
         output::success("SSH key configured");
@@ -0,0 +1,349 @@
+use anyhow::{bail, Context, Result};
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::LocalConfig;
+
+const VAGRANTFILE_TEMPLATE: &str = include_str!("../../templates/Vagrantfile.j2");
+const DEFAULT_MULTIPASS_IMAGE: &str = "24.04";
+const DEFAULT_VAGRANT_BOX: &str = "bento/ubuntu-24.04";
+
+/// Backend that creates and manages local VMs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Multipass,
+    /// Vagrant boxes (QEMU/KVM through vagrant-libvirt, or VirtualBox)
+    Vagrant,
+}
+
+impl Provider {
+    pub fn from_config(s: &str) -> Result<Self> {
+        match s {
+            "multipass" => Ok(Provider::Multipass),
+            "vagrant" => Ok(Provider::Vagrant),
+            other => bail!("Unsupported local provider '{}'. Supported: multipass, vagrant", other),
+        }
+    }
+
+    /// Default SSH user of the provider's images
+    pub fn default_user(&self) -> &'static str {
+        match self {
+            Provider::Multipass => "ubuntu",
+            Provider::Vagrant => "vagrant",
+        }
+    }
+
+    pub fn ensure_installed(&self) -> Result<()> {
+        match self {
+            Provider::Multipass => {
+                if which::which("multipass").is_err() {
+                    bail!("Multipass is not installed. Install it from https://multipass.run/");
+                }
+            }
+            Provider::Vagrant => {
+                if which::which("vagrant").is_err() {
+                    bail!(
+                        "Vagrant is not installed. Install it from https://developer.hashicorp.com/vagrant \
+                         (for QEMU/KVM also run `vagrant plugin install vagrant-libvirt`)"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn launch(&self, name: &str, local: &LocalConfig, project_root: &Path) -> Result<()> {
+        let status = match self {
+            Provider::Multipass => Command::new("multipass")
+                .args([
+                    "launch",
+                    local.image.as_deref().unwrap_or(DEFAULT_MULTIPASS_IMAGE),
+                    "--name",
+                    name,
+                    "--cpus",
+                    &local.cpus.to_string(),
+                    "--memory",
+                    &format!("{}M", local.memory_mb),
+                    "--disk",
+                    &format!("{}G", local.disk_gb),
+                ])
+                .status()
+                .context("Failed to launch Multipass VM")?,
+            Provider::Vagrant => {
+                let dir = vagrant_dir(project_root, name);
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+                let mut env = Environment::new();
+                env.add_template("Vagrantfile", VAGRANTFILE_TEMPLATE)?;
+                let vagrantfile = env.get_template("Vagrantfile").unwrap().render(
+                    minijinja::context! {
+                        name => name,
+                        vm_box => local.vagrant_box.as_deref().unwrap_or(DEFAULT_VAGRANT_BOX),
+                        cpus => local.cpus,
+                        memory_mb => local.memory_mb,
+                        disk_gb => local.disk_gb,
+                    },
+                )?;
+                std::fs::write(dir.join("Vagrantfile"), vagrantfile)
+                    .context("Failed to write Vagrantfile")?;
+
+                let mut cmd = Command::new("vagrant");
+                cmd.arg("up").current_dir(&dir);
+                if let Some(provider) = &local.vagrant_provider {
+                    cmd.args(["--provider", provider]);
+                }
+                cmd.status().context("Failed to run vagrant up")?
+            }
+        };
+
+        if !status.success() {
+            bail!("Failed to create {:?} VM '{}'", self, name);
+        }
+        Ok(())
+    }
+
+    pub fn ip(&self, name: &str, project_root: &Path) -> Result<String> {
+        match self {
+            Provider::Multipass => {
+                let output = Command::new("multipass")
+                    .args(["info", name, "--format", "json"])
+                    .output()
+                    .context("Failed to get VM info")?;
+
+                if !output.status.success() {
+                    bail!("Failed to get VM info");
+                }
+
+                let json_str = String::from_utf8_lossy(&output.stdout);
+                let v: serde_json::Value = serde_json::from_str(&json_str)?;
+
+                Ok(v["info"][name]["ipv4"][0]
+                    .as_str()
+                    .context("Failed to parse VM IP")?
+                    .to_string())
+            }
+            Provider::Vagrant => {
+                // The private_network interface is added after the NAT one
+                let out = self.exec_output(
+                    name,
+                    project_root,
+                    "ip -4 -o addr show scope global | awk '{print $4}' | cut -d/ -f1 | tail -1",
+                )?;
+                let ip = out.trim().to_string();
+                if ip.is_empty() {
+                    bail!("Failed to determine the IP of Vagrant VM '{}'", name);
+                }
+                Ok(ip)
+            }
+        }
+    }
+
+    /// Run a shell command inside the VM, returning whether it succeeded
+    pub fn exec(&self, name: &str, project_root: &Path, script: &str) -> Result<bool> {
+        let status = self
+            .exec_command(name, project_root, script)
+            .status()
+            .context("Failed to run command in VM")?;
+        Ok(status.success())
+    }
+
+    fn exec_output(&self, name: &str, project_root: &Path, script: &str) -> Result<String> {
+        let output = self
+            .exec_command(name, project_root, script)
+            .output()
+            .context("Failed to run command in VM")?;
+        if !output.status.success() {
+            bail!(
+                "Command failed in VM '{}': {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn exec_command(&self, name: &str, project_root: &Path, script: &str) -> Command {
+        match self {
+            Provider::Multipass => {
+                let mut cmd = Command::new("multipass");
+                cmd.args(["exec", name, "--", "bash", "-c", script]);
+                cmd
+            }
+            Provider::Vagrant => {
+                let mut cmd = Command::new("vagrant");
+                cmd.args(["ssh", "-c", script])
+                    .current_dir(vagrant_dir(project_root, name));
+                cmd
+            }
+        }
+    }
+
+    /// Open an interactive shell without going through shipit's SSH setup
+    pub fn shell(&self, name: &str, project_root: &Path) -> Result<()> {
+        let mut cmd = match self {
+            Provider::Multipass => {
+                let mut cmd = Command::new("multipass");
+                cmd.args(["shell", name]);
+                cmd
+            }
+            Provider::Vagrant => {
+                let mut cmd = Command::new("vagrant");
+                cmd.arg("ssh").current_dir(vagrant_dir(project_root, name));
+                cmd
+            }
+        };
+        cmd.status().context("Failed to open VM shell")?;
+        Ok(())
+    }
+
+    /// Save a named snapshot of the VM
+    pub fn snapshot(&self, name: &str, snapshot: &str, project_root: &Path) -> Result<()> {
+        match self {
+            // Multipass only snapshots stopped instances
+            Provider::Multipass => {
+                run(Command::new("multipass").args(["stop", name]))?;
+                let result = run(Command::new("multipass").args(["snapshot", "--name", snapshot, name]));
+                run(Command::new("multipass").args(["start", name]))?;
+                result
+            }
+            Provider::Vagrant => run(Command::new("vagrant")
+                .args(["snapshot", "save", "--force", snapshot])
+                .current_dir(vagrant_dir(project_root, name))),
+        }
+        .with_context(|| format!("Failed to snapshot VM '{}'", name))
+    }
+
+    /// Roll the VM back to a named snapshot (discarding current state)
+    pub fn restore(&self, name: &str, snapshot: &str, project_root: &Path) -> Result<()> {
+        match self {
+            Provider::Multipass => {
+                run(Command::new("multipass").args(["stop", name]))?;
+                let result = run(Command::new("multipass").args([
+                    "restore",
+                    "--destructive",
+                    &format!("{}.{}", name, snapshot),
+                ]));
+                run(Command::new("multipass").args(["start", name]))?;
+                result
+            }
+            Provider::Vagrant => run(Command::new("vagrant")
+                .args(["snapshot", "restore", snapshot])
+                .current_dir(vagrant_dir(project_root, name))),
+        }
+        .with_context(|| format!("Failed to restore VM '{}' to snapshot '{}'", name, snapshot))
+    }
+
+    /// Mount a host directory into the VM at `target`
+    pub fn mount(&self, name: &str, source: &Path, target: &str) -> Result<()> {
+        match self {
+            Provider::Multipass => run(Command::new("multipass").args([
+                "mount",
+                &source.display().to_string(),
+                &format!("{}:{}", name, target),
+            ]))
+            .with_context(|| format!("Failed to mount {} into VM '{}'", source.display(), name)),
+            Provider::Vagrant => bail!(
+                "Mounts are not supported with the Vagrant provider; use `shipit local deploy --sync`"
+            ),
+        }
+    }
+
+    pub fn unmount(&self, name: &str, target: &str) -> Result<()> {
+        match self {
+            Provider::Multipass => run(Command::new("multipass")
+                .args(["umount", &format!("{}:{}", name, target)]))
+            .with_context(|| format!("Failed to unmount {} from VM '{}'", target, name)),
+            Provider::Vagrant => bail!("Mounts are not supported with the Vagrant provider"),
+        }
+    }
+
+    pub fn destroy(&self, name: &str, project_root: &Path) -> Result<()> {
+        let status = match self {
+            Provider::Multipass => Command::new("multipass")
+                .args(["delete", "--purge", name])
+                .status()
+                .context("Failed to destroy VM")?,
+            Provider::Vagrant => {
+                let dir = vagrant_dir(project_root, name);
+                let status = Command::new("vagrant")
+                    .args(["destroy", "-f"])
+                    .current_dir(&dir)
+                    .status()
+                    .context("Failed to destroy VM")?;
+                if status.success() {
+                    let _ = std::fs::remove_dir_all(&dir);
+                }
+                status
+            }
+        };
+
+        if !status.success() {
+            bail!("Failed to destroy VM");
+        }
+        Ok(())
+    }
+
+    /// Human-readable state/resource lines for `shipit local status`
+    pub fn info_lines(&self, name: &str, project_root: &Path) -> Vec<String> {
+        match self {
+            Provider::Multipass => {
+                let Ok(out) = Command::new("multipass").args(["info", name]).output() else {
+                    return Vec::new();
+                };
+                if !out.status.success() {
+                    return Vec::new();
+                }
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| {
+                        line.starts_with("State:")
+                            || line.starts_with("CPU(s):")
+                            || line.starts_with("Memory usage:")
+                            || line.starts_with("Disk usage:")
+                    })
+                    .map(str::to_string)
+                    .collect()
+            }
+            Provider::Vagrant => {
+                let Ok(out) = Command::new("vagrant")
+                    .args(["status", "--machine-readable"])
+                    .current_dir(vagrant_dir(project_root, name))
+                    .output()
+                else {
+                    return Vec::new();
+                };
+                // Lines look like: timestamp,target,state,running
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .filter_map(|line| {
+                        let fields: Vec<&str> = line.split(',').collect();
+                        (fields.len() >= 4 && fields[2] == "state")
+                            .then(|| format!("State: {}", fields[3]))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Run a provider CLI command, failing on a non-zero exit
+fn run(cmd: &mut Command) -> Result<()> {
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+    if !status.success() {
+        bail!("{:?} exited with {}", cmd.get_program(), status);
+    }
+    Ok(())
+}
+
+/// Directory holding the generated Vagrantfile and Vagrant's machine state
+fn vagrant_dir(project_root: &Path, name: &str) -> PathBuf {
+    project_root.join(".shipit").join("vagrant").join(name)
+}
@@ -1,13 +1,24 @@
 mod accessory;
+mod audit;
+mod backup;
 mod cli;
 mod compose;
 mod config;
+mod cron;
 mod deploy;
+mod gc;
+mod hardening;
+mod http;
+mod import;
 mod llms;
 mod local;
 mod nixos;
+mod notify;
 mod os;
 mod output;
+mod plugin;
+mod provision;
+mod registry;
 mod release;
 mod secrets;
 mod ssh;
@@ -15,14 +26,18 @@ mod traefik;
 mod wireguard;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 use tracing_subscriber::EnvFilter;
 
-use cli::{AccessoryAction, Cli, Command, ConfigAction, SecretsAction};
+use cli::{
+    AccessoryAction, AppAction, BackupAction, Cli, Command, ConfigAction, CronAction, HostsAction,
+    ProvisionProvider, SecretsAction,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     // Setup tracing
     let filter = match cli.verbose {
@@ -39,46 +54,336 @@ async fn main() -> Result<()> {
         .without_time()
         .init();
 
+    output::configure(cli.quiet, cli.no_color, cli.plain);
+
+    if let Some(path) = cli.log_file.clone().or_else(|| {
+        std::env::var_os("SHIPIT_LOG_FILE")
+            .filter(|v| !v.is_empty())
+            .map(Into::into)
+    }) {
+        ssh::transcript::open(&path)?;
+    }
+
     let project_root = std::env::current_dir()?;
+    let config_path = cli.config.clone();
 
+    let invocation = audit::Invocation::start(&matches);
+    let result = run(cli, project_root.clone()).await;
+    if let Some(invocation) = invocation {
+        invocation
+            .finish(&config_path, &project_root, &result)
+            .await;
+    }
+    result
+}
+
+async fn run(cli: Cli, project_root: std::path::PathBuf) -> Result<()> {
     match cli.command {
-        Command::Init => {
-            cli::init::run()?;
+        Command::Init {
+            template,
+            from_kamal,
+            from_capistrano,
+            add_stage,
+            hosts,
+            user,
+            domain,
+            tls,
+            acme_email,
+            ci,
+        } => {
+            cli::init::run(&cli::init::InitOptions {
+                template,
+                from_kamal,
+                from_capistrano,
+                add_stage: add_stage.map(|name| cli::init::NewStage {
+                    name,
+                    hosts,
+                    user,
+                    domain,
+                    tls,
+                    acme_email,
+                }),
+                ci,
+            })
+            .await?;
         }
 
-        Command::Setup { stage } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
-            cli::setup::run(config, &stage).await?;
+        Command::Validate => {
+            cli::validate::run(&cli.config, &project_root)?;
         }
 
-        Command::Deploy { stage } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
-            cli::deploy::run(config, &stage, project_root).await?;
+        Command::Doctor => {
+            cli::doctor::run(&cli.config, &project_root).await?;
+        }
+
+        Command::Setup {
+            stage,
+            check,
+            bootstrap,
+            root_user,
+            only,
+            skip,
+            dry_run,
+        } => {
+            let config = cli::load_for_stage(&cli.config, &stage, &project_root)?;
+            ssh::set_dry_run(dry_run);
+            if check {
+                cli::setup::check(config, &stage).await?;
+            } else {
+                let opts = cli::setup::SetupOptions {
+                    bootstrap_as: bootstrap.then_some(root_user),
+                    only,
+                    skip,
+                };
+                cli::setup::run(config, &stage, &opts).await?;
+            }
+        }
+
+        Command::Teardown {
+            stage,
+            remove_traefik,
+            remove_docker,
+            yes,
+        } => {
+            let config = cli::load_for_stage(&cli.config, &stage, &project_root)?;
+            let opts = cli::teardown::TeardownOptions {
+                remove_traefik,
+                remove_docker,
+                yes,
+            };
+            cli::teardown::run(config, &stage, opts).await?;
         }
 
-        Command::Rollback { stage, release } => {
+        Command::Deploy {
+            stage,
+            auto_setup,
+            dry_run,
+        } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            ssh::set_dry_run(dry_run);
+            cli::deploy::run(config, &stage, project_root, auto_setup).await?;
+        }
+
+        Command::Watch {
+            stage,
+            interval,
+            cooldown,
+            remote,
+        } => {
             let config = config::ShipitConfig::load(&cli.config)?;
-            cli::rollback::run(config, &stage, release.as_deref()).await?;
+            let stage = cli::resolve_stage(&config, stage)?;
+            let opts = cli::watch::WatchOptions {
+                interval,
+                cooldown,
+                remote,
+            };
+            cli::watch::run(&cli.config, project_root, &stage, &opts).await?;
+        }
+
+        Command::Server { listen, remote } => {
+            cli::server::run(&cli.config, project_root, &listen, remote).await?;
+        }
+
+        Command::Promote { from, to } => {
+            let from_config = cli::load_for_stage(&cli.config, &from, &project_root)?;
+            let to_config = cli::load_for_stage(&cli.config, &to, &project_root)?;
+            cli::promote::run(from_config, &from, to_config, &to, project_root).await?;
+        }
+
+        Command::Rollback {
+            stage,
+            release,
+            list,
+        } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::rollback::run(config, &stage, &project_root, release.as_deref(), list).await?;
+        }
+
+        Command::Rollforward { stage } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::rollback::rollforward(config, &stage, &project_root).await?;
         }
 
         Command::Releases { stage } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
             cli::releases::run(config, &stage).await?;
         }
 
+        Command::Status { stage } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::status::run(config, &stage).await?;
+        }
+
+        Command::Certs { stage } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::certs::run(config, &stage).await?;
+        }
+
+        Command::Verify { stage, redeploy } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::verify::run(config, &stage, project_root, redeploy).await?;
+        }
+
+        Command::Gc { stage } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::gc::run(config, &stage).await?;
+        }
+
+        Command::Prune {
+            stage,
+            keep,
+            images,
+        } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::prune::run(config, &stage, keep, images).await?;
+        }
+
         Command::Logs {
             stage,
             service,
             lines,
             follow,
         } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
             cli::logs::run(config, &stage, service.as_deref(), lines, follow).await?;
         }
 
-        Command::Run { stage, cmd } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
-            cli::run::run(config, &stage, &cmd).await?;
+        Command::Run { stage, tty, cmd } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::run::run(config, &stage, &cmd, tty).await?;
+        }
+
+        Command::Shell {
+            stage,
+            service,
+            host,
+        } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            cli::shell::run(config, &stage, service.as_deref(), host.as_deref()).await?;
+        }
+
+        Command::Exec {
+            stage,
+            hosts,
+            sudo,
+            serial,
+            cmd,
+        } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            let opts = cli::exec::ExecOptions {
+                hosts,
+                sudo,
+                serial,
+            };
+            cli::exec::run(config, &stage, &cmd, &opts).await?;
+        }
+
+        Command::Backup { action } => {
+            let stage = match &action {
+                BackupAction::Run { stage, .. }
+                | BackupAction::List { stage }
+                | BackupAction::Restore { stage, .. } => stage.clone(),
+            };
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            match action {
+                BackupAction::Run { host, .. } => {
+                    cli::backup::run(config, &stage, &project_root, host.as_deref()).await?
+                }
+                BackupAction::List { .. } => cli::backup::list(config, &stage).await?,
+                BackupAction::Restore {
+                    id,
+                    only,
+                    host,
+                    yes,
+                    ..
+                } => {
+                    let opts = cli::backup::RestoreOptions {
+                        id: &id,
+                        only: &only,
+                        host: host.as_deref(),
+                        yes,
+                    };
+                    cli::backup::restore(config, &stage, &opts).await?
+                }
+            }
+        }
+
+        Command::Cron { action } => {
+            let stage = match &action {
+                CronAction::List { stage } | CronAction::Runs { stage, .. } => stage.clone(),
+            };
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            match action {
+                CronAction::List { .. } => cli::cron::list(config, &stage).await?,
+                CronAction::Runs { job, lines, .. } => {
+                    cli::cron::runs(config, &stage, job.as_deref(), lines).await?
+                }
+            }
+        }
+
+        Command::Provision { provider } => match provider {
+            ProvisionProvider::Hetzner {
+                stage,
+                count,
+                server_type,
+                image,
+                location,
+                token_secret,
+                setup,
+            } => {
+                let opts = cli::provision::HetznerOptions {
+                    count,
+                    server_type,
+                    image,
+                    location,
+                    token_secret,
+                    setup,
+                };
+                cli::provision::hetzner(&cli.config, &project_root, &stage, &opts).await?;
+            }
+            ProvisionProvider::Ec2 {
+                stage,
+                count,
+                instance_type,
+                ami,
+                region,
+                subnet,
+                vpc,
+                admin_user,
+                setup,
+            } => {
+                let opts = cli::provision::Ec2Options {
+                    count,
+                    instance_type,
+                    ami,
+                    region,
+                    subnet_id: subnet,
+                    vpc_id: vpc,
+                    admin_user,
+                    setup,
+                };
+                cli::provision::ec2(&cli.config, &project_root, &stage, &opts).await?;
+            }
+        },
+
+        Command::Hosts { action } => match action {
+            HostsAction::Import {
+                stage,
+                terraform,
+                output,
+            } => {
+                cli::hosts::import_terraform(&cli.config, &stage, &terraform, &output)?;
+            }
+        },
+
+        Command::Ping { stage } => {
+            let config = cli::load_for_stage(&cli.config, &stage, &project_root)?;
+            cli::ping::run(config, &stage, &cli.config).await?;
+        }
+
+        Command::Trust { stage, replace } => {
+            let config = cli::load_for_stage(&cli.config, &stage, &project_root)?;
+            cli::trust::run(config, &stage, replace).await?;
         }
 
         Command::Secrets { action } => {
@@ -99,6 +404,19 @@ async fn main() -> Result<()> {
                 SecretsAction::Edit { stage } => {
                     cli::secrets_cmd::edit(&config, &stage, &project_root)?;
                 }
+                SecretsAction::Run { stage, cmd } => {
+                    cli::secrets_cmd::run(&config, &stage, &cmd, &project_root)?;
+                }
+            }
+        }
+
+        Command::App { stage, action } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            match action {
+                AppAction::Start => cli::app::start(config, &stage).await?,
+                AppAction::Stop { yes } => cli::app::stop(config, &stage, yes).await?,
+                AppAction::Restart => cli::app::restart(config, &stage).await?,
+                AppAction::Status => cli::app::status(config, &stage).await?,
             }
         }
 
@@ -106,59 +424,109 @@ async fn main() -> Result<()> {
             let config_path = &cli.config;
             match action {
                 ConfigAction::Set { stage, pair } => {
-                    let config = config::ShipitConfig::load(config_path)?;
+                    let config = cli::load_for_stage(config_path, &stage, &project_root)?;
                     cli::config_cmd::set(config, &stage, &pair).await?;
                 }
                 ConfigAction::Unset { stage, key } => {
-                    let config = config::ShipitConfig::load(config_path)?;
+                    let config = cli::load_for_stage(config_path, &stage, &project_root)?;
                     cli::config_cmd::unset(config, &stage, &key).await?;
                 }
                 ConfigAction::List { stage } => {
-                    let config = config::ShipitConfig::load(config_path)?;
+                    let config = cli::load_for_stage(config_path, &stage, &project_root)?;
                     cli::config_cmd::list(config, &stage).await?;
                 }
+                ConfigAction::Edit {
+                    stage,
+                    restart,
+                    yes,
+                } => {
+                    let config = cli::load_for_stage(config_path, &stage, &project_root)?;
+                    cli::config_cmd::edit(config, &stage, restart, yes).await?;
+                }
+                ConfigAction::Migrate => {
+                    cli::config_cmd::migrate(config_path)?;
+                }
             }
         }
 
         Command::Accessory { action } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
+            let stage = match &action {
+                AccessoryAction::Boot { stage, .. }
+                | AccessoryAction::Stop { stage, .. }
+                | AccessoryAction::Restart { stage, .. } => Some(stage.clone()),
+                AccessoryAction::Logs { stage, .. } => stage.clone(),
+            };
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
             match action {
-                AccessoryAction::Boot { stage, name } => {
-                    cli::accessory::boot(config, &stage, name.as_deref()).await?;
+                AccessoryAction::Boot { name, .. } => {
+                    cli::accessory::boot(config, &stage, &project_root, name.as_deref()).await?;
                 }
-                AccessoryAction::Stop { stage, name } => {
+                AccessoryAction::Stop { name, .. } => {
                     cli::accessory::stop(config, &stage, name.as_deref()).await?;
                 }
-                AccessoryAction::Restart { stage, name } => {
+                AccessoryAction::Restart { name, .. } => {
                     cli::accessory::restart(config, &stage, name.as_deref()).await?;
                 }
-                AccessoryAction::Logs {
-                    stage,
-                    name,
-                    follow,
-                } => {
+                AccessoryAction::Logs { name, follow, .. } => {
                     cli::accessory::logs(config, &stage, &name, follow).await?;
                 }
             }
         }
 
-        Command::Monitor { stage, interval } => {
-            let config = config::ShipitConfig::load(&cli.config)?;
-            cli::monitor::run(config, &stage, interval).await?;
+        Command::Monitor {
+            stage,
+            interval,
+            once,
+            json,
+            web,
+            exporter,
+            listen,
+        } => {
+            let (config, stage) = cli::load_stage(&cli.config, stage, &project_root)?;
+            let opts = cli::monitor::MonitorOptions {
+                interval,
+                once,
+                json,
+                web,
+                exporter,
+                listen,
+            };
+            cli::monitor::run(config, &stage, &opts).await?;
         }
 
         Command::Local { action } => {
             let config = if cli.config.exists() {
-                Some(config::ShipitConfig::load(&cli.config)?)
+                Some(cli::load_for_stage(
+                    &cli.config,
+                    local::STAGE_NAME,
+                    &project_root,
+                )?)
             } else {
                 None
             };
             cli::local::run(&action, config, project_root).await?;
         }
 
+        Command::Audit {
+            stage,
+            limit,
+            remote,
+        } => {
+            let opts = cli::audit::AuditOptions {
+                stage,
+                limit,
+                remote,
+            };
+            cli::audit::run(&cli.config, &project_root, &opts).await?;
+        }
+
         Command::Llms { action } => {
             cli::llms::run(&action)?;
         }
+
+        Command::Plugin(args) => {
+            plugin::run_subcommand(&args, &cli.config)?;
+        }
     }
 
     Ok(())
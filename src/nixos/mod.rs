@@ -50,7 +50,7 @@ pub async fn apply_module(session: &SshSession, user: &str) -> Result<()> {
     // 4. Add import of ./shipit.nix to configuration.nix if not present
     let config_path = "/etc/nixos/configuration.nix";
     let config_contents = session
-        .exec(&format!("cat {}", config_path))
+        .query(&format!("cat {}", config_path))
         .await
         .context("Failed to read /etc/nixos/configuration.nix")?;
 
@@ -66,14 +66,17 @@ pub async fn apply_module(session: &SshSession, user: &str) -> Result<()> {
     let has_import = session
         .exec_ok("grep -q '\\./shipit.nix' /etc/nixos/configuration.nix")
         .await?;
-    if !has_import {
+    // A dry run only printed the update
+    if !has_import && !crate::ssh::is_dry_run() {
         bail!("shipit.nix import was not found after update");
     }
 
     // 5. Single nixos-rebuild switch
     let spinner = output::create_spinner("Running nixos-rebuild switch...");
     session
-        .sudo_exec("nixos-rebuild switch")
+        .sudo_exec_streamed("nixos-rebuild switch", |line| {
+            spinner.suspend(|| output::remote_line(line))
+        })
         .await
         .context("nixos-rebuild switch failed")?;
     spinner.finish_and_clear();
@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::{EmailConfig, ShipitConfig};
+use crate::secrets::{key, store as secrets_store};
+
+/// A plain-text mail, ready to hand to the local `curl` over SMTP
+pub struct Email {
+    url: String,
+    tls: bool,
+    from: String,
+    to: Vec<String>,
+    /// `user:password`, passed on curl's stdin so it stays out of the process list
+    login: Option<String>,
+    message: String,
+}
+
+impl Email {
+    /// Build the message; the SMTP password comes from the stage's secrets
+    pub fn new(
+        config: &ShipitConfig,
+        email: &EmailConfig,
+        project_root: &Path,
+        stage_name: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<Self> {
+        let login = match &email.username {
+            Some(username) => {
+                let identity = key::load_identity(&config.app.name)?;
+                let secrets = secrets_store::read_secrets(project_root, stage_name, &identity)?;
+                let password = secrets.get(&email.password_secret).with_context(|| {
+                    format!(
+                        "SMTP password not found: set it with `shipit secrets set -s {} {}=...`",
+                        stage_name, email.password_secret
+                    )
+                })?;
+                Some(format!("{}:{}", username, password))
+            }
+            None => None,
+        };
+
+        let scheme = if email.port == 465 { "smtps" } else { "smtp" };
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            email.from,
+            email.to.join(", "),
+            subject,
+            chrono::Local::now().to_rfc2822(),
+            body.replace('\n', "\r\n")
+        );
+
+        Ok(Self {
+            url: format!("{}://{}:{}", scheme, email.host, email.port),
+            tls: email.tls,
+            from: email.from.clone(),
+            to: email.to.clone(),
+            login,
+            message,
+        })
+    }
+
+    pub fn send(&self) -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new().context("Failed to create temp file")?;
+        file.write_all(self.message.as_bytes())
+            .context("Failed to write mail")?;
+
+        let mut cmd = Command::new("curl");
+        cmd.args(["-fsS", "--max-time", "30", "--url", &self.url]);
+        if self.tls {
+            cmd.arg("--ssl-reqd");
+        }
+        cmd.args(["--mail-from", &self.from]);
+        for rcpt in &self.to {
+            cmd.args(["--mail-rcpt", rcpt]);
+        }
+        cmd.arg("--upload-file").arg(file.path());
+        if self.login.is_some() {
+            cmd.args(["-K", "-"]);
+        }
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to run curl (is it installed?)")?;
+
+        if let (Some(login), Some(mut stdin)) = (&self.login, child.stdin.take()) {
+            let quoted = login.replace('\\', "\\\\").replace('"', "\\\"");
+            stdin
+                .write_all(format!("user = \"{}\"\n", quoted).as_bytes())
+                .context("Failed to pass SMTP login to curl")?;
+        }
+        let status = child.wait().context("Failed to wait for curl")?;
+
+        if !status.success() {
+            bail!("SMTP delivery via {} failed ({})", self.url, status);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+pub mod email;
+pub mod sentry;
+pub mod webhook;
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::config::ShipitConfig;
+use crate::deploy::context::DeployContext;
+use crate::output;
+
+/// POST a JSON payload to a webhook URL (uses the local `curl` binary)
+pub fn post_json(url: &str, payload: &serde_json::Value) -> Result<()> {
+    post_json_with_headers(url, payload, &[])
+}
+
+/// Like `post_json`, with extra headers. They are passed on curl's stdin so
+/// tokens don't show up in the process list.
+pub fn post_json_with_headers(
+    url: &str,
+    payload: &serde_json::Value,
+    headers: &[String],
+) -> Result<()> {
+    let mut child = Command::new("curl")
+        .args([
+            "-fsS",
+            "--max-time",
+            "10",
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "-H",
+            "@-",
+            "--data-binary",
+            &payload.to_string(),
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run curl (is it installed?)")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(headers.join("\n").as_bytes())
+            .context("Failed to pass headers to curl")?;
+    }
+    let status = child.wait().context("Failed to wait for curl")?;
+
+    if !status.success() {
+        bail!("Webhook {} failed ({})", url, status);
+    }
+    Ok(())
+}
+
+/// Ping a healthchecks.io-style URL, optionally with a text body that
+/// shows up in the check's log
+pub fn ping(url: &str, body: Option<&str>) -> Result<()> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["-fsS", "--max-time", "10", "--retry", "3"]);
+    if let Some(body) = body {
+        cmd.args(["--data-raw", body]);
+    }
+    let status = cmd
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run curl (is it installed?)")?;
+
+    if !status.success() {
+        bail!("Ping {} failed ({})", url, status);
+    }
+    Ok(())
+}
+
+/// Report that a deploy is starting. Notification failures only warn.
+pub async fn deploy_started(ctx: &DeployContext) {
+    if let Some(url) = &ctx.config.notify.healthchecks_url {
+        let url = format!("{}/start", url.trim_end_matches('/'));
+        let body = format!(
+            "Deploying {} to {} (release {})",
+            ctx.config.app.name, ctx.stage_name, ctx.release.name
+        );
+        send("healthchecks", move || ping(&url, Some(&body))).await;
+    }
+
+    let payload = event(
+        "deploy.started",
+        &ctx.config,
+        &ctx.stage_name,
+        serde_json::json!({
+            "release": ctx.release.name,
+            "sha": ctx.git_sha(),
+        }),
+    );
+    emit(&ctx.config, &ctx.project_root, &ctx.webhooks, payload).await;
+}
+
+/// Report a finished deploy step on one host
+pub async fn deploy_step(ctx: &DeployContext, host: &str, step: &str, elapsed: Duration, ok: bool) {
+    if ctx.config.plugins.is_empty() && !ctx.webhooks.iter().any(|w| w.wants("deploy.step")) {
+        return;
+    }
+    let payload = event(
+        "deploy.step",
+        &ctx.config,
+        &ctx.stage_name,
+        serde_json::json!({
+            "release": ctx.release.name,
+            "host": host,
+            "step": step,
+            "duration_ms": elapsed.as_millis() as u64,
+            "status": if ok { "success" } else { "failure" },
+        }),
+    );
+    emit(&ctx.config, &ctx.project_root, &ctx.webhooks, payload).await;
+}
+
+/// Report how a deploy ended. Notification failures only warn.
+pub async fn deploy_finished(ctx: &DeployContext, result: &Result<()>) {
+    if let Some(url) = &ctx.config.notify.healthchecks_url {
+        let url = url.trim_end_matches('/').to_string();
+        let (url, body) = match result {
+            Ok(()) => (
+                url,
+                format!("Release {} is live on {}", ctx.release.name, ctx.stage_name),
+            ),
+            Err(e) => (format!("{}/fail", url), format!("{:#}", e)),
+        };
+        send("healthchecks", move || ping(&url, Some(&body))).await;
+    }
+
+    if let (Some(sentry), Ok(())) = (&ctx.config.notify.sentry, result) {
+        match sentry::Release::new(ctx, sentry) {
+            Ok(release) => send("Sentry", move || release.create()).await,
+            Err(e) => output::warning(&format!("Sentry notification skipped: {:#}", e)),
+        }
+    }
+
+    let (subject, body) = match result {
+        Ok(()) => (
+            format!("Deployed {} to {}", ctx.config.app.name, ctx.stage_name),
+            format!(
+                "Release {} is live on {}.",
+                ctx.release.name, ctx.stage_name
+            ),
+        ),
+        Err(e) => (
+            format!(
+                "Deploy of {} to {} failed",
+                ctx.config.app.name, ctx.stage_name
+            ),
+            format!("Release {} failed:\n\n{:#}", ctx.release.name, e),
+        ),
+    };
+    let payload = event(
+        "deploy.finished",
+        &ctx.config,
+        &ctx.stage_name,
+        serde_json::json!({
+            "release": ctx.release.name,
+            "sha": ctx.git_sha(),
+            "duration_ms": ctx.started.elapsed().as_millis() as u64,
+            "steps": step_timings(ctx),
+            "status": status(result),
+            "error": result.as_ref().err().map(|e| format!("{:#}", e)),
+        }),
+    );
+    emit(&ctx.config, &ctx.project_root, &ctx.webhooks, payload).await;
+
+    send_email(
+        &ctx.config,
+        &ctx.project_root,
+        &ctx.stage_name,
+        &subject,
+        &body,
+    )
+    .await;
+}
+
+/// Report how a rollback ended. Notification failures only warn.
+pub async fn rollback_finished(
+    config: &ShipitConfig,
+    project_root: &Path,
+    stage_name: &str,
+    result: &Result<String>,
+    elapsed: Duration,
+) {
+    let webhooks = webhook::load(config, project_root, stage_name);
+    let payload = event(
+        "rollback.finished",
+        config,
+        stage_name,
+        serde_json::json!({
+            "release": result.as_ref().ok(),
+            "duration_ms": elapsed.as_millis() as u64,
+            "status": status(result),
+            "error": result.as_ref().err().map(|e| format!("{:#}", e)),
+        }),
+    );
+    emit(config, project_root, &webhooks, payload).await;
+
+    let (subject, body) = match result {
+        Ok(target) => (
+            format!("Rolled back {} on {}", config.app.name, stage_name),
+            format!("Release {} is live again on {}.", target, stage_name),
+        ),
+        Err(e) => (
+            format!("Rollback of {} on {} failed", config.app.name, stage_name),
+            format!("{:#}", e),
+        ),
+    };
+    send_email(config, project_root, stage_name, &subject, &body).await;
+}
+
+/// Report an accessory that was booted
+pub async fn accessory_booted(
+    config: &ShipitConfig,
+    project_root: &Path,
+    stage_name: &str,
+    accessory: &str,
+    host: &str,
+) {
+    let webhooks = webhook::load(config, project_root, stage_name);
+    let payload = event(
+        "accessory.booted",
+        config,
+        stage_name,
+        serde_json::json!({ "accessory": accessory, "host": host }),
+    );
+    emit(config, project_root, &webhooks, payload).await;
+}
+
+/// The envelope every webhook event shares, extended with `fields`
+fn event(
+    name: &str,
+    config: &ShipitConfig,
+    stage_name: &str,
+    fields: serde_json::Value,
+) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "event": name,
+        "app": config.app.name,
+        "stage": stage_name,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let (Some(payload), serde_json::Value::Object(fields)) = (payload.as_object_mut(), fields) {
+        payload.extend(fields);
+    }
+    payload
+}
+
+/// `[{"host", "step", "duration_ms"}]` in the order the steps ran
+fn step_timings(ctx: &DeployContext) -> serde_json::Value {
+    let Ok(timings) = ctx.timings.lock() else {
+        return serde_json::Value::Array(Vec::new());
+    };
+    timings
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "host": t.host,
+                "step": t.step,
+                "duration_ms": t.elapsed.as_millis() as u64,
+            })
+        })
+        .collect()
+}
+
+fn status<T>(result: &Result<T>) -> &'static str {
+    if result.is_ok() {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+/// POST an event to every webhook subscribed to it and pipe it to every plugin
+async fn emit(
+    config: &ShipitConfig,
+    project_root: &Path,
+    webhooks: &[webhook::Webhook],
+    payload: serde_json::Value,
+) {
+    let name = payload["event"].as_str().unwrap_or_default().to_string();
+    for hook in webhooks.iter().filter(|w| w.wants(&name)) {
+        let hook = hook.clone();
+        let payload = payload.clone();
+        send("Webhook", move || hook.post(&payload)).await;
+    }
+    for plugin in &config.plugins {
+        let (plugin, project_root, payload) =
+            (plugin.clone(), project_root.to_path_buf(), payload.clone());
+        let label = format!("Plugin {}", plugin);
+        send(&label, move || {
+            crate::plugin::send_event(&plugin, &project_root, &payload)
+        })
+        .await;
+    }
+}
+
+/// The `deploy.before` event; plugins can fail it to stop the deploy
+pub async fn before_deploy(ctx: &DeployContext) -> Result<()> {
+    if ctx.config.plugins.is_empty() {
+        return Ok(());
+    }
+    let payload = event(
+        crate::plugin::BEFORE_DEPLOY,
+        &ctx.config,
+        &ctx.stage_name,
+        serde_json::json!({
+            "release": ctx.release.name,
+            "sha": ctx.git_sha(),
+            "hosts": ctx.stage.hosts.iter().map(|h| &h.address).collect::<Vec<_>>(),
+        }),
+    );
+    crate::plugin::before_deploy(&ctx.config, &ctx.project_root, payload).await
+}
+
+async fn send_email(
+    config: &ShipitConfig,
+    project_root: &Path,
+    stage_name: &str,
+    subject: &str,
+    body: &str,
+) {
+    let Some(settings) = &config.notify.email else {
+        return;
+    };
+    match email::Email::new(config, settings, project_root, stage_name, subject, body) {
+        Ok(mail) => send("Email", move || mail.send()).await,
+        Err(e) => output::warning(&format!("Email notification skipped: {:#}", e)),
+    }
+}
+
+/// Run a blocking notifier off the async runtime and downgrade errors to warnings
+async fn send<F>(name: &str, f: F)
+where
+    F: FnOnce() -> Result<()> + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+    if let Err(e) = result {
+        output::warning(&format!("{} notification failed: {:#}", name, e));
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+
+use crate::config::SentryConfig;
+use crate::deploy::context::DeployContext;
+use crate::secrets::{key, store as secrets_store};
+
+/// A Sentry release (version = git SHA) and its deploy to the stage's environment
+pub struct Release {
+    api: String,
+    token: String,
+    project: String,
+    version: String,
+    environment: String,
+    name: String,
+}
+
+impl Release {
+    /// Collect everything needed up front; the token comes from the stage's secrets
+    pub fn new(ctx: &DeployContext, sentry: &SentryConfig) -> Result<Self> {
+        let identity = key::load_identity(&ctx.config.app.name)?;
+        let secrets = secrets_store::read_secrets(&ctx.project_root, &ctx.stage_name, &identity)?;
+        let token = secrets.get(&sentry.token_secret).with_context(|| {
+            format!(
+                "Sentry token not found: set it with `shipit secrets set -s {} {}=...`",
+                ctx.stage_name, sentry.token_secret
+            )
+        })?;
+
+        Ok(Self {
+            api: format!(
+                "{}/api/0/organizations/{}",
+                sentry.url.trim_end_matches('/'),
+                sentry.org
+            ),
+            token: token.clone(),
+            project: sentry.project.clone(),
+            version: ctx
+                .git_sha()
+                .context("Could not read the git SHA of HEAD")?,
+            environment: ctx.stage_name.clone(),
+            name: ctx.release.name.clone(),
+        })
+    }
+
+    /// Create the release (a no-op if it already exists) and record the deploy
+    pub fn create(&self) -> Result<()> {
+        let headers = [format!("Authorization: Bearer {}", self.token)];
+
+        crate::notify::post_json_with_headers(
+            &format!("{}/releases/", self.api),
+            &serde_json::json!({
+                "version": self.version,
+                "projects": [self.project],
+            }),
+            &headers,
+        )
+        .context("Failed to create Sentry release")?;
+
+        crate::notify::post_json_with_headers(
+            &format!("{}/releases/{}/deploys/", self.api, self.version),
+            &serde_json::json!({
+                "environment": self.environment,
+                "name": self.name,
+            }),
+            &headers,
+        )
+        .context("Failed to record Sentry deploy")
+    }
+}
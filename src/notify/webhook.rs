@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use indicatif::HumanDuration;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::secrets::{key, store as secrets_store};
+
+/// A `[[notify.webhooks]]` entry with its signing key resolved
+#[derive(Clone)]
+pub struct Webhook {
+    url: String,
+    key: Option<String>,
+    events: Vec<String>,
+    format: String,
+}
+
+/// Resolve the configured webhooks for a stage. A webhook whose signing key
+/// can't be read is skipped with a warning rather than sent unsigned.
+pub fn load(config: &ShipitConfig, project_root: &Path, stage_name: &str) -> Vec<Webhook> {
+    let webhooks = &config.notify.webhooks;
+    let secrets = if webhooks.iter().any(|w| w.secret.is_some()) {
+        match key::load_identity(&config.app.name)
+            .and_then(|identity| secrets_store::read_secrets(project_root, stage_name, &identity))
+        {
+            Ok(secrets) => Some(secrets),
+            Err(e) => {
+                output::warning(&format!("Could not read webhook signing keys: {:#}", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    webhooks
+        .iter()
+        .filter_map(|webhook| {
+            let key = match &webhook.secret {
+                Some(name) => match secrets.as_ref().and_then(|s| s.get(name)) {
+                    Some(key) => Some(key.clone()),
+                    None => {
+                        output::warning(&format!(
+                            "Webhook {} skipped: secret {} not found (set it with `shipit secrets set -s {} {}=...`)",
+                            webhook.url, name, stage_name, name
+                        ));
+                        return None;
+                    }
+                },
+                None => None,
+            };
+            Some(Webhook {
+                url: webhook.url.clone(),
+                key,
+                events: webhook.events.clone(),
+                format: webhook.format.clone(),
+            })
+        })
+        .collect()
+}
+
+impl Webhook {
+    /// Chat webhooks get every event but `deploy.step` unless they list events
+    pub fn wants(&self, event: &str) -> bool {
+        if self.events.is_empty() {
+            return self.format == "json" || event != "deploy.step";
+        }
+        self.events.iter().any(|e| e == event)
+    }
+
+    /// POST the event, signed with `X-Shipit-Signature: sha256=<hex>` when a key is set.
+    /// Slack and Discord webhooks get the event as a one-message text instead.
+    pub fn post(&self, payload: &serde_json::Value) -> Result<()> {
+        let mut headers = vec![format!(
+            "X-Shipit-Event: {}",
+            payload["event"].as_str().unwrap_or_default()
+        )];
+        let payload = &match self.format.as_str() {
+            "slack" => serde_json::json!({ "text": chat_message(payload) }),
+            "discord" => serde_json::json!({ "content": chat_message(payload) }),
+            _ => payload.clone(),
+        };
+        if let Some(key) = &self.key {
+            let signature = hmac_sha256(key.as_bytes(), payload.to_string().as_bytes());
+            headers.push(format!(
+                "X-Shipit-Signature: sha256={}",
+                hex::encode(signature)
+            ));
+        }
+        crate::notify::post_json_with_headers(&self.url, payload, &headers)
+            .with_context(|| format!("Webhook {} failed", self.url))
+    }
+}
+
+/// A one-line summary of an event for chat, with the error on a second line
+fn chat_message(payload: &serde_json::Value) -> String {
+    let field = |name: &str| payload[name].as_str().unwrap_or_default();
+    let (app, stage, release) = (field("app"), field("stage"), field("release"));
+    let sha = &field("sha")[..field("sha").len().min(7)];
+    let took = payload["duration_ms"]
+        .as_u64()
+        .map(|ms| format!(" in {}", HumanDuration(Duration::from_millis(ms))))
+        .unwrap_or_default();
+    let failed = field("status") == "failure";
+
+    let message = match field("event") {
+        "deploy.started" => format!(
+            "Deploying {} to {}: release {} ({})",
+            app, stage, release, sha
+        ),
+        "deploy.finished" if failed => format!(
+            "Deploy of {} to {} failed{}: release {} ({})",
+            app, stage, took, release, sha
+        ),
+        "deploy.finished" => format!(
+            "Deployed {} to {}{}: release {} ({})",
+            app, stage, took, release, sha
+        ),
+        "deploy.step" => format!(
+            "{} {} on {}{}: {}",
+            app,
+            field("step"),
+            field("host"),
+            took,
+            field("status")
+        ),
+        "rollback.finished" if failed => format!("Rollback of {} on {} failed{}", app, stage, took),
+        "rollback.finished" => format!(
+            "Rolled back {} on {}{}: release {} is live",
+            app, stage, took, release
+        ),
+        "accessory.booted" => format!(
+            "Booted accessory {} of {} on {} ({})",
+            field("accessory"),
+            app,
+            field("host"),
+            stage
+        ),
+        other => format!("{} on {}: {}", app, stage, other),
+    };
+    match payload["error"].as_str() {
+        Some(error) => format!("{}\n{}", message, error),
+        None => message,
+    }
+}
+
+/// HMAC-SHA256 (RFC 2104)
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hmac_sha256;
+
+    // RFC 4231 test cases 1, 2 and 6
+    #[test]
+    fn matches_rfc_4231_vectors() {
+        assert_eq!(
+            hex::encode(hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn hashes_keys_longer_than_a_block() {
+        assert_eq!(
+            hex::encode(hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
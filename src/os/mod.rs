@@ -5,13 +5,15 @@ use crate::ssh::SshSession;
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HostOs {
     Ubuntu,
+    /// RHEL family (Rocky, AlmaLinux, CentOS Stream, Fedora)
+    Rhel,
     NixOs,
 }
 
 impl HostOs {
     /// Auto-detect OS by reading /etc/os-release via SSH.
     pub async fn detect(session: &SshSession) -> Result<Self> {
-        let output = session.exec("cat /etc/os-release").await?;
+        let output = session.query("cat /etc/os-release").await?;
         for line in output.lines() {
             if line.starts_with("ID=") {
                 let id = line.trim_start_matches("ID=").trim_matches('"');
@@ -29,8 +31,12 @@ impl HostOs {
     fn from_id(id: &str) -> Result<Self> {
         match id {
             "ubuntu" | "debian" => Ok(HostOs::Ubuntu),
+            "rocky" | "almalinux" | "rhel" | "centos" | "fedora" => Ok(HostOs::Rhel),
             "nixos" => Ok(HostOs::NixOs),
-            other => bail!("Unsupported OS: '{}'. Supported: ubuntu, debian, nixos", other),
+            other => bail!(
+                "Unsupported OS: '{}'. Supported: ubuntu, debian, rocky, almalinux, rhel, centos, fedora, nixos",
+                other
+            ),
         }
     }
 
@@ -45,6 +51,12 @@ impl HostOs {
     pub fn install_docker_cmd(&self) -> &'static str {
         match self {
             HostOs::Ubuntu => "curl -fsSL https://get.docker.com | sh",
+            HostOs::Rhel => {
+                "dnf -y -q install dnf-plugins-core && \
+                 dnf config-manager --add-repo https://download.docker.com/linux/centos/docker-ce.repo && \
+                 dnf -y -q install docker-ce docker-ce-cli containerd.io docker-buildx-plugin docker-compose-plugin && \
+                 systemctl enable --now docker"
+            }
             // On NixOS, Docker is handled by shipit.nix unified module
             HostOs::NixOs => "true",
         }
@@ -53,6 +65,7 @@ impl HostOs {
     pub fn install_wireguard_cmd(&self) -> &'static str {
         match self {
             HostOs::Ubuntu => "apt-get update -qq && apt-get install -y -qq wireguard-tools",
+            HostOs::Rhel => "dnf -y -q install wireguard-tools",
             // On NixOS, wireguard-tools is handled by shipit.nix unified module
             HostOs::NixOs => "true",
         }
@@ -60,12 +73,25 @@ impl HostOs {
 
     pub fn add_docker_group_cmd(&self, user: &str) -> String {
         match self {
-            HostOs::Ubuntu => format!("usermod -aG docker {}", user),
+            HostOs::Ubuntu | HostOs::Rhel => format!("usermod -aG docker {}", user),
             // On NixOS, docker group is handled by shipit.nix unified module
             HostOs::NixOs => "true".to_string(),
         }
     }
 
+    /// Install packages with the distro's package manager
+    pub fn install_packages_cmd(&self, packages: &str) -> String {
+        match self {
+            HostOs::Ubuntu => format!("apt-get update -qq && apt-get install -y -qq {}", packages),
+            // Most extras (e.g. fail2ban) live in EPEL
+            HostOs::Rhel => format!(
+                "(dnf -y -q install epel-release || true) && dnf -y -q install {}",
+                packages
+            ),
+            HostOs::NixOs => "true".to_string(),
+        }
+    }
+
     /// Whether this OS uses the unified shipit.nix module for Docker, Traefik, and WireGuard.
     pub fn needs_unified_module(&self) -> bool {
         matches!(self, HostOs::NixOs)
@@ -1,8 +1,64 @@
 use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
 use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+
+tokio::task_local! {
+    static HOST_PREFIX: String;
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Apply the global `--quiet`, `--no-color` and `--plain` flags. `NO_COLOR`
+/// in the environment has the same effect as `--no-color`.
+pub fn configure(quiet: bool, no_color: bool, plain: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    PLAIN.store(plain, Ordering::Relaxed);
+    if no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+/// `--quiet`: only errors, warnings and final results are printed
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// `--plain`: no spinners or progress bars, one line per message instead
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Run `fut` with every line it prints prefixed by `[host]`, so output from
+/// hosts processed concurrently stays attributable.
+pub async fn with_host_prefix<F: Future>(host: &str, fut: F) -> F::Output {
+    HOST_PREFIX
+        .scope(format!("{} ", style(format!("[{}]", host)).dim()), fut)
+        .await
+}
+
+fn prefix() -> String {
+    HOST_PREFIX.try_with(|p| p.clone()).unwrap_or_default()
+}
 
 pub fn create_spinner(msg: &str) -> ProgressBar {
+    if is_quiet() {
+        return ProgressBar::hidden();
+    }
+    // Concurrent spinners would redraw over each other; print the message instead
+    let prefix = prefix();
+    if !prefix.is_empty() || is_plain() {
+        println!("{}{} {}", prefix, style("…").dim(), msg);
+        return ProgressBar::hidden();
+    }
+
     let pb = ProgressBar::new_spinner();
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -15,30 +71,159 @@ pub fn create_spinner(msg: &str) -> ProgressBar {
     pb
 }
 
+/// Progress of a byte stream: transferred bytes and throughput, plus a bar
+/// and ETA when the `total` is known. Hidden where a spinner would be.
+pub fn create_transfer_bar(msg: &str, total: Option<u64>) -> ProgressBar {
+    if is_quiet() {
+        return ProgressBar::hidden();
+    }
+    let prefix = prefix();
+    if !prefix.is_empty() || is_plain() {
+        println!("{}{} {}", prefix, style("…").dim(), msg);
+        return ProgressBar::hidden();
+    }
+
+    let pb = match total {
+        Some(total) => ProgressBar::new(total).with_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{msg} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta}",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::default_spinner()
+                .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
+                .template("{spinner:.cyan} {msg} {bytes} {binary_bytes_per_sec}")
+                .unwrap(),
+        ),
+    };
+    pb.set_message(msg.to_string());
+    pb.enable_steady_tick(Duration::from_millis(80));
+    pb
+}
+
+/// Counts the bytes read through it on a progress bar
+pub struct ProgressReader<R> {
+    inner: R,
+    bar: ProgressBar,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, bar: ProgressBar) -> Self {
+        Self { inner, bar }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.bar.inc((buf.filled().len() - before) as u64);
+        }
+        poll
+    }
+}
+
 pub fn step(num: usize, total: usize, msg: &str) {
+    if is_quiet() {
+        return;
+    }
     println!(
-        "{} {}",
+        "{}{} {}",
+        prefix(),
         style(format!("[{}/{}]", num, total)).bold().cyan(),
         msg
     );
 }
 
 pub fn success(msg: &str) {
-    println!("{} {}", style("✓").bold().green(), msg);
+    if is_quiet() {
+        return;
+    }
+    println!("{}{} {}", prefix(), style("✓").bold().green(), msg);
+}
+
+/// The final result of a command, printed even with `--quiet`
+pub fn done(msg: &str) {
+    println!("{}{} {}", prefix(), style("✓").bold().green(), msg);
 }
 
 pub fn error(msg: &str) {
-    eprintln!("{} {}", style("✗").bold().red(), msg);
+    eprintln!("{}{} {}", prefix(), style("✗").bold().red(), msg);
 }
 
 pub fn warning(msg: &str) {
-    eprintln!("{} {}", style("!").bold().yellow(), msg);
+    eprintln!("{}{} {}", prefix(), style("!").bold().yellow(), msg);
 }
 
 pub fn info(msg: &str) {
-    println!("{} {}", style("→").bold().blue(), msg);
+    if is_quiet() {
+        return;
+    }
+    println!("{}{} {}", prefix(), style("→").bold().blue(), msg);
 }
 
 pub fn header(msg: &str) {
+    if is_quiet() {
+        return;
+    }
     println!("\n{}", style(msg).bold().underlined());
 }
+
+/// A titled table of pre-aligned rows under a dimmed header row
+pub fn table(title: &str, head: &str, rows: &[String]) {
+    if is_quiet() {
+        return;
+    }
+    println!();
+    info(title);
+    println!("{}{}", prefix(), style(head).dim());
+    for row in rows {
+        println!("{}{}", prefix(), row);
+    }
+}
+
+/// `--dry-run`: a remote command that would run. Printed even with `--quiet`,
+/// since it is what the user asked for.
+pub fn planned(host: &str, cmd: &str) {
+    let mut lines = cmd.lines();
+    println!(
+        "{}  {} {} {}",
+        prefix(),
+        style("would run").yellow(),
+        style(format!("[{}]", host)).dim(),
+        lines.next().unwrap_or_default()
+    );
+    for line in lines {
+        println!("{}    {}", prefix(), line);
+    }
+}
+
+/// `--dry-run`: a file that would be written, with its content
+pub fn planned_write(host: &str, path: &str, content: &str) {
+    println!(
+        "{}  {} {} {}",
+        prefix(),
+        style("would write").yellow(),
+        style(format!("[{}]", host)).dim(),
+        path
+    );
+    for line in content.lines() {
+        println!("{}    {} {}", prefix(), style("│").dim(), line);
+    }
+}
+
+/// Print a line of streamed remote output, indented under the current step
+pub fn remote_line(line: &str) {
+    if is_quiet() {
+        return;
+    }
+    println!("{}  {} {}", prefix(), style("│").dim(), style(line).dim());
+}
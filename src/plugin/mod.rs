@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::ShipitConfig;
+use crate::output;
+
+/// Event a plugin can fail to stop the deploy before anything changes
+pub const BEFORE_DEPLOY: &str = "deploy.before";
+
+/// Executable implementing the plugin
+pub fn executable(name: &str) -> String {
+    format!("shipit-{}", name)
+}
+
+fn find(name: &str) -> Option<PathBuf> {
+    which::which(executable(name)).ok()
+}
+
+/// Run `shipit <name> <args>` as `shipit-<name> <args>` from PATH, the way
+/// git and cargo dispatch unknown subcommands
+pub fn run_subcommand(args: &[String], config_path: &Path) -> Result<()> {
+    let Some((name, args)) = args.split_first() else {
+        bail!("No command given. Run `shipit --help`");
+    };
+    let Some(path) = find(name) else {
+        bail!(
+            "Unknown command '{}' (no {} plugin on PATH). Run `shipit --help` for the built-in commands",
+            name,
+            executable(name)
+        );
+    };
+
+    let status = command(&path)
+        .env("SHIPIT_CONFIG", config_path)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", path.display()))?;
+    if !status.success() {
+        bail!("{} exited with {}", executable(name), status);
+    }
+    Ok(())
+}
+
+/// Pipe a lifecycle event to `shipit-<name> hook` as JSON on stdin
+pub fn send_event(name: &str, project_root: &Path, payload: &serde_json::Value) -> Result<()> {
+    let path = find(name).with_context(|| format!("{} not found on PATH", executable(name)))?;
+    let mut child = command(&path)
+        .arg("hook")
+        .current_dir(project_root)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", path.display()))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A plugin that ignores an event may exit without reading it
+        let _ = stdin.write_all(payload.to_string().as_bytes());
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {}", executable(name)))?;
+    if !status.success() {
+        bail!("{} hook exited with {}", executable(name), status);
+    }
+    Ok(())
+}
+
+/// Send `deploy.before` to every plugin; any failure aborts the deploy
+pub async fn before_deploy(
+    config: &ShipitConfig,
+    project_root: &Path,
+    payload: serde_json::Value,
+) -> Result<()> {
+    for name in &config.plugins {
+        output::info(&format!("Running plugin {}", name));
+        let (name, project_root, payload) =
+            (name.clone(), project_root.to_path_buf(), payload.clone());
+        tokio::task::spawn_blocking(move || send_event(&name, &project_root, &payload))
+            .await?
+            .context("A plugin stopped the deploy")?;
+    }
+    Ok(())
+}
+
+/// Plugins can call back into the running shipit binary through $SHIPIT_BIN
+fn command(path: &Path) -> Command {
+    let mut cmd = Command::new(path);
+    if let Ok(exe) = std::env::current_exe() {
+        cmd.env("SHIPIT_BIN", exe);
+    }
+    cmd
+}
@@ -0,0 +1,77 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use super::{ec2, hetzner};
+use crate::config::{HostConfig, ShipitConfig};
+use crate::output;
+
+/// Fill in the hosts of a stage that uses `discovery` by asking its provider.
+/// Hosts are ordered by server name so host indexes (and WireGuard IPs) stay
+/// stable while the set doesn't change.
+pub fn resolve(config: &mut ShipitConfig, stage_name: &str, project_root: &Path) -> Result<()> {
+    let Some(discovery) = config
+        .stages
+        .get(stage_name)
+        .and_then(|stage| stage.discovery.clone())
+    else {
+        return Ok(());
+    };
+
+    let tags: Vec<(String, String)> = discovery
+        .tag
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+
+    let mut found: Vec<(String, String)> = match discovery.provider.as_str() {
+        "hetzner" => {
+            let token =
+                super::stage_secret(config, project_root, stage_name, &discovery.token_secret)?;
+            hetzner::Hetzner::new(token)
+                .servers_with_labels(&discovery.tag)?
+                .into_iter()
+                .map(|server| (server.name, server.address))
+                .collect()
+        }
+        "ec2" => ec2::Ec2::new(discovery.region.clone())
+            .instances_with_tags(&tags)?
+            .into_iter()
+            .map(|(name, instance)| (name, instance.address))
+            .collect(),
+        other => bail!("Unsupported discovery provider '{}'", other),
+    };
+    found.sort();
+
+    if found.is_empty() {
+        bail!(
+            "Discovery found no {} servers tagged {} for stage {}",
+            discovery.provider,
+            discovery.tag,
+            stage_name
+        );
+    }
+    output::info(&format!(
+        "Discovered {} host(s) for {}: {}",
+        found.len(),
+        stage_name,
+        found
+            .iter()
+            .map(|(_, address)| address.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+
+    let stage = config
+        .stages
+        .get_mut(stage_name)
+        .context("Stage disappeared during discovery")?;
+    stage.hosts = found
+        .into_iter()
+        .map(|(_, address)| HostConfig {
+            address,
+            role: Vec::new(),
+        })
+        .collect();
+    Ok(())
+}
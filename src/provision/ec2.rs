@@ -0,0 +1,273 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::Command;
+
+/// Ubuntu 24.04 LTS (amd64) for the region, published by Canonical in SSM
+const UBUNTU_AMI_PARAMETER: &str =
+    "/aws/service/canonical/ubuntu/server/24.04/stable/current/amd64/hvm/ebs-gp3/ami-id";
+
+/// EC2 through the local `aws` CLI, which brings its own credentials
+/// (profiles, SSO, environment) and request signing
+pub struct Ec2 {
+    region: Option<String>,
+}
+
+/// What to launch
+pub struct InstanceSpec<'a> {
+    pub count: usize,
+    pub instance_type: &'a str,
+    pub ami: &'a str,
+    pub key_name: &'a str,
+    pub security_group_id: &'a str,
+    pub subnet_id: Option<&'a str>,
+    /// Tags applied to every instance (also used for discovery)
+    pub tags: Vec<(String, String)>,
+}
+
+pub struct Instance {
+    pub id: String,
+    pub address: String,
+}
+
+impl Ec2 {
+    pub fn new(region: Option<String>) -> Self {
+        Self { region }
+    }
+
+    fn aws(&self, args: &[&str]) -> Result<serde_json::Value> {
+        let mut cmd = Command::new("aws");
+        cmd.args(args).args(["--output", "json"]);
+        if let Some(region) = &self.region {
+            cmd.args(["--region", region]);
+        }
+        let output = cmd
+            .output()
+            .context("Failed to run the aws CLI (is it installed?)")?;
+        if !output.status.success() {
+            bail!(
+                "aws {} failed: {}",
+                args.iter().take(2).copied().collect::<Vec<_>>().join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&stdout).context("Invalid JSON from the aws CLI")
+    }
+
+    /// The current Ubuntu LTS AMI of the region
+    pub fn default_ami(&self) -> Result<String> {
+        let response = self.aws(&["ssm", "get-parameters", "--names", UBUNTU_AMI_PARAMETER])?;
+        response["Parameters"][0]["Value"]
+            .as_str()
+            .map(str::to_string)
+            .context("Could not look up the Ubuntu AMI; pass --ami")
+    }
+
+    /// Import `public_key` as key pair `name` unless a key pair by that name exists
+    pub fn ensure_key_pair(&self, name: &str, public_key: &str) -> Result<()> {
+        let existing = self.aws(&[
+            "ec2",
+            "describe-key-pairs",
+            "--filters",
+            &format!("Name=key-name,Values={}", name),
+        ])?;
+        if existing["KeyPairs"]
+            .as_array()
+            .is_some_and(|k| !k.is_empty())
+        {
+            return Ok(());
+        }
+
+        let mut file = tempfile::NamedTempFile::new().context("Failed to create temp file")?;
+        file.write_all(public_key.as_bytes())
+            .context("Failed to write public key")?;
+        self.aws(&[
+            "ec2",
+            "import-key-pair",
+            "--key-name",
+            name,
+            "--public-key-material",
+            &format!("fileb://{}", file.path().display()),
+        ])
+        .context("Failed to import SSH key pair")?;
+        Ok(())
+    }
+
+    /// The id of security group `name`, created with SSH, HTTP(S) and
+    /// WireGuard open when missing
+    pub fn ensure_security_group(
+        &self,
+        name: &str,
+        vpc_id: Option<&str>,
+        wireguard_port: u16,
+    ) -> Result<String> {
+        let existing = self.aws(&[
+            "ec2",
+            "describe-security-groups",
+            "--filters",
+            &format!("Name=group-name,Values={}", name),
+        ])?;
+        if let Some(id) = existing["SecurityGroups"][0]["GroupId"].as_str() {
+            return Ok(id.to_string());
+        }
+
+        let mut args = vec![
+            "ec2",
+            "create-security-group",
+            "--group-name",
+            name,
+            "--description",
+            "shipit: SSH, HTTP(S) and WireGuard",
+        ];
+        if let Some(vpc_id) = vpc_id {
+            args.extend(["--vpc-id", vpc_id]);
+        }
+        let created = self.aws(&args).context("Failed to create security group")?;
+        let id = created["GroupId"]
+            .as_str()
+            .context("aws did not return the security group id")?
+            .to_string();
+
+        let rule = |protocol: &str, port: u16| {
+            serde_json::json!({
+                "IpProtocol": protocol,
+                "FromPort": port,
+                "ToPort": port,
+                "IpRanges": [{ "CidrIp": "0.0.0.0/0" }],
+                "Ipv6Ranges": [{ "CidrIpv6": "::/0" }],
+            })
+        };
+        let permissions = serde_json::json!([
+            rule("tcp", 22),
+            rule("tcp", 80),
+            rule("tcp", 443),
+            rule("udp", wireguard_port),
+        ]);
+        self.aws(&[
+            "ec2",
+            "authorize-security-group-ingress",
+            "--group-id",
+            &id,
+            "--ip-permissions",
+            &permissions.to_string(),
+        ])
+        .context("Failed to open ports on the security group")?;
+        Ok(id)
+    }
+
+    /// Launch the instances and return their ids
+    pub fn run_instances(&self, spec: &InstanceSpec) -> Result<Vec<String>> {
+        let tags: Vec<String> = spec
+            .tags
+            .iter()
+            .map(|(k, v)| format!("{{Key={},Value={}}}", k, v))
+            .collect();
+        let count = spec.count.to_string();
+        let tag_spec = format!("ResourceType=instance,Tags=[{}]", tags.join(","));
+        let mut args = vec![
+            "ec2",
+            "run-instances",
+            "--image-id",
+            spec.ami,
+            "--instance-type",
+            spec.instance_type,
+            "--count",
+            &count,
+            "--key-name",
+            spec.key_name,
+            "--security-group-ids",
+            spec.security_group_id,
+            "--tag-specifications",
+            &tag_spec,
+        ];
+        if let Some(subnet_id) = spec.subnet_id {
+            args.extend(["--subnet-id", subnet_id, "--associate-public-ip-address"]);
+        }
+
+        let response = self.aws(&args).context("Failed to launch instances")?;
+        Ok(response["Instances"]
+            .as_array()
+            .map(|instances| {
+                instances
+                    .iter()
+                    .filter_map(|i| i["InstanceId"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Running instances carrying every `key=value` tag, with their Name tags
+    pub fn instances_with_tags(
+        &self,
+        tags: &[(String, String)],
+    ) -> Result<Vec<(String, Instance)>> {
+        let mut filters = vec!["Name=instance-state-name,Values=running".to_string()];
+        filters.extend(
+            tags.iter()
+                .map(|(k, v)| format!("Name=tag:{},Values={}", k, v)),
+        );
+        let mut args = vec!["ec2", "describe-instances", "--filters"];
+        args.extend(filters.iter().map(String::as_str));
+        let response = self.aws(&args)?;
+
+        let mut instances = Vec::new();
+        for reservation in response["Reservations"].as_array().into_iter().flatten() {
+            for instance in reservation["Instances"].as_array().into_iter().flatten() {
+                let Some(address) = instance["PublicIpAddress"].as_str() else {
+                    continue;
+                };
+                let name = instance["Tags"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|t| t["Key"] == "Name")
+                    .and_then(|t| t["Value"].as_str())
+                    .unwrap_or_default();
+                instances.push((
+                    name.to_string(),
+                    Instance {
+                        id: instance["InstanceId"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        address: address.to_string(),
+                    },
+                ));
+            }
+        }
+        Ok(instances)
+    }
+
+    /// Block until the instances are running and return their public IPs
+    pub fn wait_running(&self, ids: &[String]) -> Result<Vec<Instance>> {
+        let mut args = vec!["ec2", "wait", "instance-running", "--instance-ids"];
+        args.extend(ids.iter().map(String::as_str));
+        self.aws(&args)
+            .context("Instances did not reach the running state")?;
+
+        let mut args = vec!["ec2", "describe-instances", "--instance-ids"];
+        args.extend(ids.iter().map(String::as_str));
+        let response = self.aws(&args)?;
+
+        let mut instances = Vec::new();
+        for reservation in response["Reservations"].as_array().into_iter().flatten() {
+            for instance in reservation["Instances"].as_array().into_iter().flatten() {
+                let id = instance["InstanceId"].as_str().unwrap_or_default();
+                let address = instance["PublicIpAddress"].as_str().with_context(|| {
+                    format!(
+                        "Instance {} has no public IP (use a subnet that assigns one)",
+                        id
+                    )
+                })?;
+                instances.push(Instance {
+                    id: id.to_string(),
+                    address: address.to_string(),
+                });
+            }
+        }
+        Ok(instances)
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{bail, Context, Result};
+use std::time::{Duration, Instant};
+
+use super::api_request;
+
+const API: &str = "https://api.hetzner.cloud/v1";
+
+/// A Hetzner Cloud project, addressed with an API token
+pub struct Hetzner {
+    token: String,
+}
+
+/// What to create
+pub struct ServerSpec<'a> {
+    pub name: String,
+    pub server_type: &'a str,
+    pub image: &'a str,
+    pub location: Option<&'a str>,
+    pub ssh_key_id: u64,
+    /// `key=value` labels, used later to find the servers again
+    pub labels: Vec<(String, String)>,
+}
+
+pub struct Server {
+    pub id: u64,
+    pub name: String,
+    pub address: String,
+}
+
+impl Hetzner {
+    pub fn new(token: String) -> Self {
+        Self { token }
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        api_request(
+            method,
+            &format!("{}{}", API, path),
+            &[format!("Authorization: Bearer {}", self.token)],
+            body,
+        )
+    }
+
+    /// The id of the project's SSH key matching `public_key`, uploading it if needed
+    pub fn ensure_ssh_key(&self, public_key: &str, name: &str) -> Result<u64> {
+        // Compare type and key material only; the comment may differ
+        let material = |key: &str| key.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+        let wanted = material(public_key);
+
+        let keys = self.list("/ssh_keys?", "ssh_keys")?;
+        if let Some(id) = keys
+            .iter()
+            .find(|k| material(k["public_key"].as_str().unwrap_or("")) == wanted)
+            .and_then(|k| k["id"].as_u64())
+        {
+            return Ok(id);
+        }
+
+        let created = self
+            .request(
+                "POST",
+                "/ssh_keys",
+                Some(&serde_json::json!({ "name": name, "public_key": public_key })),
+            )
+            .context("Failed to upload SSH key to Hetzner")?;
+        created["ssh_key"]["id"]
+            .as_u64()
+            .context("Hetzner did not return the SSH key id")
+    }
+
+    pub fn create_server(&self, spec: &ServerSpec) -> Result<Server> {
+        let labels: serde_json::Map<String, serde_json::Value> = spec
+            .labels
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+            .collect();
+        let mut body = serde_json::json!({
+            "name": spec.name,
+            "server_type": spec.server_type,
+            "image": spec.image,
+            "ssh_keys": [spec.ssh_key_id],
+            "labels": labels,
+            "start_after_create": true,
+        });
+        if let Some(location) = spec.location {
+            body["location"] = serde_json::Value::String(location.to_string());
+        }
+
+        let created = self
+            .request("POST", "/servers", Some(&body))
+            .with_context(|| format!("Failed to create server {}", spec.name))?;
+        parse_server(&created["server"])
+    }
+
+    /// Poll until the server reports `running`
+    pub async fn wait_running(&self, id: u64, timeout: Duration) -> Result<Server> {
+        let started = Instant::now();
+        loop {
+            let response = self.request("GET", &format!("/servers/{}", id), None)?;
+            if response["server"]["status"] == "running" {
+                return parse_server(&response["server"]);
+            }
+            if started.elapsed() > timeout {
+                bail!("Server {} was not running after {}s", id, timeout.as_secs());
+            }
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+    }
+
+    /// Servers matching a label selector (`app=myapp,stage=prod`)
+    pub fn servers_with_labels(&self, selector: &str) -> Result<Vec<Server>> {
+        let path = format!("/servers?label_selector={}&", urlencode(selector));
+        self.list(&path, "servers")?
+            .iter()
+            .map(parse_server)
+            .collect()
+    }
+
+    /// Every item of a paginated listing, following `meta.pagination.next_page`.
+    /// `path` ends in `?` or `&` so the page parameters can be appended.
+    fn list(&self, path: &str, key: &str) -> Result<Vec<serde_json::Value>> {
+        let mut items = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut response =
+                self.request("GET", &format!("{}per_page=50&page={}", path, page), None)?;
+            if let Some(batch) = response[key].as_array_mut() {
+                items.append(batch);
+            }
+            match response["meta"]["pagination"]["next_page"].as_u64() {
+                Some(next) if next > page => page = next,
+                _ => return Ok(items),
+            }
+        }
+    }
+}
+
+fn parse_server(server: &serde_json::Value) -> Result<Server> {
+    Ok(Server {
+        id: server["id"].as_u64().context("Server without id")?,
+        name: server["name"].as_str().unwrap_or_default().to_string(),
+        address: server["public_net"]["ipv4"]["ip"]
+            .as_str()
+            .context("Server has no public IPv4 address")?
+            .to_string(),
+    })
+}
+
+/// Percent-encode a query parameter value
+fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,251 @@
+pub mod discovery;
+pub mod ec2;
+pub mod hetzner;
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::config::ShipitConfig;
+use crate::output;
+use crate::secrets::{key, store as secrets_store};
+
+/// Read a provider token from the stage's encrypted secrets
+pub fn stage_secret(
+    config: &ShipitConfig,
+    project_root: &Path,
+    stage_name: &str,
+    name: &str,
+) -> Result<String> {
+    let identity = key::load_identity(&config.app.name)?;
+    let secrets = secrets_store::read_secrets(project_root, stage_name, &identity)?;
+    secrets.get(name).cloned().with_context(|| {
+        format!(
+            "{} not found: set it with `shipit secrets set -s {} {}=...`",
+            name, stage_name, name
+        )
+    })
+}
+
+/// Call a JSON API with the local `curl`. Headers (tokens) go over stdin so
+/// they stay out of the process list. Non-2xx responses become errors that
+/// include the response body.
+pub fn api_request(
+    method: &str,
+    url: &str,
+    headers: &[String],
+    body: Option<&serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let mut cmd = Command::new("curl");
+    cmd.args([
+        "-sS",
+        "--max-time",
+        "60",
+        "-X",
+        method,
+        "-H",
+        "Content-Type: application/json",
+        "-H",
+        "@-",
+        "-w",
+        "\n%{http_code}",
+    ]);
+    if let Some(body) = body {
+        cmd.args(["--data-binary", &body.to_string()]);
+    }
+    let mut child = cmd
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run curl (is it installed?)")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(headers.join("\n").as_bytes())
+            .context("Failed to pass headers to curl")?;
+    }
+    let output = child
+        .wait_with_output()
+        .context("Failed to wait for curl")?;
+    if !output.status.success() {
+        bail!(
+            "{} {} failed: {}",
+            method,
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", stdout.as_ref()));
+    let status: u16 = status.trim().parse().unwrap_or(0);
+    if !(200..300).contains(&status) {
+        bail!(
+            "{} {} returned HTTP {}: {}",
+            method,
+            url,
+            status,
+            body.trim()
+        );
+    }
+    if body.trim().is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+    serde_json::from_str(body).with_context(|| format!("Invalid JSON from {}", url))
+}
+
+/// Wait until the host accepts TCP connections on the SSH port
+pub async fn wait_for_ssh(address: &str, port: u16, timeout: Duration) -> Result<()> {
+    let started = Instant::now();
+    let target = format!("{}:{}", address, port);
+    loop {
+        let attempt = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::net::TcpStream::connect(&target),
+        )
+        .await;
+        if let Ok(Ok(_)) = attempt {
+            return Ok(());
+        }
+        if started.elapsed() > timeout {
+            bail!(
+                "{} did not accept SSH connections within {}s",
+                target,
+                timeout.as_secs()
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+    }
+}
+
+/// Replace the `hosts` list of `[stages.<name>]` in shipit.toml, adding the
+/// stage if it doesn't exist. Only the stage's `hosts = [...]` lines are
+/// rewritten; the rest of the file (comments included) is left alone.
+pub fn write_stage_hosts(config_path: &Path, stage_name: &str, addresses: &[String]) -> Result<()> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+
+    // Keep any extra keys of hosts that stay in the list
+    let existing: Vec<toml::Table> = table
+        .get("stages")
+        .and_then(|s| s.get(stage_name))
+        .and_then(|s| s.get("hosts"))
+        .and_then(|h| h.as_array())
+        .map(|hosts| {
+            hosts
+                .iter()
+                .filter_map(|h| match h {
+                    toml::Value::Table(t) => Some(t.clone()),
+                    toml::Value::String(address) => {
+                        let mut t = toml::Table::new();
+                        t.insert("address".to_string(), toml::Value::String(address.clone()));
+                        Some(t)
+                    }
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let entries: Vec<String> = addresses
+        .iter()
+        .map(|address| {
+            let mut host = existing
+                .iter()
+                .find(|t| t.get("address").and_then(|a| a.as_str()) == Some(address.as_str()))
+                .cloned()
+                .unwrap_or_default();
+            host.insert("address".to_string(), toml::Value::String(address.clone()));
+            inline_table(&host)
+        })
+        .collect();
+    let hosts_line = format!(
+        "hosts = [\n{}\n]",
+        entries
+            .iter()
+            .map(|e| format!("  {},", e))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+
+    let header = format!("[stages.{}]", stage_name);
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    match lines.iter().position(|l| l.trim() == header) {
+        None if table
+            .get("stages")
+            .and_then(|s| s.get(stage_name))
+            .is_some() =>
+        {
+            bail!(
+                "stages.{} is not written as a [stages.{}] section; set its hosts by hand:\n{}",
+                stage_name,
+                stage_name,
+                hosts_line
+            )
+        }
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.push(hosts_line);
+        }
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| l.trim_start().starts_with('['))
+                .map(|i| start + 1 + i)
+                .unwrap_or(lines.len());
+            let hosts_at = (start + 1..end).find(|&i| {
+                lines[i]
+                    .split_once('=')
+                    .is_some_and(|(k, _)| k.trim() == "hosts")
+            });
+            match hosts_at {
+                Some(first) => {
+                    // The old list may span several lines; it ends where brackets balance
+                    let mut depth = 0i32;
+                    let mut last = first;
+                    for (i, line) in lines.iter().enumerate().skip(first) {
+                        depth += line.matches('[').count() as i32;
+                        depth -= line.matches(']').count() as i32;
+                        last = i;
+                        if depth <= 0 {
+                            break;
+                        }
+                    }
+                    lines.splice(first..=last, [hosts_line]);
+                }
+                None => lines.insert(start + 1, hosts_line),
+            }
+        }
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    toml::from_str::<toml::Table>(&updated)
+        .context("Updating the hosts would produce invalid TOML; edit shipit.toml by hand")?;
+    std::fs::write(config_path, updated)
+        .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    output::info(&format!(
+        "Wrote {} host(s) to stages.{} in {}",
+        addresses.len(),
+        stage_name,
+        config_path.display()
+    ));
+    Ok(())
+}
+
+/// `{ address = "10.0.0.1", ... }`
+fn inline_table(table: &toml::Table) -> String {
+    let fields: Vec<String> = table
+        .iter()
+        .map(|(k, v)| format!("{} = {}", k, v))
+        .collect();
+    format!("{{ {} }}", fields.join(", "))
+}
@@ -0,0 +1,77 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{RegistryConfig, ShipitConfig};
+use crate::output;
+use crate::secrets::{key, store as secrets_store};
+use crate::ssh::SshSession;
+
+/// Decrypt the registry password from the stage's secrets.
+/// Returns None when no `[registry]` is configured.
+pub fn password(config: &ShipitConfig, project_root: &Path, stage_name: &str) -> Result<Option<String>> {
+    let Some(registry) = &config.registry else {
+        return Ok(None);
+    };
+
+    let identity = key::load_identity(&config.app.name)?;
+    let secrets = secrets_store::read_secrets(project_root, stage_name, &identity)?;
+
+    match secrets.get(&registry.password_secret) {
+        Some(password) => Ok(Some(password.clone())),
+        None => bail!(
+            "Registry password not found: set it with `shipit secrets set -s {} {}=...`",
+            stage_name,
+            registry.password_secret
+        ),
+    }
+}
+
+/// `docker login` on the host as the SSH user. The password is passed on
+/// stdin so it never appears in the remote process list or shell history.
+pub async fn login(session: &SshSession, registry: &RegistryConfig, password: &str) -> Result<()> {
+    // `sg docker` picks up a docker group membership added earlier in this session
+    let cmd = format!(
+        "sg docker -c 'docker login {} -u {} --password-stdin' >/dev/null",
+        registry.server, registry.username
+    );
+
+    let mut input = password.as_bytes();
+    session
+        .exec_with_input(&cmd, &mut input)
+        .await
+        .with_context(|| format!("Failed to log in to registry {}", registry.server))?;
+
+    output::success(&format!("Logged in to {}", registry.server));
+    Ok(())
+}
+
+/// `docker login` on this machine, so registry builds can be pushed
+pub fn login_local(registry: &RegistryConfig, password: &str) -> Result<()> {
+    let mut child = Command::new("docker")
+        .args([
+            "login",
+            &registry.server,
+            "-u",
+            &registry.username,
+            "--password-stdin",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Failed to run docker login")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open docker login stdin")?
+        .write_all(password.as_bytes())
+        .context("Failed to pass the password to docker login")?;
+
+    let status = child.wait().context("Failed to wait for docker login")?;
+    if !status.success() {
+        bail!("Failed to log in to registry {} locally", registry.server);
+    }
+    Ok(())
+}
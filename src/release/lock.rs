@@ -3,6 +3,61 @@ use serde::{Deserialize, Serialize};
 
 use crate::ssh::SshSession;
 
+/// Copy of the lock kept in each release directory, recording how that
+/// release was deployed
+const RELEASE_RECORD: &str = ".shipit-release.json";
+
+/// Who ran a deploy or rollback, and from where
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deployer {
+    pub user: String,
+    pub hostname: String,
+    pub shipit_version: String,
+    pub ci: bool,
+}
+
+impl Deployer {
+    /// The git user (falling back to $USER) on this machine
+    pub fn current() -> Self {
+        let command_output = |program: &str, args: &[&str]| {
+            std::process::Command::new(program)
+                .args(args)
+                .output()
+                .ok()
+                .filter(|o| o.status.success())
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        Self {
+            user: command_output("git", &["config", "user.name"])
+                .or_else(|| env("USER"))
+                .or_else(|| env("USERNAME"))
+                .unwrap_or_else(|| "unknown".to_string()),
+            hostname: env("HOSTNAME")
+                .or_else(|| command_output("hostname", &[]))
+                .unwrap_or_else(|| "unknown".to_string()),
+            shipit_version: env!("CARGO_PKG_VERSION").to_string(),
+            // Set by GitHub Actions, GitLab CI, CircleCI, Buildkite, ...
+            ci: env("CI").is_some_and(|v| v != "false" && v != "0"),
+        }
+    }
+}
+
+impl std::fmt::Display for Deployer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on {} (shipit {}{})",
+            self.user,
+            self.hostname,
+            self.shipit_version,
+            if self.ci { ", CI" } else { "" }
+        )
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShipitLock {
     pub current_release: String,
@@ -11,6 +66,13 @@ pub struct ShipitLock {
     pub deployed_at: String,
     #[serde(default)]
     pub secrets_hash: Option<String>,
+    /// Missing in locks written before it was recorded
+    #[serde(default)]
+    pub deployed_by: Option<Deployer>,
+    /// Release that was live before a rollback wrote this lock, for
+    /// `shipit rollforward`; None after a deploy or a roll-forward
+    #[serde(default)]
+    pub rolled_back_from: Option<String>,
 }
 
 impl ShipitLock {
@@ -26,6 +88,16 @@ impl ShipitLock {
             git_sha,
             deployed_at: chrono::Local::now().to_rfc3339(),
             secrets_hash,
+            deployed_by: Some(Deployer::current()),
+            rolled_back_from: None,
+        }
+    }
+
+    /// Who deployed and when, e.g. "alice on laptop (shipit 0.4.1), 2025-02-19T12:00:00+01:00"
+    pub fn origin(&self) -> String {
+        match &self.deployed_by {
+            Some(deployer) => format!("{}, {}", deployer, self.deployed_at),
+            None => self.deployed_at.clone(),
         }
     }
 
@@ -36,7 +108,7 @@ impl ShipitLock {
             return Ok(None);
         }
 
-        let content = session.exec(&format!("cat {}", lock_path)).await?;
+        let content = session.query(&format!("cat {}", lock_path)).await?;
         let lock: Self = serde_json::from_str(content.trim())?;
         Ok(Some(lock))
     }
@@ -47,4 +119,39 @@ impl ShipitLock {
         session.write_file(&lock_path, &content).await?;
         Ok(())
     }
+
+    /// Keep a copy in the release's directory, so each release remembers its deploy
+    pub async fn write_release_record(&self, session: &SshSession, app_path: &str) -> Result<()> {
+        let path = format!(
+            "{}/releases/{}/{}",
+            app_path, self.current_release, RELEASE_RECORD
+        );
+        session
+            .write_file(&path, &serde_json::to_string(self)?)
+            .await
+    }
+
+    /// Release names (newest first) with the record of how each was deployed,
+    /// when there is one
+    pub async fn read_release_records(
+        session: &SshSession,
+        app_path: &str,
+    ) -> Result<Vec<(String, Option<Self>)>> {
+        let listing = session
+            .query(&format!(
+                "cd {}/releases 2>/dev/null || exit 0; \
+                 for r in $(ls -1 | sort -r); do \
+                 printf '%s\t' \"$r\"; tr -d '\\n' < \"$r\"/{} 2>/dev/null; echo; done",
+                app_path, RELEASE_RECORD
+            ))
+            .await?;
+        Ok(listing
+            .lines()
+            .filter_map(|line| {
+                let (name, record) = line.split_once('\t').unwrap_or((line, ""));
+                let name = name.trim();
+                (!name.is_empty()).then(|| (name.to_string(), serde_json::from_str(record).ok()))
+            })
+            .collect())
+    }
 }
@@ -1,16 +1,190 @@
 pub mod lock;
 
-use chrono::Local;
+use anyhow::{bail, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+
+use crate::ssh::SshSession;
+use lock::ShipitLock;
+
+/// Release names when `deploy.release_format` is not set
+pub const DEFAULT_FORMAT: &str = "%Y%m%d-%H%M%S";
 
 pub struct Release {
     pub name: String,
 }
 
 impl Release {
-    pub fn new() -> Self {
+    /// Name the release from `deploy.release_format` (validated when the
+    /// config is loaded) and the SHA being deployed
+    pub fn new(format: Option<&str>, sha: Option<&str>) -> Self {
         let now = Local::now();
-        Self {
-            name: now.format("%Y%m%d-%H%M%S").to_string(),
+        let name = render(format.unwrap_or(DEFAULT_FORMAT), now, sha)
+            .unwrap_or_else(|_| now.format(DEFAULT_FORMAT).to_string());
+        Self { name }
+    }
+}
+
+/// Expand `{sha}`/`{short_sha}` and the strftime specifiers of a release
+/// format. The result names a directory and tags images, so it must be a
+/// valid Docker tag.
+pub fn render(format: &str, time: DateTime<Local>, sha: Option<&str>) -> Result<String> {
+    let sha = sha.unwrap_or("unknown");
+    let expanded = format
+        .replace("{short_sha}", &sha[..7.min(sha.len())])
+        .replace("{sha}", sha);
+
+    let items: Vec<Item> = StrftimeItems::new(&expanded).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        bail!("'{}' has an invalid strftime specifier", format);
+    }
+    let name = time.format_with_items(items.into_iter()).to_string();
+
+    if name.is_empty()
+        || name.len() > 128
+        || name.starts_with(['.', '-'])
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    {
+        bail!(
+            "'{}' gives '{}'; release names may only use letters, digits, '_', '.' and '-'",
+            format,
+            name
+        );
+    }
+    Ok(name)
+}
+
+/// Whether names from a release format sort by deploy time: it must start
+/// with a full timestamp, %Y%m%d%H%M%S, optionally with '-', '_' or '.'
+/// between the fields
+pub fn is_sortable(format: &str) -> bool {
+    let mut rest = format;
+    for (i, field) in ["%Y", "%m", "%d", "%H", "%M", "%S"].iter().enumerate() {
+        if i > 0 {
+            rest = rest.trim_start_matches(['-', '_', '.']);
+        }
+        match rest.strip_prefix(field) {
+            Some(after) => rest = after,
+            None => return false,
         }
     }
+    true
+}
+
+/// Remove the releases of an app beyond the newest `keep`, sparing those
+/// younger than `keep_days` and whatever a rollback needs (the current and
+/// previous release). `rmi` is passed to `docker compose down --rmi`.
+/// Returns the removed release names.
+pub async fn remove_old(
+    session: &SshSession,
+    app_path: &str,
+    keep: usize,
+    keep_days: Option<u64>,
+    rmi: &str,
+) -> Result<Vec<String>> {
+    let releases_dir = format!("{}/releases", app_path);
+
+    // "<name> <mtime>", newest name first
+    let output_str = session
+        .query(&format!(
+            "find {} -mindepth 1 -maxdepth 1 -type d -printf '%f %T@\\n' | sort -r",
+            releases_dir
+        ))
+        .await?;
+
+    let releases: Vec<(&str, f64)> = output_str
+        .lines()
+        .filter_map(|line| {
+            let (name, mtime) = line.rsplit_once(' ')?;
+            Some((name, mtime.parse().unwrap_or_default()))
+        })
+        .collect();
+
+    if releases.len() <= keep {
+        return Ok(Vec::new());
+    }
+
+    // Whatever the count and age, never remove what a rollback needs
+    let lock = ShipitLock::read(session, app_path).await?;
+    let protected: Vec<&str> = lock
+        .iter()
+        .flat_map(|l| std::iter::once(&l.current_release).chain(&l.previous_release))
+        .map(String::as_str)
+        .collect();
+    let cutoff =
+        keep_days.map(|days| chrono::Utc::now().timestamp() as f64 - (days * 86_400) as f64);
+
+    let to_remove: Vec<&str> = releases[keep..]
+        .iter()
+        .filter(|(name, mtime)| {
+            !protected.contains(name) && cutoff.is_none_or(|cutoff| *mtime < cutoff)
+        })
+        .map(|(name, _)| *name)
+        .collect();
+
+    let mut removed = Vec::new();
+    for release in to_remove {
+        let release_path = format!("{}/{}", releases_dir, release);
+
+        // Stop containers and remove images
+        let _ = session
+            .exec(&format!(
+                "cd {} && docker compose down --rmi {} 2>/dev/null || true",
+                release_path, rmi
+            ))
+            .await;
+
+        // Remove directory
+        session.exec(&format!("rm -rf {}", release_path)).await?;
+
+        removed.push(release.to_string());
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::{is_sortable, render, DEFAULT_FORMAT};
+
+    const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn renders_timestamps_and_shas() {
+        let time = Local.with_ymd_and_hms(2025, 2, 19, 14, 0, 5).unwrap();
+        assert_eq!(
+            render(DEFAULT_FORMAT, time, Some(SHA)).unwrap(),
+            "20250219-140005"
+        );
+        assert_eq!(
+            render("%Y%m%d-%H%M%S-{short_sha}", time, Some(SHA)).unwrap(),
+            "20250219-140005-0123456"
+        );
+        assert_eq!(
+            render("%Y%m%d%H%M%S.{sha}", time, None).unwrap(),
+            "20250219140005.unknown"
+        );
+    }
+
+    #[test]
+    fn rejects_names_that_are_not_docker_tags() {
+        let time = Local.with_ymd_and_hms(2025, 2, 19, 14, 0, 5).unwrap();
+        assert!(render("%Y/%m/%d", time, None).is_err());
+        assert!(render("-%Y", time, None).is_err());
+        assert!(render("%Y %H", time, None).is_err());
+        assert!(render("%Y%Q", time, None).is_err());
+    }
+
+    #[test]
+    fn requires_a_full_timestamp_prefix() {
+        assert!(is_sortable(DEFAULT_FORMAT));
+        assert!(is_sortable("%Y%m%d%H%M%S"));
+        assert!(is_sortable("%Y-%m-%d_%H.%M.%S-{short_sha}"));
+        assert!(!is_sortable("%Y%m%d-{short_sha}"));
+        assert!(!is_sortable("{short_sha}-%Y%m%d%H%M%S"));
+        assert!(!is_sortable("%Y%d%m%H%M%S"));
+    }
 }
@@ -97,7 +97,9 @@ pub fn read_secrets(
         .with_context(|| format!("Failed to read secrets file: {}", path.display()))?;
 
     let plaintext = decrypt(&ciphertext, identity)?;
-    Ok(parse_dotenv(&plaintext))
+    let secrets = parse_dotenv(&plaintext);
+    crate::ssh::transcript::add_secrets(secrets.values());
+    Ok(secrets)
 }
 
 /// Encrypt and write secrets for a given stage
@@ -1,21 +1,71 @@
+use std::future::Future;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::time::{Duration, Instant};
+
 use anyhow::{bail, Context, Result};
+use openssh::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
 use tracing::debug;
 
-use super::SshSession;
+use super::transcript::{self, Capture};
+use super::{is_dry_run, SshSession};
+use crate::output;
 
 impl SshSession {
-    /// Execute a command and return stdout
+    /// Run a command through `bash -c`, enforcing ssh.command_timeout if set
+    async fn output(&self, cmd: &str) -> Result<Output> {
+        let mut command = self.session.command("bash");
+        command.arg("-c").arg(cmd);
+
+        let started = Instant::now();
+        let result = match self.command_timeout {
+            Some(limit) => tokio::time::timeout(limit, command.output())
+                .await
+                .map_err(|_| self.timed_out(limit, cmd))?,
+            None => command.output().await,
+        };
+
+        let output =
+            result.with_context(|| format!("Failed to execute command on {}: {}", self.host, cmd));
+        match &output {
+            Ok(output) => transcript::record(
+                &self.host,
+                cmd,
+                started.elapsed(),
+                Some(output.status),
+                &format!(
+                    "{}{}",
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ),
+            Err(e) => transcript::record(
+                &self.host,
+                cmd,
+                started.elapsed(),
+                None,
+                &format!("{:#}", e),
+            ),
+        }
+        output
+    }
+
+    /// Execute a command and return stdout. With `--dry-run` the command is
+    /// printed instead and the output is empty.
     pub async fn exec(&self, cmd: &str) -> Result<String> {
+        if is_dry_run() {
+            self.plan(cmd);
+            return Ok(String::new());
+        }
+        self.query(cmd).await
+    }
+
+    /// Like `exec`, for commands that only read: they also run with `--dry-run`
+    pub async fn query(&self, cmd: &str) -> Result<String> {
         debug!("[{}] exec: {}", self.host, cmd);
 
-        let output = self
-            .session
-            .command("bash")
-            .arg("-c")
-            .arg(cmd)
-            .output()
-            .await
-            .with_context(|| format!("Failed to execute command on {}: {}", self.host, cmd))?;
+        let output = self.output(cmd).await?;
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
         let stderr = String::from_utf8_lossy(&output.stderr).to_string();
@@ -34,20 +84,198 @@ impl SshSession {
         Ok(stdout)
     }
 
-    /// Execute a command, returning Ok(true) if exit 0, Ok(false) otherwise
+    /// Execute a command, returning Ok(true) if exit 0, Ok(false) otherwise.
+    /// Used for checks, so it also runs with `--dry-run`.
     pub async fn exec_ok(&self, cmd: &str) -> Result<bool> {
         debug!("[{}] exec_ok: {}", self.host, cmd);
 
-        let output = self
-            .session
-            .command("bash")
+        let output = self.output(cmd).await?;
+
+        Ok(output.status.success())
+    }
+
+    /// Execute a command, invoking `on_line` for every stdout/stderr line as it
+    /// arrives instead of buffering. Fails if the command exits non-zero.
+    pub async fn exec_streamed<F>(&self, cmd: &str, on_line: F) -> Result<()>
+    where
+        F: FnMut(&str),
+    {
+        let status = self.exec_streamed_status(cmd, on_line).await?;
+        if !status.success() {
+            bail!("Command failed on {} (exit {}): {}", self.host, status, cmd);
+        }
+        Ok(())
+    }
+
+    /// Like `exec_streamed`, but returns the exit status instead of failing
+    /// on a non-zero one
+    pub async fn exec_streamed_status<F>(&self, cmd: &str, mut on_line: F) -> Result<ExitStatus>
+    where
+        F: FnMut(&str),
+    {
+        if is_dry_run() {
+            self.plan(cmd);
+            return Ok(ExitStatus::from_raw(0));
+        }
+        debug!("[{}] exec_streamed: {}", self.host, cmd);
+
+        let started = Instant::now();
+        let mut command = self.session.command("bash");
+        command
             .arg("-c")
             .arg(cmd)
-            .output()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = command
+            .spawn()
             .await
             .with_context(|| format!("Failed to execute command on {}: {}", self.host, cmd))?;
 
-        Ok(output.status.success())
+        let stdout = child
+            .stdout()
+            .take()
+            .context("Failed to capture remote stdout")?;
+        let stderr = child
+            .stderr()
+            .take()
+            .context("Failed to capture remote stderr")?;
+
+        let mut capture = Capture::default();
+        let status = self
+            .bounded(cmd, async {
+                let pumped = pump_lines(stdout, stderr, &mut |line: &str| {
+                    capture.push(line);
+                    on_line(line);
+                })
+                .await;
+                match pumped {
+                    Ok(()) => child.wait().await.with_context(|| {
+                        format!("Failed to execute command on {}: {}", self.host, cmd)
+                    }),
+                    Err(e) => {
+                        // Closing the channel hangs up on the remote command
+                        let _ = child.disconnect().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await;
+        transcript::record(
+            &self.host,
+            cmd,
+            started.elapsed(),
+            status.as_ref().ok().copied(),
+            capture.as_str(),
+        );
+        status
+    }
+
+    /// Like `exec_streamed`, but when the stage sets `ssh.forward_agent` the
+    /// command runs over a dedicated `ssh -A` connection so it can use the
+    /// local SSH agent (e.g. for private Git dependencies during builds).
+    pub async fn exec_streamed_with_agent<F>(&self, cmd: &str, mut on_line: F) -> Result<()>
+    where
+        F: FnMut(&str),
+    {
+        let Some(agent_args) = self.agent_ssh_args.as_ref().filter(|_| !is_dry_run()) else {
+            return self.exec_streamed(cmd, on_line).await;
+        };
+
+        debug!("[{}] exec_streamed_with_agent: {}", self.host, cmd);
+
+        let started = Instant::now();
+        let mut child = tokio::process::Command::new("ssh")
+            .args(agent_args)
+            .arg(cmd)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to spawn ssh for agent-forwarded command")?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .context("Failed to capture remote stdout")?;
+        let stderr = child
+            .stderr
+            .take()
+            .context("Failed to capture remote stderr")?;
+
+        let mut capture = Capture::default();
+        let status = self
+            .bounded(cmd, async {
+                let pumped = pump_lines(stdout, stderr, &mut |line: &str| {
+                    capture.push(line);
+                    on_line(line);
+                })
+                .await;
+                match pumped {
+                    Ok(()) => child.wait().await.with_context(|| {
+                        format!("Failed to execute command on {}: {}", self.host, cmd)
+                    }),
+                    Err(e) => {
+                        let _ = child.kill().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await;
+        transcript::record(
+            &self.host,
+            cmd,
+            started.elapsed(),
+            status.as_ref().ok().copied(),
+            capture.as_str(),
+        );
+        let status = status?;
+
+        if !status.success() {
+            bail!("Command failed on {} (exit {}): {}", self.host, status, cmd);
+        }
+
+        Ok(())
+    }
+
+    /// Streaming variant of `sudo_exec`
+    pub async fn sudo_exec_streamed<F>(&self, cmd: &str, on_line: F) -> Result<()>
+    where
+        F: FnMut(&str),
+    {
+        self.exec_streamed(&format!("sudo bash -c '{}'", cmd.replace('\'', "'\\''")), on_line)
+            .await
+    }
+
+    /// Run a command on a pseudo-terminal attached to the local one, for
+    /// interactive programs (shells, consoles). Multiplexed over the master
+    /// connection; returns the remote exit status.
+    pub async fn exec_interactive(&self, cmd: &str) -> Result<ExitStatus> {
+        if !console::user_attended() {
+            bail!("An interactive command needs a terminal");
+        }
+        debug!("[{}] exec_interactive: {}", self.host, cmd);
+
+        let started = Instant::now();
+        let status = tokio::process::Command::new("ssh")
+            .arg("-o")
+            .arg(format!("ControlPath={}", self.control_path().display()))
+            .arg("-t")
+            .arg(&self.host)
+            .arg(cmd)
+            .status()
+            .await
+            .with_context(|| format!("Failed to execute command on {}: {}", self.host, cmd));
+        transcript::record(
+            &self.host,
+            cmd,
+            started.elapsed(),
+            status.as_ref().ok().copied(),
+            "",
+        );
+        status
     }
 
     /// Check if a path exists on the remote
@@ -55,22 +283,134 @@ impl SshSession {
         self.exec_ok(&format!("test -e {}", path)).await
     }
 
+    /// Remote clock minus local clock, in seconds
+    pub async fn clock_skew(&self) -> Result<i64> {
+        let out = self.query("date +%s").await?;
+        let remote: i64 = out
+            .trim()
+            .parse()
+            .with_context(|| format!("Unexpected `date` output on {}: {}", self.host, out.trim()))?;
+        Ok(remote - chrono::Utc::now().timestamp())
+    }
+
     /// Write content to a file on the remote
     pub async fn write_file(&self, path: &str, content: &str) -> Result<()> {
-        let escaped = content.replace('\'', "'\\''");
-        self.exec(&format!("cat > {} << 'SHIPIT_EOF'\n{}\nSHIPIT_EOF", path, escaped))
-            .await?;
-        Ok(())
+        self.upload(path, content.as_bytes()).await
     }
 
     /// Write content to a file with sudo
     pub async fn sudo_write_file(&self, path: &str, content: &str) -> Result<()> {
-        let escaped = content.replace('\'', "'\\''");
-        self.exec(&format!(
-            "sudo tee {} > /dev/null << 'SHIPIT_EOF'\n{}\nSHIPIT_EOF",
-            path, escaped
-        ))
-        .await?;
+        self.sudo_upload(path, content.as_bytes()).await
+    }
+
+    /// Upload raw bytes to a file on the remote.
+    /// Data is streamed over the session's stdin (binary-safe) into a temp file
+    /// that is then renamed over the destination.
+    pub async fn upload(&self, path: &str, content: &[u8]) -> Result<()> {
+        if is_dry_run() {
+            self.plan_write(path, content);
+            return Ok(());
+        }
+        debug!("[{}] upload: {} ({} bytes)", self.host, path, content.len());
+
+        let tmp = format!("{}.shipit-tmp", path);
+        let mut input = content;
+        self.exec_with_input(&format!("cat > {} && mv -f {} {}", tmp, tmp, path), &mut input)
+            .await
+            .with_context(|| format!("Failed to upload {} to {}", path, self.host))
+    }
+
+    /// Upload raw bytes to a privileged path: stage in /tmp as the SSH user,
+    /// then copy into place with sudo (keeps the destination's owner and mode).
+    pub async fn sudo_upload(&self, path: &str, content: &[u8]) -> Result<()> {
+        if is_dry_run() {
+            self.plan_write(path, content);
+            return Ok(());
+        }
+        debug!("[{}] sudo_upload: {} ({} bytes)", self.host, path, content.len());
+
+        let tmp = self
+            .exec("mktemp /tmp/shipit-upload.XXXXXX")
+            .await?
+            .trim()
+            .to_string();
+
+        let mut input = content;
+        self.exec_with_input(&format!("cat > {}", tmp), &mut input)
+            .await
+            .with_context(|| format!("Failed to upload {} to {}", path, self.host))?;
+
+        let result = self
+            .exec(&format!("sudo tee {} < {} > /dev/null", path, tmp))
+            .await
+            .with_context(|| format!("Failed to move upload into {} on {}", path, self.host));
+
+        let _ = self.exec(&format!("rm -f {}", tmp)).await;
+        result.map(|_| ())
+    }
+
+    /// Run a command with `input` streamed to its stdin until EOF
+    pub async fn exec_with_input<R>(&self, cmd: &str, input: &mut R) -> Result<()>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        if is_dry_run() {
+            self.plan(&format!("{} < (input)", cmd));
+            return Ok(());
+        }
+        debug!("[{}] exec_with_input: {}", self.host, cmd);
+
+        let started = Instant::now();
+        let mut command = self.session.command("bash");
+        command
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let mut child = command
+            .spawn()
+            .await
+            .with_context(|| format!("Failed to execute command on {}: {}", self.host, cmd))?;
+
+        let mut stdin = child
+            .stdin()
+            .take()
+            .context("Failed to open remote stdin")?;
+        let output = self
+            .bounded(cmd, async {
+                tokio::io::copy(input, &mut stdin)
+                    .await
+                    .context("Failed to stream input to remote")?;
+                stdin.shutdown().await?;
+                drop(stdin);
+
+                child
+                    .wait_with_output()
+                    .await
+                    .with_context(|| format!("Failed to execute command on {}: {}", self.host, cmd))
+            })
+            .await?;
+        // The input itself (uploaded files, image streams) is never recorded
+        transcript::record(
+            &self.host,
+            cmd,
+            started.elapsed(),
+            Some(output.status),
+            &String::from_utf8_lossy(&output.stderr),
+        );
+
+        if !output.status.success() {
+            bail!(
+                "Command failed on {} (exit {}): {}\nstderr: {}",
+                self.host,
+                output.status,
+                cmd,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
         Ok(())
     }
 
@@ -87,4 +427,95 @@ impl SshSession {
         self.exec(&format!("sudo bash -c '{}'", cmd.replace('\'', "'\\''")))
             .await
     }
+    /// Bound a running command by ssh.command_timeout, if set
+    async fn bounded<T>(&self, cmd: &str, run: impl Future<Output = Result<T>>) -> Result<T> {
+        match self.command_timeout {
+            Some(limit) => tokio::time::timeout(limit, run)
+                .await
+                .map_err(|_| self.timed_out(limit, cmd))?,
+            None => run.await,
+        }
+    }
+
+    fn timed_out(&self, limit: Duration, cmd: &str) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Command timed out on {} after {}s (ssh.command_timeout): {}",
+            self.host,
+            limit.as_secs(),
+            cmd
+        )
+    }
+
+    /// `--dry-run`: print a command instead of running it
+    fn plan(&self, cmd: &str) {
+        output::planned(&self.host, &transcript::redact(cmd));
+    }
+
+    /// `--dry-run`: print a file write instead of uploading it
+    fn plan_write(&self, path: &str, content: &[u8]) {
+        let content = match std::str::from_utf8(content) {
+            Ok(text) => transcript::redact(text),
+            Err(_) => format!("({} bytes of binary content)", content.len()),
+        };
+        output::planned_write(&self.host, path, &content);
+    }
+}
+
+/// Read stdout and stderr concurrently, calling `on_line` for each line.
+/// Output that isn't valid UTF-8 is passed on lossily rather than failing.
+async fn pump_lines<O, E, F>(stdout: O, stderr: E, on_line: &mut F) -> Result<()>
+where
+    O: AsyncRead + Unpin,
+    E: AsyncRead + Unpin,
+    F: FnMut(&str),
+{
+    let mut stdout = BufReader::new(stdout);
+    let mut stderr = BufReader::new(stderr);
+    let mut stdout_line = Vec::new();
+    let mut stderr_line = Vec::new();
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+
+    while !(stdout_done && stderr_done) {
+        tokio::select! {
+            read = stdout.read_until(b'\n', &mut stdout_line), if !stdout_done => {
+                stdout_done = read? == 0;
+                emit_line(&mut stdout_line, on_line);
+            },
+            read = stderr.read_until(b'\n', &mut stderr_line), if !stderr_done => {
+                stderr_done = read? == 0;
+                emit_line(&mut stderr_line, on_line);
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Pass a read line (if any) to `on_line` without its line ending
+fn emit_line<F: FnMut(&str)>(line: &mut Vec<u8>, on_line: &mut F) {
+    if line.is_empty() {
+        return;
+    }
+    let text = String::from_utf8_lossy(line);
+    on_line(text.trim_end_matches(['\n', '\r']));
+    line.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pump_lines;
+
+    #[tokio::test]
+    async fn pumps_lines_from_both_streams_lossily() {
+        let stdout: &[u8] = b"one\r\ntwo \xff\nlast";
+        let stderr: &[u8] = b"warning\n";
+
+        let mut lines = Vec::new();
+        pump_lines(stdout, stderr, &mut |line: &str| lines.push(line.to_string()))
+            .await
+            .expect("should pump");
+        lines.sort();
+        assert_eq!(lines, ["last", "one", "two \u{fffd}", "warning"]);
+    }
 }
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+const KNOWN_HOSTS_FILE: &str = ".shipit/known_hosts";
+
+/// Project-scoped known_hosts file: .shipit/known_hosts
+pub fn path() -> Result<PathBuf> {
+    let root = std::env::current_dir().context("Could not determine current directory")?;
+    Ok(root.join(KNOWN_HOSTS_FILE))
+}
+
+/// The name ssh records a host under (`[host]:port` for non-default ports)
+pub fn host_pattern(host: &str, port: Option<u16>) -> String {
+    match port {
+        Some(p) if p != 22 => format!("[{}]:{}", host, p),
+        _ => host.to_string(),
+    }
+}
+
+/// Remove any recorded keys for a host
+pub fn forget(file: &Path, host: &str, port: Option<u16>) -> Result<()> {
+    if !file.exists() {
+        return Ok(());
+    }
+
+    let output = Command::new("ssh-keygen")
+        .args(["-R", &host_pattern(host, port), "-f"])
+        .arg(file)
+        .output()
+        .context("Failed to run ssh-keygen -R")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "ssh-keygen -R failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // ssh-keygen leaves a backup next to the file
+    let _ = std::fs::remove_file(file.with_extension("old"));
+    Ok(())
+}
+
+/// Fingerprints recorded for a host, one per key type
+pub fn fingerprints(file: &Path, host: &str, port: Option<u16>) -> Result<Vec<String>> {
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+
+    let output = Command::new("ssh-keygen")
+        .args(["-l", "-F", &host_pattern(host, port), "-f"])
+        .arg(file)
+        .output()
+        .context("Failed to run ssh-keygen -F")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| line.trim().to_string())
+        .collect())
+}
+
+/// Fingerprints of the keys a host presents now, read by connecting with a
+/// throwaway known_hosts file (authentication need not succeed)
+pub fn scan(
+    user: &str,
+    host: &str,
+    port: Option<u16>,
+    proxy: Option<&str>,
+    connect_timeout: u64,
+) -> Result<Vec<String>> {
+    let dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+    let file = dir.path().join("known_hosts");
+
+    let mut command = Command::new("ssh");
+    for option in [
+        "BatchMode=yes".to_string(),
+        "StrictHostKeyChecking=accept-new".to_string(),
+        format!("UserKnownHostsFile={}", file.display()),
+        "GlobalKnownHostsFile=/dev/null".to_string(),
+        format!("ConnectTimeout={}", connect_timeout),
+    ] {
+        command.arg("-o").arg(option);
+    }
+    if let Some(port) = port {
+        command.arg("-p").arg(port.to_string());
+    }
+    if let Some(jump) = proxy {
+        command.arg("-J").arg(jump);
+    }
+    command
+        .arg(format!("{}@{}", user, host))
+        .arg("true")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .context("Failed to run ssh")?;
+
+    fingerprints(&file, host, port)
+}
+
+/// The `SHA256:...` part of a fingerprint line
+pub fn key_of(fingerprint: &str) -> &str {
+    fingerprint.split_whitespace().nth(1).unwrap_or(fingerprint)
+}
@@ -1,25 +1,106 @@
 pub mod exec;
+pub mod known_hosts;
+pub mod pool;
+pub mod transcript;
 
-use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
 use openssh::{KnownHosts, Session, SessionBuilder};
 use tracing::debug;
 
+use crate::config::SshConfig;
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// `--dry-run`: commands and file writes that change a host are printed
+/// instead of run. Reads (`query`, `exec_ok`, `path_exists`) still run.
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
 pub struct SshSession {
     session: Session,
     host: String,
+    command_timeout: Option<Duration>,
+    /// Arguments for a standalone `ssh -A` to this host (set when ssh.forward_agent)
+    agent_ssh_args: Option<Vec<String>>,
 }
 
 impl SshSession {
-    pub async fn connect(user: &str, host: &str, port: Option<u16>, proxy: Option<&str>) -> Result<Self> {
+    pub async fn connect(
+        user: &str,
+        host: &str,
+        port: Option<u16>,
+        proxy: Option<&str>,
+        ssh: &SshConfig,
+    ) -> Result<Self> {
+        let known_hosts = if ssh.strict_host_keys {
+            KnownHosts::Strict
+        } else {
+            KnownHosts::Accept
+        };
+        Self::connect_with(user, host, port, proxy, ssh, known_hosts).await
+    }
+
+    /// Connect and record the host's key in the project known_hosts file
+    /// (used by `shipit trust`). Keys that are already recorded must match.
+    pub async fn connect_trusting(
+        user: &str,
+        host: &str,
+        port: Option<u16>,
+        proxy: Option<&str>,
+        ssh: &SshConfig,
+    ) -> Result<Self> {
+        Self::connect_with(user, host, port, proxy, ssh, KnownHosts::Add).await
+    }
+
+    async fn connect_with(
+        user: &str,
+        host: &str,
+        port: Option<u16>,
+        proxy: Option<&str>,
+        ssh: &SshConfig,
+        known_hosts: KnownHosts,
+    ) -> Result<Self> {
         if let Some(jump) = proxy {
             debug!("Connecting to {}@{} via proxy {}", user, host, jump);
         } else {
             debug!("Connecting to {}@{}", user, host);
         }
 
+        let connect_timeout = Duration::from_secs(ssh.connect_timeout);
+        let uses_project_keys = !matches!(known_hosts, KnownHosts::Accept);
+
         let mut builder = SessionBuilder::default();
-        builder.known_hosts_check(KnownHosts::Accept);
+        builder.known_hosts_check(known_hosts);
         builder.user(user.to_string());
+        builder.connect_timeout(connect_timeout);
+
+        if uses_project_keys {
+            builder.user_known_hosts_file(known_hosts::path()?);
+        }
+
+        if ssh.keepalive_interval > 0 {
+            builder.server_alive_interval(Duration::from_secs(ssh.keepalive_interval));
+        }
+
+        // SessionBuilder has no ServerAliveCountMax knob; pass it through a
+        // generated config that still includes the user's own ~/.ssh/config.
+        let _keepalive_config = match ssh.keepalive_count_max {
+            Some(count) => {
+                let file = keepalive_config(count)?;
+                builder.config_file(file.path());
+                Some(file)
+            }
+            None => None,
+        };
 
         if let Some(port) = port {
             builder.port(port);
@@ -29,14 +110,45 @@ impl SshSession {
             builder.jump_hosts([jump]);
         }
 
-        let session = builder
-            .connect(host)
+        // ConnectTimeout only covers the TCP handshake; also bound the whole
+        // connect so a host that accepts but never answers can't hang us.
+        let result = tokio::time::timeout(connect_timeout, builder.connect(host))
             .await
-            .with_context(|| format!("Failed to connect to {}@{}", user, host))?;
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Timed out connecting to {}@{} after {}s (ssh.connect_timeout)",
+                    user,
+                    host,
+                    ssh.connect_timeout
+                )
+            })?;
+
+        let session = match result {
+            Ok(session) => session,
+            Err(e) if uses_project_keys && is_host_key_error(&e) => {
+                bail!(
+                    "Host key verification failed for {}: its key is missing from or does not \
+                     match .shipit/known_hosts. Run `shipit trust` for this stage if the host is \
+                     new or was legitimately rebuilt.",
+                    host
+                );
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to connect to {}@{}", user, host))
+            }
+        };
+
+        let agent_ssh_args = if ssh.forward_agent {
+            Some(agent_ssh_args(user, host, port, proxy, ssh)?)
+        } else {
+            None
+        };
 
         Ok(Self {
             session,
             host: host.to_string(),
+            command_timeout: ssh.command_timeout.map(Duration::from_secs),
+            agent_ssh_args,
         })
     }
 
@@ -44,6 +156,24 @@ impl SshSession {
         &self.host
     }
 
+    /// Lift ssh.command_timeout for commands that run as long as the user
+    /// wants (followed logs, `shipit run`)
+    pub fn without_command_timeout(mut self) -> Self {
+        self.command_timeout = None;
+        self
+    }
+
+    /// Path to the ControlMaster socket backing this session
+    pub fn control_path(&self) -> &Path {
+        self.session.control_socket()
+    }
+
+    /// An `ssh` invocation that multiplexes over this session's master
+    /// connection, for local tools (e.g. `GIT_SSH_COMMAND`) that shell out to ssh.
+    pub fn ssh_command(&self) -> String {
+        format!("ssh -o ControlPath={}", self.control_path().display())
+    }
+
     pub async fn close(self) -> Result<()> {
         self.session
             .close()
@@ -52,3 +182,99 @@ impl SshSession {
         Ok(())
     }
 }
+
+/// Quote a string for a POSIX shell (single quotes, embedded ones escaped)
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// The local user's SSH public key (~/.ssh/id_ed25519.pub, then id_rsa.pub)
+pub fn local_public_key() -> Result<Option<String>> {
+    let home = std::env::var("HOME").context("HOME not set")?;
+    for name in ["id_ed25519.pub", "id_rsa.pub"] {
+        let path = Path::new(&home).join(".ssh").join(name);
+        if path.exists() {
+            let key = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            return Ok(Some(key.trim().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Build `ssh -A` arguments mirroring the session's connection settings.
+/// The shared master is not reused because it was started without agent
+/// forwarding, which OpenSSH requires on the master side.
+fn agent_ssh_args(
+    user: &str,
+    host: &str,
+    port: Option<u16>,
+    proxy: Option<&str>,
+    ssh: &SshConfig,
+) -> Result<Vec<String>> {
+    let mut args = vec![
+        "-A".to_string(),
+        "-T".to_string(),
+        "-o".to_string(),
+        format!("ConnectTimeout={}", ssh.connect_timeout),
+    ];
+
+    if ssh.strict_host_keys {
+        args.extend([
+            "-o".to_string(),
+            "StrictHostKeyChecking=yes".to_string(),
+            "-o".to_string(),
+            format!("UserKnownHostsFile={}", known_hosts::path()?.display()),
+        ]);
+    } else {
+        args.extend(["-o".to_string(), "StrictHostKeyChecking=no".to_string()]);
+    }
+
+    if ssh.keepalive_interval > 0 {
+        args.extend([
+            "-o".to_string(),
+            format!("ServerAliveInterval={}", ssh.keepalive_interval),
+        ]);
+    }
+    if let Some(count) = ssh.keepalive_count_max {
+        args.extend(["-o".to_string(), format!("ServerAliveCountMax={}", count)]);
+    }
+
+    if let Some(port) = port {
+        args.extend(["-p".to_string(), port.to_string()]);
+    }
+    if let Some(jump) = proxy {
+        args.extend(["-J".to_string(), jump.to_string()]);
+    }
+    args.push(format!("{}@{}", user, host));
+
+    Ok(args)
+}
+
+fn keepalive_config(count_max: u32) -> Result<tempfile::NamedTempFile> {
+    use std::io::Write as _;
+
+    let mut file = tempfile::Builder::new()
+        .prefix("shipit-ssh-")
+        .suffix(".conf")
+        .tempfile()
+        .context("Failed to create SSH config file")?;
+    write!(
+        file,
+        "ServerAliveCountMax {}\nInclude ~/.ssh/config\n",
+        count_max
+    )
+    .context("Failed to write SSH config file")?;
+    Ok(file)
+}
+
+fn is_host_key_error(e: &openssh::Error) -> bool {
+    let mut msg = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(inner) = source {
+        msg.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    msg.contains("Host key verification failed")
+        || msg.contains("REMOTE HOST IDENTIFICATION HAS CHANGED")
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+
+use crate::config::SshConfig;
+
+use super::SshSession;
+
+/// Keeps one SSH session per host open for the lifetime of a command, so
+/// repeated operations against the same host reuse a single transport.
+#[derive(Default)]
+pub struct SessionPool {
+    sessions: HashMap<String, SshSession>,
+}
+
+impl SessionPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the session for `host`, connecting on first use.
+    pub async fn get(
+        &mut self,
+        user: &str,
+        host: &str,
+        port: Option<u16>,
+        proxy: Option<&str>,
+        ssh: &SshConfig,
+    ) -> Result<&SshSession> {
+        let key = format!("{}@{}", user, host);
+        if !self.sessions.contains_key(&key) {
+            let session = SshSession::connect(user, host, port, proxy, ssh).await?;
+            self.sessions.insert(key.clone(), session);
+        }
+        self.sessions
+            .get(&key)
+            .with_context(|| format!("No SSH session for {}", key))
+    }
+
+    pub async fn close_all(self) -> Result<()> {
+        for (_, session) in self.sessions {
+            session.close().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+/// Output kept per command; the tail is where errors usually are
+const MAX_OUTPUT: usize = 4096;
+
+/// Secrets shorter than this are too likely to match ordinary output
+const MIN_SECRET_LEN: usize = 4;
+
+static TRANSCRIPT: OnceLock<Mutex<File>> = OnceLock::new();
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Start recording every remote command to `path` (`--log-file` /
+/// `SHIPIT_LOG_FILE`). Appends, so several runs can share one file.
+pub fn open(path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file {}", path.display()))?;
+    writeln!(
+        file,
+        "=== {} shipit {} {}",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        env!("CARGO_PKG_VERSION"),
+        command_line(std::env::args().skip(1))
+    )
+    .with_context(|| format!("Failed to write log file {}", path.display()))?;
+    let _ = TRANSCRIPT.set(Mutex::new(file));
+    Ok(())
+}
+
+/// The invocation for the header. Nothing is decrypted yet, so `KEY=VALUE`
+/// arguments are masked like in the audit log rather than by value.
+fn command_line(args: impl Iterator<Item = String>) -> String {
+    args.map(|arg| crate::audit::redact(&arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_enabled() -> bool {
+    TRANSCRIPT.get().is_some()
+}
+
+/// Values to mask in the transcript; every decrypted secret is registered
+pub fn add_secrets<'a>(values: impl IntoIterator<Item = &'a String>) {
+    let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    for value in values {
+        if value.len() >= MIN_SECRET_LEN && !secrets.contains(value) {
+            secrets.push(value.clone());
+        }
+    }
+    // Longest first, so a secret containing another is masked whole
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+}
+
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    secrets.iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret.as_str(), "[REDACTED]")
+    })
+}
+
+/// Record one remote command: host, duration, exit status (None when it
+/// could not run or timed out) and output
+pub fn record(host: &str, cmd: &str, elapsed: Duration, status: Option<ExitStatus>, output: &str) {
+    let Some(file) = TRANSCRIPT.get() else {
+        return;
+    };
+    let status = match status.map(|s| s.code()) {
+        Some(Some(code)) => format!("exit {}", code),
+        Some(None) => "killed by a signal".to_string(),
+        None => "did not complete".to_string(),
+    };
+
+    let output = redact(output.trim_end());
+    let output = match output.char_indices().nth_back(MAX_OUTPUT - 1) {
+        Some((start, _)) if start > 0 => {
+            format!("[… earlier output truncated]\n{}", &output[start..])
+        }
+        _ => output,
+    };
+
+    let mut entry = format!(
+        "--- {} [{}] {:.2}s {}\n$ {}\n",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        host,
+        elapsed.as_secs_f64(),
+        status,
+        redact(cmd)
+    );
+    for line in output.lines() {
+        entry.push_str("  ");
+        entry.push_str(line);
+        entry.push('\n');
+    }
+
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    // A full disk must not fail the deploy it is logging
+    let _ = file.write_all(entry.as_bytes());
+}
+
+/// Collects a streamed command's output for `record`, only when logging
+pub struct Capture(Option<String>);
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self(is_enabled().then(String::new))
+    }
+}
+
+impl Capture {
+    pub fn push(&mut self, line: &str) {
+        if let Some(buf) = &mut self.0 {
+            buf.push_str(line);
+            buf.push('\n');
+            // Only the tail is recorded; don't hold a long build log in memory
+            if buf.len() > MAX_OUTPUT * 4 {
+                let mut cut = buf.len() - MAX_OUTPUT * 2;
+                while !buf.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                buf.drain(..cut);
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_deref().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_secrets, command_line, redact};
+
+    #[test]
+    fn masks_registered_secrets() {
+        add_secrets(&[
+            "abc".to_string(),
+            "s3cret-token".to_string(),
+            "s3cret-token-extended".to_string(),
+        ]);
+
+        assert_eq!(
+            redact("TOKEN=s3cret-token curl -H s3cret-token-extended"),
+            "TOKEN=[REDACTED] curl -H [REDACTED]"
+        );
+        // Too short to register
+        assert_eq!(redact("abc"), "abc");
+        assert_eq!(redact("nothing to hide"), "nothing to hide");
+    }
+
+    #[test]
+    fn masks_assignments_in_the_command_line() {
+        let args = ["secrets", "set", "-s", "prod", "DB_PASSWORD=hunter2"];
+        assert_eq!(
+            command_line(args.iter().map(|a| a.to_string())),
+            "secrets set -s prod DB_PASSWORD=[redacted]"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+pub mod public;
+
 use anyhow::{Context, Result};
 use minijinja::Environment;
 
@@ -41,7 +43,7 @@ pub async fn install(session: &SshSession, acme_email: Option<&str>, host_os: Ho
 
     match host_os {
         HostOs::NixOs => install_nixos(session).await?,
-        HostOs::Ubuntu => install_systemd(session).await?,
+        HostOs::Ubuntu | HostOs::Rhel => install_systemd(session).await?,
     }
 
     output::success("Traefik installed and running");
@@ -0,0 +1,98 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::process::Command;
+
+use crate::ssh::shell_quote;
+
+/// Where Traefik keeps the certificates it obtained from Let's Encrypt
+pub const ACME_FILE: &str = "/etc/traefik/acme.json";
+
+/// The certificate a domain serves on 443
+pub struct Served {
+    pub issuer: String,
+    pub expires: DateTime<Utc>,
+}
+
+impl Served {
+    pub fn days_left(&self) -> i64 {
+        (self.expires - Utc::now()).num_days()
+    }
+}
+
+/// Status code of a GET from this machine, as users would send it. A TLS
+/// error (e.g. Traefik's self-signed default certificate) is an error.
+pub fn http_status(url: &str) -> Result<u16> {
+    let output = Command::new("curl")
+        .args(["-sS", "-o", "/dev/null", "-w", "%{http_code}"])
+        .args(["--max-time", "10", url])
+        .output()
+        .context("Failed to run curl (is it installed?)")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .with_context(|| format!("curl returned no status for {}", url))
+}
+
+/// Fetch the certificate `domain` serves, via the local openssl
+pub fn served(domain: &str) -> Result<Served> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "openssl s_client -connect {d}:443 -servername {d} </dev/null 2>/dev/null \
+             | openssl x509 -noout -enddate -issuer",
+            d = shell_quote(domain)
+        ))
+        .output()
+        .context("Failed to run openssl (is it installed?)")?;
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let field = |key: &str| {
+        text.lines()
+            .find_map(|l| l.strip_prefix(key))
+            .map(|v| v.trim().to_string())
+    };
+    let (Some(end), Some(issuer)) = (field("notAfter="), field("issuer=")) else {
+        bail!("No certificate served on {}:443", domain);
+    };
+
+    // e.g. "Mar  1 12:00:00 2026 GMT"
+    let end = end.split_whitespace().collect::<Vec<_>>().join(" ");
+    let expires = NaiveDateTime::parse_from_str(&end, "%b %d %H:%M:%S %Y GMT")
+        .with_context(|| format!("Unexpected certificate date '{}'", end))?
+        .and_utc();
+    Ok(Served { issuer, expires })
+}
+
+/// Domains Traefik holds certificates for, from the content of acme.json
+pub fn stored_domains(acme_json: &str) -> Vec<String> {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(acme_json) else {
+        return Vec::new();
+    };
+    let mut domains = Vec::new();
+    for resolver in value.as_object().into_iter().flat_map(|o| o.values()) {
+        let certificates = resolver
+            .get("Certificates")
+            .and_then(|c| c.as_array())
+            .into_iter()
+            .flatten();
+        for certificate in certificates {
+            let domain = &certificate["domain"];
+            let names = domain["main"].as_str().into_iter().chain(
+                domain["sans"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|s| s.as_str()),
+            );
+            for name in names {
+                if !domains.iter().any(|d| d == name) {
+                    domains.push(name.to_string());
+                }
+            }
+        }
+    }
+    domains
+}
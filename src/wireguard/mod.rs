@@ -1,12 +1,12 @@
 use anyhow::{Context, Result};
 
-use crate::config::{HostConfig, StageConfig};
+use crate::config::HostConfig;
 use crate::os::HostOs;
 use crate::output;
 use crate::ssh::SshSession;
 
 const WG_SUBNET: &str = "10.10.0";
-const WG_PORT: u16 = 51820;
+pub const WG_PORT: u16 = 51820;
 
 /// Assign a WireGuard IP based on host index (1-based): 10.10.0.1, 10.10.0.2, ...
 fn wg_ip(index: usize) -> String {
@@ -15,9 +15,13 @@ fn wg_ip(index: usize) -> String {
 
 /// Setup WireGuard mesh between all hosts in a stage.
 ///
-/// Requires all SSH sessions to be open simultaneously so we can
+/// Takes one open SSH session per host (same order as `hosts`) so we can
 /// exchange public keys between hosts.
-pub async fn setup(stage: &StageConfig, hosts: &[HostConfig], os_config: Option<&str>) -> Result<()> {
+pub async fn setup(
+    hosts: &[HostConfig],
+    sessions: &[SshSession],
+    os_config: Option<&str>,
+) -> Result<()> {
     if hosts.len() < 2 {
         output::info("WireGuard skipped (only 1 host, no peers needed)");
         return Ok(());
@@ -25,14 +29,7 @@ pub async fn setup(stage: &StageConfig, hosts: &[HostConfig], os_config: Option<
 
     output::header("Setting up WireGuard mesh");
 
-    let user = stage.user.as_deref().unwrap_or("deploy");
-
-    // Step 1: Connect to all hosts
-    let mut sessions: Vec<SshSession> = Vec::new();
-    for host in hosts {
-        let session = SshSession::connect(user, &host.address, stage.port, stage.proxy.as_deref()).await?;
-        sessions.push(session);
-    }
+    // Step 1: Sessions are already connected by the caller
 
     // Step 2: Detect OS and install wireguard-tools on each host
     let mut host_os_list: Vec<HostOs> = Vec::new();
@@ -89,11 +86,6 @@ pub async fn setup(stage: &StageConfig, hosts: &[HostConfig], os_config: Option<
         activate_wireguard(session, host_os_list[i]).await?;
     }
 
-    // Close all sessions
-    for session in sessions {
-        session.close().await?;
-    }
-
     output::success("WireGuard mesh configured and active");
     Ok(())
 }
@@ -122,7 +114,7 @@ async fn generate_keypair(session: &SshSession) -> Result<String> {
 
     if has_keys {
         let pubkey = session
-            .exec("sudo cat /etc/wireguard/publickey")
+            .query("sudo cat /etc/wireguard/publickey")
             .await
             .context("Failed to read existing public key")?;
         output::success("WireGuard keys already exist");
@@ -197,9 +189,12 @@ async fn activate_wireguard(session: &SshSession, host_os: HostOs) -> Result<()>
     let _ = session.sudo_exec("wg-quick down wg0 2>/dev/null || true").await;
 
     match host_os {
-        HostOs::Ubuntu => {
+        HostOs::Ubuntu | HostOs::Rhel => {
             session
-                .sudo_exec("systemctl enable wg-quick@wg0 && wg-quick up wg0")
+                .sudo_exec_streamed(
+                    "systemctl enable wg-quick@wg0 && wg-quick up wg0",
+                    output::remote_line,
+                )
                 .await
                 .context("Failed to activate WireGuard")?;
         }
@@ -207,7 +202,7 @@ async fn activate_wireguard(session: &SshSession, host_os: HostOs) -> Result<()>
             // On NixOS, wg-quick@.service template doesn't exist.
             // Just bring the interface up; persistence is handled by the config.
             session
-                .sudo_exec("wg-quick up wg0")
+                .sudo_exec_streamed("wg-quick up wg0", output::remote_line)
                 .await
                 .context("Failed to activate WireGuard")?;
         }